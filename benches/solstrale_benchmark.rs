@@ -1,11 +1,9 @@
-use std::sync::mpsc::channel;
-use std::thread;
 use std::time::Duration;
 
 use criterion::{BenchmarkId, black_box, Criterion, criterion_group, criterion_main, Throughput};
 use derive_more::{Constructor, Display};
 
-use solstrale::ray_trace;
+use solstrale::RenderProgressIter;
 use solstrale::renderer::RenderConfig;
 
 use crate::scenes::{create_test_scene, new_bvh_test_scene};
@@ -44,21 +42,7 @@ pub fn bvh_benchmark(c: &mut Criterion) {
                             bvh_input.num_triangles,
                         )
                     },
-                    |scene| {
-                        let (output_sender, output_receiver) = channel();
-                        let (_, abort_receiver) = channel();
-
-                        thread::spawn(move || {
-                            ray_trace(
-                                scene,
-                                &output_sender,
-                                &abort_receiver,
-                            )
-                            .unwrap();
-                        });
-
-                        for _ in output_receiver {}
-                    },
+                    |scene| for _ in RenderProgressIter::new(scene).unwrap() {},
                 );
             },
         );
@@ -78,27 +62,13 @@ pub fn scene_benchmark(c: &mut Criterion) {
                 };
                 create_test_scene(render_config)
             },
-            |scene| {
-                let (output_sender, output_receiver) = channel();
-                let (_, abort_receiver) = channel();
-
-                thread::spawn(move || {
-                    ray_trace(
-                        scene,
-                        &output_sender,
-                        &abort_receiver,
-                    )
-                    .unwrap();
-                });
-
-                for _ in output_receiver {}
-            },
+            |scene| for _ in RenderProgressIter::new(scene).unwrap() {},
         )
     });
 }
 
 #[derive(Constructor, Display)]
-#[display(fmt = "{} {}", num_triangles, use_bvh)]
+#[display("{} {}", num_triangles, use_bvh)]
 struct BvhInput {
     num_triangles: u32,
     use_bvh: bool,
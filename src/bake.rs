@@ -0,0 +1,379 @@
+//! Bakes the lighting on the surfaces of meshes into images, as ambient occlusion
+//! or as the irradiance from a path traced scene. The images are laid out by the
+//! lightmap texture coordinates of the meshes, falling back to the regular
+//! texture coordinates, so it can be used as an offline baker for game pipelines
+use std::error::Error;
+
+use image::{DynamicImage, GrayImage, Luma, Rgb, Rgb32FImage, RgbImage};
+use rayon::prelude::*;
+use simple_error::SimpleError;
+
+use crate::geo::vec3::{random_cosine_direction, Vec3, ZERO_VECTOR};
+use crate::geo::{Onb, Ray, Uv};
+use crate::hittable::Hittables::{BvhType, QuadType, TriangleMeshType, TriangleType};
+use crate::hittable::{Hittable, Hittables};
+use crate::renderer::{Renderer, Scene};
+use crate::sampler;
+use crate::util::interval::{Interval, RAY_INTERVAL};
+
+/// Configuration of an ambient occlusion bake
+#[derive(Copy, Clone, Debug)]
+pub struct BakeConfig {
+    /// Width of the baked image in pixels
+    pub width: u32,
+    /// Height of the baked image in pixels
+    pub height: u32,
+    /// Number of rays shot from each pixel, at least one
+    pub samples_per_pixel: u32,
+    /// Surfaces farther away than this do not occlude, only used for ambient occlusion
+    pub max_distance: f64,
+    /// Number of pixels that the baked areas are extended by,
+    /// so there are no dark seams when the image is filtered
+    pub padding: u32,
+}
+
+impl Default for BakeConfig {
+    fn default() -> Self {
+        BakeConfig {
+            width: 512,
+            height: 512,
+            samples_per_pixel: 64,
+            max_distance: f64::INFINITY,
+            padding: 2,
+        }
+    }
+}
+
+/// A point on the mesh that a pixel is baked for
+#[derive(Copy, Clone)]
+struct Texel {
+    point: Vec3,
+    normal: Vec3,
+    x: usize,
+    y: usize,
+}
+
+/// Bakes the fraction of the sky that is visible from the triangles and quads of the mesh,
+/// including those contained in a [`crate::hittable::Bvh`]. The world is what occludes
+/// the sky and should normally contain the mesh itself. White is fully visible sky.
+/// Pixels not covered by the texture coordinates of the mesh, nor its padding, are black
+pub fn bake_ambient_occlusion(
+    mesh: &Hittables,
+    world: &Hittables,
+    config: &BakeConfig,
+) -> Result<RgbImage, Box<dyn Error>> {
+    check_samples(config)?;
+    let visibility = bake(mesh, config, |texel| {
+        let onb = Onb::new(texel.normal);
+        let ray_length = Interval::new(RAY_INTERVAL.min, config.max_distance);
+        let visible = (0..config.samples_per_pixel)
+            .filter(|_| {
+                let ray = Ray::new(texel.point, onb.local(random_cosine_direction()));
+                world.hit(&ray, &ray_length).is_none()
+            })
+            .count();
+        let v = visible as f64 / config.samples_per_pixel as f64;
+        Vec3::new(v, v, v)
+    });
+
+    let gray = GrayImage::from_fn(config.width, config.height, |x, y| {
+        let v = visibility[y as usize * config.width as usize + x as usize];
+        Luma([(v.x * 255.).round() as u8])
+    });
+    Ok(DynamicImage::ImageLuma8(gray).into_rgb8())
+}
+
+/// Bakes the light arriving at the triangles and quads of each of the meshes, path traced
+/// in the scene the same way as when rendering, into one high dynamic range image per mesh.
+/// The meshes should normally be part of the world of the scene. The baked irradiance is
+/// divided by pi, so multiplying it by the albedo of a surface gives its diffusely
+/// reflected light. Pixels not covered by the texture coordinates, nor the padding, are black
+pub fn bake_irradiance(
+    scene: Scene,
+    meshes: &[Hittables],
+    config: &BakeConfig,
+) -> Result<Vec<Rgb32FImage>, Box<dyn Error>> {
+    check_samples(config)?;
+    let sampler = scene.render_config.sampler;
+    let renderer = Renderer::new(scene)?;
+
+    Ok(meshes
+        .iter()
+        .map(|mesh| {
+            let irradiance = bake(mesh, config, |texel| {
+                let onb = Onb::new(texel.normal);
+                let light: Vec3 = (0..config.samples_per_pixel)
+                    .map(|i| {
+                        sampler::start_pixel_sample(
+                            sampler,
+                            texel.x,
+                            texel.y,
+                            i,
+                            config.samples_per_pixel,
+                        );
+                        let direction = onb.local(random_cosine_direction());
+                        renderer.incident_radiance(texel.point, direction)
+                    })
+                    .fold(ZERO_VECTOR, |sum, l| sum + l);
+                light / config.samples_per_pixel as f64
+            });
+
+            Rgb32FImage::from_fn(config.width, config.height, |x, y| {
+                let c = irradiance[y as usize * config.width as usize + x as usize];
+                Rgb([c.x as f32, c.y as f32, c.z as f32])
+            })
+        })
+        .collect())
+}
+
+/// Returns an error if the pixels would not be sampled, as their mean would be undefined
+fn check_samples(config: &BakeConfig) -> Result<(), Box<dyn Error>> {
+    if config.samples_per_pixel == 0 {
+        return Err(Box::new(SimpleError::new(
+            "Baking needs at least one sample per pixel",
+        )));
+    }
+    Ok(())
+}
+
+/// Bakes a color for each pixel covered by the mesh, with the padding applied.
+/// Pixels that are not covered are black
+fn bake<F>(mesh: &Hittables, config: &BakeConfig, color: F) -> Vec<Vec3>
+where
+    F: Fn(Texel) -> Vec3 + Sync,
+{
+    let (width, height) = (config.width as usize, config.height as usize);
+    let mut texels = vec![None; width * height];
+    for (points, uvs) in uv_triangles(mesh) {
+        rasterize(&mut texels, width, height, points, uvs);
+    }
+
+    let mut colors: Vec<Option<Vec3>> = texels.par_iter().map(|texel| texel.map(&color)).collect();
+    for _ in 0..config.padding {
+        colors = dilate(&colors, width, height);
+    }
+    colors.into_iter().map(|c| c.unwrap_or(ZERO_VECTOR)).collect()
+}
+
+/// Triangles of the mesh, with the corners both in space and in texture coordinates
+fn uv_triangles(hittable: &Hittables) -> Vec<([Vec3; 3], [Uv; 3])> {
+    match hittable {
+        BvhType(b) => b.leaves().into_iter().flat_map(uv_triangles).collect(),
+        TriangleType(t) => vec![(t.vertices_and_tex_coords().0, t.lightmap_tex_coords())],
+        TriangleMeshType(m) => m
+            .triangles()
+            .iter()
+            .map(|t| (t.vertices_and_tex_coords().0, t.lightmap_tex_coords()))
+            .collect(),
+        QuadType(q) => {
+            let ([p0, p1, p2, p3], [uv0, uv1, uv2, uv3]) = q.corners_and_tex_coords();
+            vec![([p0, p1, p2], [uv0, uv1, uv2]), ([p0, p2, p3], [uv0, uv2, uv3])]
+        }
+        _ => vec![],
+    }
+}
+
+/// Sets the points on the triangle for all pixels whose centers are within the triangle
+/// in texture space
+fn rasterize(
+    texels: &mut [Option<Texel>],
+    width: usize,
+    height: usize,
+    points: [Vec3; 3],
+    uvs: [Uv; 3],
+) {
+    let normal = (points[1] - points[0]).cross(points[2] - points[0]).unit();
+    // Pixel coordinates, where y goes down from the top of the image
+    let [a, b, c] = uvs.map(|uv| (uv.u as f64 * width as f64, (1. - uv.v as f64) * height as f64));
+    let area = edge(a, b, c);
+    if area == 0. {
+        return;
+    }
+
+    let min_x = a.0.min(b.0).min(c.0).floor().max(0.) as usize;
+    let max_x = (a.0.max(b.0).max(c.0).ceil().max(0.) as usize).min(width);
+    let min_y = a.1.min(b.1).min(c.1).floor().max(0.) as usize;
+    let max_y = (a.1.max(b.1).max(c.1).ceil().max(0.) as usize).min(height);
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let p = (x as f64 + 0.5, y as f64 + 0.5);
+            let weights = [edge(b, c, p) / area, edge(c, a, p) / area, edge(a, b, p) / area];
+            if weights.iter().all(|w| *w >= 0.) {
+                texels[y * width + x] = Some(Texel {
+                    point: points[0] * weights[0] + points[1] * weights[1] + points[2] * weights[2],
+                    normal,
+                    x,
+                    y,
+                });
+            }
+        }
+    }
+}
+
+/// Twice the signed area of the triangle a, b, c
+fn edge(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> f64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+/// Extends the baked areas one pixel, with the average of the neighbouring baked pixels
+fn dilate(values: &[Option<Vec3>], width: usize, height: usize) -> Vec<Option<Vec3>> {
+    (0..values.len())
+        .map(|i| {
+            if values[i].is_some() {
+                return values[i];
+            }
+            let (x, y) = (i % width, i / width);
+            let neighbours = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < width).then(|| i + 1),
+                (y > 0).then(|| i - width),
+                (y + 1 < height).then(|| i + width),
+            ];
+            let baked: Vec<Vec3> = neighbours
+                .into_iter()
+                .flatten()
+                .filter_map(|n| values[n])
+                .collect();
+            (!baked.is_empty())
+                .then(|| baked.iter().fold(ZERO_VECTOR, |sum, v| sum + *v) / baked.len() as f64)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::slice;
+
+    use crate::camera::CameraConfig;
+    use crate::geo::transformation::NopTransformer;
+    use crate::hittable::{Bvh, EnvironmentMap, Quad, Triangle, TriangleOptions};
+    use crate::material::texture::SolidColor;
+    use crate::material::Lambertian;
+    use crate::renderer::RenderConfig;
+
+    use super::*;
+
+    fn floor() -> Hittables {
+        Quad::new(
+            Vec3::new(-1., 0., 1.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 0., -2.),
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        )
+    }
+
+    #[test]
+    fn test_bake_ambient_occlusion() {
+        // A low ceiling over the left half of the floor
+        let ceiling = Quad::new(
+            Vec3::new(-10., 0.1, -10.),
+            Vec3::new(10., 0., 0.),
+            Vec3::new(0., 0., 20.),
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        );
+        let world = Bvh::new(vec![floor(), ceiling]);
+        let config = BakeConfig {
+            width: 8,
+            height: 8,
+            samples_per_pixel: 200,
+            ..BakeConfig::default()
+        };
+
+        let image = bake_ambient_occlusion(&floor(), &world, &config).unwrap();
+        assert!(image.get_pixel(1, 4)[0] < 50);
+        assert!(image.get_pixel(6, 4)[0] > 200);
+
+        let config = BakeConfig {
+            samples_per_pixel: 0,
+            ..config
+        };
+        let res = bake_ambient_occlusion(&floor(), &world, &config);
+        assert_eq!(
+            format!("{}", res.err().unwrap()),
+            "Baking needs at least one sample per pixel"
+        );
+    }
+
+    #[test]
+    fn test_padding() {
+        // Covers the lower left half of the texture
+        let triangle = Triangle::new_with_options(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 0., -1.),
+            TriangleOptions {
+                uvs: [Uv::new(0., 0.), Uv::new(1., 0.), Uv::new(0., 1.)],
+                ..Default::default()
+            },
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        );
+        let config = BakeConfig {
+            width: 8,
+            height: 8,
+            samples_per_pixel: 1,
+            padding: 0,
+            ..BakeConfig::default()
+        };
+        let image = bake_ambient_occlusion(&triangle, &triangle, &config).unwrap();
+        assert_eq!(image.get_pixel(0, 7)[0], 255);
+        assert_eq!(image.get_pixel(3, 2)[0], 0);
+        assert_eq!(image.get_pixel(7, 0)[0], 0);
+
+        let config = BakeConfig { padding: 1, ..config };
+        let image = bake_ambient_occlusion(&triangle, &triangle, &config).unwrap();
+        assert_eq!(image.get_pixel(3, 2)[0], 255);
+        assert_eq!(image.get_pixel(7, 0)[0], 0);
+    }
+
+    #[test]
+    fn test_bake_irradiance() {
+        // Only the lightmap texture coordinates cover the lower left half of the texture
+        let triangle = Triangle::new_with_options(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 0., -1.),
+            TriangleOptions {
+                lightmap_uvs: Some([Uv::new(0., 0.), Uv::new(1., 0.), Uv::new(0., 1.)]),
+                ..Default::default()
+            },
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        );
+        let sky = Rgb32FImage::from_pixel(4, 2, Rgb([0.5, 1., 2.]));
+        let scene = || Scene {
+            world: triangle.clone(),
+            camera: CameraConfig::default(),
+            views: HashMap::new(),
+            background_color: ZERO_VECTOR,
+            environment_map: Some(EnvironmentMap::new(sky.clone(), 1.)),
+            background: None,
+            render_config: RenderConfig::default(),
+        };
+        let config = BakeConfig {
+            width: 8,
+            height: 8,
+            samples_per_pixel: 4,
+            padding: 0,
+            ..BakeConfig::default()
+        };
+
+        let images = bake_irradiance(scene(), slice::from_ref(&triangle), &config).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].get_pixel(0, 7), &Rgb([0.5, 1., 2.]));
+        assert_eq!(images[0].get_pixel(7, 0), &Rgb([0., 0., 0.]));
+
+        let config = BakeConfig {
+            samples_per_pixel: 0,
+            ..config
+        };
+        let res = bake_irradiance(scene(), slice::from_ref(&triangle), &config);
+        assert_eq!(
+            format!("{}", res.err().unwrap()),
+            "Baking needs at least one sample per pixel"
+        );
+    }
+}
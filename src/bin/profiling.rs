@@ -1,9 +1,6 @@
-use std::sync::mpsc::channel;
-use std::thread;
-
 use image::RgbImage;
 
-use solstrale::ray_trace;
+use solstrale::RenderProgressIter;
 use solstrale::renderer::RenderConfig;
 
 use crate::scenes::create_test_scene;
@@ -20,17 +17,10 @@ fn main() {
     };
     let scene = create_test_scene(render_config);
 
-    let (output_sender, output_receiver) = channel();
-    let (_, abort_receiver) = channel();
-
-    thread::spawn(move || {
-        ray_trace(scene, &output_sender, &abort_receiver).unwrap();
-    });
-
     let mut image = RgbImage::new(800, 400);
-    for render_output in output_receiver {
+    for render_output in RenderProgressIter::new(scene).unwrap() {
         if let Some(render_image) = render_output.render_image {
-            image = render_image;
+            image = render_image.into_rgb8();
         }
     }
 
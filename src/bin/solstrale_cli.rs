@@ -0,0 +1,128 @@
+//! Renders a scene description file to an image.
+//!
+//! `solstrale-cli <scene_file> <output_file> [--width <w>] [--height <h>] [--samples <n>]`
+//!
+//! The format of the scene file is described in [`solstrale::loader::scene`].
+//! The output format is decided by the file extension, where `.exr` gives a linear float image
+//! with the raw radiance of the rendering
+use std::error::Error;
+use std::io::Write;
+use std::time::Duration;
+use std::{env, fs, io, process};
+
+use simple_error::SimpleError;
+
+use solstrale::loader::scene::parse_scene;
+use solstrale::renderer::ImageFormat;
+use solstrale::RenderProgressIter;
+
+const USAGE: &str =
+    "Usage: solstrale-cli <scene_file> <output_file> [--width <w>] [--height <h>] [--samples <n>]";
+const PROGRESS_BAR_WIDTH: usize = 40;
+
+struct Args {
+    scene_file: String,
+    output_file: String,
+    width: Option<usize>,
+    height: Option<usize>,
+    samples: Option<u32>,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = parse_args(env::args().skip(1))?;
+
+    let description = fs::read_to_string(&args.scene_file)
+        .map_err(|e| format!("Failed to read {}: {}", args.scene_file, e))?;
+    let mut scene = parse_scene(&description)?;
+    if let Some(width) = args.width {
+        scene.render_config.width = width;
+    }
+    if let Some(height) = args.height {
+        scene.render_config.height = height;
+    }
+    if let Some(samples) = args.samples {
+        scene.render_config.samples_per_pixel = samples;
+    }
+    if args.output_file.to_lowercase().ends_with(".exr") {
+        scene.render_config.image_format = ImageFormat::Rgb32F;
+    }
+
+    let mut image = None;
+    for progress in RenderProgressIter::new(scene)? {
+        print_progress(progress.progress, progress.estimated_time_left);
+        if progress.render_image.is_some() {
+            image = progress.render_image;
+        }
+    }
+    eprintln!();
+
+    let image = image.ok_or("No image was rendered")?;
+    image.save(&args.output_file)?;
+    eprintln!("Saved {}", args.output_file);
+    Ok(())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, Box<dyn Error>> {
+    let mut positional = Vec::new();
+    let mut width = None;
+    let mut height = None;
+    let mut samples = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => width = Some(parse_value(&arg, args.next())?),
+            "--height" => height = Some(parse_value(&arg, args.next())?),
+            "--samples" => samples = Some(parse_value(&arg, args.next())?),
+            "-h" | "--help" => return Err(USAGE.into()),
+            _ if arg.starts_with("--") => {
+                return Err(format!("Unknown option {}\n{}", arg, USAGE).into())
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    match <[String; 2]>::try_from(positional) {
+        Ok([scene_file, output_file]) => Ok(Args {
+            scene_file,
+            output_file,
+            width,
+            height,
+            samples,
+        }),
+        Err(_) => Err(USAGE.into()),
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(
+    option: &str,
+    value: Option<String>,
+) -> Result<T, Box<dyn Error>> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", option))?;
+    value.parse().map_err(|_| {
+        Box::new(SimpleError::new(format!(
+            "Invalid value {} for {}",
+            value, option
+        )))
+        .into()
+    })
+}
+
+fn print_progress(progress: f64, estimated_time_left: Duration) {
+    let done = (progress * PROGRESS_BAR_WIDTH as f64) as usize;
+    eprint!(
+        "\r[{}{}] {:3.0}% {:>6.0}s left",
+        "#".repeat(done),
+        " ".repeat(PROGRESS_BAR_WIDTH - done),
+        progress * 100.,
+        estimated_time_left.as_secs_f64()
+    );
+    let _ = io::stderr().flush();
+}
+
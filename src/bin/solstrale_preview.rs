@@ -0,0 +1,189 @@
+//! Shows a scene description file in a window while it is rendered progressively.
+//!
+//! `solstrale-preview <scene_file>`
+//!
+//! Drag with the left mouse button or use the arrow keys to orbit the camera around the
+//! point it looks at, and use page up and page down to move closer or further away.
+//! Every change is sent to the running render, which restarts from the new camera position.
+use std::error::Error;
+use std::f64::consts::FRAC_PI_2;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::Duration;
+use std::{env, fs, process, thread};
+
+use image::RgbImage;
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+
+use solstrale::camera::CameraConfig;
+use solstrale::geo::vec3::Vec3;
+use solstrale::loader::scene::parse_scene;
+use solstrale::renderer::{RenderCommand, RenderImageStrategy, RenderProgress, Renderer, Scene};
+
+const USAGE: &str = "Usage: solstrale-preview <scene_file>";
+const KEY_ROTATION: f64 = 0.1;
+const MOUSE_ROTATION: f64 = 0.01;
+const ZOOM_FACTOR: f64 = 1.1;
+
+/// Direction and distance of the camera, relative to the point it looks at
+#[derive(Copy, Clone)]
+struct Orbit {
+    yaw: f64,
+    pitch: f64,
+    distance: f64,
+}
+
+impl Orbit {
+    fn from_camera(camera: &CameraConfig) -> Orbit {
+        let offset = camera.look_from - camera.look_at;
+        let distance = offset.length();
+        Orbit {
+            yaw: offset.x.atan2(offset.z),
+            pitch: (offset.y / distance).asin(),
+            distance,
+        }
+    }
+
+    fn rotate(&mut self, yaw: f64, pitch: f64) {
+        // Stay away from the poles, where the camera up direction is undefined
+        let max_pitch = FRAC_PI_2 - 0.01;
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-max_pitch, max_pitch);
+    }
+
+    fn look_from(&self, look_at: Vec3) -> Vec3 {
+        look_at
+            + Vec3::new(
+                self.pitch.cos() * self.yaw.sin(),
+                self.pitch.sin(),
+                self.pitch.cos() * self.yaw.cos(),
+            ) * self.distance
+    }
+}
+
+/// A render running on a separate thread, that follows the camera
+struct Render {
+    output: Receiver<RenderProgress>,
+    commands: Sender<RenderCommand>,
+    abort: Sender<bool>,
+}
+
+impl Render {
+    fn start(mut scene: Scene) -> Render {
+        scene.render_config.render_image_strategy =
+            RenderImageStrategy::Interval(Duration::from_millis(100));
+
+        let (output_sender, output_receiver) = channel();
+        let (command_sender, command_receiver) = channel();
+        let (abort_sender, abort_receiver) = channel();
+        thread::spawn(move || {
+            let res = Renderer::new(scene).and_then(|renderer| {
+                renderer.render_interactive(&command_receiver, &output_sender, &abort_receiver)
+            });
+            if let Err(e) = res {
+                eprintln!("Render failed: {}", e);
+            }
+        });
+
+        Render {
+            output: output_receiver,
+            commands: command_sender,
+            abort: abort_sender,
+        }
+    }
+
+    fn move_camera(&self, camera: CameraConfig) {
+        // The render has stopped if it failed, which has been reported already
+        let _ = self.commands.send(RenderCommand::MoveCamera(camera));
+    }
+}
+
+impl Drop for Render {
+    fn drop(&mut self) {
+        // The render might have failed already, in which case there is nothing to abort
+        let _ = self.abort.send(true);
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let [scene_file] = args.as_slice() else {
+        return Err(USAGE.into());
+    };
+    let description = fs::read_to_string(scene_file)
+        .map_err(|e| format!("Failed to read {}: {}", scene_file, e))?;
+
+    let scene = parse_scene(&description)?;
+    let (width, height) = (scene.render_config.width, scene.render_config.height);
+    let mut camera = scene.camera.clone();
+    let mut orbit = Orbit::from_camera(&camera);
+    let render = Render::start(scene);
+
+    let mut window = Window::new(
+        &format!("Solstrale - {}", scene_file),
+        width,
+        height,
+        WindowOptions::default(),
+    )?;
+    window.set_target_fps(30);
+
+    let mut buffer = vec![0u32; width * height];
+    let mut last_mouse_pos = None;
+
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        let mut changed = false;
+        for key in window.get_keys_pressed(KeyRepeat::Yes) {
+            match key {
+                Key::Left => orbit.rotate(-KEY_ROTATION, 0.),
+                Key::Right => orbit.rotate(KEY_ROTATION, 0.),
+                Key::Up => orbit.rotate(0., KEY_ROTATION),
+                Key::Down => orbit.rotate(0., -KEY_ROTATION),
+                Key::PageUp => orbit.distance /= ZOOM_FACTOR,
+                Key::PageDown => orbit.distance *= ZOOM_FACTOR,
+                _ => continue,
+            }
+            changed = true;
+        }
+
+        let mouse_pos = window
+            .get_mouse_pos(MouseMode::Discard)
+            .filter(|_| window.get_mouse_down(MouseButton::Left));
+        if let (Some((x, y)), Some((last_x, last_y))) = (mouse_pos, last_mouse_pos) {
+            if (x, y) != (last_x, last_y) {
+                orbit.rotate(
+                    (last_x - x) as f64 * MOUSE_ROTATION,
+                    (y - last_y) as f64 * MOUSE_ROTATION,
+                );
+                changed = true;
+            }
+        }
+        last_mouse_pos = mouse_pos;
+
+        if changed {
+            camera.look_from = orbit.look_from(camera.look_at);
+            render.move_camera(camera.clone());
+        }
+
+        while let Ok(progress) = render.output.try_recv() {
+            if let Some(image) = progress.render_image {
+                copy_to_buffer(&image.into_rgb8(), &mut buffer);
+            }
+        }
+        window.update_with_buffer(&buffer, width, height)?;
+    }
+    Ok(())
+}
+
+/// Copies the image into the window buffer, that has one 0RGB pixel per u32
+fn copy_to_buffer(image: &RgbImage, buffer: &mut [u32]) {
+    for (pixel, target) in image.pixels().zip(buffer.iter_mut()) {
+        let [r, g, b] = pixel.0;
+        *target = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+    }
+}
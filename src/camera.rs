@@ -1,10 +1,11 @@
 //! Provides a camera used by raytracer to shoot rays into the scene
 
 use crate::geo::vec3::{random_in_unit_disc, Vec3, ZERO_VECTOR};
-use crate::geo::{Ray, Uv};
+use crate::geo::{Ray, RayDifferentials, Uv};
 use crate::util::degrees_to_radians;
 
 /// Contains all needed parameters for constructing a camera
+#[derive(Clone)]
 pub struct CameraConfig {
     /// Vertical field of view in degrees
     pub vertical_fov_degrees: f64,
@@ -16,6 +17,9 @@ pub struct CameraConfig {
     pub look_at: Vec3,
     /// Direction pointing "up" for the camera
     pub up: Vec3,
+    /// Distance from the camera to the plane that is in focus,
+    /// defaults to the distance to the look at point
+    pub focus_distance: Option<f64>,
 }
 
 impl Default for CameraConfig {
@@ -26,12 +30,15 @@ impl Default for CameraConfig {
             look_from: ZERO_VECTOR,
             look_at: ZERO_VECTOR,
             up: Vec3::new(0., 1., 0.),
+            focus_distance: None,
         }
     }
 }
 
 /// Contains all data needed to describe a cameras position, field of view and
-/// where it is pointing
+/// where it is pointing. Host applications can create one with the same configuration and
+/// image size as a render, to shoot the same rays as the renderer does
+#[derive(Clone, Debug)]
 pub struct Camera {
     origin: Vec3,
     lower_left_corner: Vec3,
@@ -40,6 +47,10 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     lens_radius: f64,
+    pixel_horizontal: Vec3,
+    pixel_vertical: Vec3,
+    image_width: usize,
+    image_height: usize,
 }
 
 impl Camera {
@@ -52,7 +63,7 @@ impl Camera {
         let view_port_width = aspect_ratio * view_port_height;
 
         let look_v = c.look_from - c.look_at;
-        let focus_distance = look_v.length();
+        let focus_distance = c.focus_distance.unwrap_or_else(|| look_v.length());
         let w = look_v.unit();
         let u = c.up.unit().cross(w).unit();
         let v = w.cross(u);
@@ -70,11 +81,16 @@ impl Camera {
             u,
             v,
             lens_radius: c.aperture_size / 2.,
+            pixel_horizontal: horizontal / (image_width.max(2) - 1) as f64,
+            pixel_vertical: vertical / (image_height.max(2) - 1) as f64,
+            image_width,
+            image_height,
         }
     }
 
     /// A function for generating a ray for a certain u/v for the raytraced image
-    pub fn get_ray(&self, uv: Uv) -> Ray {
+    /// at the given point in time
+    pub fn get_ray(&self, uv: Uv, time: f64) -> Ray {
         let offset = if self.lens_radius > 0. {
             let rd = random_in_unit_disc() * self.lens_radius;
             self.u * rd.x + self.v * rd.y
@@ -85,6 +101,102 @@ impl Camera {
         let r_dir = self.lower_left_corner + (self.horizontal * uv.u) + (self.vertical * uv.v)
             - self.origin
             - offset;
-        Ray::new(self.origin + offset, r_dir)
+        let origin = self.origin + offset;
+        Ray::new_at_time(origin, r_dir, time).with_differentials(RayDifferentials {
+            dx_origin: origin,
+            dx_direction: r_dir + self.pixel_horizontal,
+            dy_origin: origin,
+            dy_direction: r_dir + self.pixel_vertical,
+        })
+    }
+
+    /// Generates a ray through the given point of the image in pixel coordinates, where 0, 0
+    /// is the top left corner of the image and 0.5, 0.5 the center of the top left pixel
+    pub fn get_pixel_ray(&self, x: f64, y: f64, time: f64) -> Ray {
+        let u = x / (self.image_width.max(2) - 1) as f64;
+        let v = (self.image_height as f64 - y) / (self.image_height.max(2) - 1) as f64;
+        self.get_ray(Uv::new(u as f32, v as f32), time)
+    }
+
+    /// Projects a point in the scene onto the image, the inverse of [`Camera::get_pixel_ray`].
+    /// Returns the pixel coordinates, that may be outside of the image,
+    /// or none if the point is not in front of the camera
+    pub fn world_to_pixel(&self, point: Vec3) -> Option<(f64, f64)> {
+        let center =
+            self.lower_left_corner + self.horizontal / 2. + self.vertical / 2. - self.origin;
+        let focus_distance = center.length();
+        let forward = center / focus_distance;
+
+        let direction = point - self.origin;
+        let depth = direction.dot(forward);
+        if depth <= 0. {
+            return None;
+        }
+        let on_focus_plane = direction * (focus_distance / depth) - center;
+        let u = on_focus_plane.dot(self.u) / self.horizontal.length() + 0.5;
+        let v = on_focus_plane.dot(self.v) / self.vertical.length() + 0.5;
+
+        Some((
+            u * (self.image_width.max(2) - 1) as f64,
+            self.image_height as f64 - v * (self.image_height.max(2) - 1) as f64,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_focus_distance() {
+        let config = CameraConfig {
+            aperture_size: 1.,
+            look_from: Vec3::new(0., 0., 5.),
+            focus_distance: Some(2.),
+            ..CameraConfig::default()
+        };
+        let camera = Camera::new(10, 10, &config);
+
+        // All rays through the center of the image meet at the focus distance
+        for _ in 0..10 {
+            let ray = camera.get_ray(Uv::new(0.5, 0.5), 0.);
+            assert!((ray.at(1.) - Vec3::new(0., 0., 3.)).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_pixel_ray() {
+        let config = CameraConfig {
+            look_from: Vec3::new(0., 0., 5.),
+            ..CameraConfig::default()
+        };
+        let camera = Camera::new(20, 10, &config);
+
+        let ray = camera.get_pixel_ray(0., 0., 0.);
+        assert!(ray.direction.x < 0. && ray.direction.y > 0.);
+        let ray = camera.get_pixel_ray(20., 10., 0.);
+        assert!(ray.direction.x > 0. && ray.direction.y < 0.);
+
+        // Same as the ray through the texture coordinates of the bottom left corner
+        let ray = camera.get_pixel_ray(0., 10., 0.);
+        assert_eq!(ray, camera.get_ray(Uv::new(0., 0.), 0.));
+    }
+
+    #[test]
+    fn test_world_to_pixel() {
+        let config = CameraConfig {
+            look_from: Vec3::new(1., 2., 5.),
+            look_at: Vec3::new(0., 0.5, 0.),
+            ..CameraConfig::default()
+        };
+        let camera = Camera::new(20, 10, &config);
+
+        for (x, y) in [(0., 0.), (3.5, 7.25), (20., 10.), (-4., 12.)] {
+            let point = camera.get_pixel_ray(x, y, 0.).at(3.);
+            let (px, py) = camera.world_to_pixel(point).unwrap();
+            // Rays are shot through 32 bit texture coordinates
+            assert!((px - x).abs() < 1e-4 && (py - y).abs() < 1e-4);
+        }
+        assert!(camera.world_to_pixel(Vec3::new(2., 3.5, 10.)).is_none());
     }
 }
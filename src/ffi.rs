@@ -0,0 +1,359 @@
+//! A C ABI for embedding the ray tracer in applications not written in Rust.
+//!
+//! Scenes are described using the text format of [`crate::loader::scene`].
+//! A render is started with [`solstrale_render_start`], which returns an opaque handle
+//! that is polled for progress and pixels, and finally released with [`solstrale_render_free`].
+//!
+//! Enable the `ffi` feature and build the crate as a `cdylib` or `staticlib`
+//! (e.g. `cargo rustc --release --features ffi --crate-type cdylib`) to get a linkable library.
+//! The functions are plain `extern "C"` functions, so a header can be generated with cbindgen.
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{c_char, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use image::RgbImage;
+
+use crate::loader::scene::parse_scene;
+use crate::ray_trace;
+use crate::renderer::{panic_message, RenderProgress};
+
+thread_local! {
+    static LAST_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// An ongoing render started by [`solstrale_render_start`]
+pub struct SolstraleRender {
+    width: usize,
+    height: usize,
+    state: Arc<Mutex<RenderState>>,
+    abort: Sender<bool>,
+}
+
+#[derive(Default)]
+struct RenderState {
+    progress: f64,
+    image: Option<RgbImage>,
+    finished: bool,
+    error: Option<String>,
+}
+
+fn set_last_error(message: String) {
+    LAST_ERROR.with(|e| *e.borrow_mut() = message);
+}
+
+/// Locks the state even if a thread panicked while holding the lock,
+/// as panics must not unwind into the calling application
+fn lock(state: &Mutex<RenderState>) -> MutexGuard<'_, RenderState> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs the render, storing its progress and images in the state. The render is only marked
+/// as finished after the last image is stored, and a panic is stored as the error of the render
+fn run_render(
+    state: Arc<Mutex<RenderState>>,
+    render: impl FnOnce(&Sender<RenderProgress>) -> Result<(), Box<dyn Error>>,
+) {
+    let (output_sender, output_receiver) = channel::<RenderProgress>();
+    let progress_state = state.clone();
+    let progress = thread::spawn(move || {
+        for render_output in output_receiver {
+            let mut state = lock(&progress_state);
+            state.progress = render_output.progress;
+            if let Some(image) = render_output.render_image {
+                state.image = Some(image.into_rgb8());
+            }
+        }
+    });
+
+    let res = panic::catch_unwind(AssertUnwindSafe(|| render(&output_sender)));
+    drop(output_sender);
+    let progress_res = progress.join();
+
+    let mut state = lock(&state);
+    state.error = match (res, progress_res) {
+        (Ok(Ok(())), Ok(())) => None,
+        (Ok(Err(e)), _) => Some(e.to_string()),
+        (Err(payload), _) | (_, Err(payload)) => Some(format!(
+            "Render panicked: {}",
+            panic_message(payload.as_ref())
+        )),
+    };
+    state.finished = true;
+}
+
+/// Parses the null terminated scene description and starts rendering it in a background thread.
+/// Returns a handle to the render, or null if the scene could not be created.
+/// In which case the reason is available through [`solstrale_last_error`].
+///
+/// # Safety
+/// `description` must be a valid pointer to a null terminated string
+#[no_mangle]
+pub unsafe extern "C" fn solstrale_render_start(
+    description: *const c_char,
+) -> *mut SolstraleRender {
+    if description.is_null() {
+        set_last_error("Scene description is null".to_string());
+        return ptr::null_mut();
+    }
+    let description = match CStr::from_ptr(description).to_str() {
+        Ok(d) => d,
+        Err(_) => {
+            set_last_error("Scene description is not valid UTF-8".to_string());
+            return ptr::null_mut();
+        }
+    };
+    let scene = match panic::catch_unwind(|| parse_scene(description)) {
+        Ok(Ok(s)) => s,
+        Ok(Err(e)) => {
+            set_last_error(e.to_string());
+            return ptr::null_mut();
+        }
+        Err(payload) => {
+            set_last_error(format!(
+                "Scene parsing panicked: {}",
+                panic_message(payload.as_ref())
+            ));
+            return ptr::null_mut();
+        }
+    };
+
+    // Only the region of the image is rendered
+    let region = scene.render_config.region();
+    let (width, height) = (region.width, region.height);
+    let state = Arc::new(Mutex::new(RenderState::default()));
+    let (abort_sender, abort_receiver) = channel();
+
+    let render_state = state.clone();
+    thread::spawn(move || {
+        run_render(render_state, |output| {
+            ray_trace(scene, output, &abort_receiver)
+        })
+    });
+
+    Box::into_raw(Box::new(SolstraleRender {
+        width,
+        height,
+        state,
+        abort: abort_sender,
+    }))
+}
+
+/// Returns the progress of the render between 0 and 1
+///
+/// # Safety
+/// `render` must be a handle returned by [`solstrale_render_start`] that has not been freed
+#[no_mangle]
+pub unsafe extern "C" fn solstrale_render_progress(render: *const SolstraleRender) -> f64 {
+    lock(&(*render).state).progress
+}
+
+/// Returns true when the render is no longer running, either because it is complete,
+/// aborted or failed. A failure message is available through [`solstrale_last_error`]
+/// after calling this function.
+///
+/// # Safety
+/// `render` must be a handle returned by [`solstrale_render_start`] that has not been freed
+#[no_mangle]
+pub unsafe extern "C" fn solstrale_render_is_finished(render: *const SolstraleRender) -> bool {
+    let state = lock(&(*render).state);
+    if let Some(e) = &state.error {
+        set_last_error(e.clone());
+    }
+    state.finished
+}
+
+/// Returns the width in pixels of the rendered image
+///
+/// # Safety
+/// `render` must be a handle returned by [`solstrale_render_start`] that has not been freed
+#[no_mangle]
+pub unsafe extern "C" fn solstrale_render_width(render: *const SolstraleRender) -> usize {
+    (*render).width
+}
+
+/// Returns the height in pixels of the rendered image
+///
+/// # Safety
+/// `render` must be a handle returned by [`solstrale_render_start`] that has not been freed
+#[no_mangle]
+pub unsafe extern "C" fn solstrale_render_height(render: *const SolstraleRender) -> usize {
+    (*render).height
+}
+
+/// Copies the latest rendered image as tightly packed 8-bit RGB rows into the given buffer,
+/// which must hold at least `width * height * 3` bytes.
+/// Returns false if no image is available yet or the buffer is too small.
+///
+/// # Safety
+/// `render` must be a handle returned by [`solstrale_render_start`] that has not been freed.
+/// `buffer` must be valid for writes of `buffer_len` bytes
+#[no_mangle]
+pub unsafe extern "C" fn solstrale_render_copy_pixels(
+    render: *const SolstraleRender,
+    buffer: *mut u8,
+    buffer_len: usize,
+) -> bool {
+    let state = lock(&(*render).state);
+    match &state.image {
+        Some(image) if !buffer.is_null() && buffer_len >= image.as_raw().len() => {
+            let pixels = image.as_raw();
+            ptr::copy_nonoverlapping(pixels.as_ptr(), buffer, pixels.len());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Requests the render to stop as soon as possible
+///
+/// # Safety
+/// `render` must be a handle returned by [`solstrale_render_start`] that has not been freed
+#[no_mangle]
+pub unsafe extern "C" fn solstrale_render_abort(render: *const SolstraleRender) {
+    // The render thread might have finished already, in which case there is nothing to abort
+    let _ = (*render).abort.send(true);
+}
+
+/// Aborts the render if still running and releases the handle
+///
+/// # Safety
+/// `render` must be a handle returned by [`solstrale_render_start`] that has not been freed,
+/// and must not be used after this call
+#[no_mangle]
+pub unsafe extern "C" fn solstrale_render_free(render: *mut SolstraleRender) {
+    if !render.is_null() {
+        let render = Box::from_raw(render);
+        let _ = render.abort.send(true);
+    }
+}
+
+/// Copies the last error message of the calling thread as a null terminated string
+/// into the given buffer, truncating it if needed.
+/// Returns the length of the full message, excluding the null terminator.
+///
+/// # Safety
+/// `buffer` must be valid for writes of `buffer_len` bytes
+#[no_mangle]
+pub unsafe extern "C" fn solstrale_last_error(buffer: *mut c_char, buffer_len: usize) -> usize {
+    LAST_ERROR.with(|e| {
+        let message = e.borrow();
+        if !buffer.is_null() && buffer_len > 0 {
+            let len = message.len().min(buffer_len - 1);
+            ptr::copy_nonoverlapping(message.as_ptr() as *const c_char, buffer, len);
+            *buffer.add(len) = 0;
+        }
+        message.len()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn render_through_c_api() {
+        let description = CString::new(
+            "size 8 4
+             samples 2
+             background 0.2 0.3 0.5
+             camera 30 0 0 1 5 0 0 0 0 1 0
+             material yellow lambertian 1 1 0
+             material lamp light 10 10 10
+             sphere 0 0 0 0.5 yellow
+             sphere 0 10 0 3 lamp",
+        )
+        .unwrap();
+
+        unsafe {
+            let render = solstrale_render_start(description.as_ptr());
+            assert!(!render.is_null());
+
+            while !solstrale_render_is_finished(render) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            // The final image is stored before the render is finished
+            let mut pixels = vec![0u8; 8 * 4 * 3];
+            assert!(solstrale_render_copy_pixels(
+                render,
+                pixels.as_mut_ptr(),
+                pixels.len()
+            ));
+
+            assert_eq!(solstrale_render_width(render), 8);
+            assert_eq!(solstrale_render_height(render), 4);
+            assert_eq!(solstrale_render_progress(render), 1.);
+            assert!(pixels.iter().any(|p| *p > 0));
+
+            solstrale_render_free(render);
+        }
+    }
+
+    #[test]
+    fn render_region_through_c_api() {
+        let description = CString::new(
+            "size 8 4
+             region 2 1 3 2
+             samples 1
+             material lamp light 10 10 10
+             sphere 0 10 0 3 lamp",
+        )
+        .unwrap();
+
+        unsafe {
+            let render = solstrale_render_start(description.as_ptr());
+            assert!(!render.is_null());
+            assert_eq!(solstrale_render_width(render), 3);
+            assert_eq!(solstrale_render_height(render), 2);
+
+            while !solstrale_render_is_finished(render) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            let mut pixels = vec![0u8; 3 * 2 * 3];
+            assert!(solstrale_render_copy_pixels(
+                render,
+                pixels.as_mut_ptr(),
+                pixels.len()
+            ));
+
+            solstrale_render_free(render);
+        }
+    }
+
+    #[test]
+    fn panicking_render_is_finished_with_error() {
+        let state = Arc::new(Mutex::new(RenderState::default()));
+        run_render(state.clone(), |_| panic!("broken scene"));
+
+        let state = lock(&state);
+        assert!(state.finished);
+        assert_eq!(
+            state.error.as_deref(),
+            Some("Render panicked: broken scene")
+        );
+    }
+
+    #[test]
+    fn invalid_description() {
+        let description = CString::new("cylinder").unwrap();
+
+        unsafe {
+            let render = solstrale_render_start(description.as_ptr());
+            assert!(render.is_null());
+
+            let mut buffer = vec![0 as c_char; 64];
+            let len = solstrale_last_error(buffer.as_mut_ptr(), buffer.len());
+            let message = CStr::from_ptr(buffer.as_ptr()).to_str().unwrap();
+            assert_eq!(message, "line 1: unknown keyword 'cylinder'");
+            assert_eq!(len, message.len());
+        }
+    }
+}
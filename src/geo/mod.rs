@@ -6,6 +6,7 @@ use crate::geo::vec3::Vec3;
 use crate::util::interval::{combine_intervals, Interval, EMPTY_INTERVAL};
 
 pub mod transformation;
+pub mod unit;
 pub mod vec3;
 
 const PAD_DELTA: f64 = 0.0001;
@@ -19,6 +20,21 @@ pub struct Uv {
     pub v: f32,
 }
 
+impl Add for Uv {
+    type Output = Uv;
+
+    /// returns a Uv that has all values added with corresponding value in given Uv
+    /// # Examples:
+    /// ```
+    /// # use solstrale::geo::Uv;
+    /// let res = Uv::new(1., 2.) + Uv::new(6., 5.);
+    /// assert_eq!(Uv::new(7., 7.), res)
+    /// ```
+    fn add(self, rhs: Self) -> Self::Output {
+        Uv::new(self.u + rhs.u, self.v + rhs.v)
+    }
+}
+
 impl Sub for Uv {
     type Output = Uv;
 
@@ -35,7 +51,7 @@ impl Sub for Uv {
 }
 
 /// Axis Aligned Bounding Box
-#[derive(Clone, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct Aabb {
     /// X axis interval
     pub x: Interval,
@@ -218,6 +234,36 @@ impl Aabb {
             self.z.min - self.z.max,
         ).length()
     }
+
+    /// return the surface area of the aabb
+    /// # Examples:
+    /// ```
+    /// # use solstrale::geo::Aabb;
+    /// # use solstrale::geo::vec3::Vec3;
+    /// let aabb = Aabb::new_from_2_points(Vec3::new(0., 0., 0.), Vec3::new(1., 2., 3.));
+    /// assert_eq!(aabb.surface_area(), 22.);
+    /// ```
+    pub fn surface_area(&self) -> f64 {
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        2. * (x * y + y * z + z * x)
+    }
+
+    /// return the squared distance from the given point to the aabb, zero if the point is inside
+    /// # Examples:
+    /// ```
+    /// # use solstrale::geo::Aabb;
+    /// # use solstrale::geo::vec3::Vec3;
+    /// let aabb = Aabb::new_from_2_points(Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.));
+    /// assert_eq!(aabb.distance_squared(Vec3::new(3., 0.5, -2.)), 8.);
+    /// assert_eq!(aabb.distance_squared(Vec3::new(0.5, 0.5, 0.5)), 0.);
+    /// ```
+    pub fn distance_squared(&self, p: Vec3) -> f64 {
+        Vec3::new(
+            self.x.clamp(p.x) - p.x,
+            self.y.clamp(p.y) - p.y,
+            self.z.clamp(p.z) - p.z,
+        ).length_squared()
+    }
 }
 
 impl Add<Vec3> for &Aabb {
@@ -270,17 +316,110 @@ pub struct Ray {
     /// Direction of the ray
     pub direction: Vec3,
     direction_inverted: Vec3,
+    /// Point in time of the ray, used for animated textures and materials
+    pub time: f64,
+    /// Rays offset by one pixel horizontally and vertically, only set for camera rays
+    pub differentials: Option<RayDifferentials>,
+}
+
+/// Origins and directions of the rays through the neighbouring pixels of a camera ray,
+/// used to estimate how large area of a texture a pixel covers
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct RayDifferentials {
+    /// Origin of the ray offset one pixel horizontally
+    pub dx_origin: Vec3,
+    /// Direction of the ray offset one pixel horizontally
+    pub dx_direction: Vec3,
+    /// Origin of the ray offset one pixel vertically
+    pub dy_origin: Vec3,
+    /// Direction of the ray offset one pixel vertically
+    pub dy_direction: Vec3,
+}
+
+/// How much the texture coordinates change between neighbouring pixels
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct UvDerivatives {
+    /// Change of texture coordinate per pixel horizontally
+    pub duv_dx: Uv,
+    /// Change of texture coordinate per pixel vertically
+    pub duv_dy: Uv,
+}
+
+impl UvDerivatives {
+    /// Estimates the uv derivatives at a hit point by intersecting the ray differentials
+    /// with the tangent plane of the hit. `dp_du` and `dp_dv` are the change of the hit point
+    /// per unit of texture coordinate. Returns none if the ray has no differentials
+    pub fn new(
+        ray: &Ray,
+        hit_point: Vec3,
+        normal: Vec3,
+        dp_du: Vec3,
+        dp_dv: Vec3,
+    ) -> Option<UvDerivatives> {
+        let differentials = ray.differentials.as_ref()?;
+
+        let d = normal.dot(hit_point);
+        let plane_hit = |origin: Vec3, direction: Vec3| {
+            let denom = normal.dot(direction);
+            if denom == 0. {
+                return None;
+            }
+            let t = (d - normal.dot(origin)) / denom;
+            Some(origin + direction * t)
+        };
+        let dp_dx = plane_hit(differentials.dx_origin, differentials.dx_direction)? - hit_point;
+        let dp_dy = plane_hit(differentials.dy_origin, differentials.dy_direction)? - hit_point;
+
+        // Solve the overdetermined system in the two axes where the plane has largest extent
+        let (a0, a1) = if normal.x.abs() > normal.y.abs() && normal.x.abs() > normal.z.abs() {
+            (1, 2)
+        } else if normal.y.abs() > normal.z.abs() {
+            (0, 2)
+        } else {
+            (0, 1)
+        };
+        let det = dp_du.axis(a0) * dp_dv.axis(a1) - dp_dv.axis(a0) * dp_du.axis(a1);
+        if det == 0. || !det.is_finite() {
+            return None;
+        }
+        let solve = |dp: Vec3| {
+            Uv::new(
+                ((dp_dv.axis(a1) * dp.axis(a0) - dp_dv.axis(a0) * dp.axis(a1)) / det) as f32,
+                ((dp_du.axis(a0) * dp.axis(a1) - dp_du.axis(a1) * dp.axis(a0)) / det) as f32,
+            )
+        };
+
+        Some(UvDerivatives {
+            duv_dx: solve(dp_dx),
+            duv_dy: solve(dp_dy),
+        })
+    }
 }
 
 impl Ray {
-    /// Create a new ray instance
+    /// Create a new ray instance at time zero
     pub fn new(origin: Vec3, dir: Vec3) -> Ray {
+        Ray::new_at_time(origin, dir, 0.)
+    }
+
+    /// Create a new ray instance at the given point in time
+    pub fn new_at_time(origin: Vec3, dir: Vec3, time: f64) -> Ray {
         let dir_inv = Vec3::new(1. / dir.x, 1. / dir.y, 1. / dir.z);
 
         Ray {
             origin,
             direction: dir,
             direction_inverted: dir_inv,
+            time,
+            differentials: None,
+        }
+    }
+
+    /// Returns the ray with the given differentials
+    pub fn with_differentials(self, differentials: RayDifferentials) -> Ray {
+        Ray {
+            differentials: Some(differentials),
+            ..self
         }
     }
 
@@ -306,7 +445,7 @@ impl Ray {
 #[cfg(test)]
 mod ray_tests {
     use crate::geo::vec3::Vec3;
-    use crate::geo::Ray;
+    use crate::geo::{Ray, RayDifferentials, Uv, UvDerivatives};
 
     #[test]
     fn test_at() {
@@ -375,4 +514,26 @@ mod ray_tests {
         assert_eq!(r1.shortest_distance(&r2), 229.4765553708466);
         assert_eq!(r2.shortest_distance(&r1), 229.4765553708466);
     }
+
+    #[test]
+    fn test_uv_derivatives() {
+        let origin = Vec3::new(0., 0., 0.);
+        let direction = Vec3::new(0., 0., -1.);
+        let ray = Ray::new(origin, direction);
+        let hit_point = Vec3::new(0., 0., -1.);
+        let normal = Vec3::new(0., 0., 1.);
+        let dp_du = Vec3::new(2., 0., 0.);
+        let dp_dv = Vec3::new(0., 4., 0.);
+        assert_eq!(UvDerivatives::new(&ray, hit_point, normal, dp_du, dp_dv), None);
+
+        let ray = ray.with_differentials(RayDifferentials {
+            dx_origin: origin,
+            dx_direction: Vec3::new(0.25, 0., -1.),
+            dy_origin: origin,
+            dy_direction: Vec3::new(0., 0.5, -1.),
+        });
+        let d = UvDerivatives::new(&ray, hit_point, normal, dp_du, dp_dv).unwrap();
+        assert_eq!(d.duv_dx, Uv::new(0.125, 0.));
+        assert_eq!(d.duv_dy, Uv::new(0., 0.125));
+    }
 }
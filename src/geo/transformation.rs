@@ -8,6 +8,18 @@ use crate::util::degrees_to_radians;
 pub trait Transformer {
     /// Applies transformation
     fn transform(&self, _vec: Vec3, _skip_translation: bool) -> Vec3;
+
+    /// Transforms a surface normal, so that it stays perpendicular to the transformed surface.
+    /// Uses the inverse transpose of the transformation, which differs from transforming
+    /// the normal as a vector when the scaling is non-uniform or there is shear.
+    /// The result is of unit length
+    fn transform_normal(&self, normal: Vec3) -> Vec3 {
+        let a = self.transform(Vec3::new(1., 0., 0.), true);
+        let b = self.transform(Vec3::new(0., 1., 0.), true);
+        let c = self.transform(Vec3::new(0., 0., 1.), true);
+        let det = a.dot(b.cross(c));
+        ((b.cross(c) * normal.x + c.cross(a) * normal.y + a.cross(b) * normal.z) / det).unit()
+    }
 }
 
 /// A transformer that does nothing
@@ -204,3 +216,166 @@ impl Transformer for Scale {
         vec * self.scale
     }
 }
+
+/// A transformation by a 4x4 matrix, that can combine translation, rotation around any axis,
+/// non-uniform scaling and shear. Points are transformed as columns multiplied with the matrix
+/// from the right. The bottom row is expected to be 0, 0, 0, 1
+/// # Examples:
+/// ```
+/// # use solstrale::geo::transformation::{Matrix4, RotationY, Transformer};
+/// # use solstrale::geo::vec3::Vec3;
+/// let matrix =
+///     Matrix4::scale(Vec3::new(2., 1., 1.)).then(&Matrix4::translation(Vec3::new(0., 3., 0.)));
+/// assert_eq!(Vec3::new(2., 4., 1.), matrix.transform(Vec3::new(1., 1., 1.), false));
+/// assert_eq!(Vec3::new(2., 1., 1.), matrix.transform(Vec3::new(1., 1., 1.), true));
+/// // Normals are not stretched along with the surface
+/// let normal = matrix.transform_normal(Vec3::new(1., 1., 0.).unit());
+/// assert!((normal - Vec3::new(1., 2., 0.).unit()).length() < 1e-9);
+///
+/// let rotation = Matrix4::rotation(Vec3::new(0., 1., 0.), 90.);
+/// let v = Vec3::new(2., 1., 0.);
+/// let expected = RotationY::new(90.).transform(v, false);
+/// assert!((rotation.transform(v, false) - expected).length() < 1e-9);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Matrix4 {
+    rows: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    /// Creates a matrix from its rows
+    pub fn new(rows: [[f64; 4]; 4]) -> Matrix4 {
+        Matrix4 { rows }
+    }
+
+    /// The matrix that does not transform anything
+    pub fn identity() -> Matrix4 {
+        Matrix4::scale(Vec3::new(1., 1., 1.))
+    }
+
+    /// Translation by the given offset
+    pub fn translation(offset: Vec3) -> Matrix4 {
+        Matrix4::new([
+            [1., 0., 0., offset.x],
+            [0., 1., 0., offset.y],
+            [0., 0., 1., offset.z],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    /// Scaling by a separate factor along each axis
+    pub fn scale(factors: Vec3) -> Matrix4 {
+        Matrix4::new([
+            [factors.x, 0., 0., 0.],
+            [0., factors.y, 0., 0.],
+            [0., 0., factors.z, 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    /// Rotation by angle degrees around the axis through the origin. Positive angles rotate
+    /// counterclockwise when looking from the tip of the axis towards the origin
+    pub fn rotation(axis: Vec3, angle: f64) -> Matrix4 {
+        let Vec3 { x, y, z } = axis.unit();
+        let radians = degrees_to_radians(angle);
+        let (sin, cos) = radians.sin_cos();
+        let t = 1. - cos;
+        Matrix4::new([
+            [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y, 0.],
+            [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x, 0.],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos, 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    /// The transformation of this matrix followed by the transformation of the other
+    pub fn then(&self, other: &Matrix4) -> Matrix4 {
+        let mut rows = [[0.; 4]; 4];
+        for (r, row) in rows.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| other.rows[r][k] * self.rows[k][c]).sum();
+            }
+        }
+        Matrix4::new(rows)
+    }
+}
+
+impl Transformer for Matrix4 {
+    fn transform(&self, vec: Vec3, skip_translation: bool) -> Vec3 {
+        let w = if skip_translation { 0. } else { 1. };
+        let row = |r: [f64; 4]| r[0] * vec.x + r[1] * vec.y + r[2] * vec.z + r[3] * w;
+        Vec3::new(row(self.rows[0]), row(self.rows[1]), row(self.rows[2]))
+    }
+}
+
+/// The affine transformation done by a [`Transformer`], with its inverse.
+/// Used for transforming rays into the space of a hittable and the hits back out of it
+#[derive(Clone, Debug)]
+pub(crate) struct Affine {
+    /// Columns of the linear part of the transformation
+    linear: [Vec3; 3],
+    /// Rows of the inverse of the linear part
+    inverse: [Vec3; 3],
+    translation: Vec3,
+}
+
+impl Affine {
+    /// Captures the transformation of the transformer, by transforming the origin and the axes
+    pub(crate) fn new(transformation: &dyn Transformer) -> Affine {
+        let linear = [
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            Vec3::new(0., 0., 1.),
+        ]
+        .map(|axis| transformation.transform(axis, true));
+        let [a, b, c] = linear;
+        let det = a.dot(b.cross(c));
+
+        Affine {
+            linear,
+            inverse: [b.cross(c) / det, c.cross(a) / det, a.cross(b) / det],
+            translation: transformation.transform(Vec3::new(0., 0., 0.), false),
+        }
+    }
+
+    /// The transformation as a matrix
+    pub(crate) fn matrix(&self) -> Matrix4 {
+        let [a, b, c] = &self.linear;
+        let t = &self.translation;
+        Matrix4::new([
+            [a.x, b.x, c.x, t.x],
+            [a.y, b.y, c.y, t.y],
+            [a.z, b.z, c.z, t.z],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    /// Transforms a point
+    pub(crate) fn point(&self, p: Vec3) -> Vec3 {
+        self.vector(p) + self.translation
+    }
+
+    /// Transforms a direction, without the translation
+    pub(crate) fn vector(&self, v: Vec3) -> Vec3 {
+        let [a, b, c] = &self.linear;
+        *a * v.x + *b * v.y + *c * v.z
+    }
+
+    /// Transforms a surface normal by the inverse transpose, so it stays perpendicular
+    /// to the transformed surface. The result is of unit length
+    pub(crate) fn normal(&self, n: Vec3) -> Vec3 {
+        let [x, y, z] = &self.inverse;
+        (*x * n.x + *y * n.y + *z * n.z).unit()
+    }
+
+    /// Transforms a point back, by the inverse transformation
+    pub(crate) fn inverse_point(&self, p: Vec3) -> Vec3 {
+        self.inverse_vector(p - self.translation)
+    }
+
+    /// Transforms a direction back, by the inverse transformation
+    pub(crate) fn inverse_vector(&self, v: Vec3) -> Vec3 {
+        let [x, y, z] = &self.inverse;
+        Vec3::new(x.dot(v), y.dot(v), z.dot(v))
+    }
+}
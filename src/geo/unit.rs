@@ -0,0 +1,67 @@
+//! Units of length that scenes and models can be declared in,
+//! so models made in different units can be loaded into the same scene
+use std::str::FromStr;
+
+use simple_error::SimpleError;
+
+/// A unit of length
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Unit {
+    /// Meters
+    #[default]
+    Meters,
+    /// Centimeters
+    Centimeters,
+    /// Millimeters
+    Millimeters,
+    /// Inches
+    Inches,
+    /// Feet
+    Feet,
+}
+
+impl Unit {
+    /// Length of the unit in millimeters, which can be represented exactly for all units
+    pub fn millimeters(&self) -> f64 {
+        match self {
+            Unit::Meters => 1000.,
+            Unit::Centimeters => 10.,
+            Unit::Millimeters => 1.,
+            Unit::Inches => 25.4,
+            Unit::Feet => 304.8,
+        }
+    }
+
+    /// Factor that lengths in this unit are multiplied with to get lengths in the target unit
+    /// # Examples:
+    /// ```
+    /// # use solstrale::geo::unit::Unit;
+    /// assert_eq!(100., Unit::Meters.scale_to(Unit::Centimeters));
+    /// assert_eq!(25.4, Unit::Inches.scale_to(Unit::Millimeters));
+    /// ```
+    pub fn scale_to(&self, target: Unit) -> f64 {
+        self.millimeters() / target.millimeters()
+    }
+}
+
+impl FromStr for Unit {
+    type Err = SimpleError;
+
+    /// Parses the unit from its abbreviation
+    /// # Examples:
+    /// ```
+    /// # use solstrale::geo::unit::Unit;
+    /// assert_eq!(Ok(Unit::Centimeters), "cm".parse());
+    /// assert!("furlong".parse::<Unit>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "m" => Ok(Unit::Meters),
+            "cm" => Ok(Unit::Centimeters),
+            "mm" => Ok(Unit::Millimeters),
+            "in" => Ok(Unit::Inches),
+            "ft" => Ok(Unit::Feet),
+            _ => Err(SimpleError::new(format!("unknown unit '{}'", s))),
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! Export of the triangles of a scene, and a bounding volume hierarchy over them, into flat
+//! buffers that can be uploaded to the GPU, for renderers running in compute shaders.
+//! The buffers follow the std430 layout rules, so they can be bound as storage buffers as is.
+//!
+//! Triangles, quads and triangle meshes are exported, also inside instances. Other hittables,
+//! like spheres and volumes, have no triangles and are skipped
+use crate::geo::transformation::{Matrix4, Transformer};
+use crate::geo::vec3::Vec3;
+use crate::hittable::Hittables::{QuadType, TriangleMeshType, TriangleType};
+use crate::hittable::{visit, Hittable, Hittables};
+use crate::material::Materials;
+
+/// Most triangles in a leaf of the tree
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+/// A node of the tree, 32 bytes. The left child of an inner node is the next node
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GpuBvhNode {
+    /// Smallest corner of the bounding box of the node
+    pub min: [f32; 3],
+    /// Index of the right child of inner nodes, and of the first triangle of leaves
+    pub right_or_first: u32,
+    /// Largest corner of the bounding box of the node
+    pub max: [f32; 3],
+    /// Number of triangles of leaves, which is zero for inner nodes
+    pub count: u32,
+}
+
+/// A triangle in world space, 48 bytes, with its edges from the first vertex ready for
+/// intersection tests
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GpuTriangle {
+    /// First vertex
+    pub v0: [f32; 3],
+    /// Index of the material of the triangle in [`GpuScene::materials`]
+    pub material: u32,
+    /// Second vertex minus the first
+    pub edge1: [f32; 3],
+    padding1: u32,
+    /// Third vertex minus the first
+    pub edge2: [f32; 3],
+    padding2: u32,
+}
+
+/// The exported buffers of a scene, see the [module](self) documentation
+#[derive(Clone, Debug)]
+pub struct GpuScene {
+    /// Nodes of the tree, starting with the root. Empty if there are no triangles
+    pub nodes: Vec<GpuBvhNode>,
+    /// Triangles, in the order that the leaves refer to them
+    pub triangles: Vec<GpuTriangle>,
+    /// Materials of the exported hittables, for translating into materials of the GPU renderer
+    pub materials: Vec<Materials>,
+    /// Number of hittables that were skipped as they have no triangles
+    pub skipped: usize,
+}
+
+impl GpuScene {
+    /// The nodes as bytes, in little endian
+    pub fn node_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.nodes.len() * 32);
+        for node in &self.nodes {
+            extend_f32s(&mut bytes, &node.min);
+            bytes.extend_from_slice(&node.right_or_first.to_le_bytes());
+            extend_f32s(&mut bytes, &node.max);
+            bytes.extend_from_slice(&node.count.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// The triangles as bytes, in little endian
+    pub fn triangle_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.triangles.len() * 48);
+        for triangle in &self.triangles {
+            extend_f32s(&mut bytes, &triangle.v0);
+            bytes.extend_from_slice(&triangle.material.to_le_bytes());
+            extend_f32s(&mut bytes, &triangle.edge1);
+            bytes.extend_from_slice(&0_u32.to_le_bytes());
+            extend_f32s(&mut bytes, &triangle.edge2);
+            bytes.extend_from_slice(&0_u32.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+fn extend_f32s(bytes: &mut Vec<u8>, values: &[f32]) {
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Exports the triangles of the world into buffers for the GPU
+pub fn export_scene(world: &Hittables) -> GpuScene {
+    let mut triangles: Vec<[Vec3; 3]> = Vec::new();
+    let mut triangle_materials: Vec<u32> = Vec::new();
+    let mut materials = Vec::new();
+    let mut skipped = 0;
+
+    visit(
+        world,
+        &mut |hittable: &Hittables, _, transformation: &Matrix4| {
+            let local_triangles = match hittable {
+                TriangleType(t) => vec![t.vertices_and_tex_coords().0],
+                TriangleMeshType(m) => m
+                    .triangles()
+                    .iter()
+                    .map(|t| t.vertices_and_tex_coords().0)
+                    .collect(),
+                QuadType(q) => {
+                    let [p0, p1, p2, p3] = q.corners_and_tex_coords().0;
+                    vec![[p0, p1, p2], [p0, p2, p3]]
+                }
+                _ => {
+                    if hittable.children().is_empty() {
+                        skipped += 1;
+                    }
+                    return;
+                }
+            };
+            let material = materials.len() as u32;
+            materials.extend(hittable.materials().into_iter().take(1).cloned());
+            for vertices in local_triangles {
+                triangles.push(vertices.map(|v| transformation.transform(v, false)));
+                triangle_materials.push(material);
+            }
+        },
+    );
+
+    let mut order: Vec<usize> = (0..triangles.len()).collect();
+    let mut nodes = Vec::new();
+    if !order.is_empty() {
+        build_node(&triangles, &mut order, 0, &mut nodes);
+    }
+
+    let to_f32 = |v: Vec3| [v.x as f32, v.y as f32, v.z as f32];
+    GpuScene {
+        nodes,
+        triangles: order
+            .iter()
+            .map(|&i| {
+                let [v0, v1, v2] = triangles[i];
+                GpuTriangle {
+                    v0: to_f32(v0),
+                    material: triangle_materials[i],
+                    edge1: to_f32(v1 - v0),
+                    padding1: 0,
+                    edge2: to_f32(v2 - v0),
+                    padding2: 0,
+                }
+            })
+            .collect(),
+        materials,
+        skipped,
+    }
+}
+
+/// Adds the node of the triangles, which start at the offset in the final order,
+/// and then its children. The triangles are split at the median of their centers
+/// along the longest axis of the box around the centers
+fn build_node(
+    triangles: &[[Vec3; 3]],
+    order: &mut [usize],
+    offset: usize,
+    nodes: &mut Vec<GpuBvhNode>,
+) {
+    let (min, max) = bounds(order.iter().flat_map(|&i| triangles[i]));
+    let node_index = nodes.len();
+    nodes.push(GpuBvhNode {
+        min: [min.x as f32, min.y as f32, min.z as f32],
+        right_or_first: offset as u32,
+        max: [max.x as f32, max.y as f32, max.z as f32],
+        count: order.len() as u32,
+    });
+    if order.len() <= MAX_LEAF_TRIANGLES {
+        return;
+    }
+
+    let center = |i: usize| (triangles[i][0] + triangles[i][1] + triangles[i][2]) / 3.;
+    let (center_min, center_max) = bounds(order.iter().map(|&i| center(i)));
+    let extent = center_max - center_min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+    let middle = order.len() / 2;
+    order.select_nth_unstable_by(middle, |&a, &b| {
+        center(a).axis(axis).total_cmp(&center(b).axis(axis))
+    });
+
+    let (left, right) = order.split_at_mut(middle);
+    build_node(triangles, left, offset, nodes);
+    let right_index = nodes.len();
+    build_node(triangles, right, offset + middle, nodes);
+    nodes[node_index].right_or_first = right_index as u32;
+    nodes[node_index].count = 0;
+}
+
+fn bounds(points: impl Iterator<Item = Vec3>) -> (Vec3, Vec3) {
+    let infinity = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    points.fold((infinity, infinity.neg()), |(min, max), p| {
+        (
+            Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+            Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::geo::transformation::{NopTransformer, Translation};
+    use crate::geo::Ray;
+    use crate::hittable::{Bvh, Instance, Quad, Sphere, TriangleMesh};
+    use crate::material::texture::SolidColor;
+    use crate::material::Lambertian;
+    use crate::util::interval::Interval;
+
+    use super::*;
+
+    /// Distance to the closest exported triangle, by walking the tree like a shader would
+    fn cast(scene: &GpuScene, origin: Vec3, direction: Vec3) -> Option<f64> {
+        let v = |a: [f32; 3]| Vec3::new(a[0] as f64, a[1] as f64, a[2] as f64);
+        let mut closest: Option<f64> = None;
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            let node: &GpuBvhNode = &scene.nodes[i];
+            let (min, max) = (v(node.min), v(node.max));
+            let (mut near, mut far) = (0., closest.unwrap_or(f64::INFINITY));
+            for axis in 0..3 {
+                let inverse = 1. / direction.axis(axis);
+                let t0 = (min.axis(axis) - origin.axis(axis)) * inverse;
+                let t1 = (max.axis(axis) - origin.axis(axis)) * inverse;
+                near = t0.min(t1).max(near);
+                far = t0.max(t1).min(far);
+            }
+            if near > far {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.right_or_first as usize);
+                stack.push(i + 1);
+                continue;
+            }
+            let first = node.right_or_first as usize;
+            for triangle in &scene.triangles[first..first + node.count as usize] {
+                let (edge1, edge2) = (v(triangle.edge1), v(triangle.edge2));
+                let p = direction.cross(edge2);
+                let determinant = edge1.dot(p);
+                if determinant.abs() < 1e-12 {
+                    continue;
+                }
+                let s = origin - v(triangle.v0);
+                let a = s.dot(p) / determinant;
+                let q = s.cross(edge1);
+                let b = direction.dot(q) / determinant;
+                let t = edge2.dot(q) / determinant;
+                if a >= 0. && b >= 0. && a + b <= 1. && t > 0. && closest.is_none_or(|c| t < c) {
+                    closest = Some(t);
+                }
+            }
+        }
+        closest
+    }
+
+    #[test]
+    fn test_export_scene() {
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let mut positions = Vec::new();
+        let mut faces = Vec::new();
+        for x in 0..10 {
+            for y in 0..10 {
+                let i = positions.len() as u32;
+                let corner = Vec3::new(x as f64, y as f64, (x * y) as f64 * 0.1);
+                positions.push(corner);
+                positions.push(corner + Vec3::new(1., 0., 0.));
+                positions.push(corner + Vec3::new(0., 1., 0.));
+                faces.push([i, i + 1, i + 2]);
+            }
+        }
+        let mesh = Arc::new(TriangleMesh::new(
+            positions,
+            faces,
+            mat.clone(),
+            &NopTransformer(),
+        ));
+        let quad = Quad::new(
+            Vec3::new(-5., -5., -3.),
+            Vec3::new(20., 0., 0.),
+            Vec3::new(0., 20., 0.),
+            mat.clone(),
+            &NopTransformer(),
+        );
+        let world = Bvh::new(vec![
+            quad,
+            Instance::new(mesh.clone(), &Translation::new(Vec3::new(0., 0., 5.))),
+            Instance::new(mesh, &Translation::new(Vec3::new(0., 0., 10.))),
+            Sphere::new(Vec3::new(50., 0., 0.), 1., mat),
+        ]);
+
+        let scene = export_scene(&world);
+        assert_eq!(scene.triangles.len(), 202);
+        assert_eq!(scene.materials.len(), 3);
+        assert_eq!(scene.skipped, 1);
+        assert_eq!(scene.node_bytes().len(), scene.nodes.len() * 32);
+        assert_eq!(scene.triangle_bytes().len(), 202 * 48);
+
+        // Every triangle is in exactly one leaf
+        let mut leaf_triangles: Vec<usize> = scene
+            .nodes
+            .iter()
+            .filter(|n| n.count > 0)
+            .flat_map(|n| n.right_or_first as usize..(n.right_or_first + n.count) as usize)
+            .collect();
+        leaf_triangles.sort();
+        assert_eq!(leaf_triangles, (0..202).collect::<Vec<_>>());
+
+        // Rays against the exported buffers hit the same as against the world
+        for x in 0..20 {
+            for y in 0..20 {
+                let origin = Vec3::new(x as f64 * 0.55 - 0.2, y as f64 * 0.52 - 0.1, 30.);
+                let direction = Vec3::new(0.01, 0.02, -1.).unit();
+                let expected = world
+                    .hit(
+                        &Ray::new(origin, direction),
+                        &Interval::new(0.001, f64::INFINITY),
+                    )
+                    .map(|h| h.ray_length);
+                let actual = cast(&scene, origin, direction);
+                match (expected, actual) {
+                    (Some(e), Some(a)) => assert!((e - a).abs() < 1e-4, "{} != {}", e, a),
+                    (e, a) => assert_eq!(e, a, "at {}", origin),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_export_empty_scene() {
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let scene = export_scene(&Sphere::new(Vec3::new(0., 0., 0.), 1., mat));
+        assert!(scene.nodes.is_empty() && scene.triangles.is_empty());
+        assert_eq!(scene.skipped, 1);
+    }
+}
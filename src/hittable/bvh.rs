@@ -1,54 +1,135 @@
 use std::fmt;
-
-use derive_more::Display;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::geo::Aabb;
 use crate::geo::Ray;
-use crate::hittable::{Hittable, Hittables};
-use crate::material::RayHit;
-use crate::util::interval::Interval;
+use crate::geo::vec3::Vec3;
+use crate::hittable::Hittables::{BvhType, DiscType, QuadType, TriangleMeshType, TriangleType};
+use crate::hittable::{Hittable, Hittables, Sampleable};
+use crate::loader::cache::{aabb_values, CacheKey, CacheReader, CacheWriter, SceneCache};
+use crate::material::{Material, RayHit};
+use crate::random::random_normal_float;
+use crate::util::interval::{Interval, RAY_INTERVAL};
 
-/// Bounding Volume Hierarchy
-#[derive(Display, Debug)]
-#[display("{{\"left\": {}, \"right\": {}}}", left, right)]
+/// Bounding Volume Hierarchy.
+/// The tree is stored flattened, with the nodes in an array in depth first order,
+/// and is traversed without recursion, which keeps the nodes close together in memory.
+/// The tree is shared by the clones of the bvh, which makes it cheap to use as a light
+#[derive(Clone, Debug)]
 pub struct Bvh {
-    left: Box<BvhItem>,
-    right: Box<BvhItem>,
+    data: Arc<BvhData>,
+}
+
+/// The flattened tree of a bvh, and how its lights are sampled
+#[derive(Clone, Debug)]
+struct BvhData {
+    nodes: Vec<BvhNode>,
+    /// The hittables of the leaves, in the order of the leaves in the tree
+    hittables: Vec<Hittables>,
+    /// Position of each hittable in the list the bvh was built from
+    indices: Vec<usize>,
     b_box: Aabb,
+    lights: BvhLights,
+    quality: BvhQuality,
 }
 
+/// A node of a flattened bvh
+#[derive(Debug, Clone)]
+enum BvhNode {
+    /// The left child is the next node and the right child is at the given position
+    Inner { b_box: Aabb, right: usize },
+    /// Position of the hittable of the leaf
+    Leaf(usize),
+}
+
+/// How much work goes into building a bvh, trading the time the build takes
+/// for how fast rays are traced through the tree
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum BvhQuality {
+    /// Splits the hittables at the middle of the axis they are most spread along.
+    /// Builds the fastest, but makes poor trees for scenes with unevenly spread hittables
+    Fast,
+    /// Splits the hittables where the surface area heuristic estimates that rays are traced
+    /// the fastest, trying split positions between a number of bins along each axis
+    #[default]
+    Medium,
+    /// Like [`BvhQuality::Medium`], but trying split positions between all the hittables
+    High,
+}
+
+/// How the lights contained in a bvh are sampled
+#[derive(Debug, Clone)]
+enum BvhLights {
+    /// Each light is sampled individually
+    Separate,
+    /// The bvh is sampled as one light, choosing among the contained lights by their surface area
+    Grouped {
+        lights: Vec<Hittables>,
+        cumulative_areas: Vec<f64>,
+    },
+    /// The bvh should be sampled as one light, but contains lights without a surface area
+    Unsampleable,
+}
+
+/// A bvh as a tree of boxed items, which is how it is built and rebuilt before it is flattened
 #[derive(Debug, Clone)]
 enum BvhItem {
-    Node(Bvh),
-    Leaf(Box<Hittables>),
+    Node {
+        left: Box<BvhItem>,
+        right: Box<BvhItem>,
+        b_box: Aabb,
+    },
+    /// A hittable, with its position in the list the bvh was built from
+    Leaf(Box<Hittables>, usize),
     None,
 }
 
-impl Display for BvhItem {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl BvhItem {
+    fn bounding_box(&self) -> Option<&Aabb> {
         match self {
-            BvhItem::Node(b) => write!(f, "{}", b),
-            BvhItem::Leaf(t) => write!(f, "{}", t.bounding_box().center()),
-            BvhItem::None => write!(f, "<empty>"),
+            BvhItem::Node { b_box, .. } => Some(b_box),
+            BvhItem::Leaf(l, _) => Some(l.bounding_box()),
+            BvhItem::None => None,
         }
     }
 }
 
-impl BvhItem {
-    fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
-        match self {
-            BvhItem::Node(i) => i.hit(r, ray_length),
-            BvhItem::Leaf(i) => i.hit(r, ray_length),
-            BvhItem::None => None,
+/// Stack of the nodes left to visit when traversing a bvh. Deep trees, which the surface
+/// area heuristic can build from unevenly spread hittables, spill over into a vector
+struct NodeStack {
+    nodes: [usize; 64],
+    len: usize,
+    spilled: Vec<usize>,
+}
+
+impl NodeStack {
+    fn new() -> NodeStack {
+        NodeStack {
+            nodes: [0; 64],
+            len: 0,
+            spilled: Vec::new(),
         }
     }
 
-    fn get_lights(&self) -> Vec<Hittables> {
-        match self {
-            BvhItem::Node(b) => b.get_lights(),
-            BvhItem::Leaf(l) => l.get_lights(),
-            BvhItem::None => vec![],
+    fn push(&mut self, node: usize) {
+        if self.len < self.nodes.len() {
+            self.nodes[self.len] = node;
+            self.len += 1;
+        } else {
+            self.spilled.push(node);
+        }
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        if let Some(node) = self.spilled.pop() {
+            return Some(node);
+        }
+        if self.len == 0 {
+            return None;
         }
+        self.len -= 1;
+        Some(self.nodes[self.len])
     }
 }
 
@@ -59,61 +140,645 @@ impl Bvh {
     /// where each node has a bounding box.
     /// This is to optimize the ray intersection search when having many hittable objects.
     pub fn new(list: Vec<Hittables>) -> Hittables {
+        Bvh::new_with_quality(list, BvhQuality::default())
+    }
+
+    /// Creates a new bvh like [`Bvh::new`], built with the given quality
+    pub fn new_with_quality(list: Vec<Hittables>, quality: BvhQuality) -> Hittables {
+        Hittables::from(create_bvh(list, BvhLights::Separate, quality))
+    }
+
+    /// Creates a new bvh like [`Bvh::new_with_quality`], taking the tree from the cache if
+    /// a bvh has been built before for hittables with the same bounding boxes
+    pub fn new_with_cache(
+        list: Vec<Hittables>,
+        quality: BvhQuality,
+        cache: &SceneCache,
+    ) -> Hittables {
         if list.is_empty() {
-            Hittables::from(Bvh {
-                left: Box::new(BvhItem::None),
-                right: Box::new(BvhItem::None),
-                b_box: Default::default(),
-            })
+            return Bvh::new_with_quality(list, quality);
+        }
+        let key = CacheKey::new("bvh")
+            .u32s([quality as u32])
+            .f64s(list.iter().flat_map(|h| aabb_values(h.bounding_box())))
+            .finish();
+
+        let mut used = vec![false; list.len()];
+        let cached = cache.get(key).and_then(|bytes| {
+            let mut reader = CacheReader::new(&bytes);
+            let root = read_item(&mut reader, &list, &mut used)?;
+            let complete = reader.is_done() && used.iter().all(|u| *u);
+            complete.then(|| Bvh::flatten(root, BvhLights::Separate, quality))
+        });
+        if let Some(bvh) = cached {
+            return Hittables::from(bvh);
+        }
+
+        let bvh = create_bvh(list, BvhLights::Separate, quality);
+        let mut writer = CacheWriter::default();
+        bvh.write_node(0, &mut writer);
+        cache.put(key, &writer.0);
+        Hittables::from(bvh)
+    }
+
+    /// Creates a new bvh like [`Bvh::new`], but where all lights in it are sampled as one light.
+    /// The lights are chosen by their surface area, which makes sampling an emissive mesh
+    /// of many triangles as fast as sampling a single light.
+    /// Only triangles, triangle meshes, quads and discs can be lights in the bvh,
+    /// the renderer reports an error otherwise.
+    pub fn new_light(list: Vec<Hittables>) -> Hittables {
+        let lights: Vec<Hittables> = list.iter().flat_map(|h| h.get_lights()).collect();
+        let areas: Option<Vec<f64>> = lights.iter().map(surface_area).collect();
+
+        let bvh_lights = match areas {
+            Some(areas) => {
+                let cumulative_areas = areas
+                    .iter()
+                    .scan(0., |sum, area| {
+                        *sum += area;
+                        Some(*sum)
+                    })
+                    .collect();
+                BvhLights::Grouped {
+                    lights,
+                    cumulative_areas,
+                }
+            }
+            None => BvhLights::Unsampleable,
+        };
+
+        Hittables::from(create_bvh(list, bvh_lights, BvhQuality::default()))
+    }
+
+    /// Creates a bvh for a list of hittables that has changed slightly since the previous bvh
+    /// was built from it, like when a few objects have been moved in an interactive application.
+    /// The hittables are matched with those of the previous bvh by their position in the list,
+    /// and hittables added to the end of the list are put in a subtree of their own.
+    ///
+    /// Subtrees whose bounding boxes have grown by moved hittables are rebuilt as long as
+    /// the rebuilds are estimated to finish within the time budget. Otherwise only the bounding
+    /// boxes of the subtrees are updated, which gives a slower tree instead of a long stall.
+    /// Subtrees are rebuilt with the quality of the previous bvh.
+    /// A previous bvh made by [`Bvh::new_light`], or any other hittable, is rebuilt completely.
+    pub fn rebuild(previous: Hittables, list: Vec<Hittables>, budget: Duration) -> Hittables {
+        let start = Instant::now();
+        let previous = match previous {
+            BvhType(bvh) if matches!(bvh.data.lights, BvhLights::Separate) => bvh,
+            BvhType(_) => return Bvh::new_light(list),
+            _ => return Bvh::new(list),
+        };
+
+        let quality = previous.data.quality;
+        let root = previous.into_item();
+        let mut changes = Vec::new();
+        find_changes(&root, &list, &mut changes);
+        let mut budget = RebuildBudget {
+            deadline: start + budget,
+            seconds_per_leaf: start.elapsed().as_secs_f64() / changes[0].leaves.max(1) as f64,
+            quality,
+        };
+
+        let mut slots: Vec<Option<Hittables>> = list.into_iter().map(Some).collect();
+        let root = update_item(root, &mut slots, &changes, &mut 0, &mut budget);
+
+        let added: Vec<(usize, Hittables)> = slots
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, slot)| slot.map(|h| (index, h)))
+            .collect();
+        let root = if added.is_empty() {
+            root
         } else {
-            Hittables::from(new_bvh(list))
+            join_items(root, new_bvh(added, quality))
+        };
+
+        Hittables::from(Bvh::flatten(root, BvhLights::Separate, quality))
+    }
+
+    /// All hittables in the leaves of the tree
+    pub(crate) fn leaves(&self) -> Vec<&Hittables> {
+        self.data.hittables.iter().collect()
+    }
+
+    /// Flattens the tree of items into the nodes of a bvh.
+    /// Empty items are left out, along with the nodes that only have one child left
+    fn flatten(root: BvhItem, lights: BvhLights, quality: BvhQuality) -> Bvh {
+        let mut data = BvhData {
+            nodes: Vec::new(),
+            hittables: Vec::new(),
+            indices: Vec::new(),
+            b_box: root.bounding_box().cloned().unwrap_or_default(),
+            lights,
+            quality,
+        };
+        data.push_item(root);
+        Bvh {
+            data: Arc::new(data),
+        }
+    }
+
+    /// Turns the bvh back into a tree of items, for rebuilding it.
+    /// The tree is copied if it is shared with other clones of the bvh
+    fn into_item(self) -> BvhItem {
+        let data = Arc::try_unwrap(self.data).unwrap_or_else(|data| (*data).clone());
+        let mut hittables: Vec<Option<Hittables>> = data.hittables.into_iter().map(Some).collect();
+        if data.nodes.is_empty() {
+            return BvhItem::None;
+        }
+        unflatten(&data.nodes, 0, &mut hittables, &data.indices)
+    }
+
+    /// Writes the shape of the subtree of the node depth first,
+    /// with the positions of the hittables in the leaves
+    fn write_node(&self, node: usize, writer: &mut CacheWriter) {
+        match self.data.nodes[node] {
+            BvhNode::Leaf(hittable) => {
+                writer.u8(1);
+                writer.u32(self.data.indices[hittable] as u32);
+            }
+            BvhNode::Inner { right, .. } => {
+                writer.u8(2);
+                self.write_node(node + 1, writer);
+                self.write_node(right, writer);
+            }
+        }
+    }
+
+    fn node_distance_squared(&self, node: usize, p: Vec3) -> f64 {
+        match &self.data.nodes[node] {
+            BvhNode::Inner { b_box, .. } => b_box.distance_squared(p),
+            BvhNode::Leaf(hittable) => {
+                self.data.hittables[*hittable].bounding_box().distance_squared(p)
+            }
+        }
+    }
+
+    /// Visits the nodes nearest first, skipping nodes that are farther away
+    /// than the closest point found so far
+    fn closest_point_within(&self, p: Vec3) -> Option<(Vec3, f64)> {
+        let mut closest: Option<(Vec3, f64)> = None;
+        let mut stack = NodeStack::new();
+        if !self.data.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(node) = stack.pop() {
+            if closest.is_some_and(|(_, d)| self.node_distance_squared(node, p) >= d) {
+                continue;
+            }
+            match self.data.nodes[node] {
+                BvhNode::Inner { right, .. } => {
+                    let left = node + 1;
+                    let left_first =
+                        self.node_distance_squared(left, p) <= self.node_distance_squared(right, p);
+                    let (near, far) = if left_first { (left, right) } else { (right, left) };
+                    stack.push(far);
+                    stack.push(near);
+                }
+                BvhNode::Leaf(hittable) => {
+                    if let Some(point) = self.data.hittables[hittable].closest_point(p) {
+                        let distance_squared = (point - p).length_squared();
+                        if closest.is_none_or(|(_, d)| distance_squared < d) {
+                            closest = Some((point, distance_squared));
+                        }
+                    }
+                }
+            }
+        }
+        closest
+    }
+
+    fn fmt_node(&self, node: usize, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.data.nodes[node] {
+            BvhNode::Inner { right, .. } => {
+                write!(f, "{{\"left\": ")?;
+                self.fmt_node(node + 1, f)?;
+                write!(f, ", \"right\": ")?;
+                self.fmt_node(right, f)?;
+                write!(f, "}}")
+            }
+            BvhNode::Leaf(hittable) => {
+                write!(f, "{}", self.data.hittables[hittable].bounding_box().center())
+            }
         }
     }
 }
 
-impl Clone for Bvh {
-    fn clone(&self) -> Self {
-        Bvh {
-            left: self.left.clone(),
-            right: self.right.clone(),
-            b_box: self.b_box.clone(),
-        }
-    }
-}
-
-fn new_bvh(mut list: Vec<Hittables>) -> Bvh {
-    let (left, right, b_box) = if list.len() == 1 {
-        (
-            BvhItem::Leaf(Box::new(list[0].clone())),
-            BvhItem::None,
-            list[0].bounding_box().clone(),
-        )
-    } else if list.len() == 2 {
-        (
-            BvhItem::Leaf(Box::new(list[0].clone())),
-            BvhItem::Leaf(Box::new(list[1].clone())),
-            list[0].bounding_box().combine(list[1].bounding_box()),
-        )
-    } else {
-        let mid = sort_hittables_slice_by_most_spread_axis(list.as_mut_slice());
+impl BvhData {
+    fn push_item(&mut self, item: BvhItem) {
+        match item {
+            BvhItem::None => (),
+            BvhItem::Leaf(hittable, index) => {
+                self.nodes.push(BvhNode::Leaf(self.hittables.len()));
+                self.hittables.push(*hittable);
+                self.indices.push(index);
+            }
+            BvhItem::Node { left, right, b_box } => match (*left, *right) {
+                (BvhItem::None, item) | (item, BvhItem::None) => self.push_item(item),
+                (left, right) => {
+                    let position = self.nodes.len();
+                    self.nodes.push(BvhNode::Inner { b_box, right: 0 });
+                    self.push_item(left);
+                    let right_position = self.nodes.len();
+                    if let BvhNode::Inner { right, .. } = &mut self.nodes[position] {
+                        *right = right_position;
+                    }
+                    self.push_item(right);
+                }
+            },
+        }
+    }
+}
 
-        let (l, r) = rayon::join(
-            || new_bvh(list[..mid].to_vec()),
-            || new_bvh(list[mid..].to_vec()),
-        );
+impl fmt::Display for Bvh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.data.nodes.is_empty() {
+            return write!(f, "<empty>");
+        }
+        self.fmt_node(0, f)
+    }
+}
+
+/// Rebuilds the tree of items of the subtree of the node in a flattened bvh
+fn unflatten(
+    nodes: &[BvhNode],
+    node: usize,
+    hittables: &mut [Option<Hittables>],
+    indices: &[usize],
+) -> BvhItem {
+    match &nodes[node] {
+        BvhNode::Inner { b_box, right } => BvhItem::Node {
+            left: Box::new(unflatten(nodes, node + 1, hittables, indices)),
+            right: Box::new(unflatten(nodes, *right, hittables, indices)),
+            b_box: b_box.clone(),
+        },
+        BvhNode::Leaf(hittable) => match hittables[*hittable].take() {
+            Some(h) => BvhItem::Leaf(Box::new(h), indices[*hittable]),
+            None => BvhItem::None,
+        },
+    }
+}
+
+/// How much the surface area of a subtree can grow by moved hittables before it is rebuilt
+const REBUILD_GROWTH: f64 = 1.2;
+
+/// How an item of a previous bvh is affected by the changes to the list of hittables
+struct ItemChange {
+    /// Number of items in the subtree of the item, including itself
+    items: usize,
+    leaves: usize,
+    /// Whether any hittable in the subtree has been moved or removed
+    affected: bool,
+    /// Whether the subtree should be rebuilt, as its bounding box has grown
+    grown: bool,
+}
+
+/// Lists how the item and all items below it are affected, depth first,
+/// and returns the updated bounding box of the item
+fn find_changes(item: &BvhItem, list: &[Hittables], changes: &mut Vec<ItemChange>) -> Option<Aabb> {
+    let position = changes.len();
+    changes.push(ItemChange {
+        items: 1,
+        leaves: 0,
+        affected: false,
+        grown: false,
+    });
 
-        let b_box = l.b_box.combine(&r.b_box);
-        (BvhItem::Node(l), BvhItem::Node(r), b_box)
+    let (b_box, leaves, affected, grown) = match item {
+        BvhItem::None => (None, 0, false, false),
+        BvhItem::Leaf(hittable, index) => {
+            let b_box = list.get(*index).map(|h| h.bounding_box().clone());
+            let affected = b_box.as_ref() != Some(hittable.bounding_box());
+            (b_box.clone(), usize::from(b_box.is_some()), affected, false)
+        }
+        BvhItem::Node {
+            left,
+            right,
+            b_box: previous_box,
+        } => {
+            let left_box = find_changes(left, list, changes);
+            let right_position = changes.len();
+            let right_box = find_changes(right, list, changes);
+            let (left, right) = (&changes[position + 1], &changes[right_position]);
+
+            let affected = left.affected || right.affected;
+            let b_box = match (left_box, right_box) {
+                (Some(l), Some(r)) => Some(l.combine(&r)),
+                (b_box, None) | (None, b_box) => b_box,
+            };
+            let grown = affected
+                && b_box.as_ref().is_some_and(|b| {
+                    b.surface_area() > previous_box.surface_area() * REBUILD_GROWTH
+                });
+            (b_box, left.leaves + right.leaves, affected, grown)
+        }
+    };
+
+    changes[position] = ItemChange {
+        items: changes.len() - position,
+        leaves,
+        affected,
+        grown,
     };
+    b_box
+}
+
+/// Estimates whether rebuilds finish before the deadline,
+/// from how long the work done so far has taken per hittable
+struct RebuildBudget {
+    deadline: Instant,
+    seconds_per_leaf: f64,
+    quality: BvhQuality,
+}
+
+impl RebuildBudget {
+    fn estimate(&self, leaves: usize) -> f64 {
+        let leaves = leaves as f64;
+        leaves * leaves.log2().max(1.) * self.seconds_per_leaf
+    }
+
+    fn allows(&self, leaves: usize) -> bool {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        self.estimate(leaves) < remaining.as_secs_f64()
+    }
+
+    fn rebuild(&mut self, list: Vec<(usize, Hittables)>) -> BvhItem {
+        let start = Instant::now();
+        let leaves = list.len();
+        let bvh = new_bvh(list, self.quality);
+        self.seconds_per_leaf *= start.elapsed().as_secs_f64() / self.estimate(leaves).max(1e-9);
+        bvh
+    }
+}
+
+/// Replaces the hittables of the item with those in the new list, rebuilding grown subtrees
+/// that fit in the budget. The position is that of the item in the changes
+fn update_item(
+    item: BvhItem,
+    slots: &mut [Option<Hittables>],
+    changes: &[ItemChange],
+    position: &mut usize,
+    budget: &mut RebuildBudget,
+) -> BvhItem {
+    let change = &changes[*position];
+    match item {
+        BvhItem::None => {
+            *position += 1;
+            BvhItem::None
+        }
+        BvhItem::Leaf(_, index) => {
+            *position += 1;
+            match slots.get_mut(index).and_then(Option::take) {
+                Some(hittable) => BvhItem::Leaf(Box::new(hittable), index),
+                None => BvhItem::None,
+            }
+        }
+        BvhItem::Node { .. } if change.grown && budget.allows(change.leaves) => {
+            *position += change.items;
+            let mut list = Vec::new();
+            take_leaves(&item, slots, &mut list);
+            budget.rebuild(list)
+        }
+        BvhItem::Node { left, right, .. } => {
+            *position += 1;
+            let left = update_item(*left, slots, changes, position, budget);
+            let right = update_item(*right, slots, changes, position, budget);
+            join_items(left, right)
+        }
+    }
+}
+
+/// Takes the hittables for the leaves of the item from the new list
+fn take_leaves(
+    item: &BvhItem,
+    slots: &mut [Option<Hittables>],
+    list: &mut Vec<(usize, Hittables)>,
+) {
+    match item {
+        BvhItem::Node { left, right, .. } => {
+            take_leaves(left, slots, list);
+            take_leaves(right, slots, list);
+        }
+        BvhItem::Leaf(_, index) => {
+            if let Some(hittable) = slots.get_mut(*index).and_then(Option::take) {
+                list.push((*index, hittable));
+            }
+        }
+        BvhItem::None => (),
+    }
+}
 
-    Bvh {
+fn join_items(left: BvhItem, right: BvhItem) -> BvhItem {
+    let b_box = match (left.bounding_box(), right.bounding_box()) {
+        (Some(l), Some(r)) => l.combine(r),
+        (Some(b_box), None) | (None, Some(b_box)) => b_box.clone(),
+        (None, None) => return BvhItem::None,
+    };
+    BvhItem::Node {
         left: Box::new(left),
         right: Box::new(right),
         b_box,
     }
 }
 
-fn sort_hittables_slice_by_most_spread_axis(list: &mut [Hittables]) -> usize {
+/// Reads a tree written by [`Bvh::write_node`], putting the hittables of the list in its leaves.
+/// Hittables that are used are marked, and none is used twice
+fn read_item(reader: &mut CacheReader, list: &[Hittables], used: &mut [bool]) -> Option<BvhItem> {
+    match reader.u8()? {
+        0 => Some(BvhItem::None),
+        1 => {
+            let index = reader.u32()? as usize;
+            if std::mem::replace(used.get_mut(index)?, true) {
+                return None;
+            }
+            Some(BvhItem::Leaf(Box::new(list[index].clone()), index))
+        }
+        2 => {
+            let left = read_item(reader, list, used)?;
+            let right = read_item(reader, list, used)?;
+            Some(join_items(left, right))
+        }
+        _ => None,
+    }
+}
+
+fn create_bvh(list: Vec<Hittables>, lights: BvhLights, quality: BvhQuality) -> Bvh {
+    let root = if list.is_empty() {
+        BvhItem::None
+    } else {
+        new_bvh(list.into_iter().enumerate().collect(), quality)
+    };
+    Bvh::flatten(root, lights, quality)
+}
+
+fn surface_area(hittable: &Hittables) -> Option<f64> {
+    match hittable {
+        TriangleType(t) => Some(t.area()),
+        QuadType(q) => Some(q.area()),
+        DiscType(d) => Some(d.area()),
+        TriangleMeshType(m) => Some(m.area()),
+        _ => None,
+    }
+}
+
+/// Whether the bounding box of the hittable is infinitely large, like that of a directional light
+fn is_unbounded(hittable: &Hittables) -> bool {
+    !hittable.bounding_box().surface_area().is_finite()
+}
+
+/// Builds the tree for the hittables, along with their positions in the list.
+/// Unbounded hittables are kept out of the splits, in a subtree of their own
+/// next to the bounded ones, as they would make every split infinitely expensive
+fn new_bvh(mut list: Vec<(usize, Hittables)>, quality: BvhQuality) -> BvhItem {
+    if list.len() == 1 {
+        let (index, hittable) = list.remove(0);
+        return BvhItem::Leaf(Box::new(hittable), index);
+    }
+    let unbounded = list.iter().filter(|(_, h)| is_unbounded(h)).count();
+    if unbounded > 0 && unbounded < list.len() {
+        let (unbounded, bounded): (Vec<_>, Vec<_>) =
+            list.into_iter().partition(|(_, h)| is_unbounded(h));
+        return join_items(new_bvh(bounded, quality), new_bvh(unbounded, quality));
+    }
+    let mid = if list.len() == 2 {
+        1
+    } else if unbounded > 0 {
+        list.len() / 2
+    } else {
+        match quality {
+            BvhQuality::Fast => sort_hittables_slice_by_most_spread_axis(list.as_mut_slice()),
+            BvhQuality::Medium => split_by_binned_sah(list.as_mut_slice()),
+            BvhQuality::High => split_by_sah(list.as_mut_slice()),
+        }
+    };
+
+    let right = list.split_off(mid);
+    let (left, right) = rayon::join(|| new_bvh(list, quality), || new_bvh(right, quality));
+    join_items(left, right)
+}
+
+/// Number of bins along each axis that [`BvhQuality::Medium`] tries to split between
+const SAH_BINS: usize = 16;
+
+fn center(hittable: &Hittables, axis: u8) -> f64 {
+    hittable.bounding_box().center().axis(axis)
+}
+
+/// Sorts the hittables by their centers along the axis, with ties in the order of the list
+fn sort_hittables_along(list: &mut [(usize, Hittables)], axis: u8) {
+    list.sort_unstable_by(|(ia, a), (ib, b)| {
+        center(a, axis)
+            .total_cmp(&center(b, axis))
+            .then(ia.cmp(ib))
+    });
+}
+
+/// Sorts the hittables along the axis with the cheapest split by the surface area heuristic,
+/// among the splits between bins of the centers, and returns the position of the split
+fn split_by_binned_sah(list: &mut [(usize, Hittables)]) -> usize {
+    let bin = |hittable: &Hittables, axis: u8, (spread, middle): (f64, f64)| {
+        let position = (center(hittable, axis) - middle) / spread + 0.5;
+        ((position * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+    };
+
+    let mut best: Option<(f64, u8, (f64, f64), usize)> = None;
+    for axis in 0..3 {
+        let spread = bounding_box_spread(list, axis);
+        if spread.0 <= 0. {
+            continue;
+        }
+        let mut bins: Vec<(Option<Aabb>, usize)> = vec![(None, 0); SAH_BINS];
+        for (_, hittable) in list.iter() {
+            let (b_box, count) = &mut bins[bin(hittable, axis, spread)];
+            *b_box = Some(combine(b_box.take(), hittable.bounding_box()));
+            *count += 1;
+        }
+        if let Some((split, cost)) = cheapest_split(&bins) {
+            if best.is_none_or(|(best_cost, ..)| cost < best_cost) {
+                best = Some((cost, axis, spread, split));
+            }
+        }
+    }
+
+    match best {
+        Some((_, axis, spread, split)) => {
+            sort_hittables_along(list, axis);
+            list.partition_point(|(_, h)| bin(h, axis, spread) < split)
+        }
+        // All the centers are in the same place
+        None => list.len() / 2,
+    }
+}
+
+/// Sorts the hittables along the axis with the cheapest split by the surface area heuristic,
+/// among the splits between all the hittables, and returns the position of the split
+fn split_by_sah(list: &mut [(usize, Hittables)]) -> usize {
+    let mut best: Option<(f64, u8, usize)> = None;
+    for axis in 0..3 {
+        sort_hittables_along(list, axis);
+        let items: Vec<(Option<Aabb>, usize)> = list
+            .iter()
+            .map(|(_, h)| (Some(h.bounding_box().clone()), 1))
+            .collect();
+        if let Some((split, cost)) = cheapest_split(&items) {
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, axis, split));
+            }
+        }
+    }
+
+    let (_, axis, split) = best.expect("There are at least two hittables to split");
+    // The list is still sorted along the last axis
+    if axis != 2 {
+        sort_hittables_along(list, axis);
+    }
+    split
+}
+
+/// The cheapest split by the surface area heuristic of groups of hittables into two,
+/// as the number of groups before the split and the cost of the split. The cost of each
+/// side is its surface area times its number of hittables.
+/// Splits with no hittables on one side are skipped
+fn cheapest_split(groups: &[(Option<Aabb>, usize)]) -> Option<(usize, f64)> {
+    let mut right_sides = vec![(0., 0); groups.len()];
+    let mut b_box = None;
+    let mut count = 0;
+    for split in (1..groups.len()).rev() {
+        if let Some(group_box) = &groups[split].0 {
+            b_box = Some(combine(b_box, group_box));
+        }
+        count += groups[split].1;
+        right_sides[split] = (b_box.as_ref().map_or(0., Aabb::surface_area), count);
+    }
+
+    let mut best: Option<(usize, f64)> = None;
+    let mut b_box = None;
+    let mut count = 0;
+    for split in 1..groups.len() {
+        if let Some(group_box) = &groups[split - 1].0 {
+            b_box = Some(combine(b_box, group_box));
+        }
+        count += groups[split - 1].1;
+        let (right_area, right_count) = right_sides[split];
+        if count == 0 || right_count == 0 {
+            continue;
+        }
+        let left_area = b_box.as_ref().map_or(0., Aabb::surface_area);
+        let cost = left_area * count as f64 + right_area * right_count as f64;
+        if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+            best = Some((split, cost));
+        }
+    }
+    best
+}
+
+fn combine(b_box: Option<Aabb>, other: &Aabb) -> Aabb {
+    match b_box {
+        Some(b_box) => b_box.combine(other),
+        None => other.clone(),
+    }
+}
+
+fn sort_hittables_slice_by_most_spread_axis(list: &mut [(usize, Hittables)]) -> usize {
     let (x_spread, x_center) = bounding_box_spread(list, 0);
     let (y_spread, y_center) = bounding_box_spread(list, 1);
     let (z_spread, z_center) = bounding_box_spread(list, 2);
@@ -133,10 +798,10 @@ fn sort_hittables_slice_by_most_spread_axis(list: &mut [Hittables]) -> usize {
     center
 }
 
-fn bounding_box_spread(list: &[Hittables], axis: u8) -> (f64, f64) {
+fn bounding_box_spread(list: &[(usize, Hittables)], axis: u8) -> (f64, f64) {
     let mut min = f64::INFINITY;
     let mut max = f64::NEG_INFINITY;
-    for hittable in list {
+    for (_, hittable) in list {
         let c = hittable.bounding_box().center().axis(axis);
         min = min.min(c);
         max = max.max(c);
@@ -144,15 +809,15 @@ fn bounding_box_spread(list: &[Hittables], axis: u8) -> (f64, f64) {
     (max - min, (min + max) * 0.5)
 }
 
-fn sort_hittables_by_center(list: &mut [Hittables], center: f64, axis: u8) -> usize {
-    list.sort_unstable_by(|a, b| {
+fn sort_hittables_by_center(list: &mut [(usize, Hittables)], center: f64, axis: u8) -> usize {
+    list.sort_unstable_by(|(_, a), (_, b)| {
         a.bounding_box()
             .center()
             .axis(axis)
             .total_cmp(&b.bounding_box().center().axis(axis))
     });
     let mut i = 0;
-    for t in list {
+    for (_, t) in list {
         if t.bounding_box().center().axis(axis) >= center {
             return i;
         }
@@ -161,34 +826,457 @@ fn sort_hittables_by_center(list: &mut [Hittables], center: f64, axis: u8) -> us
     i
 }
 
+impl Sampleable for Bvh {
+    /// Sums the pdf for all light surfaces along the direction,
+    /// as any of them could have been sampled
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let total_area = match &self.data.lights {
+            BvhLights::Grouped {
+                cumulative_areas, ..
+            } => cumulative_areas.last().copied().unwrap_or(0.),
+            _ => 0.,
+        };
+        let ray = Ray::new(origin, direction);
+
+        let mut pdf = 0.;
+        let mut ray_length = RAY_INTERVAL;
+        while let Some(rec) = self.hit(&ray, &ray_length) {
+            if rec.material.is_light() {
+                let distance_squared = rec.ray_length * rec.ray_length * direction.length_squared();
+                let cosine = (direction.dot(rec.normal) / direction.length()).abs();
+                pdf += distance_squared / (cosine * total_area);
+            }
+            ray_length = Interval::new(rec.ray_length + RAY_INTERVAL.min, RAY_INTERVAL.max);
+        }
+        pdf
+    }
+
+    fn random_direction(&self, origin: Vec3) -> Vec3 {
+        match &self.data.lights {
+            BvhLights::Grouped {
+                lights,
+                cumulative_areas,
+            } if !lights.is_empty() => {
+                let total_area = cumulative_areas[cumulative_areas.len() - 1];
+                let r = random_normal_float() * total_area;
+                let idx = cumulative_areas
+                    .partition_point(|a| *a <= r)
+                    .min(lights.len() - 1);
+                lights[idx]
+                    .as_sampleable()
+                    .expect("Grouped lights are triangles, triangle meshes, quads or discs")
+                    .random_direction(origin)
+            }
+            _ => panic!("Only a bvh returned by as_sampleable can be sampled"),
+        }
+    }
+}
+
 impl Hittable for Bvh {
+    fn as_sampleable(&self) -> Option<&dyn Sampleable> {
+        match &self.data.lights {
+            BvhLights::Grouped { lights, .. } if !lights.is_empty() => Some(self),
+            _ => None,
+        }
+    }
+
+    /// Visits the nodes depth first with the left child first, shortening the ray
+    /// to the closest hit found so far
     fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
-        if !self.b_box.hit(r) {
+        if self.data.nodes.is_empty() || !self.data.b_box.hit(r) {
             return None;
         }
 
-        match self.left.hit(r, ray_length) {
-            None => self.right.hit(r, ray_length),
-            Some(left_rec) => {
-                let new_ray_length = Interval::new(ray_length.min, left_rec.ray_length);
-                match self.right.hit(r, &new_ray_length) {
-                    Some(right_rec) => Some(right_rec),
-                    None => Some(left_rec),
+        let mut closest = None;
+        let mut ray_length = *ray_length;
+        let mut stack = NodeStack::new();
+        let mut node = 0;
+        loop {
+            match &self.data.nodes[node] {
+                BvhNode::Inner { b_box, right } => {
+                    if b_box.hit(r) {
+                        stack.push(*right);
+                        node += 1;
+                        continue;
+                    }
                 }
+                BvhNode::Leaf(hittable) => {
+                    if let Some(rec) = self.data.hittables[*hittable].hit(r, &ray_length) {
+                        ray_length = Interval::new(ray_length.min, rec.ray_length);
+                        closest = Some(rec);
+                    }
+                }
+            }
+            match stack.pop() {
+                Some(next) => node = next,
+                None => return closest,
             }
         }
     }
 
     fn bounding_box(&self) -> &Aabb {
-        &self.b_box
+        &self.data.b_box
     }
 
     fn get_lights(&self) -> Vec<Hittables> {
-        let mut ret = Vec::new();
+        match &self.data.lights {
+            BvhLights::Separate => {
+                self.data.hittables.iter().flat_map(|h| h.get_lights()).collect()
+            }
+            BvhLights::Grouped { lights, .. } if lights.is_empty() => vec![],
+            _ => vec![BvhType(self.clone())],
+        }
+    }
+
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        self.closest_point_within(p).map(|(point, _)| point)
+    }
+
+    fn children(&self) -> Vec<&Hittables> {
+        self.leaves()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::transformation::NopTransformer;
+    use crate::geo::vec3::{random_unit_vector, random_vec3};
+    use crate::hittable::{DirectionalLight, Quad, Sphere, Triangle};
+    use crate::util::interval::RAY_INTERVAL;
+    use crate::material::texture::SolidColor;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::util::chi_squared::chi_squared_test;
+
+    use super::*;
+
+    #[test]
+    fn test_light_bvh_pdf_matches_random_direction() {
+        let light = DiffuseLight::new(1., 1., 1., None);
+        let bvh = Bvh::new_light(vec![
+            Quad::new(
+                Vec3::new(-1., -1., 1.),
+                Vec3::new(2., 0., 0.),
+                Vec3::new(0., 2., 0.),
+                light.clone(),
+                &NopTransformer(),
+            ),
+            // Partly behind the first quad
+            Quad::new(
+                Vec3::new(0., 0., 2.),
+                Vec3::new(2., 0., 0.),
+                Vec3::new(0., 2., 0.5),
+                light.clone(),
+                &NopTransformer(),
+            ),
+            Triangle::new(
+                Vec3::new(-1., 0., -1.),
+                Vec3::new(1., 0., -1.),
+                Vec3::new(0., 1., -1.5),
+                light,
+                &NopTransformer(),
+            ),
+            Triangle::new(
+                Vec3::new(-1., -1., -0.5),
+                Vec3::new(1., -1., -0.5),
+                Vec3::new(0., 1., -0.5),
+                Lambertian::new(SolidColor::new(1., 1., 1.), None),
+                &NopTransformer(),
+            ),
+        ]);
+        let lights = bvh.get_lights();
+        assert_eq!(lights.len(), 1);
+
+        let light = lights[0].as_sampleable().unwrap();
+        let origin = Vec3::new(0.1, 0.2, 0.);
+        let res = chi_squared_test(
+            || light.random_direction(origin),
+            |direction| light.pdf_value(origin, direction),
+        );
+        assert_eq!(res, Ok(()));
+    }
+
+    #[test]
+    fn test_light_bvh_with_sphere_is_not_sampleable() {
+        let bvh = Bvh::new_light(vec![
+            Sphere::new(Vec3::new(0., 0., 0.), 1., DiffuseLight::new(1., 1., 1., None)),
+            Sphere::new(Vec3::new(2., 0., 0.), 1., DiffuseLight::new(1., 1., 1., None)),
+        ]);
+        let lights = bvh.get_lights();
+        assert_eq!(lights.len(), 1);
+        assert!(lights[0].as_sampleable().is_none());
+    }
+
+    #[test]
+    fn test_light_bvh_without_lights() {
+        let bvh = Bvh::new_light(vec![Sphere::new(
+            Vec3::new(0., 0., 0.),
+            1.,
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+        )]);
+        assert!(bvh.get_lights().is_empty());
+    }
+
+    #[test]
+    fn test_light_bvh_light_shares_tree() {
+        let light = DiffuseLight::new(1., 1., 1., None);
+        let triangles = (0..100)
+            .map(|i| {
+                let corner = Vec3::new(i as f64, 0., 0.);
+                let (u, v) = (Vec3::new(1., 0., 0.), Vec3::new(0., 1., 0.));
+                Triangle::new(corner, corner + u, corner + v, light.clone(), &NopTransformer())
+            })
+            .collect();
+        let bvh = Bvh::new_light(triangles);
+        let lights = bvh.get_lights();
+        assert_eq!(lights.len(), 1);
+        match (&bvh, &lights[0]) {
+            (BvhType(bvh), BvhType(light)) => assert!(Arc::ptr_eq(&bvh.data, &light.data)),
+            _ => panic!("Should be bvhs"),
+        }
+    }
+
+    #[test]
+    fn test_closest_point_queries() {
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let bvh = Bvh::new(vec![
+            Sphere::new(Vec3::new(0., 0., 0.), 1., mat.clone()),
+            Quad::new(
+                Vec3::new(4., 0., 0.),
+                Vec3::new(2., 0., 0.),
+                Vec3::new(0., 0., 2.),
+                mat.clone(),
+                &NopTransformer(),
+            ),
+            Triangle::new(
+                Vec3::new(0., 4., 0.),
+                Vec3::new(2., 4., 0.),
+                Vec3::new(0., 6., 0.),
+                mat,
+                &NopTransformer(),
+            ),
+        ]);
+
+        // Closest to the sphere, the quad interior, the quad edge and the triangle edge
+        let p = Vec3::new(0., -3., 0.);
+        assert_eq!(bvh.closest_point(p), Some(Vec3::new(0., -1., 0.)));
+        assert_eq!(bvh.distance(p), Some(2.));
+        let p = Vec3::new(5., 1., 1.);
+        assert_eq!(bvh.closest_point(p), Some(Vec3::new(5., 0., 1.)));
+        let p = Vec3::new(8., 0., 1.);
+        assert_eq!(bvh.closest_point(p), Some(Vec3::new(6., 0., 1.)));
+        let p = Vec3::new(2., 6., 1.);
+        assert_eq!(bvh.closest_point(p), Some(Vec3::new(1., 5., 0.)));
+
+        assert!(Bvh::new(vec![]).closest_point(p).is_none());
+
+        assert!(!bvh.is_visible(Vec3::new(0., -3., 0.), Vec3::new(0., 3., 0.)));
+        assert!(bvh.is_visible(Vec3::new(0., -3., 0.), Vec3::new(0., -1.5, 0.)));
+        // Points on the surfaces are visible from each other
+        assert!(bvh.is_visible(Vec3::new(1., 0., 0.), Vec3::new(5., 0., 1.)));
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let spheres = |moved: Vec3| {
+            (0..50)
+                .map(|i| {
+                    let center = Vec3::new((i % 10) as f64 * 3., (i / 10) as f64 * 3., 0.);
+                    let center = if i == 12 { moved } else { center };
+                    Sphere::new(center, 1., mat.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+        let original = Vec3::new(6., 3., 0.);
+        let moved = Vec3::new(100., 0., 0.);
+        let tree = |bvh: &Hittables| match bvh {
+            BvhType(b) => b.to_string(),
+            _ => panic!("Should be a bvh"),
+        };
+        let hits = |bvh: &Hittables, center: Vec3| {
+            let ray = Ray::new(center + Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+            bvh.hit(&ray, &RAY_INTERVAL).is_some()
+        };
+
+        // Nothing is rebuilt when nothing has changed
+        let bvh = Bvh::new(spheres(original));
+        let expected = tree(&bvh);
+        let bvh = Bvh::rebuild(bvh, spheres(original), Duration::ZERO);
+        assert_eq!(tree(&bvh), expected);
+
+        // Without time to rebuild, only the bounding boxes are updated
+        let refitted = Bvh::rebuild(bvh.clone(), spheres(moved), Duration::ZERO);
+        assert!(hits(&refitted, moved) && !hits(&refitted, original));
+        assert_ne!(tree(&refitted), tree(&Bvh::new(spheres(moved))));
+
+        let rebuilt = Bvh::rebuild(bvh, spheres(moved), Duration::from_secs(10));
+        assert!(hits(&rebuilt, moved) && !hits(&rebuilt, original));
+        assert_eq!(tree(&rebuilt), tree(&Bvh::new(spheres(moved))));
+
+        // Hittables can be removed from and added to the end of the list
+        let mut list = spheres(moved);
+        list.truncate(40);
+        list.push(Sphere::new(Vec3::new(0., 100., 0.), 1., mat));
+        let updated = Bvh::rebuild(rebuilt, list, Duration::ZERO);
+        assert!(!hits(&updated, Vec3::new(0., 12., 0.)));
+        assert!(hits(&updated, Vec3::new(0., 100., 0.)) && hits(&updated, moved));
+        assert_eq!(updated.children().len(), 41);
+    }
+
+    /// Sum of the surface areas of the nodes of the tree, which is how many nodes a ray
+    /// is expected to visit according to the surface area heuristic
+    fn tree_area(bvh: &Bvh) -> f64 {
+        let areas = bvh.data.nodes.iter().map(|node| match node {
+            BvhNode::Inner { b_box, .. } => b_box.surface_area(),
+            BvhNode::Leaf(hittable) => bvh.data.hittables[*hittable].bounding_box().surface_area(),
+        });
+        areas.sum()
+    }
+
+    #[test]
+    fn test_build_qualities() {
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        // A dense cluster of small spheres and a few spheres far away from it
+        let spheres: Vec<Hittables> = (0..200)
+            .map(|i| {
+                let f = i as f64;
+                if i % 50 == 0 {
+                    Sphere::new(Vec3::new(100. + f, 0., 0.), 1., mat.clone())
+                } else {
+                    let center = Vec3::new((f * 0.37) % 3., (f * 0.61) % 2., (f * 0.13) % 1.);
+                    Sphere::new(center, 0.05, mat.clone())
+                }
+            })
+            .collect();
+
+        let area = |quality: BvhQuality| match Bvh::new_with_quality(spheres.clone(), quality) {
+            BvhType(b) => {
+                assert_eq!(b.leaves().len(), spheres.len());
+                tree_area(&b)
+            }
+            _ => panic!("Should be a bvh"),
+        };
+        let (fast, medium, high) = (
+            area(BvhQuality::Fast),
+            area(BvhQuality::Medium),
+            area(BvhQuality::High),
+        );
+        assert!(medium < fast, "medium {} fast {}", medium, fast);
+        assert!(high < fast, "high {} fast {}", high, fast);
+
+        let target = spheres[1].bounding_box().center();
+        let ray = Ray::new(Vec3::new(1.5, 1., 5.), target - Vec3::new(1.5, 1., 5.));
+        let hits: Vec<Option<f64>> = [BvhQuality::Fast, BvhQuality::Medium, BvhQuality::High]
+            .map(|quality| {
+                let bvh = Bvh::new_with_quality(spheres.clone(), quality);
+                bvh.hit(&ray, &RAY_INTERVAL).map(|rec| rec.ray_length)
+            })
+            .to_vec();
+        assert!(hits[0].is_some());
+        assert!(hits.iter().all(|hit| *hit == hits[0]));
+    }
+
+    #[test]
+    fn test_directional_light_is_kept_out_of_splits() {
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let spheres: Vec<Hittables> = (0..200)
+            .map(|i| {
+                let f = i as f64;
+                let center = Vec3::new((f * 0.37) % 3., (f * 0.61) % 2., (f * 0.13) % 1.);
+                Sphere::new(center, 0.05, mat.clone())
+            })
+            .collect();
+        let sun = DirectionalLight::new(Vec3::new(0., -1., 0.), Vec3::new(1., 1., 1.), 0.53);
+        let mut list = spheres.clone();
+        list.push(sun.clone());
+
+        for quality in [BvhQuality::Medium, BvhQuality::High] {
+            let with_sun = Bvh::new_with_quality(list.clone(), quality);
+            let without_sun = Bvh::new_with_quality(spheres.clone(), quality);
+            let (BvhType(with_sun), BvhType(without_sun)) = (with_sun, without_sun) else {
+                panic!("Should be bvhs")
+            };
+            // The spheres are split as if the light was not there
+            assert_eq!(
+                format!("{}", with_sun),
+                format!(
+                    "{{\"left\": {}, \"right\": {}}}",
+                    without_sun,
+                    sun.bounding_box().center()
+                )
+            );
+
+            let towards_sun = Ray::new(Vec3::new(10., 0., 0.), Vec3::new(0., 1., 0.));
+            let rec = with_sun.hit(&towards_sun, &RAY_INTERVAL).expect("Should hit the sun");
+            assert!(rec.material.is_light());
+            let target = spheres[1].bounding_box().center();
+            let ray = Ray::new(target + Vec3::new(0., 1., 0.), Vec3::new(0., -1., 0.));
+            let closest = without_sun.hit(&ray, &RAY_INTERVAL).map(|rec| rec.ray_length);
+            assert!(closest.is_some());
+            assert_eq!(with_sun.hit(&ray, &RAY_INTERVAL).map(|rec| rec.ray_length), closest);
+        }
+    }
+
+    #[test]
+    fn test_hit_matches_hittables() {
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let spheres: Vec<Hittables> = (0..100)
+            .map(|_| {
+                let center = random_vec3(-5., 5.);
+                Sphere::new(center, random_normal_float() * 0.5 + 0.1, mat.clone())
+            })
+            .collect();
+        let bvh = Bvh::new(spheres.clone());
+
+        for _ in 0..1000 {
+            let ray = Ray::new(random_vec3(-10., 10.), random_unit_vector());
+            let closest = spheres
+                .iter()
+                .filter_map(|s| s.hit(&ray, &RAY_INTERVAL).map(|rec| rec.ray_length))
+                .min_by(f64::total_cmp);
+            assert_eq!(bvh.hit(&ray, &RAY_INTERVAL).map(|rec| rec.ray_length), closest);
+        }
+    }
+
+    #[test]
+    fn test_node_stack_spills() {
+        let mut stack = NodeStack::new();
+        (0..100).for_each(|node| stack.push(node));
+        let popped: Vec<usize> = std::iter::from_fn(|| stack.pop()).collect();
+        assert_eq!(popped, (0..100).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_cache() {
+        let dir = std::env::temp_dir().join(format!("solstrale-bvh-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = SceneCache::new(&dir);
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let spheres = |radius: f64| {
+            (0..30)
+                .map(|i| {
+                    let center = Vec3::new((i * 7 % 11) as f64, (i * 3 % 5) as f64, i as f64);
+                    Sphere::new(center, radius, mat.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+        let tree = |bvh: &Hittables| match bvh {
+            BvhType(b) => b.to_string(),
+            _ => panic!("Should be a bvh"),
+        };
 
-        ret.append(&mut self.left.get_lights());
-        ret.append(&mut self.right.get_lights());
+        let expected = tree(&Bvh::new_with_quality(spheres(1.), BvhQuality::High));
+        let built = Bvh::new_with_cache(spheres(1.), BvhQuality::High, &cache);
+        assert_eq!(tree(&built), expected);
+        let cached = Bvh::new_with_cache(spheres(1.), BvhQuality::High, &cache);
+        assert_eq!(tree(&cached), expected);
+        assert_eq!(cached.bounding_box(), built.bounding_box());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
 
-        ret
+        // Other bounding boxes or qualities are other entries
+        Bvh::new_with_cache(spheres(2.), BvhQuality::High, &cache);
+        Bvh::new_with_cache(spheres(1.), BvhQuality::Fast, &cache);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+        let _ = std::fs::remove_dir_all(&dir);
     }
 }
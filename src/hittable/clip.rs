@@ -0,0 +1,181 @@
+use crate::geo::vec3::Vec3;
+use crate::geo::Aabb;
+use crate::geo::Onb;
+use crate::geo::Ray;
+use crate::geo::Uv;
+use crate::hittable::{Hittable, Hittables};
+use crate::material::{Materials, RayHit};
+use crate::util::interval::Interval;
+
+/// A plane that cuts away all geometry in front of it, on the side that the normal points to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClipPlane {
+    /// Any point on the plane
+    pub point: Vec3,
+    /// Normal of the plane, pointing towards the side that is cut away
+    pub normal: Vec3,
+}
+
+/// Cuts the wrapped hittable with clipping planes, e.g. for sectional views of buildings.
+/// Wrapping the whole world gives scene wide clipping
+#[derive(Clone, Debug)]
+pub struct Clipped {
+    hittable: Box<Hittables>,
+    planes: Vec<ClipPlane>,
+    cap: Option<Materials>,
+}
+
+impl Clipped {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a hittable where the given hittable is cut by the planes.
+    /// If there is a cap material, solids that are cut open are closed with it at the cut,
+    /// otherwise their insides are visible
+    pub fn new(hittable: Hittables, planes: Vec<ClipPlane>, cap: Option<Materials>) -> Hittables {
+        Hittables::from(Clipped {
+            hittable: Box::new(hittable),
+            planes,
+            cap,
+        })
+    }
+
+    /// Narrows the interval to the part of the ray that is behind all planes.
+    /// Also returns the plane that the ray passes at the start of the interval, if any
+    fn clip_interval(&self, r: &Ray, ray_length: &Interval) -> (Interval, Option<&ClipPlane>) {
+        let mut interval = *ray_length;
+        let mut entry_plane = None;
+        for plane in &self.planes {
+            let distance = (r.origin - plane.point).dot(plane.normal);
+            let speed = r.direction.dot(plane.normal);
+            if speed == 0. {
+                if distance > 0. {
+                    return (Interval::new(1., 0.), None);
+                }
+                continue;
+            }
+            let t = -distance / speed;
+            if speed > 0. {
+                interval.max = interval.max.min(t);
+            } else if t > interval.min {
+                interval.min = t;
+                entry_plane = Some(plane);
+            }
+        }
+        (interval, entry_plane)
+    }
+}
+
+impl Hittable for Clipped {
+    fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
+        let (interval, entry_plane) = self.clip_interval(r, ray_length);
+        if interval.min > interval.max {
+            return None;
+        }
+        let rec = self.hittable.hit(r, &interval)?;
+
+        // The ray entered a solid at the cut if it hits the inside of it both after and
+        // before the cut. Checking both ways avoids capping open surfaces seen from behind
+        if let (Some(cap), Some(plane), false) = (&self.cap, entry_plane, rec.front_face) {
+            let hit_point = r.at(interval.min);
+            let back_ray = Ray::new_at_time(hit_point, r.direction.neg(), r.time);
+            let back_length = Interval::new(ray_length.min, interval.min - ray_length.min);
+            let inside = self
+                .hittable
+                .hit(&back_ray, &back_length)
+                .is_some_and(|back_rec| !back_rec.front_face);
+            if !inside {
+                return Some(rec);
+            }
+            let onb = Onb::new(plane.normal);
+            let uv = Uv::new(
+                hit_point.dot(onb.tangent) as f32,
+                hit_point.dot(onb.bi_tangent) as f32,
+            );
+            return Some(RayHit::new(hit_point, onb, cap, interval.min, uv, true, r.time));
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.hittable.bounding_box()
+    }
+
+    fn get_lights(&self) -> Vec<Hittables> {
+        self.hittable.get_lights()
+    }
+
+    /// The closest point of the wrapped hittable, which may be on a part that is cut away
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        self.hittable.closest_point(p)
+    }
+
+    fn children(&self) -> Vec<&Hittables> {
+        vec![&self.hittable]
+    }
+
+    fn materials(&self) -> Vec<&Materials> {
+        self.cap.iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::transformation::NopTransformer;
+    use crate::hittable::{Quad, Sphere};
+    use crate::material::texture::SolidColor;
+    use crate::material::Lambertian;
+    use crate::util::interval::RAY_INTERVAL;
+
+    use super::*;
+
+    #[test]
+    fn test_clipping() {
+        let sphere = Sphere::new(
+            Vec3::new(0., 0., 0.),
+            1.,
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+        );
+        let planes = vec![ClipPlane {
+            point: Vec3::new(0., 0., 0.),
+            normal: Vec3::new(0., 0., 1.),
+        }];
+        let ray = Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+
+        // Without cap the inside of the back half is hit
+        let open = Clipped::new(sphere.clone(), planes.clone(), None);
+        let rec = open.hit(&ray, &RAY_INTERVAL).unwrap();
+        assert_eq!(rec.ray_length, 6.);
+        assert!(!rec.front_face);
+
+        let capped = Clipped::new(
+            sphere,
+            planes,
+            Some(Lambertian::new(SolidColor::new(1., 0., 0.), None)),
+        );
+        let rec = capped.hit(&ray, &RAY_INTERVAL).unwrap();
+        assert_eq!(rec.ray_length, 5.);
+        assert_eq!(rec.normal, Vec3::new(0., 0., 1.));
+
+        // Rays passing beside the cut solid misses
+        let beside = Ray::new(Vec3::new(2., 0., 5.), Vec3::new(0., 0., -1.));
+        assert!(capped.hit(&beside, &RAY_INTERVAL).is_none());
+
+        // Open surfaces seen from behind are not capped
+        let quad = Quad::new(
+            Vec3::new(-1., -1., -1.),
+            Vec3::new(0., 2., 0.),
+            Vec3::new(2., 0., 0.),
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        );
+        let capped = Clipped::new(
+            quad,
+            vec![ClipPlane {
+                point: Vec3::new(0., 0., 0.),
+                normal: Vec3::new(0., 0., 1.),
+            }],
+            Some(Lambertian::new(SolidColor::new(1., 0., 0.), None)),
+        );
+        let rec = capped.hit(&ray, &RAY_INTERVAL).unwrap();
+        assert_eq!(rec.ray_length, 6.);
+    }
+}
@@ -71,6 +71,7 @@ impl Hittable for ConstantMedium {
                             t,
                             Uv::default(),
                             false,
+                            r.time,
                         ))
                     }
                 }
@@ -85,4 +86,16 @@ impl Hittable for ConstantMedium {
     fn get_lights(&self) -> Vec<Hittables> {
         vec![]
     }
+
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        self.boundary.closest_point(p)
+    }
+
+    fn children(&self) -> Vec<&Hittables> {
+        vec![&self.boundary]
+    }
+
+    fn materials(&self) -> Vec<&Materials> {
+        vec![&self.phase_function]
+    }
 }
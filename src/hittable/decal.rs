@@ -0,0 +1,183 @@
+use std::error::Error;
+
+use crate::geo::vec3::Vec3;
+use crate::geo::Aabb;
+use crate::geo::Onb;
+use crate::geo::Ray;
+use crate::geo::{Uv, UvDerivatives};
+use crate::hittable::{Hittable, Hittables};
+use crate::material::texture::{brightness, ImageMap, Texture, TextureFilter, Textures};
+use crate::material::{Lambertian, Material, Materials, RayHit};
+use crate::random::random_normal_float;
+use crate::util::interval::Interval;
+
+/// Projects a material onto the surfaces of the wrapped hittable that are within a box volume,
+/// like a label or poster. The projected material covers the underlying material where
+/// the alpha texture is white, and is partly transparent where it is gray
+#[derive(Clone, Debug)]
+pub struct Decal {
+    hittable: Box<Hittables>,
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    normal: Vec3,
+    depth: f64,
+    material: Materials,
+    alpha: Textures,
+}
+
+impl Decal {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a decal projecting the material onto the given hittable
+    ///
+    /// # Arguments
+    /// * `hittable` - The hittable that the decal is projected onto
+    /// * `q` - Corner of the projected rectangle, where the texture coordinate is zero
+    /// * `u` - Vector along the first side of the rectangle
+    /// * `v` - Vector along the second side of the rectangle
+    /// * `depth` - Thickness of the projection volume, centered on the rectangle
+    /// * `material` - Material of the decal, with texture coordinates 0 to 1 over the rectangle
+    /// * `alpha` - Texture where the brightness is the coverage of the decal
+    pub fn new(
+        hittable: Hittables,
+        q: Vec3,
+        u: Vec3,
+        v: Vec3,
+        depth: f64,
+        material: Materials,
+        alpha: Textures,
+    ) -> Hittables {
+        let n = u.cross(v);
+        Hittables::from(Decal {
+            hittable: Box::new(hittable),
+            q,
+            u,
+            v,
+            w: n / n.dot(n),
+            normal: n.unit(),
+            depth,
+            material,
+            alpha,
+        })
+    }
+
+    /// Creates a decal from an image file, where the alpha channel of the image
+    /// is the coverage and the colors are diffuse
+    pub fn load(
+        hittable: Hittables,
+        q: Vec3,
+        u: Vec3,
+        v: Vec3,
+        depth: f64,
+        path: &str,
+    ) -> Result<Hittables, Box<dyn Error>> {
+        let (color, alpha) = ImageMap::load_with_alpha(path, TextureFilter::Nearest)?;
+        Ok(Decal::new(hittable, q, u, v, depth, Lambertian::new(color, None), alpha))
+    }
+
+    /// Texture coordinate of the point on the rectangle, if the point is within the volume
+    fn projected_uv(&self, point: Vec3) -> Option<Uv> {
+        let p = point - self.q;
+        if p.dot(self.normal).abs() > self.depth / 2. {
+            return None;
+        }
+        let u = self.w.dot(p.cross(self.v));
+        let v = self.w.dot(self.u.cross(p));
+        if !(0. ..=1.).contains(&u) || !(0. ..=1.).contains(&v) {
+            return None;
+        }
+        Some(Uv::new(u as f32, v as f32))
+    }
+}
+
+impl Hittable for Decal {
+    fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
+        let rec = self.hittable.hit(r, ray_length)?;
+        let Some(uv) = self.projected_uv(rec.hit_point) else {
+            return Some(rec);
+        };
+
+        let alpha = brightness(self.alpha.color(uv, rec.time));
+        if alpha <= random_normal_float() {
+            return Some(rec);
+        }
+
+        let onb = Onb {
+            tangent: self.u.unit(),
+            bi_tangent: self.v.unit(),
+            normal: rec.onb.normal,
+        };
+        let uv_derivatives = UvDerivatives::new(r, rec.hit_point, rec.onb.normal, self.u, self.v);
+        Some(RayHit {
+            normal: self.material.get_transformed_normal(onb.clone(), uv, rec.time),
+            material: &self.material,
+            uv,
+            uv_derivatives,
+            onb,
+            ..rec
+        })
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.hittable.bounding_box()
+    }
+
+    fn get_lights(&self) -> Vec<Hittables> {
+        self.hittable.get_lights()
+    }
+
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        self.hittable.closest_point(p)
+    }
+
+    fn children(&self) -> Vec<&Hittables> {
+        vec![&self.hittable]
+    }
+
+    fn materials(&self) -> Vec<&Materials> {
+        vec![&self.material]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::transformation::NopTransformer;
+    use crate::hittable::Quad;
+    use crate::material::texture::SolidColor;
+    use crate::util::interval::RAY_INTERVAL;
+
+    use super::*;
+
+    #[test]
+    fn test_decal_projection() {
+        let quad = Quad::new(
+            Vec3::new(-1., -1., 0.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 2., 0.),
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        );
+        let decal = |alpha| {
+            Decal::new(
+                quad.clone(),
+                Vec3::new(0., 0., 0.),
+                Vec3::new(1., 0., 0.),
+                Vec3::new(0., 1., 0.),
+                0.1,
+                Lambertian::new(SolidColor::new(1., 0., 0.), None),
+                SolidColor::new(alpha, alpha, alpha),
+            )
+        };
+        let hit_uv = |decal: &Hittables, x, y| {
+            let ray = Ray::new(Vec3::new(x, y, 1.), Vec3::new(0., 0., -1.));
+            decal.hit(&ray, &RAY_INTERVAL).unwrap().uv
+        };
+
+        // Inside the decal the texture coordinates are those of the decal
+        assert_eq!(hit_uv(&decal(1.), 0.25, 0.5), Uv::new(0.25, 0.5));
+        // Outside the decal, and where it is transparent, the quad is hit
+        assert_eq!(hit_uv(&decal(1.), -0.5, 0.5), Uv::new(0.25, 0.75));
+        assert_eq!(hit_uv(&decal(0.), 0.25, 0.5), Uv::new(0.625, 0.75));
+    }
+}
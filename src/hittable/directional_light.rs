@@ -0,0 +1,191 @@
+use std::f64::consts::PI;
+
+use crate::geo::vec3::Vec3;
+use crate::geo::{Aabb, Onb, Ray, Uv};
+use crate::hittable::sphere::random_to_sphere;
+use crate::hittable::Hittables::DirectionalLightType;
+use crate::hittable::{Hittable, Hittables, Sampleable};
+use crate::material::{DiffuseLight, Materials, RayHit};
+use crate::util::interval::Interval;
+
+/// Smallest angular diameter in degrees, as the light must cover some part of the sky
+/// to be hit by rays
+const MIN_ANGULAR_DIAMETER: f64 = 0.01;
+/// Distance that the light is hit at, beyond anything else in the scene
+const DISTANCE: f64 = 1e100;
+
+/// A light infinitely far away that lights the whole scene from one direction, like the sun.
+/// It is seen as a small disc in the sky, which makes the shadows slightly soft,
+/// and is sampled as a light within that disc so sunlit scenes converge quickly
+#[derive(Clone, Debug)]
+pub struct DirectionalLight {
+    towards_light: Vec3,
+    cos_theta_max: f64,
+    sin_theta_max: f64,
+    mat: Materials,
+    b_box: Aabb,
+}
+
+impl DirectionalLight {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a new directional light
+    ///
+    /// # Arguments
+    /// * `direction` - The direction that the light travels in, away from the light
+    /// * `color` - Irradiance on a surface facing the light, which is the color
+    ///   that a white diffuse surface facing the light gets
+    /// * `angular_diameter` - Size of the disc of the light in the sky in degrees,
+    ///   about 0.53 for the sun
+    pub fn new(direction: Vec3, color: Vec3, angular_diameter: f64) -> Hittables {
+        let theta_max = angular_diameter.max(MIN_ANGULAR_DIAMETER).to_radians() / 2.;
+        let sin_theta_max = theta_max.sin();
+        // Radiance that gives the irradiance for a disc of the angular size
+        let radiance = color / (PI * sin_theta_max * sin_theta_max);
+
+        Hittables::from(DirectionalLight {
+            towards_light: direction.unit().neg(),
+            cos_theta_max: theta_max.cos(),
+            sin_theta_max,
+            mat: DiffuseLight::new(radiance.x, radiance.y, radiance.z, None),
+            b_box: Aabb {
+                x: Interval::new(f64::MIN, f64::MAX),
+                y: Interval::new(f64::MIN, f64::MAX),
+                z: Interval::new(f64::MIN, f64::MAX),
+            },
+        })
+    }
+
+    fn solid_angle(&self) -> f64 {
+        2. * PI * (1. - self.cos_theta_max)
+    }
+}
+
+impl Sampleable for DirectionalLight {
+    fn pdf_value(&self, _: Vec3, direction: Vec3) -> f64 {
+        if direction.unit().dot(self.towards_light) >= self.cos_theta_max {
+            1. / self.solid_angle()
+        } else {
+            0.
+        }
+    }
+
+    fn random_direction(&self, _: Vec3) -> Vec3 {
+        Onb::new(self.towards_light).local(random_to_sphere(self.sin_theta_max, 1.))
+    }
+}
+
+impl Hittable for DirectionalLight {
+    fn as_sampleable(&self) -> Option<&dyn Sampleable> {
+        Some(self)
+    }
+
+    /// Rays heading into the disc of the light hit it far beyond anything else
+    fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
+        let direction = r.direction.unit();
+        if direction.dot(self.towards_light) < self.cos_theta_max || !ray_length.contains(DISTANCE)
+        {
+            return None;
+        }
+        Some(RayHit::new(
+            r.at(DISTANCE / r.direction.length()),
+            Onb::new(direction.neg()),
+            &self.mat,
+            DISTANCE,
+            Uv::default(),
+            true,
+            r.time,
+        ))
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.b_box
+    }
+
+    fn get_lights(&self) -> Vec<Hittables> {
+        vec![DirectionalLightType(self.clone())]
+    }
+
+    fn closest_point(&self, _: Vec3) -> Option<Vec3> {
+        None
+    }
+
+    fn materials(&self) -> Vec<&Materials> {
+        vec![&self.mat]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::camera::CameraConfig;
+    use crate::geo::transformation::NopTransformer;
+    use crate::geo::vec3::ZERO_VECTOR;
+    use crate::hittable::{Bvh, Quad};
+    use crate::material::texture::SolidColor;
+    use crate::material::Lambertian;
+    use crate::renderer::{RenderConfig, Renderer, Scene};
+    use crate::util::interval::RAY_INTERVAL;
+
+    use super::*;
+
+    #[test]
+    fn test_hit_and_sample() {
+        let light = DirectionalLight::new(Vec3::new(0., -1., 0.), Vec3::new(1., 1., 1.), 0.53);
+        let up = Ray::new(ZERO_VECTOR, Vec3::new(0., 2., 0.));
+        let rec = light.hit(&up, &RAY_INTERVAL).unwrap();
+        assert_eq!(rec.ray_length, DISTANCE);
+        assert!(light.hit(&up, &Interval::new(0.001, 10.)).is_none());
+        let sideways = Ray::new(ZERO_VECTOR, Vec3::new(0.1, 1., 0.));
+        assert!(light.hit(&sideways, &RAY_INTERVAL).is_none());
+
+        let sampleable = light.as_sampleable().unwrap();
+        for _ in 0..100 {
+            let direction = sampleable.random_direction(ZERO_VECTOR);
+            assert!(light
+                .hit(&Ray::new(ZERO_VECTOR, direction), &RAY_INTERVAL)
+                .is_some());
+            assert!(sampleable.pdf_value(ZERO_VECTOR, direction) > 0.);
+        }
+        assert_eq!(sampleable.pdf_value(ZERO_VECTOR, sideways.direction), 0.);
+    }
+
+    #[test]
+    fn test_irradiance() {
+        let white = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let ground = Quad::new(
+            Vec3::new(-10., 0., -10.),
+            Vec3::new(0., 0., 20.),
+            Vec3::new(20., 0., 0.),
+            white,
+            &NopTransformer(),
+        );
+        let light = DirectionalLight::new(Vec3::new(0., -1., 0.), Vec3::new(1., 1., 1.), 0.53);
+        let renderer = Renderer::new(Scene {
+            world: Bvh::new(vec![ground, light]),
+            camera: CameraConfig::default(),
+            views: HashMap::new(),
+            background_color: ZERO_VECTOR,
+            environment_map: None,
+            background: None,
+            render_config: RenderConfig::default(),
+        })
+        .unwrap();
+
+        // A white diffuse surface reflects the irradiance evenly over the hemisphere
+        let samples = 40000;
+        let radiance = (0..samples)
+            .map(|_| {
+                renderer
+                    .incident_radiance(Vec3::new(0., 1., 0.), Vec3::new(0., -1., 0.))
+                    .x
+            })
+            .sum::<f64>()
+            / samples as f64;
+        assert!(
+            (radiance - 1. / PI).abs() < 0.01,
+            "radiance was {}",
+            radiance
+        );
+    }
+}
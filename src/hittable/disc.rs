@@ -0,0 +1,209 @@
+use std::f64::consts::PI;
+
+use crate::geo::vec3::{Vec3, ALMOST_ZERO};
+use crate::geo::{Aabb, Onb, Ray};
+use crate::geo::{Uv, UvDerivatives};
+use crate::hittable::Hittables::DiscType;
+use crate::hittable::{Hittable, Hittables, Sampleable};
+use crate::material::{Material, Materials, RayHit};
+use crate::sampler::next_2d;
+use crate::util::interval::{Interval, RAY_INTERVAL};
+
+/// A circular flat hittable object, that can be used as an area light.
+/// The texture coordinates map the square around the disc to 0 to 1
+#[derive(Clone, Debug)]
+pub struct Disc {
+    center: Vec3,
+    radius: f64,
+    onb: Onb,
+    d: f64,
+    mat: Materials,
+    b_box: Aabb,
+}
+
+impl Disc {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a new disc around the center, facing the direction of the normal
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, mat: Materials) -> Hittables {
+        let onb = Onb::new(normal);
+        let normal = onb.normal;
+
+        // Extent of the disc along each axis
+        let extent = |n: f64| radius * (1. - n * n).max(0.).sqrt();
+        let e = Vec3::new(extent(normal.x), extent(normal.y), extent(normal.z));
+        let b_box = Aabb::new_from_2_points(center - e, center + e).pad_if_needed();
+
+        Hittables::from(Disc {
+            center,
+            radius,
+            d: normal.dot(center),
+            onb,
+            mat,
+            b_box,
+        })
+    }
+
+    /// Surface area of the disc
+    pub(crate) fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+
+    /// Point on the disc for a point in the unit square,
+    /// where uniformly distributed points in the square are uniformly distributed on the disc
+    fn surface_point(&self, s: f64, t: f64) -> Vec3 {
+        let r = self.radius * s.sqrt();
+        let phi = 2. * PI * t;
+        self.center + self.onb.tangent * (r * phi.cos()) + self.onb.bi_tangent * (r * phi.sin())
+    }
+}
+
+impl Sampleable for Disc {
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let ray = Ray::new(origin, direction);
+
+        match self.hit(&ray, &RAY_INTERVAL) {
+            None => 0.,
+            Some(rec) => {
+                let distance_squared = rec.ray_length * rec.ray_length * direction.length_squared();
+                let cosine = (direction.dot(rec.normal) / direction.length()).abs();
+                distance_squared / (cosine * self.area())
+            }
+        }
+    }
+
+    fn random_direction(&self, origin: Vec3) -> Vec3 {
+        let (s, t) = next_2d();
+        self.surface_point(s, t) - origin
+    }
+}
+
+impl Hittable for Disc {
+    fn as_sampleable(&self) -> Option<&dyn Sampleable> {
+        Some(self)
+    }
+
+    fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
+        let normal = self.onb.normal;
+        let denom = normal.dot(r.direction);
+
+        // No hit if the ray is parallel to the disc
+        if denom.abs() < ALMOST_ZERO {
+            return None;
+        }
+
+        let t = (self.d - normal.dot(r.origin)) / denom;
+        if !ray_length.contains(t) {
+            return None;
+        }
+
+        let hit_point = r.at(t);
+        let planar_vector = hit_point - self.center;
+        if planar_vector.length_squared() > self.radius * self.radius {
+            return None;
+        }
+
+        let diameter = 2. * self.radius;
+        let uv = Uv::new(
+            (0.5 + planar_vector.dot(self.onb.tangent) / diameter) as f32,
+            (0.5 + planar_vector.dot(self.onb.bi_tangent) / diameter) as f32,
+        );
+        let uv_derivatives = UvDerivatives::new(
+            r,
+            hit_point,
+            normal,
+            self.onb.tangent * diameter,
+            self.onb.bi_tangent * diameter,
+        );
+
+        let front_face = denom < 0.;
+        Some(
+            RayHit::new(
+                hit_point,
+                Onb {
+                    normal: if front_face { normal } else { normal.neg() },
+                    ..self.onb.clone()
+                },
+                &self.mat,
+                t,
+                uv,
+                front_face,
+                r.time,
+            )
+            .with_uv_derivatives(uv_derivatives),
+        )
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.b_box
+    }
+
+    fn get_lights(&self) -> Vec<Hittables> {
+        if self.mat.is_light() {
+            vec![DiscType(self.clone())]
+        } else {
+            vec![]
+        }
+    }
+
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        let normal = self.onb.normal;
+        let projected = p - normal * (normal.dot(p) - self.d);
+        let planar_vector = projected - self.center;
+        if planar_vector.length_squared() <= self.radius * self.radius {
+            Some(projected)
+        } else {
+            Some(self.center + planar_vector.unit() * self.radius)
+        }
+    }
+
+    fn materials(&self) -> Vec<&Materials> {
+        vec![&self.mat]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::material::texture::SolidColor;
+    use crate::material::Lambertian;
+    use crate::util::chi_squared::chi_squared_test;
+
+    use super::*;
+
+    fn disc() -> Hittables {
+        Disc::new(
+            Vec3::new(0., 2., 0.),
+            Vec3::new(0., -1., 0.),
+            1.,
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+        )
+    }
+
+    #[test]
+    fn test_pdf_matches_random_direction() {
+        let disc = disc();
+        let light = disc.as_sampleable().unwrap();
+        let origin = Vec3::new(0.5, 0., 0.2);
+
+        let res = chi_squared_test(
+            || light.random_direction(origin),
+            |direction| light.pdf_value(origin, direction),
+        );
+        assert_eq!(res, Ok(()));
+    }
+
+    #[test]
+    fn test_hit() {
+        let disc = disc();
+        let ray = Ray::new(Vec3::new(0.5, 0., 0.5), Vec3::new(0., 1., 0.));
+        let rec = disc.hit(&ray, &RAY_INTERVAL).unwrap();
+        assert_eq!(rec.ray_length, 2.);
+        assert!(rec.front_face);
+
+        // Inside the bounding box, but outside the disc
+        let ray = Ray::new(Vec3::new(0.8, 0., 0.8), Vec3::new(0., 1., 0.));
+        assert!(disc.hit(&ray, &RAY_INTERVAL).is_none());
+
+        let closest = disc.closest_point(Vec3::new(3., 2., 0.)).unwrap();
+        assert!((closest - Vec3::new(1., 2., 0.)).length() < 1e-9);
+    }
+}
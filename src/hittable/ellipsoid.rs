@@ -0,0 +1,269 @@
+use crate::geo::transformation::Transformer;
+use crate::geo::vec3::Vec3;
+use crate::geo::{Aabb, Onb, Ray};
+use crate::geo::UvDerivatives;
+use crate::hittable::Hittables::{EllipsoidType, SphereType};
+use crate::hittable::{sphere_uv, sphere_uv_derivatives, Hittable, Hittables, Sampleable, Sphere};
+use crate::material::{Material, Materials, RayHit};
+use crate::util::interval::Interval;
+
+/// An ellipsoid shaped hittable object, that is a sphere scaled differently along its axes.
+/// The texture coordinates are those of the unscaled sphere
+#[derive(Clone, Debug)]
+pub struct Ellipsoid {
+    center: Vec3,
+    /// Axes of the ellipsoid, with the length of the radius along each axis
+    axes: [Vec3; 3],
+    /// Rows of the inverse of the matrix with the axes as columns
+    inverse_axes: [Vec3; 3],
+    /// Sphere around the ellipsoid, that directions towards it are sampled from
+    bounding_sphere: Sphere,
+    mat: Materials,
+    b_box: Aabb,
+}
+
+impl Ellipsoid {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a new ellipsoid with the given radii along the x, y and z axes.
+    /// The transformation moves the center and transforms the axes, so that the ellipsoid
+    /// can also be sheared, like with a [`crate::geo::transformation::Matrix4`]
+    pub fn new(
+        center: Vec3,
+        radii: Vec3,
+        mat: Materials,
+        transformation: &dyn Transformer,
+    ) -> Hittables {
+        let center = transformation.transform(center, false);
+        let axes = [
+            Vec3::new(radii.x, 0., 0.),
+            Vec3::new(0., radii.y, 0.),
+            Vec3::new(0., 0., radii.z),
+        ]
+        .map(|axis| transformation.transform(axis, true));
+        let [a, b, c] = axes;
+
+        let det = a.dot(b.cross(c));
+        let inverse_axes = [b.cross(c) / det, c.cross(a) / det, a.cross(b) / det];
+
+        // Extent of the ellipsoid along each of the scene axes
+        let extent = |axis: u8| {
+            let (a, b, c) = (a.axis(axis), b.axis(axis), c.axis(axis));
+            (a * a + b * b + c * c).sqrt()
+        };
+        let e = Vec3::new(extent(0), extent(1), extent(2));
+        let b_box = Aabb::new_from_2_points(center - e, center + e);
+
+        // With perpendicular axes the longest one is the radius, otherwise the half diagonal
+        // of the bounding box is used, which the ellipsoid is always within
+        let perpendicular = [a.dot(b), b.dot(c), c.dot(a)]
+            .iter()
+            .all(|d| d.abs() < 1e-9 * e.length_squared());
+        let radius = if perpendicular {
+            axes.iter().map(|axis| axis.length()).fold(0., f64::max)
+        } else {
+            e.length()
+        };
+        let bounding_sphere = match Sphere::new(center, radius, mat.clone()) {
+            SphereType(sphere) => sphere,
+            _ => unreachable!(),
+        };
+
+        Hittables::from(Ellipsoid {
+            center,
+            axes,
+            inverse_axes,
+            bounding_sphere,
+            mat,
+            b_box,
+        })
+    }
+
+    /// Vector in the space of the ellipsoid, where it is a unit sphere
+    fn unit_sphere_vector(&self, v: Vec3) -> Vec3 {
+        let [x, y, z] = &self.inverse_axes;
+        Vec3::new(x.dot(v), y.dot(v), z.dot(v))
+    }
+
+    /// Vector in the space of the scene, for a vector in the space of the unit sphere
+    fn scene_vector(&self, v: Vec3) -> Vec3 {
+        let [a, b, c] = &self.axes;
+        *a * v.x + *b * v.y + *c * v.z
+    }
+}
+
+impl Sampleable for Ellipsoid {
+    /// Directions are sampled towards the sphere around the ellipsoid,
+    /// so some of them miss the ellipsoid
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        self.bounding_sphere.pdf_value(origin, direction)
+    }
+
+    fn random_direction(&self, origin: Vec3) -> Vec3 {
+        self.bounding_sphere.random_direction(origin)
+    }
+}
+
+impl Hittable for Ellipsoid {
+    fn as_sampleable(&self) -> Option<&dyn Sampleable> {
+        Some(self)
+    }
+
+    fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
+        // Hit the unit sphere with the ray in the space of the ellipsoid,
+        // where the distances along the ray are the same
+        let oc = self.unit_sphere_vector(r.origin - self.center);
+        let direction = self.unit_sphere_vector(r.direction);
+        let a = direction.length_squared();
+        let half_b = oc.dot(direction);
+        let c = oc.length_squared() - 1.;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0. {
+            return None;
+        }
+        let sqrt_d = discriminant.sqrt();
+
+        let mut root = (-half_b - sqrt_d) / a;
+        if !ray_length.contains(root) {
+            root = (-half_b + sqrt_d) / a;
+            if !ray_length.contains(root) {
+                return None;
+            }
+        }
+
+        let hit_point = r.at(root);
+        let unit_normal = (oc + direction * root).unit();
+        let uv = sphere_uv(unit_normal);
+
+        // Normals are transformed by the inverse transpose of the axes
+        let [x, y, z] = &self.inverse_axes;
+        let mut normal = (*x * unit_normal.x + *y * unit_normal.y + *z * unit_normal.z).unit();
+
+        let pole = self.axes[1];
+        let tangent = pole.cross(normal);
+        let tangent = if tangent.near_zero() {
+            self.axes[0].unit()
+        } else {
+            tangent.unit()
+        };
+        let bi_tangent = normal.cross(tangent);
+
+        let (dp_du, dp_dv) = sphere_uv_derivatives(unit_normal, 1.);
+        let uv_derivatives = UvDerivatives::new(
+            r,
+            hit_point,
+            normal,
+            self.scene_vector(dp_du),
+            self.scene_vector(dp_dv),
+        );
+
+        let front_face = r.direction.dot(normal) < 0.;
+        if !front_face {
+            normal = normal.neg();
+        }
+        Some(
+            RayHit::new(
+                hit_point,
+                Onb {
+                    tangent,
+                    bi_tangent,
+                    normal,
+                },
+                &self.mat,
+                root,
+                uv,
+                front_face,
+                r.time,
+            )
+            .with_uv_derivatives(uv_derivatives),
+        )
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.b_box
+    }
+
+    fn get_lights(&self) -> Vec<Hittables> {
+        if self.mat.is_light() {
+            vec![EllipsoidType(self.clone())]
+        } else {
+            vec![]
+        }
+    }
+
+    /// The point on the ellipsoid in the direction of the point from the center,
+    /// which is only the closest point for spheres
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        let direction = self.unit_sphere_vector(p - self.center);
+        if direction.near_zero() {
+            return Some(self.center + self.axes[1]);
+        }
+        Some(self.center + self.scene_vector(direction.unit()))
+    }
+
+    fn materials(&self) -> Vec<&Materials> {
+        vec![&self.mat]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::transformation::{NopTransformer, RotationZ};
+    use crate::material::texture::SolidColor;
+    use crate::material::Lambertian;
+    use crate::util::chi_squared::chi_squared_test;
+    use crate::util::interval::RAY_INTERVAL;
+
+    use super::*;
+
+    fn ellipsoid(transformation: &dyn Transformer) -> Hittables {
+        Ellipsoid::new(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(2., 1., 1.),
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            transformation,
+        )
+    }
+
+    #[test]
+    fn test_hit() {
+        let ellipsoid = ellipsoid(&NopTransformer());
+        let ray = Ray::new(Vec3::new(5., 0., 0.), Vec3::new(-1., 0., 0.));
+        let rec = ellipsoid.hit(&ray, &RAY_INTERVAL).unwrap();
+        assert!((rec.hit_point - Vec3::new(2., 0., 0.)).length() < 1e-9);
+        assert!((rec.normal - Vec3::new(1., 0., 0.)).length() < 1e-9);
+
+        // The normal of a stretched sphere leans towards the short axis
+        let ray = Ray::new(Vec3::new(2f64.sqrt(), 5., 0.), Vec3::new(0., -1., 0.));
+        let rec = ellipsoid.hit(&ray, &RAY_INTERVAL).unwrap();
+        let expected = Vec3::new(2f64.sqrt() / 4., 1. / 2f64.sqrt(), 0.).unit();
+        assert!((rec.normal - expected).length() < 1e-9);
+
+        let ray = Ray::new(Vec3::new(0., 5., 1.1), Vec3::new(0., -1., 0.));
+        assert!(ellipsoid.hit(&ray, &RAY_INTERVAL).is_none());
+    }
+
+    #[test]
+    fn test_rotated() {
+        let ellipsoid = ellipsoid(&RotationZ::new(90.));
+        let b_box = ellipsoid.bounding_box();
+        assert!((b_box.y.max - 2.).abs() < 1e-9 && (b_box.x.max - 1.).abs() < 1e-9);
+
+        let ray = Ray::new(Vec3::new(0., 5., 0.), Vec3::new(0., -1., 0.));
+        let rec = ellipsoid.hit(&ray, &RAY_INTERVAL).unwrap();
+        assert!((rec.hit_point - Vec3::new(0., 2., 0.)).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_pdf_matches_random_direction() {
+        let ellipsoid = ellipsoid(&NopTransformer());
+        let light = ellipsoid.as_sampleable().unwrap();
+        let origin = Vec3::new(0.5, 3., 2.);
+
+        let res = chi_squared_test(
+            || light.random_direction(origin),
+            |direction| light.pdf_value(origin, direction),
+        );
+        assert_eq!(res, Ok(()));
+    }
+}
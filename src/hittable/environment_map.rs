@@ -0,0 +1,213 @@
+use std::error::Error;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use image::Rgb32FImage;
+use simple_error::SimpleError;
+
+use crate::geo::vec3::{Vec3, ZERO_VECTOR};
+use crate::geo::{Aabb, Ray};
+use crate::hittable::{sphere_uv, Hittable, Hittables, Sampleable};
+use crate::material::RayHit;
+use crate::random::random_normal_float;
+use crate::util::interval::Interval;
+use crate::util::rgb_color::luminance;
+
+/// An equirectangular high dynamic range image surrounding the scene, that is seen
+/// where rays do not hit anything in the world. The center of the image is towards
+/// positive x and the top of the image is towards positive y.
+/// Set it as the environment map of the [`crate::renderer::Scene`] and it is also
+/// importance sampled as a light, with the brighter parts of the image sampled more often
+#[derive(Clone, Debug)]
+pub struct EnvironmentMap {
+    image: Arc<Rgb32FImage>,
+    intensity: f64,
+    /// Cumulative sampling weights of the pixels, row by row
+    cumulative_weights: Arc<Vec<f64>>,
+    b_box: Aabb,
+}
+
+impl EnvironmentMap {
+    /// Creates an environment map from an image, where the colors are multiplied by the intensity
+    pub fn new(image: Rgb32FImage, intensity: f64) -> EnvironmentMap {
+        let (width, height) = image.dimensions();
+        let cumulative_weights = image
+            .enumerate_pixels()
+            .scan(0., |sum, (_, y, pixel)| {
+                // Rows close to the poles cover a smaller part of the sphere
+                let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+                let [r, g, b] = pixel.0.map(|c| c as f64);
+                *sum += luminance(Vec3::new(r, g, b)).max(0.) * sin_theta;
+                Some(*sum)
+            })
+            .collect::<Vec<f64>>();
+
+        // A completely black image is sampled uniformly
+        let cumulative_weights = if cumulative_weights.last().is_some_and(|w| *w > 0.) {
+            cumulative_weights
+        } else {
+            (1..=(width * height) as usize).map(|i| i as f64).collect()
+        };
+
+        EnvironmentMap {
+            image: Arc::new(image),
+            intensity,
+            cumulative_weights: Arc::new(cumulative_weights),
+            b_box: Aabb::new_from_2_points(ZERO_VECTOR, ZERO_VECTOR),
+        }
+    }
+
+    /// Loads an environment map from an image file, typically in the Radiance .hdr format
+    pub fn load(path: &str, intensity: f64) -> Result<EnvironmentMap, Box<dyn Error>> {
+        let image = image::open(path).map_err(|err| {
+            SimpleError::new(format!("failed to load environment map {}: {}", path, err))
+        })?;
+        Ok(EnvironmentMap::new(image.into_rgb32f(), intensity))
+    }
+
+    /// Color of the environment seen in the given direction
+    pub fn color(&self, direction: Vec3) -> Vec3 {
+        let (x, y) = self.pixel(direction);
+        let [r, g, b] = self.image.get_pixel(x, y).0.map(|c| c as f64);
+        Vec3::new(r, g, b) * self.intensity
+    }
+
+    /// Pixel of the image that is seen in the given direction
+    fn pixel(&self, direction: Vec3) -> (u32, u32) {
+        let (width, height) = self.image.dimensions();
+        let uv = sphere_uv(direction.unit());
+        let x = ((uv.u as f64 * width as f64) as u32).min(width - 1);
+        let y = (((1. - uv.v as f64) * height as f64) as u32).min(height - 1);
+        (x, y)
+    }
+
+    fn pixel_weight(&self, index: usize) -> f64 {
+        match index {
+            0 => self.cumulative_weights[0],
+            i => self.cumulative_weights[i] - self.cumulative_weights[i - 1],
+        }
+    }
+}
+
+impl Sampleable for EnvironmentMap {
+    fn pdf_value(&self, _: Vec3, direction: Vec3) -> f64 {
+        let direction = direction.unit();
+        let sin_theta = (1. - direction.y * direction.y).max(0.).sqrt();
+        if sin_theta == 0. {
+            return 0.;
+        }
+        let (width, height) = self.image.dimensions();
+        let (x, y) = self.pixel(direction);
+        let total_weight = self.cumulative_weights[self.cumulative_weights.len() - 1];
+        let pixel_probability = self.pixel_weight((y * width + x) as usize) / total_weight;
+        // A pixel covers the solid angle 2 * PI * PI * sin_theta / pixel_count
+        pixel_probability * (width * height) as f64 / (2. * PI * PI * sin_theta)
+    }
+
+    fn random_direction(&self, _: Vec3) -> Vec3 {
+        let (width, height) = self.image.dimensions();
+        let total_weight = self.cumulative_weights[self.cumulative_weights.len() - 1];
+        let weight = random_normal_float() * total_weight;
+        let index = self
+            .cumulative_weights
+            .partition_point(|w| *w <= weight)
+            .min(self.cumulative_weights.len() - 1);
+        let x = (index % width as usize) as f64 + random_normal_float();
+        let y = (index / width as usize) as f64 + random_normal_float();
+
+        // Inverse of the sphere texture coordinates
+        let phi = 2. * PI * x / width as f64;
+        let theta = PI * (1. - y / height as f64);
+        Vec3::new(
+            -theta.sin() * phi.cos(),
+            -theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+}
+
+impl Hittable for EnvironmentMap {
+    fn as_sampleable(&self) -> Option<&dyn Sampleable> {
+        Some(self)
+    }
+
+    /// The environment is infinitely far away, and is never hit
+    fn hit(&self, _: &Ray, _: &Interval) -> Option<RayHit> {
+        None
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.b_box
+    }
+
+    fn get_lights(&self) -> Vec<Hittables> {
+        vec![]
+    }
+
+    fn closest_point(&self, _: Vec3) -> Option<Vec3> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use crate::geo::vec3::{random_unit_vector, UNIT_Y};
+
+    use super::*;
+
+    /// Black with a single bright pixel
+    fn environment_map() -> EnvironmentMap {
+        let image = Rgb32FImage::from_fn(8, 4, |x, y| match (x, y) {
+            (5, 1) => Rgb([10., 8., 6.]),
+            _ => Rgb([0., 0., 0.]),
+        });
+        EnvironmentMap::new(image, 2.)
+    }
+
+    #[test]
+    fn test_color() {
+        let image = Rgb32FImage::from_fn(8, 4, |_, y| match y {
+            0 => Rgb([0.25, 0.5, 0.75]),
+            _ => Rgb([0., 0., 0.]),
+        });
+        let environment_map = EnvironmentMap::new(image, 2.);
+        assert_eq!(environment_map.color(UNIT_Y), Vec3::new(0.5, 1., 1.5));
+        assert_eq!(environment_map.color(UNIT_Y.neg()), ZERO_VECTOR);
+    }
+
+    #[test]
+    fn test_sampling_bright_pixel() {
+        let environment_map = environment_map();
+        for _ in 0..100 {
+            let direction = environment_map.random_direction(ZERO_VECTOR);
+            assert!((direction.length() - 1.).abs() < 1e-9);
+            assert_eq!(environment_map.pixel(direction), (5, 1));
+            assert!(environment_map.pdf_value(ZERO_VECTOR, direction) > 0.);
+        }
+        assert_eq!(environment_map.pdf_value(ZERO_VECTOR, UNIT_Y.neg()), 0.);
+    }
+
+    #[test]
+    fn test_pdf_integrates_to_one() {
+        let environment_map = EnvironmentMap::new(
+            Rgb32FImage::from_fn(16, 8, |x, y| Rgb([x as f32, y as f32, 1.])),
+            1.,
+        );
+        let samples = 200_000;
+        let sum: f64 = (0..samples)
+            .map(|_| environment_map.pdf_value(ZERO_VECTOR, random_unit_vector()))
+            .sum();
+        // Uniformly distributed directions have the pdf 1 / (4 * PI)
+        let integral = sum / samples as f64 * 4. * PI;
+        assert!((integral - 1.).abs() < 0.02, "integral was {}", integral);
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let res = EnvironmentMap::load("resources/textures/missing.hdr", 1.);
+        assert!(format!("{}", res.err().unwrap())
+            .starts_with("failed to load environment map resources/textures/missing.hdr"));
+    }
+}
@@ -0,0 +1,245 @@
+use std::sync::Arc;
+
+use crate::geo::transformation::{Affine, Matrix4, Transformer};
+use crate::geo::vec3::Vec3;
+use crate::geo::{Aabb, Onb, Ray, RayDifferentials};
+use crate::hittable::Hittables::InstanceType;
+use crate::hittable::{Hittable, Hittables, Sampleable};
+use crate::material::RayHit;
+use crate::util::interval::Interval;
+
+/// A transformed copy of a shared hittable. The hittable, like a mesh with its bvh,
+/// is only kept once in memory however many instances of it there are.
+/// Lights in the hittable are sampled correctly for transformations made of
+/// translations, rotations and uniform scaling
+#[derive(Clone, Debug)]
+pub struct Instance {
+    hittable: Arc<Hittables>,
+    transformation: Affine,
+    b_box: Aabb,
+}
+
+impl Instance {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates an instance of the hittable, transformed by the transformation
+    pub fn new(hittable: Arc<Hittables>, transformation: &dyn Transformer) -> Hittables {
+        Hittables::from(Instance::new_instance(hittable, Affine::new(transformation)))
+    }
+
+    fn new_instance(hittable: Arc<Hittables>, transformation: Affine) -> Instance {
+        let b = hittable.bounding_box();
+        let corners = [b.x.min, b.x.max].into_iter().flat_map(|x| {
+            [b.y.min, b.y.max]
+                .into_iter()
+                .flat_map(move |y| [b.z.min, b.z.max].map(|z| Vec3::new(x, y, z)))
+        });
+        let b_box = corners
+            .map(|corner| transformation.point(corner))
+            .map(|p| Aabb::new_from_2_points(p, p))
+            .reduce(|a, b| a.combine(&b))
+            .unwrap_or_default()
+            .pad_if_needed();
+
+        Instance {
+            hittable,
+            transformation,
+            b_box,
+        }
+    }
+
+    /// The transformation of the instance, from the space of the hittable to the scene
+    pub fn transformation(&self) -> Matrix4 {
+        self.transformation.matrix()
+    }
+
+    /// The ray in the space of the hittable, where distances along it are the same
+    fn inverse_ray(&self, r: &Ray) -> Ray {
+        let t = &self.transformation;
+        let ray = Ray::new_at_time(
+            t.inverse_point(r.origin),
+            t.inverse_vector(r.direction),
+            r.time,
+        );
+        match &r.differentials {
+            Some(d) => ray.with_differentials(RayDifferentials {
+                dx_origin: t.inverse_point(d.dx_origin),
+                dx_direction: t.inverse_vector(d.dx_direction),
+                dy_origin: t.inverse_point(d.dy_origin),
+                dy_direction: t.inverse_vector(d.dy_direction),
+            }),
+            None => ray,
+        }
+    }
+}
+
+impl Sampleable for Instance {
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        match self.hittable.as_sampleable() {
+            Some(light) => light.pdf_value(
+                self.transformation.inverse_point(origin),
+                self.transformation.inverse_vector(direction),
+            ),
+            None => 0.,
+        }
+    }
+
+    fn random_direction(&self, origin: Vec3) -> Vec3 {
+        match self.hittable.as_sampleable() {
+            Some(light) => self.transformation.vector(
+                light.random_direction(self.transformation.inverse_point(origin)),
+            ),
+            None => Vec3::new(0., 0., 0.),
+        }
+    }
+}
+
+impl Hittable for Instance {
+    fn as_sampleable(&self) -> Option<&dyn Sampleable> {
+        self.hittable.as_sampleable().map(|_| self as &dyn Sampleable)
+    }
+
+    fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
+        let mut rec = self.hittable.hit(&self.inverse_ray(r), ray_length)?;
+        let t = &self.transformation;
+
+        rec.hit_point = t.point(rec.hit_point);
+        rec.normal = t.normal(rec.normal);
+
+        let normal = t.normal(rec.onb.normal);
+        let tangent = t.vector(rec.onb.tangent).unit();
+        let bi_tangent = normal.cross(tangent);
+        // Mirroring transformations flip the handedness of the tangent space
+        let bi_tangent = if bi_tangent.dot(t.vector(rec.onb.bi_tangent)) < 0. {
+            bi_tangent.neg()
+        } else {
+            bi_tangent
+        };
+        rec.onb = Onb {
+            tangent,
+            bi_tangent,
+            normal,
+        };
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.b_box
+    }
+
+    fn get_lights(&self) -> Vec<Hittables> {
+        self.hittable
+            .get_lights()
+            .into_iter()
+            .map(|light| {
+                InstanceType(Instance::new_instance(
+                    Arc::new(light),
+                    self.transformation.clone(),
+                ))
+            })
+            .collect()
+    }
+
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        let t = &self.transformation;
+        self.hittable
+            .closest_point(t.inverse_point(p))
+            .map(|closest| t.point(closest))
+    }
+
+    fn children(&self) -> Vec<&Hittables> {
+        vec![&self.hittable]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::transformation::{
+        Matrix4, NopTransformer, RotationY, Scale, Transformations, Translation,
+    };
+    use crate::hittable::{Ellipsoid, Quad, Sphere};
+    use crate::material::texture::SolidColor;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::util::chi_squared::chi_squared_test;
+    use crate::util::interval::RAY_INTERVAL;
+
+    use super::*;
+
+    fn transformation() -> Transformations {
+        Transformations::new(vec![
+            Box::new(Scale::new(2.)),
+            Box::new(RotationY::new(90.)),
+            Box::new(Translation::new(Vec3::new(5., 0., 0.))),
+        ])
+    }
+
+    #[test]
+    fn test_hit() {
+        let sphere = Arc::new(Sphere::new(
+            Vec3::new(1., 0., 0.),
+            1.,
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+        ));
+        let instances = [
+            Instance::new(sphere.clone(), &transformation()),
+            Instance::new(sphere.clone(), &Translation::new(Vec3::new(0., 10., 0.))),
+        ];
+        assert_eq!(Arc::strong_count(&sphere), 3);
+
+        // The sphere is scaled to radius 2 and moved to 5, 0, -2
+        let ray = Ray::new(Vec3::new(5., 10., -2.), Vec3::new(0., -1., 0.));
+        let rec = instances[0].hit(&ray, &RAY_INTERVAL).unwrap();
+        assert!((rec.hit_point - Vec3::new(5., 2., -2.)).length() < 1e-9);
+        assert!((rec.normal - Vec3::new(0., 1., 0.)).length() < 1e-9);
+        assert!((rec.ray_length - 8.).abs() < 1e-9);
+
+        let b_box = instances[0].bounding_box();
+        assert!((b_box.center() - Vec3::new(5., 0., -2.)).length() < 1e-9);
+
+        let rec = instances[1].hit(&ray, &RAY_INTERVAL);
+        assert!(rec.is_none());
+        let closest = instances[1].closest_point(Vec3::new(1., 0., 0.)).unwrap();
+        assert!((closest - Vec3::new(1., 9., 0.)).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_non_uniform_scale() {
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let matrix = Matrix4::rotation(Vec3::new(1., 1., 0.), 30.)
+            .then(&Matrix4::scale(Vec3::new(3., 1., 0.5)))
+            .then(&Matrix4::translation(Vec3::new(0., 1., 0.)));
+        let instance = Instance::new(
+            Arc::new(Sphere::new(Vec3::new(0., 0., 0.), 1., mat.clone())),
+            &matrix,
+        );
+        let ellipsoid = Ellipsoid::new(Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.), mat, &matrix);
+
+        let ray = Ray::new(Vec3::new(0.5, 5., 0.2), Vec3::new(0., -1., 0.));
+        let rec = instance.hit(&ray, &RAY_INTERVAL).unwrap();
+        let expected = ellipsoid.hit(&ray, &RAY_INTERVAL).unwrap();
+        assert!((rec.hit_point - expected.hit_point).length() < 1e-9);
+        assert!((rec.normal - expected.normal).length() < 1e-9);
+        assert!(rec.onb.tangent.dot(rec.normal).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pdf_matches_random_direction() {
+        let quad = Arc::new(Quad::new(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 0., 1.),
+            DiffuseLight::new(1., 1., 1., None),
+            &NopTransformer(),
+        ));
+        let instance = Instance::new(quad, &transformation());
+        let lights = instance.get_lights();
+        assert_eq!(lights.len(), 1);
+
+        let light = lights[0].as_sampleable().unwrap();
+        let origin = Vec3::new(5.8, -0.6, -1.2);
+        let res = chi_squared_test(
+            || light.random_direction(origin),
+            |direction| light.pdf_value(origin, direction),
+        );
+        assert_eq!(res, Ok(()));
+    }
+}
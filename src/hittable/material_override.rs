@@ -0,0 +1,137 @@
+use crate::geo::vec3::Vec3;
+use crate::geo::Aabb;
+use crate::geo::Ray;
+use crate::hittable::{Hittable, Hittables};
+use crate::material::{Material, MaterialOverrides, RayHit};
+use crate::util::interval::Interval;
+
+/// Applies material overrides to all hits on the wrapped hittable, so several copies
+/// of the same model with the same materials can each get their own look
+#[derive(Clone, Debug)]
+pub struct MaterialOverride {
+    hittable: Box<Hittables>,
+    overrides: MaterialOverrides,
+}
+
+impl MaterialOverride {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a hittable that applies the overrides to the materials of the given hittable
+    pub fn new(hittable: Hittables, overrides: MaterialOverrides) -> Hittables {
+        Hittables::from(MaterialOverride {
+            hittable: Box::new(hittable),
+            overrides,
+        })
+    }
+}
+
+impl Hittable for MaterialOverride {
+    fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
+        let mut rec = self.hittable.hit(r, ray_length)?;
+        if self.overrides.uv_offset != Default::default() {
+            rec.uv = rec.uv + self.overrides.uv_offset;
+            rec.normal = rec
+                .material
+                .get_transformed_normal(rec.onb.clone(), rec.uv, rec.time);
+        }
+        rec.overrides = rec.overrides.combine(&self.overrides);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.hittable.bounding_box()
+    }
+
+    fn get_lights(&self) -> Vec<Hittables> {
+        self.hittable.get_lights()
+    }
+
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        self.hittable.closest_point(p)
+    }
+
+    fn children(&self) -> Vec<&Hittables> {
+        vec![&self.hittable]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::transformation::NopTransformer;
+    use crate::geo::vec3::{Vec3, ZERO_VECTOR};
+    use crate::geo::Uv;
+    use crate::hittable::{Quad, Sphere};
+    use crate::material::texture::{ObjectId, SolidColor, Texture};
+    use crate::material::{DiffuseLight, Lambertian, Metal, RayScatter};
+    use crate::util::interval::RAY_INTERVAL;
+
+    use super::*;
+
+    #[test]
+    fn test_overrides() {
+        let quad = |material| {
+            Quad::new(
+                Vec3::new(-1., -1., 0.),
+                Vec3::new(2., 0., 0.),
+                Vec3::new(0., 2., 0.),
+                material,
+                &NopTransformer(),
+            )
+        };
+        let overrides = MaterialOverrides {
+            tint: Vec3::new(1., 0.5, 0.25),
+            roughness_multiplier: 0.,
+            uv_offset: Uv::new(0.25, 0.),
+            ..MaterialOverrides::default()
+        };
+        let ray = Ray::new(Vec3::new(0., 0., 1.), Vec3::new(0.5, 0.5, -1.));
+
+        let lambertian = MaterialOverride::new(
+            quad(Lambertian::new(SolidColor::new(0.8, 0.8, 0.8), None)),
+            overrides,
+        );
+        let rec = lambertian.hit(&ray, &RAY_INTERVAL).unwrap();
+        assert_eq!(rec.uv, Uv::new(1., 0.75));
+        let lights = [Sphere::new(Vec3::new(0., 0., 5.), 1., DiffuseLight::new(1., 1., 1., None))];
+        match rec.material.scatter(&ray, &rec, &lights) {
+            RayScatter::ScatterPdf(s) => assert_eq!(s.color, Vec3::new(0.8, 0.4, 0.2)),
+            _ => panic!("Lambertian should scatter by pdf"),
+        }
+
+        // Metal with its roughness removed reflects perfectly
+        let metal = MaterialOverride::new(
+            quad(Metal::new(SolidColor::new(1., 1., 1.), None, 1.)),
+            overrides,
+        );
+        let rec = metal.hit(&ray, &RAY_INTERVAL).unwrap();
+        match rec.material.scatter(&ray, &rec, &[]) {
+            RayScatter::ScatterBasic(s) => {
+                assert!((s.ray.direction - Vec3::new(0.5, 0.5, 1.).unit()).near_zero())
+            }
+            _ => panic!("Metal should scatter basic"),
+        }
+    }
+
+    #[test]
+    fn test_object_id() {
+        let texture = ObjectId::new();
+        let ray = Ray::new(Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.));
+        let color_of_id = |object_id| {
+            let overrides = MaterialOverrides {
+                object_id,
+                ..MaterialOverrides::default()
+            };
+            let sphere = Sphere::new(
+                ZERO_VECTOR,
+                0.5,
+                Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            );
+            let hittable = MaterialOverride::new(sphere, overrides);
+            let rec = hittable.hit(&ray, &RAY_INTERVAL).unwrap();
+            rec.texture_color(&texture)
+        };
+
+        assert_eq!(color_of_id(1), color_of_id(1));
+        assert_ne!(color_of_id(1), color_of_id(2));
+        assert_eq!(color_of_id(0), texture.color(Uv::default(), 0.));
+    }
+}
@@ -2,36 +2,71 @@
 //! Some of these hittable objects are containers for other objects
 
 mod bvh;
+mod clip;
 mod constant_medium;
+mod decal;
+mod directional_light;
+mod disc;
+mod ellipsoid;
+mod environment_map;
+mod instance;
+mod material_override;
+mod plane;
+mod point_light;
 mod quad;
+mod room;
 mod sphere;
 mod triangle;
+mod triangle_mesh;
+mod visitor;
 
 use crate::geo::vec3::Vec3;
 use crate::geo::Aabb;
 use crate::geo::Ray;
-pub use crate::hittable::bvh::Bvh;
+pub use crate::hittable::bvh::{Bvh, BvhQuality};
+pub use crate::hittable::clip::{ClipPlane, Clipped};
 pub use crate::hittable::constant_medium::ConstantMedium;
-pub use crate::hittable::quad::Quad;
+pub use crate::hittable::decal::Decal;
+pub use crate::hittable::directional_light::DirectionalLight;
+pub use crate::hittable::disc::Disc;
+pub use crate::hittable::ellipsoid::Ellipsoid;
+pub use crate::hittable::environment_map::EnvironmentMap;
+pub use crate::hittable::instance::Instance;
+pub use crate::hittable::material_override::MaterialOverride;
+pub use crate::hittable::plane::Plane;
+pub use crate::hittable::point_light::PointLight;
+pub use crate::hittable::quad::{BoxFace, BoxFaces, Quad};
+pub use crate::hittable::room::{Room, RoomOpening, RoomWall};
 pub use crate::hittable::sphere::Sphere;
-pub use crate::hittable::triangle::Triangle;
-use crate::hittable::Hittables::{BvhType, ConstantMediumType, QuadType, SphereType, TriangleType};
-use crate::material::RayHit;
-use crate::util::interval::Interval;
+pub(crate) use crate::hittable::sphere::{sphere_uv, sphere_uv_derivatives};
+pub use crate::hittable::triangle::{Triangle, TriangleOptions};
+pub use crate::hittable::triangle_mesh::{TriangleMesh, TriangleMeshOptions};
+pub use crate::hittable::visitor::{visit, HittableVisitor};
+use crate::hittable::Hittables::{
+    BvhType, ClippedType, ConstantMediumType, DecalType, DirectionalLightType, DiscType,
+    EllipsoidType, EnvironmentMapType, InstanceType, MaterialOverrideType, PlaneType, QuadType,
+    SphereType, TriangleMeshType, TriangleType,
+};
+use crate::material::{Materials, RayHit};
+use crate::util::interval::{Interval, RAY_INTERVAL};
 use enum_dispatch::enum_dispatch;
 
+/// Hittables that directions towards can be sampled for, which is needed to be used as lights
+pub trait Sampleable {
+    /// Return the pdf value for the hittable given the origin and direction of the ray that hits
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64;
+
+    /// Generate a random direction from the given origin towards a point on the hittable
+    fn random_direction(&self, origin: Vec3) -> Vec3;
+}
+
 /// The common trait for all objects in the ray tracing scene
 /// that can be hit by rays
 #[enum_dispatch]
 pub trait Hittable {
-    /// Return the pdf value for the hittable given the origin and direction of the ray that hits
-    fn pdf_value(&self, _origin: Vec3, _direction: Vec3) -> f64 {
-        panic!("Should not be used for materials that can not be lights")
-    }
-
-    /// Generate a random direction from the given point on the hittable
-    fn random_direction(&self, _origin: Vec3) -> Vec3 {
-        panic!("Should not be used for materials that can not be lights")
+    /// Returns the hittable as [`Sampleable`] if it can be used as a light
+    fn as_sampleable(&self) -> Option<&dyn Sampleable> {
+        None
     }
 
     /// Check if the given ray hits the hittable within the interval
@@ -42,6 +77,37 @@ pub trait Hittable {
 
     /// Is the hittable a light? Or does it contain any lights?
     fn get_lights(&self) -> Vec<Hittables>;
+
+    /// Returns the point on the surface of the hittable that is closest to the given point,
+    /// or none if there is no surface. Volumes return the closest point on their boundary
+    fn closest_point(&self, p: Vec3) -> Option<Vec3>;
+
+    /// The hittables directly contained in this one, like the leaves of a [`Bvh`]
+    /// or the wrapped hittable of an [`Instance`]. See [`visit`] for walking the whole tree
+    fn children(&self) -> Vec<&Hittables> {
+        vec![]
+    }
+
+    /// The materials of the hittable itself, not including those of its children
+    fn materials(&self) -> Vec<&Materials> {
+        vec![]
+    }
+
+    /// Distance from the given point to the closest point on the surface of the hittable
+    fn distance(&self, p: Vec3) -> Option<f64> {
+        self.closest_point(p).map(|c| (c - p).length())
+    }
+
+    /// Checks that there is nothing of the hittable in between the two points
+    fn is_visible(&self, a: Vec3, b: Vec3) -> bool {
+        let d = b - a;
+        let length = d.length();
+        if length <= 2. * RAY_INTERVAL.min {
+            return true;
+        }
+        let ray_length = Interval::new(RAY_INTERVAL.min, length - RAY_INTERVAL.min);
+        self.hit(&Ray::new(a, d / length), &ray_length).is_none()
+    }
 }
 
 #[enum_dispatch(Hittable)]
@@ -58,6 +124,26 @@ pub enum Hittables {
     TriangleType(Triangle),
     /// [`Hittable`] of the type [`Bvh`]
     BvhType(Bvh),
+    /// [`Hittable`] of the type [`MaterialOverride`]
+    MaterialOverrideType(MaterialOverride),
+    /// [`Hittable`] of the type [`Decal`]
+    DecalType(Decal),
+    /// [`Hittable`] of the type [`Clipped`]
+    ClippedType(Clipped),
+    /// [`Hittable`] of the type [`EnvironmentMap`]
+    EnvironmentMapType(EnvironmentMap),
+    /// [`Hittable`] of the type [`Plane`]
+    PlaneType(Plane),
+    /// [`Hittable`] of the type [`Disc`]
+    DiscType(Disc),
+    /// [`Hittable`] of the type [`Ellipsoid`]
+    EllipsoidType(Ellipsoid),
+    /// [`Hittable`] of the type [`Instance`]
+    InstanceType(Instance),
+    /// [`Hittable`] of the type [`TriangleMesh`]
+    TriangleMeshType(TriangleMesh),
+    /// [`Hittable`] of the type [`DirectionalLight`]
+    DirectionalLightType(DirectionalLight),
 }
 
 impl Clone for Hittables {
@@ -68,6 +154,43 @@ impl Clone for Hittables {
             QuadType(h) => QuadType(h.clone()),
             TriangleType(h) => TriangleType(h.clone()),
             BvhType(h) => BvhType(h.clone()),
+            MaterialOverrideType(h) => MaterialOverrideType(h.clone()),
+            DecalType(h) => DecalType(h.clone()),
+            ClippedType(h) => ClippedType(h.clone()),
+            EnvironmentMapType(h) => EnvironmentMapType(h.clone()),
+            PlaneType(h) => PlaneType(h.clone()),
+            DiscType(h) => DiscType(h.clone()),
+            EllipsoidType(h) => EllipsoidType(h.clone()),
+            InstanceType(h) => InstanceType(h.clone()),
+            TriangleMeshType(h) => TriangleMeshType(h.clone()),
+            DirectionalLightType(h) => DirectionalLightType(h.clone()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::material::texture::SolidColor;
+    use crate::material::{DiffuseLight, Lambertian};
+
+    use super::*;
+
+    #[test]
+    fn test_as_sampleable() {
+        let sphere = Sphere::new(Vec3::new(0., 0., 0.), 1., DiffuseLight::new(1., 1., 1., None));
+        assert!(sphere.as_sampleable().is_some());
+
+        let medium = ConstantMedium::new(sphere.clone(), 0.1, Vec3::new(1., 1., 1.));
+        assert!(medium.as_sampleable().is_none());
+
+        let bvh = Bvh::new(vec![
+            sphere,
+            Sphere::new(
+                Vec3::new(2., 0., 0.),
+                1.,
+                Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            ),
+        ]);
+        assert!(bvh.as_sampleable().is_none());
+    }
+}
@@ -0,0 +1,169 @@
+use crate::geo::vec3::{Vec3, ALMOST_ZERO};
+use crate::geo::{Aabb, Onb, Ray};
+use crate::geo::{Uv, UvDerivatives};
+use crate::hittable::Hittables::PlaneType;
+use crate::hittable::{Hittable, Hittables};
+use crate::material::{Material, Materials, RayHit};
+use crate::util::interval::Interval;
+
+/// Bounds of the plane along the axes that it extends infinitely along.
+/// Kept finite so the center of the bounding box is defined
+const UNBOUNDED: Interval = Interval {
+    min: f64::MIN,
+    max: f64::MAX,
+};
+
+/// An infinite flat hittable object, like a ground plane.
+/// The texture coordinates are in scene units along the plane, so textures repeat every unit
+#[derive(Clone, Debug)]
+pub struct Plane {
+    point: Vec3,
+    onb: Onb,
+    d: f64,
+    mat: Materials,
+    b_box: Aabb,
+}
+
+impl Plane {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a new plane through the point, facing the direction of the normal
+    pub fn new(point: Vec3, normal: Vec3, mat: Materials) -> Hittables {
+        let onb = Onb::new(normal);
+        let normal = onb.normal;
+
+        // Only a plane facing along an axis is bounded along that axis
+        let bounds = |n: f64, p: f64| {
+            if n.abs() == 1. {
+                Interval::new(p, p)
+            } else {
+                UNBOUNDED
+            }
+        };
+        let b_box = Aabb {
+            x: bounds(normal.x, point.x),
+            y: bounds(normal.y, point.y),
+            z: bounds(normal.z, point.z),
+        }
+        .pad_if_needed();
+
+        Hittables::from(Plane {
+            point,
+            d: normal.dot(point),
+            onb,
+            mat,
+            b_box,
+        })
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
+        let normal = self.onb.normal;
+        let denom = normal.dot(r.direction);
+
+        // No hit if the ray is parallel to the plane
+        if denom.abs() < ALMOST_ZERO {
+            return None;
+        }
+
+        let t = (self.d - normal.dot(r.origin)) / denom;
+        if !ray_length.contains(t) {
+            return None;
+        }
+
+        let hit_point = r.at(t);
+        let planar_vector = hit_point - self.point;
+        let uv = Uv::new(
+            planar_vector.dot(self.onb.tangent) as f32,
+            planar_vector.dot(self.onb.bi_tangent) as f32,
+        );
+        let uv_derivatives =
+            UvDerivatives::new(r, hit_point, normal, self.onb.tangent, self.onb.bi_tangent);
+
+        let front_face = denom < 0.;
+        Some(
+            RayHit::new(
+                hit_point,
+                Onb {
+                    normal: if front_face { normal } else { normal.neg() },
+                    ..self.onb.clone()
+                },
+                &self.mat,
+                t,
+                uv,
+                front_face,
+                r.time,
+            )
+            .with_uv_derivatives(uv_derivatives),
+        )
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.b_box
+    }
+
+    fn get_lights(&self) -> Vec<Hittables> {
+        // An emissive plane is returned even though it can not be sampled,
+        // so the renderer can report it
+        if self.mat.is_light() {
+            vec![PlaneType(self.clone())]
+        } else {
+            vec![]
+        }
+    }
+
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        let normal = self.onb.normal;
+        Some(p - normal * (normal.dot(p) - self.d))
+    }
+
+    fn materials(&self) -> Vec<&Materials> {
+        vec![&self.mat]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::material::texture::SolidColor;
+    use crate::material::Lambertian;
+    use crate::util::interval::RAY_INTERVAL;
+
+    use super::*;
+
+    fn ground() -> Hittables {
+        Plane::new(
+            Vec3::new(0., -1., 0.),
+            Vec3::new(0., 1., 0.),
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+        )
+    }
+
+    #[test]
+    fn test_hit_far_away() {
+        let ground = ground();
+        let ray = Ray::new(Vec3::new(1000., 5., -2000.), Vec3::new(1., -1., 0.));
+        let rec = ground.hit(&ray, &RAY_INTERVAL).unwrap();
+        assert!((rec.hit_point - Vec3::new(1006., -1., -2000.)).length() < 1e-9);
+        assert!(rec.front_face);
+
+        let ray = Ray::new(Vec3::new(0., 5., 0.), Vec3::new(1., 0., 0.));
+        assert!(ground.hit(&ray, &RAY_INTERVAL).is_none());
+
+        assert!(ground.bounding_box().hit(&Ray::new(
+            Vec3::new(1e9, 5., 1e9),
+            Vec3::new(0., -1., 0.)
+        )));
+    }
+
+    #[test]
+    fn test_closest_point() {
+        let plane = Plane::new(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 1., 0.),
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+        );
+        let closest = plane.closest_point(Vec3::new(2., 2., 3.)).unwrap();
+        assert!((closest - Vec3::new(0., 0., 3.)).length() < 1e-9);
+        assert!(plane.bounding_box().center().x.is_finite());
+    }
+}
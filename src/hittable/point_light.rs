@@ -0,0 +1,75 @@
+use std::f64::consts::PI;
+
+use crate::geo::vec3::Vec3;
+use crate::hittable::{Hittables, Sphere};
+use crate::material::DiffuseLight;
+
+/// Smallest radius of a point light, as the light must have some size to be hit by rays
+const MIN_RADIUS: f64 = 1e-6;
+
+/// A light that shines evenly in all directions from a point, like a light bulb.
+/// It is a small light emitting [`Sphere`], whose brightness is given by its intensity
+/// instead of its surface, so it lights the scene the same whatever its radius.
+/// The sphere is sampled as a light, so scenes lit by point lights converge quickly
+pub struct PointLight();
+
+impl PointLight {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a new point light
+    ///
+    /// # Arguments
+    /// * `position` - Center of the light
+    /// * `color` - Intensity of the light, which is the irradiance on a surface
+    ///   facing the light one unit away from it
+    /// * `radius` - Radius of the sphere of the light, where larger lights give softer shadows
+    pub fn new(position: Vec3, color: Vec3, radius: f64) -> Hittables {
+        let radius = radius.max(MIN_RADIUS);
+        // Radiance of a sphere with the intensity, seen as a disc of the radius
+        let radiance = color / (PI * radius * radius);
+        Sphere::new(
+            position,
+            radius,
+            DiffuseLight::new(radiance.x, radiance.y, radiance.z, None),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::vec3::ZERO_VECTOR;
+    use crate::geo::Ray;
+    use crate::hittable::Hittable;
+    use crate::material::{Material, RayScatter};
+    use crate::util::interval::RAY_INTERVAL;
+
+    use super::*;
+
+    #[test]
+    fn test_irradiance_is_independent_of_radius() {
+        for radius in [0.01, 0.1] {
+            let light = PointLight::new(Vec3::new(0., 2., 0.), Vec3::new(4., 4., 4.), radius);
+            let sampleable = light.as_sampleable().unwrap();
+            // Irradiance on a surface facing up, from the radiance of sampled directions
+            let samples = 10000;
+            let irradiance = (0..samples)
+                .map(|_| {
+                    let direction = sampleable.random_direction(ZERO_VECTOR).unit();
+                    let ray = Ray::new(ZERO_VECTOR, direction);
+                    let rec = light.hit(&ray, &RAY_INTERVAL).unwrap();
+                    let radiance = match rec.material.scatter(&ray, &rec, &[]) {
+                        RayScatter::ScatterEmission(s) => s.color.x,
+                        _ => panic!("Expected emission"),
+                    };
+                    radiance * direction.y / sampleable.pdf_value(ZERO_VECTOR, direction)
+                })
+                .sum::<f64>()
+                / samples as f64;
+            // Falls off with the square of the distance from the intensity
+            assert!(
+                (irradiance - 1.).abs() < 0.01,
+                "irradiance was {}",
+                irradiance
+            );
+        }
+    }
+}
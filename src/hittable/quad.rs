@@ -4,12 +4,12 @@ use crate::combine_aabbs;
 use crate::geo::{Aabb, Onb};
 use crate::geo::Ray;
 use crate::geo::transformation::Transformer;
-use crate::geo::Uv;
+use crate::geo::{Uv, UvDerivatives};
 use crate::geo::vec3::{ALMOST_ZERO, Vec3};
-use crate::hittable::{Hittable, Hittables};
+use crate::hittable::{Hittable, Hittables, Sampleable};
 use crate::hittable::Hittables::QuadType;
-use crate::material::{Material, Materials, RayHit};
-use crate::random::random_normal_float;
+use crate::material::{is_cut_out, Material, Materials, RayHit};
+use crate::sampler::next_2d;
 use crate::util::interval::{Interval, RAY_INTERVAL};
 
 const ZERO_TO_ONE: RangeInclusive<f32> = 0. ..= 1.;
@@ -26,6 +26,59 @@ pub struct Quad {
     mat: Materials,
     b_box: Aabb,
     area: f64,
+    uv_offset: Uv,
+    uv_scale: Uv,
+}
+
+/// Material and texture coordinate scaling of one face of a box
+#[derive(Clone, Debug)]
+pub struct BoxFace {
+    /// Material of the face
+    pub material: Materials,
+    /// Scaling of the texture coordinates, where values above 1 repeats the texture
+    pub uv_scale: Uv,
+}
+
+impl BoxFace {
+    /// Creates a box face with the given material and unscaled texture coordinates
+    pub fn new(material: Materials) -> BoxFace {
+        BoxFace {
+            material,
+            uv_scale: Uv::new(1., 1.),
+        }
+    }
+}
+
+/// The faces of an axis aligned box, named as seen when looking towards negative z
+#[derive(Clone, Debug)]
+pub struct BoxFaces {
+    /// Face at max z
+    pub front: BoxFace,
+    /// Face at max x
+    pub right: BoxFace,
+    /// Face at min z
+    pub back: BoxFace,
+    /// Face at min x
+    pub left: BoxFace,
+    /// Face at max y
+    pub top: BoxFace,
+    /// Face at min y
+    pub bottom: BoxFace,
+}
+
+impl BoxFaces {
+    /// Creates box faces that all have the same material
+    pub fn all(material: Materials) -> BoxFaces {
+        let face = BoxFace::new(material);
+        BoxFaces {
+            front: face.clone(),
+            right: face.clone(),
+            back: face.clone(),
+            left: face.clone(),
+            top: face.clone(),
+            bottom: face,
+        }
+    }
 }
 
 impl Quad {
@@ -37,6 +90,32 @@ impl Quad {
         v: Vec3,
         mat: Materials,
         transformation: &dyn Transformer,
+    ) -> Hittables {
+        Quad::new_with_uv_scale(q, u, v, mat, Uv::new(1., 1.), transformation)
+    }
+
+    /// Creates a new quad where the texture coordinates are scaled,
+    /// so values above 1 repeats the texture over the quad
+    pub fn new_with_uv_scale(
+        q: Vec3,
+        u: Vec3,
+        v: Vec3,
+        mat: Materials,
+        uv_scale: Uv,
+        transformation: &dyn Transformer,
+    ) -> Hittables {
+        Quad::new_with_uv_transform(q, u, v, mat, Uv::default(), uv_scale, transformation)
+    }
+
+    /// Creates a new quad where the texture coordinates are scaled and then offset
+    pub(crate) fn new_with_uv_transform(
+        q: Vec3,
+        u: Vec3,
+        v: Vec3,
+        mat: Materials,
+        uv_offset: Uv,
+        uv_scale: Uv,
+        transformation: &dyn Transformer,
     ) -> Hittables {
         let q = transformation.transform(q, false);
         let u = transformation.transform(u, true);
@@ -62,6 +141,8 @@ impl Quad {
             mat,
             b_box,
             area: n.length(),
+            uv_offset,
+            uv_scale,
         })
     }
 
@@ -72,8 +153,17 @@ impl Quad {
         mat: Materials,
         transformation: &dyn Transformer,
     ) -> Vec<Hittables> {
-        let mut sides = Vec::new();
+        Quad::new_box_with_faces(a, b, BoxFaces::all(mat), transformation)
+    }
 
+    /// creates a new box shaped hittable object with individual material
+    /// and texture coordinate scaling for each face
+    pub fn new_box_with_faces(
+        a: Vec3,
+        b: Vec3,
+        faces: BoxFaces,
+        transformation: &dyn Transformer,
+    ) -> Vec<Hittables> {
         let min = Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
         let max = Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
 
@@ -81,54 +171,47 @@ impl Quad {
         let dy = Vec3::new(0., max.y - min.y, 0.);
         let dz = Vec3::new(0., 0., max.z - min.z);
 
-        sides.push(Quad::new(
-            Vec3::new(min.x, min.y, max.z),
-            dx,
-            dy,
-            mat.clone(),
-            transformation,
-        ));
-        sides.push(Quad::new(
-            Vec3::new(max.x, min.y, max.z),
-            dz.neg(),
-            dy,
-            mat.clone(),
-            transformation,
-        ));
-        sides.push(Quad::new(
-            Vec3::new(max.x, min.y, min.z),
-            dx.neg(),
-            dy,
-            mat.clone(),
-            transformation,
-        ));
-        sides.push(Quad::new(
-            Vec3::new(min.x, min.y, min.z),
-            dz,
-            dy,
-            mat.clone(),
-            transformation,
-        ));
-        sides.push(Quad::new(
-            Vec3::new(min.x, max.y, max.z),
-            dx,
-            dz.neg(),
-            mat.clone(),
-            transformation,
-        ));
-        sides.push(Quad::new(
-            Vec3::new(min.x, min.y, min.z),
-            dx,
-            dz,
-            mat,
-            transformation,
-        ));
+        [
+            (Vec3::new(min.x, min.y, max.z), dx, dy, faces.front),
+            (Vec3::new(max.x, min.y, max.z), dz.neg(), dy, faces.right),
+            (Vec3::new(max.x, min.y, min.z), dx.neg(), dy, faces.back),
+            (Vec3::new(min.x, min.y, min.z), dz, dy, faces.left),
+            (Vec3::new(min.x, max.y, max.z), dx, dz.neg(), faces.top),
+            (Vec3::new(min.x, min.y, min.z), dx, dz, faces.bottom),
+        ]
+        .into_iter()
+        .map(|(q, u, v, face)| {
+            Quad::new_with_uv_scale(q, u, v, face.material, face.uv_scale, transformation)
+        })
+        .collect()
+    }
 
-        sides
+    /// Surface area of the quad
+    pub(crate) fn area(&self) -> f64 {
+        self.area
+    }
+
+    /// Corners of the quad in counterclockwise order with their texture coordinates
+    pub(crate) fn corners_and_tex_coords(&self) -> ([Vec3; 4], [Uv; 4]) {
+        let (o, s) = (self.uv_offset, self.uv_scale);
+        (
+            [self.q, self.q + self.u, self.q + self.u + self.v, self.q + self.v],
+            [
+                o,
+                Uv::new(o.u + s.u, o.v),
+                Uv::new(o.u + s.u, o.v + s.v),
+                Uv::new(o.u, o.v + s.v),
+            ],
+        )
+    }
+
+    /// Point and normal on the quad for a point in the unit square
+    pub(crate) fn surface_point(&self, s: f64, t: f64) -> (Vec3, Vec3) {
+        (self.q + self.u * s + self.v * t, self.normal)
     }
 }
 
-impl Hittable for Quad {
+impl Sampleable for Quad {
     fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
         let ray = Ray::new(origin, direction);
 
@@ -143,9 +226,16 @@ impl Hittable for Quad {
     }
 
     fn random_direction(&self, origin: Vec3) -> Vec3 {
-        let p = self.q + self.u * random_normal_float() + self.v * random_normal_float();
+        let (s, t) = next_2d();
+        let (p, _) = self.surface_point(s, t);
         p - origin
     }
+}
+
+impl Hittable for Quad {
+    fn as_sampleable(&self) -> Option<&dyn Sampleable> {
+        Some(self)
+    }
 
     fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
         let denom = self.normal.dot(r.direction);
@@ -171,6 +261,13 @@ impl Hittable for Quad {
         if !ZERO_TO_ONE.contains(&u) || !ZERO_TO_ONE.contains(&v) {
             return None;
         }
+        let uv = Uv::new(
+            self.uv_offset.u + u * self.uv_scale.u,
+            self.uv_offset.v + v * self.uv_scale.v,
+        );
+        if self.mat.alpha().is_some_and(|alpha| is_cut_out(alpha, uv, r.time)) {
+            return None;
+        }
 
         let front_face = r.direction.dot(self.normal) < 0.;
         let normal = if front_face {
@@ -179,18 +276,30 @@ impl Hittable for Quad {
             self.normal.neg()
         };
 
-        Some(RayHit::new(
+        let uv_derivatives = UvDerivatives::new(
+            r,
             hit_point,
-            Onb {
-                tangent: self.u.unit(),
-                bi_tangent: self.v.unit(),
-                normal,
-            },
-            &self.mat,
-            t,
-            Uv::new(u, v),
-            front_face,
-        ))
+            self.normal,
+            self.u / self.uv_scale.u as f64,
+            self.v / self.uv_scale.v as f64,
+        );
+
+        Some(
+            RayHit::new(
+                hit_point,
+                Onb {
+                    tangent: self.u.unit(),
+                    bi_tangent: self.v.unit(),
+                    normal,
+                },
+                &self.mat,
+                t,
+                uv,
+                front_face,
+                r.time,
+            )
+            .with_uv_derivatives(uv_derivatives),
+        )
     }
 
     fn bounding_box(&self) -> &Aabb {
@@ -204,4 +313,114 @@ impl Hittable for Quad {
             vec![]
         }
     }
+
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        // Plane coordinates of the point projected onto the plane of the quad
+        let planar_vector = p - self.q;
+        let u = self.w.dot(planar_vector.cross(self.v));
+        let v = self.w.dot(self.u.cross(planar_vector));
+        if (0. ..=1.).contains(&u) && (0. ..=1.).contains(&v) {
+            return Some(self.q + self.u * u + self.v * v);
+        }
+
+        // Otherwise the closest point is on one of the edges
+        let (corners, _) = self.corners_and_tex_coords();
+        (0..4)
+            .map(|i| closest_point_on_segment(p, corners[i], corners[(i + 1) % 4]))
+            .min_by(|a, b| (*a - p).length_squared().total_cmp(&(*b - p).length_squared()))
+    }
+
+    fn materials(&self) -> Vec<&Materials> {
+        vec![&self.mat]
+    }
+}
+
+/// Returns the point on the line segment between a and b that is closest to p
+fn closest_point_on_segment(p: Vec3, a: Vec3, b: Vec3) -> Vec3 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.length_squared()).clamp(0., 1.);
+    a + ab * t
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::transformation::NopTransformer;
+    use crate::material::texture::SolidColor;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::util::chi_squared::chi_squared_test;
+
+    use super::*;
+
+    #[test]
+    fn test_pdf_matches_random_direction() {
+        let quad = Quad::new(
+            Vec3::new(-1., -1., 1.),
+            Vec3::new(2., 0., 0.5),
+            Vec3::new(0., 1.5, 0.),
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        );
+        let light = quad.as_sampleable().unwrap();
+        let origin = Vec3::new(0.1, 0.2, 0.);
+
+        let res = chi_squared_test(
+            || light.random_direction(origin),
+            |direction| light.pdf_value(origin, direction),
+        );
+        assert_eq!(res, Ok(()));
+    }
+
+    #[test]
+    fn test_box_with_faces() {
+        let lambertian = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let faces = BoxFaces {
+            front: BoxFace {
+                material: lambertian.clone(),
+                uv_scale: Uv::new(2., 4.),
+            },
+            top: BoxFace::new(DiffuseLight::new(1., 1., 1., None)),
+            ..BoxFaces::all(lambertian)
+        };
+        let sides = Quad::new_box_with_faces(
+            Vec3::new(-1., -1., -1.),
+            Vec3::new(1., 1., 1.),
+            faces,
+            &NopTransformer(),
+        );
+        assert_eq!(sides.len(), 6);
+
+        let lights: Vec<Hittables> = sides.iter().flat_map(|s| s.get_lights()).collect();
+        assert_eq!(lights.len(), 1);
+        assert_eq!(lights[0].bounding_box().center().y, 1.);
+
+        let ray = Ray::new(Vec3::new(0.5, 0.5, 5.), Vec3::new(0., 0., -1.));
+        let rec = sides[0].hit(&ray, &RAY_INTERVAL).unwrap();
+        assert_eq!(rec.uv, Uv::new(1.5, 3.));
+    }
+
+    #[test]
+    fn test_alpha_cutout() {
+        let hits = |alpha| {
+            let quad = Quad::new(
+                Vec3::new(-1., -1., 0.),
+                Vec3::new(2., 0., 0.),
+                Vec3::new(0., 2., 0.),
+                Lambertian::new_with_alpha(
+                    SolidColor::new(1., 1., 1.),
+                    None,
+                    SolidColor::new(alpha, alpha, alpha),
+                ),
+                &NopTransformer(),
+            );
+            let ray = Ray::new(Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.));
+            (0..1000)
+                .filter(|_| quad.hit(&ray, &RAY_INTERVAL).is_some())
+                .count()
+        };
+        assert_eq!(hits(1.), 1000);
+        assert_eq!(hits(0.), 0);
+        // Partly transparent surfaces let some of the rays through
+        let half = hits(0.5);
+        assert!(half > 400 && half < 600, "hits were {}", half);
+    }
 }
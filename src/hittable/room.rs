@@ -0,0 +1,225 @@
+use crate::geo::transformation::Transformer;
+use crate::geo::vec3::Vec3;
+use crate::geo::Uv;
+use crate::hittable::{BoxFace, BoxFaces, Hittables, Quad};
+
+/// The walls of a [`Room`], named as seen when looking towards negative z
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RoomWall {
+    /// Wall at max z
+    Front,
+    /// Wall at max x
+    Right,
+    /// Wall at min z
+    Back,
+    /// Wall at min x
+    Left,
+    /// Ceiling at max y
+    Top,
+    /// Floor at min y
+    Bottom,
+}
+
+/// A rectangular hole in a wall of a [`Room`], like a door or a window
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RoomOpening {
+    /// The wall that has the opening
+    pub wall: RoomWall,
+    /// Lower left corner of the opening in wall coordinates, that go from 0 to 1
+    /// left to right and bottom to top as seen from inside the room
+    pub min: Uv,
+    /// Upper right corner of the opening in wall coordinates
+    pub max: Uv,
+}
+
+/// Builds the inside of a box, with the quads facing inwards
+pub struct Room();
+
+impl Room {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates the walls of a room with corners a and b. Each wall has its own material and
+    /// texture coordinate scaling, and the walls are split into several quads around openings
+    pub fn new(
+        a: Vec3,
+        b: Vec3,
+        walls: BoxFaces,
+        openings: &[RoomOpening],
+        transformation: &dyn Transformer,
+    ) -> Vec<Hittables> {
+        let min = Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+        let dx = Vec3::new(max.x - min.x, 0., 0.);
+        let dy = Vec3::new(0., max.y - min.y, 0.);
+        let dz = Vec3::new(0., 0., max.z - min.z);
+
+        [
+            (RoomWall::Front, Vec3::new(max.x, min.y, max.z), dx.neg(), dy, walls.front),
+            (RoomWall::Right, Vec3::new(max.x, min.y, min.z), dz, dy, walls.right),
+            (RoomWall::Back, Vec3::new(min.x, min.y, min.z), dx, dy, walls.back),
+            (RoomWall::Left, Vec3::new(min.x, min.y, max.z), dz.neg(), dy, walls.left),
+            (RoomWall::Top, Vec3::new(min.x, max.y, min.z), dx, dz, walls.top),
+            (RoomWall::Bottom, Vec3::new(min.x, min.y, max.z), dx, dz.neg(), walls.bottom),
+        ]
+        .into_iter()
+        .flat_map(|(wall, q, u, v, face)| {
+            let wall_openings: Vec<&RoomOpening> =
+                openings.iter().filter(|o| o.wall == wall).collect();
+            create_wall(q, u, v, face, &wall_openings, transformation)
+        })
+        .collect()
+    }
+}
+
+/// Splits the wall into a grid along the edges of the openings,
+/// and creates quads for the horizontal runs of cells that are not in an opening
+fn create_wall(
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+    face: BoxFace,
+    openings: &[&RoomOpening],
+    transformation: &dyn Transformer,
+) -> Vec<Hittables> {
+    let us = grid_lines(openings.iter().flat_map(|o| [o.min.u, o.max.u]));
+    let vs = grid_lines(openings.iter().flat_map(|o| [o.min.v, o.max.v]));
+
+    let is_open = |u0: f32, u1: f32, v0: f32, v1: f32| {
+        let (mid_u, mid_v) = ((u0 + u1) / 2., (v0 + v1) / 2.);
+        openings.iter().any(|o| {
+            o.min.u.min(o.max.u) < mid_u
+                && mid_u < o.min.u.max(o.max.u)
+                && o.min.v.min(o.max.v) < mid_v
+                && mid_v < o.min.v.max(o.max.v)
+        })
+    };
+
+    let mut quads = Vec::new();
+    for row in vs.windows(2) {
+        let (v0, v1) = (row[0], row[1]);
+        let mut run_start = None;
+        for (i, column) in us.windows(2).enumerate() {
+            let open = is_open(column[0], column[1], v0, v1);
+            match (run_start, open) {
+                (None, false) => run_start = Some(us[i]),
+                (Some(u0), true) => {
+                    quads.push(create_part(q, u, v, &face, u0, us[i], v0, v1, transformation));
+                    run_start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(u0) = run_start {
+            quads.push(create_part(q, u, v, &face, u0, 1., v0, v1, transformation));
+        }
+    }
+    quads
+}
+
+/// Sorted and deduplicated coordinates of the grid lines, always including 0 and 1
+fn grid_lines(coordinates: impl Iterator<Item = f32>) -> Vec<f32> {
+    let mut lines: Vec<f32> = coordinates.map(|c| c.clamp(0., 1.)).collect();
+    lines.push(0.);
+    lines.push(1.);
+    lines.sort_by(|a, b| a.total_cmp(b));
+    lines.dedup();
+    lines
+}
+
+/// Creates a quad for a part of a wall, with texture coordinates continuing over the whole wall
+#[allow(clippy::too_many_arguments)]
+fn create_part(
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+    face: &BoxFace,
+    u0: f32,
+    u1: f32,
+    v0: f32,
+    v1: f32,
+    transformation: &dyn Transformer,
+) -> Hittables {
+    Quad::new_with_uv_transform(
+        q + u * u0 as f64 + v * v0 as f64,
+        u * (u1 - u0) as f64,
+        v * (v1 - v0) as f64,
+        face.material.clone(),
+        Uv::new(u0 * face.uv_scale.u, v0 * face.uv_scale.v),
+        Uv::new((u1 - u0) * face.uv_scale.u, (v1 - v0) * face.uv_scale.v),
+        transformation,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::transformation::NopTransformer;
+    use crate::geo::Ray;
+    use crate::hittable::Hittable;
+    use crate::material::texture::SolidColor;
+    use crate::material::Lambertian;
+    use crate::util::interval::RAY_INTERVAL;
+
+    use super::*;
+
+    fn create_room(openings: &[RoomOpening]) -> Vec<Hittables> {
+        let walls = BoxFaces {
+            back: BoxFace {
+                material: Lambertian::new(SolidColor::new(1., 1., 1.), None),
+                uv_scale: Uv::new(2., 2.),
+            },
+            ..BoxFaces::all(Lambertian::new(SolidColor::new(1., 1., 1.), None))
+        };
+        Room::new(
+            Vec3::new(-1., -1., -1.),
+            Vec3::new(1., 1., 1.),
+            walls,
+            openings,
+            &NopTransformer(),
+        )
+    }
+
+    fn hit(walls: &[Hittables], direction: Vec3) -> Option<(bool, Uv)> {
+        let ray = Ray::new(Vec3::new(0., 0., 0.), direction);
+        walls
+            .iter()
+            .filter_map(|w| w.hit(&ray, &RAY_INTERVAL))
+            .map(|rec| (rec.front_face, rec.uv))
+            .next()
+    }
+
+    #[test]
+    fn test_walls_face_inwards() {
+        let walls = create_room(&[]);
+        assert_eq!(walls.len(), 6);
+
+        for direction in [
+            Vec3::new(1., 0.2, 0.1),
+            Vec3::new(-1., 0.2, 0.1),
+            Vec3::new(0.1, 1., 0.2),
+            Vec3::new(0.1, -1., 0.2),
+            Vec3::new(0.2, 0.1, 1.),
+            Vec3::new(0.2, 0.1, -1.),
+        ] {
+            let (front_face, _) = hit(&walls, direction).unwrap();
+            assert!(front_face);
+        }
+    }
+
+    #[test]
+    fn test_opening() {
+        let walls = create_room(&[RoomOpening {
+            wall: RoomWall::Back,
+            min: Uv::new(0.25, 0.25),
+            max: Uv::new(0.75, 0.5),
+        }]);
+        // The back wall is split into a row below, two parts beside and a row above the opening
+        assert_eq!(walls.len(), 9);
+
+        assert_eq!(hit(&walls, Vec3::new(0., -0.25, -1.)), None);
+
+        let (_, uv) = hit(&walls, Vec3::new(0., -0.75, -1.)).unwrap();
+        assert_eq!(uv, Uv::new(1., 0.25));
+        let (_, uv) = hit(&walls, Vec3::new(0., 0.5, -1.)).unwrap();
+        assert_eq!(uv, Uv::new(1., 1.5));
+    }
+}
@@ -3,19 +3,28 @@ use std::f64::consts::PI;
 use crate::geo::Aabb;
 use crate::geo::Onb;
 use crate::geo::Ray;
-use crate::geo::Uv;
-use crate::geo::vec3::{UNIT_Y, Vec3};
-use crate::hittable::{Hittable, Hittables};
+use crate::geo::{Uv, UvDerivatives};
+use crate::geo::transformation::{NopTransformer, Transformer};
+use crate::geo::vec3::{random_unit_vector, UNIT_Y, Vec3};
+use crate::hittable::{Hittable, Hittables, Sampleable};
 use crate::hittable::Hittables::SphereType;
 use crate::material::{Material, Materials, RayHit};
 use crate::random::random_normal_float;
 use crate::util::interval::{Interval, RAY_INTERVAL};
 
-/// A sphere shaped hittable object
+/// Smallest distance from the poles, in sine of the polar angle, that the texture coordinate
+/// derivatives are evaluated at. Keeps them finite at the poles
+const MIN_SIN_THETA: f64 = 1e-4;
+
+/// A sphere shaped hittable object.
+/// The texture coordinates wrap around the y axis, with the poles at v 0 and 1.
+/// Image maps on spheres should use [`crate::material::texture::WrapMode::Spherical`]
+/// to be filtered without seams at the poles
 #[derive(Debug)]
 pub struct Sphere {
     center: Vec3,
     radius: f64,
+    uv_orientation: Onb,
     mat: Materials,
     b_box: Aabb,
 }
@@ -24,19 +33,56 @@ impl Sphere {
     #![allow(clippy::new_ret_no_self)]
     ///Creates a new sphere
     pub fn new(center: Vec3, radius: f64, mat: Materials) -> Hittables {
+        Sphere::new_with_uv_orientation(center, radius, mat, &NopTransformer())
+    }
+
+    /// Creates a new sphere with the texture coordinates rotated by the orientation,
+    /// so that the poles of the texture are along the rotated y axis
+    pub fn new_with_uv_orientation(
+        center: Vec3,
+        radius: f64,
+        mat: Materials,
+        orientation: &dyn Transformer,
+    ) -> Hittables {
         let r_vec = Vec3::new(radius, radius, radius);
         let b_box = Aabb::new_from_2_points(center - r_vec, center + r_vec);
 
+        let y = orientation.transform(UNIT_Y, true).unit();
+        let x = y
+            .cross(orientation.transform(Vec3::new(0., 0., 1.), true))
+            .unit();
+        let z = x.cross(y);
+
         Hittables::from(Sphere {
             center,
             radius,
+            uv_orientation: Onb {
+                tangent: x,
+                bi_tangent: y,
+                normal: z,
+            },
             mat,
             b_box,
         })
     }
+
+    /// Surface area of the sphere
+    pub(crate) fn area(&self) -> f64 {
+        4. * PI * self.radius * self.radius
+    }
+
+    /// Point and normal on the sphere for a point in the unit square,
+    /// where uniformly distributed points in the square are uniformly distributed on the sphere
+    pub(crate) fn surface_point(&self, s: f64, t: f64) -> (Vec3, Vec3) {
+        let z = 1. - 2. * s;
+        let r = (1. - z * z).sqrt();
+        let phi = 2. * PI * t;
+        let normal = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+        (self.center + normal * self.radius, normal)
+    }
 }
 
-impl Hittable for Sphere {
+impl Sampleable for Sphere {
     fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
         let ray = Ray::new(origin, direction);
 
@@ -45,9 +91,13 @@ impl Hittable for Sphere {
         match hit {
             None => 0.,
             Some(_) => {
-                let cos_theta_max = (1.
-                    - self.radius * self.radius / (self.center - origin).length_squared())
-                .sqrt();
+                let distance_squared = (self.center - origin).length_squared();
+                // From inside the sphere every direction is sampled uniformly
+                if distance_squared < self.radius * self.radius {
+                    return 1. / (4. * PI);
+                }
+
+                let cos_theta_max = (1. - self.radius * self.radius / distance_squared).sqrt();
                 let solid_angle = 2. * PI * (1. - cos_theta_max);
 
                 1. / solid_angle
@@ -57,8 +107,19 @@ impl Hittable for Sphere {
 
     fn random_direction(&self, origin: Vec3) -> Vec3 {
         let direction = self.center - origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared < self.radius * self.radius {
+            return random_unit_vector();
+        }
+
         let uvw = Onb::new(direction);
-        uvw.local(random_to_sphere(self.radius, direction.length_squared()))
+        uvw.local(random_to_sphere(self.radius, distance_squared))
+    }
+}
+
+impl Hittable for Sphere {
+    fn as_sampleable(&self) -> Option<&dyn Sampleable> {
+        Some(self)
     }
 
     fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
@@ -84,27 +145,52 @@ impl Hittable for Sphere {
         let hit_point = r.at(root);
         let n = hit_point - self.center;
         let mut normal = n.unit();
-        let uv = calculate_sphere_uv(normal);
+        let orientation = &self.uv_orientation;
+        let uv_normal = Vec3::new(
+            normal.dot(orientation.tangent),
+            normal.dot(orientation.bi_tangent),
+            normal.dot(orientation.normal),
+        );
+        let uv = sphere_uv(uv_normal);
 
-        let tangent = UNIT_Y.cross(n).unit();
+        // At the poles any direction along the surface is perpendicular to the pole axis
+        let tangent = orientation.bi_tangent.cross(n);
+        let tangent = if tangent.near_zero() {
+            orientation.tangent
+        } else {
+            tangent.unit()
+        };
         let bi_tangent = n.cross(tangent);
 
+        let (dp_du, dp_dv) = sphere_uv_derivatives(uv_normal, self.radius);
+        let uv_derivatives = UvDerivatives::new(
+            r,
+            hit_point,
+            normal,
+            orientation.local(dp_du),
+            orientation.local(dp_dv),
+        );
+
         let front_face = r.direction.dot(normal) < 0.;
         if !front_face {
             normal = normal.neg();
         }
-        Some(RayHit::new(
-            hit_point,
-            Onb {
-                tangent,
-                bi_tangent,
-                normal,
-            },
-            &self.mat,
-            root,
-            uv,
-            front_face,
-        ))
+        Some(
+            RayHit::new(
+                hit_point,
+                Onb {
+                    tangent,
+                    bi_tangent,
+                    normal,
+                },
+                &self.mat,
+                root,
+                uv,
+                front_face,
+                r.time,
+            )
+            .with_uv_derivatives(uv_derivatives),
+        )
     }
 
     fn bounding_box(&self) -> &Aabb {
@@ -118,6 +204,18 @@ impl Hittable for Sphere {
             vec![]
         }
     }
+
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        let direction = p - self.center;
+        if direction.near_zero() {
+            return Some(self.center + UNIT_Y * self.radius);
+        }
+        Some(self.center + direction.unit() * self.radius)
+    }
+
+    fn materials(&self) -> Vec<&Materials> {
+        vec![&self.mat]
+    }
 }
 
 impl Clone for Sphere {
@@ -125,13 +223,15 @@ impl Clone for Sphere {
         Sphere {
             center: self.center,
             radius: self.radius,
+            uv_orientation: self.uv_orientation.clone(),
             mat: self.mat.clone(),
             b_box: self.b_box.clone(),
         }
     }
 }
 
-fn calculate_sphere_uv(point_on_sphere: Vec3) -> Uv {
+/// Texture coordinates for a point on a unit sphere
+pub(crate) fn sphere_uv(point_on_sphere: Vec3) -> Uv {
     let theta = (-point_on_sphere.y).acos();
     let phi = -point_on_sphere.z.atan2(point_on_sphere.x) + PI;
     let u = phi / (2. * PI);
@@ -139,7 +239,20 @@ fn calculate_sphere_uv(point_on_sphere: Vec3) -> Uv {
     Uv::new(u as f32, v as f32)
 }
 
-fn random_to_sphere(radius: f64, distance_squared: f64) -> Vec3 {
+/// Change of the point on a sphere per unit of the texture coordinates given by [`sphere_uv`].
+/// At the poles they are evaluated at a point slightly beside the pole
+pub(crate) fn sphere_uv_derivatives(point_on_sphere: Vec3, radius: f64) -> (Vec3, Vec3) {
+    let Vec3 { mut x, y, mut z } = point_on_sphere;
+    let mut sin_theta = (x * x + z * z).sqrt();
+    if sin_theta < MIN_SIN_THETA {
+        (x, z, sin_theta) = (MIN_SIN_THETA, 0., MIN_SIN_THETA);
+    }
+    let dp_du = Vec3::new(z, 0., -x) * (2. * PI * radius);
+    let dp_dv = Vec3::new(-x * y / sin_theta, sin_theta, -z * y / sin_theta) * (PI * radius);
+    (dp_du, dp_dv)
+}
+
+pub(crate) fn random_to_sphere(radius: f64, distance_squared: f64) -> Vec3 {
     let r1 = random_normal_float();
     let r2 = random_normal_float();
     let z = 1. + r2 * ((1. - radius * radius / distance_squared).sqrt() - 1.);
@@ -151,3 +264,91 @@ fn random_to_sphere(radius: f64, distance_squared: f64) -> Vec3 {
 
     Vec3::new(x, y, z)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::transformation::RotationZ;
+    use crate::geo::RayDifferentials;
+    use crate::material::texture::SolidColor;
+    use crate::material::Lambertian;
+    use crate::util::chi_squared::chi_squared_test;
+
+    use super::*;
+
+    #[test]
+    fn test_pdf_matches_random_direction() {
+        let sphere = Sphere::new(
+            Vec3::new(0.5, 0.2, 2.),
+            1.,
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+        );
+        let light = sphere.as_sampleable().unwrap();
+        let origin = Vec3::new(0., 0., 0.);
+
+        let res = chi_squared_test(
+            || light.random_direction(origin),
+            |direction| light.pdf_value(origin, direction),
+        );
+        assert_eq!(res, Ok(()));
+    }
+
+    #[test]
+    fn test_pdf_matches_random_direction_from_inside() {
+        let sphere = Sphere::new(
+            Vec3::new(0.5, 0.2, 0.),
+            100.,
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+        );
+        let light = sphere.as_sampleable().unwrap();
+        let origin = Vec3::new(0., 0., 0.);
+
+        let res = chi_squared_test(
+            || light.random_direction(origin),
+            |direction| light.pdf_value(origin, direction),
+        );
+        assert_eq!(res, Ok(()));
+    }
+
+    /// Hit on the sphere at the origin, by a ray towards the center from the direction
+    fn hit_from(sphere: &Hittables, direction: Vec3) -> RayHit {
+        let origin = direction * 3.;
+        let ray = Ray::new(origin, origin.neg()).with_differentials(RayDifferentials {
+            dx_origin: origin + Vec3::new(0.01, 0., 0.),
+            dx_direction: origin.neg(),
+            dy_origin: origin + Vec3::new(0., 0., 0.01),
+            dy_direction: origin.neg(),
+        });
+        sphere.hit(&ray, &RAY_INTERVAL).unwrap()
+    }
+
+    #[test]
+    fn test_uv_orientation() {
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let sphere = Sphere::new(Vec3::new(0., 0., 0.), 1., mat.clone());
+        let rotation = RotationZ::new(90.);
+        let rotated =
+            Sphere::new_with_uv_orientation(Vec3::new(0., 0., 0.), 1., mat, &rotation);
+
+        let direction = Vec3::new(0.6, 0., 0.8);
+        let uv = hit_from(&sphere, direction).uv;
+        let rotated_uv = hit_from(&rotated, rotation.transform(direction, true)).uv;
+        assert!((uv.u - rotated_uv.u).abs() < 1e-6 && (uv.v - rotated_uv.v).abs() < 1e-6);
+
+        // The pole of the texture is along the rotated y axis
+        let pole = hit_from(&rotated, rotation.transform(UNIT_Y, true));
+        assert!((pole.uv.v - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_derivatives_at_pole() {
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let sphere = Sphere::new(Vec3::new(0., 0., 0.), 1., mat);
+
+        let rec = hit_from(&sphere, UNIT_Y);
+        let d = rec.uv_derivatives.unwrap();
+        for c in [d.duv_dx.u, d.duv_dx.v, d.duv_dy.u, d.duv_dy.v] {
+            assert!(c.is_finite());
+        }
+        assert!(rec.onb.tangent.length() > 0.99 && rec.onb.bi_tangent.length() > 0.99);
+    }
+}
@@ -1,34 +1,63 @@
 use crate::geo::{Aabb, Onb};
 use crate::geo::Ray;
 use crate::geo::transformation::Transformer;
-use crate::geo::Uv;
-use crate::geo::vec3::{ALMOST_ZERO, Vec3};
-use crate::hittable::{Hittable, Hittables};
+use crate::geo::{Uv, UvDerivatives};
+use crate::geo::vec3::Vec3;
+use crate::hittable::{Hittable, Hittables, Sampleable};
 use crate::hittable::Hittables::TriangleType;
-use crate::material::{Material, Materials, RayHit};
-use crate::random::random_normal_float;
+use crate::material::{is_cut_out, Material, Materials, RayHit};
+use crate::sampler::next_2d;
 use crate::util::interval::{Interval, RAY_INTERVAL};
 
 /// A triangle shaped hittable object
 #[derive(Clone, Debug)]
 pub struct Triangle {
     v0: Vec3,
+    v1: Vec3,
+    v2: Vec3,
     v0v1: Vec3,
     v0v2: Vec3,
     uv0: Uv,
     uv1: Uv,
     uv2: Uv,
+    lightmap_uvs: Option<Box<[Uv; 3]>>,
+    colors: Option<Box<[Vec3; 3]>>,
+    /// Normals of the corners, that the shading normal is interpolated from
+    normals: Option<Box<[Vec3; 3]>>,
     normal: Vec3,
     tangent: Vec3,
     bi_tangent: Vec3,
+    dp_du: Vec3,
+    dp_dv: Vec3,
     mat: Materials,
     b_box: Aabb,
     area: f64,
+    cull_backfaces: bool,
+}
+
+/// Optional data of the corners of a triangle, and how it is hit,
+/// for [`Triangle::new_with_options`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TriangleOptions {
+    /// Texture coordinates of the corners, all zero by default
+    pub uvs: [Uv; 3],
+    /// Second set of texture coordinates of the corners, that lightmaps are laid out by
+    /// when baking. Lightmaps use the regular texture coordinates if not set
+    pub lightmap_uvs: Option<[Uv; 3]>,
+    /// Normals of the corners, that are interpolated over the triangle, which makes meshes
+    /// of few triangles look smooth. Without normals the triangle is flat
+    pub normals: Option<[Vec3; 3]>,
+    /// Colors of the corners, that are interpolated over the triangle and used by the
+    /// [`crate::material::texture::VertexColor`] texture
+    pub colors: Option<[Vec3; 3]>,
+    /// The triangle is not hit by rays coming from behind it
+    pub cull_backfaces: bool,
 }
 
 impl Triangle {
     #![allow(clippy::new_ret_no_self)]
-    /// Creates a new triangle hittable object with no texture coordinates
+    /// Creates a new flat triangle hittable object with no texture coordinates.
+    /// A counterclockwise winding is expected
     pub fn new(
         v0: Vec3,
         v1: Vec3,
@@ -36,30 +65,27 @@ impl Triangle {
         mat: Materials,
         transformation: &dyn Transformer,
     ) -> Hittables {
-        Triangle::new_with_tex_coords(
-            v0,
-            v1,
-            v2,
-            Uv { u: 0.0, v: 0.0 },
-            Uv { u: 0.0, v: 0.0 },
-            Uv { u: 0.0, v: 0.0 },
-            mat,
-            transformation,
-        )
+        Triangle::new_with_options(v0, v1, v2, TriangleOptions::default(), mat, transformation)
     }
 
-    #[allow(clippy::too_many_arguments)]
-    /// Creates a new triangle flat hittable object. A counterclockwise winding is expected
-    pub fn new_with_tex_coords(
+    /// Creates a new triangle hittable object like [`Triangle::new`],
+    /// with the data of the corners and settings of the options
+    pub fn new_with_options(
         v0: Vec3,
         v1: Vec3,
         v2: Vec3,
-        uv0: Uv,
-        uv1: Uv,
-        uv2: Uv,
+        options: TriangleOptions,
         mat: Materials,
         transformation: &dyn Transformer,
     ) -> Hittables {
+        let TriangleOptions {
+            uvs,
+            lightmap_uvs,
+            normals,
+            colors,
+            cull_backfaces,
+        } = options;
+        let [uv0, uv1, uv2] = uvs;
         let v0 = transformation.transform(v0, false);
         let v1 = transformation.transform(v1, false);
         let v2 = transformation.transform(v2, false);
@@ -71,32 +97,64 @@ impl Triangle {
         let normal = n.unit();
         let area = n.length() / 2.;
 
-        let delta_pos_1 = v1 - v0;
-        let delta_pos_2 = v2 - v0;
-        let delta_uv_1 = uv1 - uv0;
-        let delta_uv_2 = uv2 - uv0;
-        let r = 1. / (delta_uv_1.u * delta_uv_2.v - delta_uv_1.v * delta_uv_2.u);
-        let tangent = ((delta_pos_1 * delta_uv_2.v - delta_pos_2 * delta_uv_1.v) * r).unit();
-        let bi_tangent = ((delta_pos_2 * delta_uv_1.u - delta_pos_1 * delta_uv_2.u) * r).unit();
+        let (dp_du, dp_dv) = uv_tangents(v0v1, v0v2, uvs);
 
         Hittables::from(Triangle {
             v0,
+            v1,
+            v2,
             v0v1,
             v0v2,
             uv0,
             uv1,
             uv2,
+            lightmap_uvs: lightmap_uvs.map(Box::new),
+            colors: colors.map(Box::new),
+            normals: normals.map(|n| Box::new(n.map(|n| transformation.transform_normal(n)))),
             normal,
-            tangent,
-            bi_tangent,
+            tangent: dp_du.unit(),
+            bi_tangent: dp_dv.unit(),
+            dp_du,
+            dp_dv,
             mat,
             b_box,
             area,
+            cull_backfaces,
         })
     }
+
+    /// Surface area of the triangle
+    pub(crate) fn area(&self) -> f64 {
+        self.area
+    }
+
+    /// Corners of the triangle with their texture coordinates
+    pub(crate) fn vertices_and_tex_coords(&self) -> ([Vec3; 3], [Uv; 3]) {
+        ([self.v0, self.v1, self.v2], [self.uv0, self.uv1, self.uv2])
+    }
+
+    /// Texture coordinates of the corners that lightmaps are laid out by,
+    /// which are the regular texture coordinates if there is no second set
+    pub(crate) fn lightmap_tex_coords(&self) -> [Uv; 3] {
+        match &self.lightmap_uvs {
+            Some(uvs) => **uvs,
+            None => [self.uv0, self.uv1, self.uv2],
+        }
+    }
+
+    /// Point and normal on the triangle for a point in the unit square,
+    /// where uniformly distributed points in the square are uniformly distributed on the triangle
+    pub(crate) fn surface_point(&self, mut s: f64, mut t: f64) -> (Vec3, Vec3) {
+        // Fold points in the parallelogram outside the triangle back inside it
+        if s + t > 1. {
+            s = 1. - s;
+            t = 1. - t;
+        }
+        (self.v0 + self.v0v1 * s + self.v0v2 * t, self.normal)
+    }
 }
 
-impl Hittable for Triangle {
+impl Sampleable for Triangle {
     fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
         let ray = Ray::new(origin, direction);
 
@@ -104,7 +162,7 @@ impl Hittable for Triangle {
             None => 0.,
             Some(rec) => {
                 let distance_squared = rec.ray_length * rec.ray_length * direction.length_squared();
-                let cosine = (direction.dot(rec.normal) / direction.length()).abs();
+                let cosine = (direction.dot(self.normal) / direction.length()).abs();
 
                 distance_squared / (cosine * self.area)
             }
@@ -112,64 +170,74 @@ impl Hittable for Triangle {
     }
 
     fn random_direction(&self, origin: Vec3) -> Vec3 {
-        let p = self.v0 + self.v0v1 * random_normal_float() + self.v0v2 * random_normal_float();
+        let (s, t) = next_2d();
+        let (p, _) = self.surface_point(s, t);
         p - origin
     }
+}
 
-    fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
-        let p_vec = r.direction.cross(self.v0v2);
-        let det = self.v0v1.dot(p_vec);
-
-        // No hit if the ray is parallel to the plane
-        if det.abs() < ALMOST_ZERO {
-            return None;
-        }
+impl Hittable for Triangle {
+    fn as_sampleable(&self) -> Option<&dyn Sampleable> {
+        Some(self)
+    }
 
-        let inv_det = 1. / det;
-        let t_vec = r.origin - self.v0;
-        let q_vec = t_vec.cross(self.v0v1);
+    fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
+        let (tt, b1, b2) = intersect([self.v0, self.v1, self.v2], r, ray_length)?;
 
-        // Is hit point outside of primitive
-        let u = (t_vec.dot(p_vec) * inv_det) as f32;
-        if !(0. ..=1.).contains(&u) {
-            return None;
-        }
-        let v = (r.direction.dot(q_vec) * inv_det) as f32;
-        if v < 0. || u + v > 1. {
+        let front_face = r.direction.dot(self.normal) < 0.;
+        if self.cull_backfaces && !front_face {
             return None;
         }
 
-        let tt = self.v0v2.dot(q_vec) * inv_det;
         let intersection = r.at(tt);
-
-        // Return false if the hit point parameter t is outside the ray length interval.
-        if !ray_length.contains(tt) {
-            return None;
-        }
+        let u = b1 as f32;
+        let v = b2 as f32;
 
         let uv0 = 1. - u - v;
         let uv = Uv::new(
             uv0 * self.uv0.u + u * self.uv1.u + v * self.uv2.u,
             uv0 * self.uv0.v + u * self.uv1.v + v * self.uv2.v,
         );
-
-        let mut normal = self.normal;
-        let front_face = r.direction.dot(normal) < 0.;
-        if !front_face {
-            normal = normal.neg()
+        if self.mat.alpha().is_some_and(|alpha| is_cut_out(alpha, uv, r.time)) {
+            return None;
         }
-        Some(RayHit::new(
-            intersection,
-            Onb {
+
+        let uv_derivatives =
+            UvDerivatives::new(r, intersection, self.normal, self.dp_du, self.dp_dv);
+        let vertex_color = self.colors.as_deref().map(|[c0, c1, c2]| {
+            *c0 * uv0 as f64 + *c1 * u as f64 + *c2 * v as f64
+        });
+
+        let mut onb = match self.normals.as_deref() {
+            Some(normals) => smooth_onb(
+                normals,
+                [1. - b1 - b2, b1, b2],
+                self.normal,
+                self.tangent,
+                self.bi_tangent,
+            ),
+            None => Onb {
                 tangent: self.tangent,
                 bi_tangent: self.bi_tangent,
-                normal,
+                normal: self.normal,
             },
-            &self.mat,
-            tt,
-            uv,
-            front_face,
-        ))
+        };
+        if !front_face {
+            onb.normal = onb.normal.neg()
+        }
+        Some(
+            RayHit::new(
+                intersection,
+                onb,
+                &self.mat,
+                tt,
+                uv,
+                front_face,
+                r.time,
+            )
+            .with_uv_derivatives(uv_derivatives)
+            .with_vertex_color(vertex_color),
+        )
     }
 
     fn bounding_box(&self) -> &Aabb {
@@ -183,4 +251,328 @@ impl Hittable for Triangle {
             vec![]
         }
     }
+
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        Some(closest_point_on_triangle([self.v0, self.v1, self.v2], p))
+    }
+
+    fn materials(&self) -> Vec<&Materials> {
+        vec![&self.mat]
+    }
+}
+
+/// Watertight ray triangle intersection by Woop, Benthin and Wald.
+/// Edges shared by adjacent triangles are evaluated identically for both,
+/// so rays can not slip through the gap between them.
+/// Returns the ray length and the barycentric coordinates of the second and third corners
+pub(crate) fn intersect(
+    vertices: [Vec3; 3],
+    r: &Ray,
+    ray_length: &Interval,
+) -> Option<(f64, f64, f64)> {
+    let [v0, v1, v2] = vertices;
+    // Permute the axes so the ray direction is largest along z, keeping the winding
+    let kz = max_axis(r.direction);
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if r.direction.axis(kz) < 0. {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    // Shear so the ray points along z from the origin
+    let dz = r.direction.axis(kz);
+    let sx = r.direction.axis(kx) / dz;
+    let sy = r.direction.axis(ky) / dz;
+    let sz = 1. / dz;
+
+    let a = v0 - r.origin;
+    let b = v1 - r.origin;
+    let c = v2 - r.origin;
+    let ax = a.axis(kx) - sx * a.axis(kz);
+    let ay = a.axis(ky) - sy * a.axis(kz);
+    let bx = b.axis(kx) - sx * b.axis(kz);
+    let by = b.axis(ky) - sy * b.axis(kz);
+    let cx = c.axis(kx) - sx * c.axis(kz);
+    let cy = c.axis(ky) - sy * c.axis(kz);
+
+    // Scaled barycentric coordinates from the edge functions
+    let e0 = cx * by - cy * bx;
+    let e1 = ax * cy - ay * cx;
+    let e2 = bx * ay - by * ax;
+
+    // Is hit point outside of primitive
+    if (e0 < 0. || e1 < 0. || e2 < 0.) && (e0 > 0. || e1 > 0. || e2 > 0.) {
+        return None;
+    }
+
+    // No hit if the ray is parallel to the plane
+    let det = e0 + e1 + e2;
+    if det == 0. {
+        return None;
+    }
+
+    let t_scaled = e0 * sz * a.axis(kz) + e1 * sz * b.axis(kz) + e2 * sz * c.axis(kz);
+    let tt = t_scaled / det;
+
+    // Return false if the hit point parameter t is outside the ray length interval.
+    if !ray_length.contains(tt) {
+        return None;
+    }
+
+    Some((tt, e1 / det, e2 / det))
+}
+
+/// Derivatives of the position on the triangle with respect to the texture coordinates,
+/// from the edges from the first corner to the other two
+pub(crate) fn uv_tangents(v0v1: Vec3, v0v2: Vec3, uvs: [Uv; 3]) -> (Vec3, Vec3) {
+    let [uv0, uv1, uv2] = uvs;
+    let delta_uv_1 = uv1 - uv0;
+    let delta_uv_2 = uv2 - uv0;
+    let r = 1. / (delta_uv_1.u * delta_uv_2.v - delta_uv_1.v * delta_uv_2.u);
+    let dp_du = (v0v1 * delta_uv_2.v - v0v2 * delta_uv_1.v) * r;
+    let dp_dv = (v0v2 * delta_uv_1.u - v0v1 * delta_uv_2.u) * r;
+    (dp_du, dp_dv)
+}
+
+/// Tangent space at a point on a triangle with normals at its corners, where the normal is
+/// interpolated by the barycentric coordinates of the point. The tangents are made
+/// perpendicular to the normal, keeping their handedness. Falls back to the normal of the
+/// triangle where the interpolated normal vanishes
+pub(crate) fn smooth_onb(
+    normals: &[Vec3; 3],
+    barycentrics: [f64; 3],
+    face_normal: Vec3,
+    tangent: Vec3,
+    bi_tangent: Vec3,
+) -> Onb {
+    let [n0, n1, n2] = normals;
+    let [b0, b1, b2] = barycentrics;
+    let normal = *n0 * b0 + *n1 * b1 + *n2 * b2;
+    let normal = if normal.near_zero() {
+        face_normal
+    } else {
+        normal.unit()
+    };
+
+    let tangent = (tangent - normal * normal.dot(tangent)).unit();
+    let smooth_bi_tangent = normal.cross(tangent);
+    Onb {
+        tangent,
+        bi_tangent: if smooth_bi_tangent.dot(bi_tangent) < 0. {
+            smooth_bi_tangent.neg()
+        } else {
+            smooth_bi_tangent
+        },
+        normal,
+    }
+}
+
+/// Closest point on triangle by Ericson, from Real-Time Collision Detection.
+/// Finds the voronoi region of the triangle that the point is in
+pub(crate) fn closest_point_on_triangle(vertices: [Vec3; 3], p: Vec3) -> Vec3 {
+    let [a, b, c] = vertices;
+    let (ab, ac) = (b - a, c - a);
+
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0. && d2 <= 0. {
+        return a;
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0. && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0. && d1 >= 0. && d3 <= 0. {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0. && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0. && d2 >= 0. && d6 <= 0. {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0. && d4 - d3 >= 0. && d5 - d6 >= 0. {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    // Inside the triangle
+    let denom = 1. / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+/// Index of the axis where the vector has the largest absolute value
+fn max_axis(v: Vec3) -> u8 {
+    let (x, y, z) = (v.x.abs(), v.y.abs(), v.z.abs());
+    if x > y && x > z {
+        0
+    } else if y > z {
+        1
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::transformation::NopTransformer;
+    use crate::material::texture::{Multiply, SolidColor, VertexColor};
+    use crate::material::Lambertian;
+    use crate::util::chi_squared::chi_squared_test;
+
+    use super::*;
+
+    #[test]
+    fn test_pdf_matches_random_direction() {
+        let triangle = Triangle::new(
+            Vec3::new(-1., -1., 1.),
+            Vec3::new(1., -0.5, 1.),
+            Vec3::new(0., 1., 1.5),
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        );
+        let light = triangle.as_sampleable().unwrap();
+        let origin = Vec3::new(0.1, 0.2, 0.);
+
+        let res = chi_squared_test(
+            || light.random_direction(origin),
+            |direction| light.pdf_value(origin, direction),
+        );
+        assert_eq!(res, Ok(()));
+    }
+
+    #[test]
+    fn test_no_gap_between_adjacent_triangles() {
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let (a, b) = (Vec3::new(-0.31, -0.73, 0.11), Vec3::new(0.57, 0.29, -0.23));
+        let triangles = [
+            Triangle::new(a, b, Vec3::new(0.47, -0.61, 0.05), mat.clone(), &NopTransformer()),
+            Triangle::new(a, Vec3::new(-0.43, 0.37, -0.17), b, mat, &NopTransformer()),
+        ];
+        let origin = Vec3::new(0.013, 0.021, 3.);
+
+        for i in 1..1000 {
+            let edge_point = a + (b - a) * (i as f64 / 1000.);
+            let ray = Ray::new(origin, edge_point - origin);
+            assert!(
+                triangles.iter().any(|t| t.hit(&ray, &RAY_INTERVAL).is_some()),
+                "Ray towards {:?} slipped between the triangles",
+                edge_point
+            );
+        }
+    }
+
+    #[test]
+    fn test_hit_barycentrics() {
+        let triangle = Triangle::new_with_options(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            TriangleOptions {
+                uvs: [Uv::new(0., 0.), Uv::new(1., 0.), Uv::new(0., 1.)],
+                ..Default::default()
+            },
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        );
+
+        let rec = triangle
+            .hit(&Ray::new(Vec3::new(0.25, 0.5, 2.), Vec3::new(0., 0., -1.)), &RAY_INTERVAL)
+            .unwrap();
+        assert_eq!(rec.ray_length, 2.);
+        assert_eq!(rec.uv, Uv::new(0.25, 0.5));
+        assert!(rec.front_face);
+
+        let rec = triangle
+            .hit(&Ray::new(Vec3::new(0.25, 0.5, -2.), Vec3::new(0., 0., 1.)), &RAY_INTERVAL)
+            .unwrap();
+        assert!(!rec.front_face);
+
+        let miss = Ray::new(Vec3::new(0.75, 0.5, 2.), Vec3::new(0., 0., -1.));
+        assert!(triangle.hit(&miss, &RAY_INTERVAL).is_none());
+    }
+
+    #[test]
+    fn test_vertex_colors() {
+        let triangle = Triangle::new_with_options(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            TriangleOptions {
+                colors: Some([Vec3::new(1., 0., 0.), Vec3::new(0., 1., 0.), Vec3::new(0., 0., 1.)]),
+                ..Default::default()
+            },
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        );
+
+        let rec = triangle
+            .hit(&Ray::new(Vec3::new(0.25, 0.5, 2.), Vec3::new(0., 0., -1.)), &RAY_INTERVAL)
+            .unwrap();
+        let tinted = Multiply::new(VertexColor::new(), SolidColor::new(0.5, 0.5, 0.5));
+        assert_eq!(rec.texture_color(&VertexColor::new()), Vec3::new(0.25, 0.25, 0.5));
+        assert_eq!(rec.texture_color(&tinted), Vec3::new(0.125, 0.125, 0.25));
+
+        let plain = Triangle::new(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        );
+        let rec = plain
+            .hit(&Ray::new(Vec3::new(0.25, 0.5, 2.), Vec3::new(0., 0., -1.)), &RAY_INTERVAL)
+            .unwrap();
+        assert_eq!(rec.texture_color(&tinted), Vec3::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn test_vertex_normals() {
+        let normals = [
+            Vec3::new(-1., -1., 1.).unit(),
+            Vec3::new(1., 0., 1.).unit(),
+            Vec3::new(0., 1., 1.).unit(),
+        ];
+        let triangle = Triangle::new_with_options(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            TriangleOptions {
+                uvs: [Uv::new(0., 0.), Uv::new(1., 0.), Uv::new(0., 1.)],
+                normals: Some(normals),
+                ..Default::default()
+            },
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        );
+
+        let corner = Ray::new(Vec3::new(0.999, 0.0005, 2.), Vec3::new(0., 0., -1.));
+        let rec = triangle.hit(&corner, &RAY_INTERVAL).unwrap();
+        assert!((rec.normal - normals[1]).length() < 1e-2);
+
+        let center = Ray::new(Vec3::new(1. / 3., 1. / 3., 2.), Vec3::new(0., 0., -1.));
+        let rec = triangle.hit(&center, &RAY_INTERVAL).unwrap();
+        let expected = (normals[0] + normals[1] + normals[2]).unit();
+        assert!((rec.normal - expected).length() < 1e-9);
+        assert!(rec.onb.tangent.dot(rec.normal).abs() < 1e-9);
+        assert!(rec.onb.bi_tangent.dot(Vec3::new(0., 1., 0.)) > 0.);
+
+        let back = Ray::new(Vec3::new(1. / 3., 1. / 3., -2.), Vec3::new(0., 0., 1.));
+        let rec = triangle.hit(&back, &RAY_INTERVAL).unwrap();
+        assert!(!rec.front_face);
+        assert!((rec.normal + expected).length() < 1e-9);
+    }
 }
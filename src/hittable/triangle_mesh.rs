@@ -0,0 +1,808 @@
+use std::sync::Arc;
+
+use crate::geo::transformation::{NopTransformer, Transformer};
+use crate::geo::vec3::Vec3;
+use crate::geo::{Aabb, Onb, Ray, Uv, UvDerivatives};
+use crate::hittable::triangle::{closest_point_on_triangle, intersect, smooth_onb, uv_tangents};
+use crate::hittable::Hittables::{TriangleMeshType, TriangleType};
+use crate::hittable::{Hittable, Hittables, Sampleable, Triangle, TriangleOptions};
+use crate::loader::cache::{CacheKey, CacheReader, CacheWriter, SceneCache};
+use crate::material::{is_cut_out, Material, Materials, RayHit};
+use crate::random::random_normal_float;
+use crate::sampler::next_2d;
+use crate::util::interval::{Interval, RAY_INTERVAL};
+
+/// Maximum number of triangles in a leaf of the tree of a mesh
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+/// A mesh of triangles sharing one material, like a model loaded from a file.
+/// The corners are shared by the triangles, which only refer to them by index,
+/// so a mesh takes far less memory than the same number of [`Triangle`]s.
+/// The mesh is shared by its clones, which makes it cheap to use as a light
+#[derive(Clone, Debug)]
+pub struct TriangleMesh {
+    data: Arc<MeshData>,
+}
+
+/// The buffers of a mesh, and the tree of bounding boxes that the triangles are sorted in
+#[derive(Debug)]
+struct MeshData {
+    positions: Vec<Vec3>,
+    /// Indices of the corners of each triangle into the positions
+    faces: Vec<[u32; 3]>,
+    /// Texture coordinates of the corners of each triangle, that can differ at seams
+    uvs: Option<Vec<[Uv; 3]>>,
+    /// Vertex colors, with the same indices as the positions
+    colors: Option<Vec<Vec3>>,
+    /// Vertex normals, and the indices of the normals of the corners of each triangle
+    normals: Option<(Vec<Vec3>, Vec<[u32; 3]>)>,
+    nodes: Vec<MeshNode>,
+    /// Running total of the areas of the triangles, for choosing a triangle to sample lights
+    /// by. Empty if the mesh is not a light
+    cumulative_areas: Vec<f64>,
+    mat: Materials,
+    b_box: Aabb,
+    cull_backfaces: bool,
+}
+
+/// A node in the tree of a mesh, where the left child of an inner node is the next node
+#[derive(Clone, Debug)]
+struct MeshNode {
+    b_box: Aabb,
+    /// First triangle of a leaf, or the right child of an inner node
+    start: u32,
+    /// Number of triangles in a leaf, zero for inner nodes
+    count: u32,
+}
+
+/// A triangle while the tree is built
+struct MeshItem {
+    face: u32,
+    b_box: Aabb,
+    center: Vec3,
+}
+
+/// Optional data of the corners of the triangles of a mesh, and how they are hit,
+/// for [`TriangleMesh::new_with_options`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TriangleMeshOptions {
+    /// Texture coordinates of the corners of each triangle, that can differ at seams
+    pub uvs: Option<Vec<[Uv; 3]>>,
+    /// A color for each position, see [`TriangleOptions::colors`]
+    pub colors: Option<Vec<Vec3>>,
+    /// Normals, with the indices of the normals of the corners of each triangle,
+    /// see [`TriangleOptions::normals`]
+    pub normals: Option<(Vec<Vec3>, Vec<[u32; 3]>)>,
+    /// The triangles are not hit by rays coming from behind them
+    pub cull_backfaces: bool,
+}
+
+impl TriangleMesh {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a new mesh of flat triangles with no texture coordinates, each referring to
+    /// three of the positions. A counterclockwise winding is expected
+    pub fn new(
+        positions: Vec<Vec3>,
+        faces: Vec<[u32; 3]>,
+        mat: Materials,
+        transformation: &dyn Transformer,
+    ) -> Hittables {
+        TriangleMesh::new_with_options(
+            positions,
+            faces,
+            TriangleMeshOptions::default(),
+            mat,
+            transformation,
+        )
+    }
+
+    /// Creates a new mesh like [`TriangleMesh::new`],
+    /// with the data of the corners and settings of the options
+    pub fn new_with_options(
+        positions: Vec<Vec3>,
+        faces: Vec<[u32; 3]>,
+        options: TriangleMeshOptions,
+        mat: Materials,
+        transformation: &dyn Transformer,
+    ) -> Hittables {
+        TriangleMesh::new_with_cache(positions, faces, options, mat, transformation, None)
+    }
+
+    /// Creates a new mesh like [`TriangleMesh::new_with_options`], taking the tree
+    /// of the triangles from the cache if the same mesh has been built before
+    pub(crate) fn new_with_cache(
+        positions: Vec<Vec3>,
+        faces: Vec<[u32; 3]>,
+        options: TriangleMeshOptions,
+        mat: Materials,
+        transformation: &dyn Transformer,
+        cache: Option<&SceneCache>,
+    ) -> Hittables {
+        let TriangleMeshOptions {
+            uvs,
+            colors,
+            normals,
+            cull_backfaces,
+        } = options;
+        let positions: Vec<Vec3> = positions
+            .into_iter()
+            .map(|p| transformation.transform(p, false))
+            .collect();
+
+        let (nodes, order) = match cache {
+            Some(cache) => cached_tree(&positions, &faces, cache),
+            None => build_tree(&positions, &faces),
+        };
+
+        // The triangles are stored in the order of the leaves
+        let faces: Vec<[u32; 3]> = order.iter().map(|face| faces[*face as usize]).collect();
+        let uvs = uvs.map(|uvs| order.iter().map(|face| uvs[*face as usize]).collect());
+        let normals = normals.map(|(normals, normal_faces)| {
+            (
+                normals
+                    .into_iter()
+                    .map(|n| transformation.transform_normal(n))
+                    .collect(),
+                order
+                    .iter()
+                    .map(|face| normal_faces[*face as usize])
+                    .collect(),
+            )
+        });
+        let b_box = nodes.first().map(|n| n.b_box.clone()).unwrap_or_default();
+
+        let mut mesh = MeshData {
+            positions,
+            faces,
+            uvs,
+            colors,
+            normals,
+            nodes,
+            cumulative_areas: Vec::new(),
+            mat,
+            b_box,
+            cull_backfaces,
+        };
+        if mesh.mat.is_light() {
+            mesh.cumulative_areas = (0..mesh.faces.len())
+                .scan(0., |sum, face| {
+                    let [v0, v1, v2] = mesh.vertices(face);
+                    *sum += (v1 - v0).cross(v2 - v0).length() / 2.;
+                    Some(*sum)
+                })
+                .collect();
+        }
+
+        Hittables::from(TriangleMesh {
+            data: Arc::new(mesh),
+        })
+    }
+
+    /// Number of triangles in the mesh
+    pub fn len(&self) -> usize {
+        self.data.faces.len()
+    }
+
+    /// Whether the mesh has no triangles
+    pub fn is_empty(&self) -> bool {
+        self.data.faces.is_empty()
+    }
+
+    /// Surface area of all the triangles of a mesh that is a light, zero for other meshes
+    pub(crate) fn area(&self) -> f64 {
+        self.data.cumulative_areas.last().copied().unwrap_or(0.)
+    }
+
+    /// The triangles of the mesh as separate triangles, for code that works with triangles
+    pub(crate) fn triangles(&self) -> Vec<Triangle> {
+        let mesh = &self.data;
+        (0..mesh.faces.len())
+            .map(|face| {
+                let [v0, v1, v2] = mesh.vertices(face);
+                let options = TriangleOptions {
+                    uvs: mesh.tex_coords(face),
+                    lightmap_uvs: None,
+                    normals: mesh.vertex_normals(face),
+                    colors: mesh.vertex_colors(face),
+                    cull_backfaces: mesh.cull_backfaces,
+                };
+                let triangle = Triangle::new_with_options(
+                    v0,
+                    v1,
+                    v2,
+                    options,
+                    mesh.mat.clone(),
+                    &NopTransformer(),
+                );
+                match triangle {
+                    TriangleType(t) => t,
+                    _ => unreachable!(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl MeshData {
+    fn vertices(&self, face: usize) -> [Vec3; 3] {
+        self.faces[face].map(|i| self.positions[i as usize])
+    }
+
+    fn tex_coords(&self, face: usize) -> [Uv; 3] {
+        match &self.uvs {
+            Some(uvs) => uvs[face],
+            None => [Uv::default(); 3],
+        }
+    }
+
+    /// Texture coordinate at the barycentric coordinates of the point on the face
+    fn tex_coord(&self, face: usize, b1: f64, b2: f64) -> Uv {
+        let uvs = self.tex_coords(face);
+        let (u, v) = (b1 as f32, b2 as f32);
+        let uv0 = 1. - u - v;
+        Uv::new(
+            uv0 * uvs[0].u + u * uvs[1].u + v * uvs[2].u,
+            uv0 * uvs[0].v + u * uvs[1].v + v * uvs[2].v,
+        )
+    }
+
+    fn vertex_colors(&self, face: usize) -> Option<[Vec3; 3]> {
+        let colors = self.colors.as_ref()?;
+        Some(self.faces[face].map(|i| colors[i as usize]))
+    }
+
+    fn vertex_normals(&self, face: usize) -> Option<[Vec3; 3]> {
+        let (normals, normal_faces) = self.normals.as_ref()?;
+        Some(normal_faces[face].map(|i| normals[i as usize]))
+    }
+
+    /// Closest hit of the ray among the triangles, with the ray length and the barycentric
+    /// coordinates of the second and third corners of the hit triangle
+    fn closest_hit(&self, r: &Ray, ray_length: &Interval) -> Option<(usize, f64, f64, f64)> {
+        let mut closest = None;
+        let mut ray_length = *ray_length;
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if !node.b_box.hit(r) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.start as usize);
+                stack.push(n + 1);
+                continue;
+            }
+            let start = node.start as usize;
+            for face in start..start + node.count as usize {
+                let vertices = self.vertices(face);
+                let Some((t, u, v)) = intersect(vertices, r, &ray_length) else {
+                    continue;
+                };
+                if self.cull_backfaces {
+                    let [v0, v1, v2] = vertices;
+                    if r.direction.dot((v1 - v0).cross(v2 - v0)) >= 0. {
+                        continue;
+                    }
+                }
+                if let Some(alpha) = self.mat.alpha() {
+                    if is_cut_out(alpha, self.tex_coord(face, u, v), r.time) {
+                        continue;
+                    }
+                }
+                ray_length = Interval::new(ray_length.min, t);
+                closest = Some((face, t, u, v));
+            }
+        }
+        closest
+    }
+}
+
+/// Builds the tree of the triangles, returning its nodes and the triangles in the order of
+/// the leaves
+fn build_tree(positions: &[Vec3], faces: &[[u32; 3]]) -> (Vec<MeshNode>, Vec<u32>) {
+    let mut items: Vec<MeshItem> = faces
+        .iter()
+        .enumerate()
+        .map(|(face, f)| {
+            let [v0, v1, v2] = f.map(|i| positions[i as usize]);
+            let b_box = Aabb::new_from_3_points(v0, v1, v2).pad_if_needed();
+            MeshItem {
+                face: face as u32,
+                center: b_box.center(),
+                b_box,
+            }
+        })
+        .collect();
+    let mut nodes = Vec::new();
+    build_nodes(&mut nodes, &mut items, 0);
+    (nodes, items.iter().map(|item| item.face).collect())
+}
+
+/// Builds the tree like [`build_tree`], or reads it from the cache
+fn cached_tree(
+    positions: &[Vec3],
+    faces: &[[u32; 3]],
+    cache: &SceneCache,
+) -> (Vec<MeshNode>, Vec<u32>) {
+    let key = CacheKey::new("mesh")
+        .f64s(positions.iter().flat_map(|p| [p.x, p.y, p.z]))
+        .u32s(faces.iter().flatten().copied())
+        .finish();
+    if let Some(tree) = cache.get(key).and_then(|bytes| read_tree(&bytes, faces.len())) {
+        return tree;
+    }
+
+    let (nodes, order) = build_tree(positions, faces);
+    cache.put(key, &write_tree(&nodes, &order));
+    (nodes, order)
+}
+
+/// Writes the tree in the format read by [`read_tree`]
+fn write_tree(nodes: &[MeshNode], order: &[u32]) -> Vec<u8> {
+    let mut writer = CacheWriter::default();
+    writer.u32(nodes.len() as u32);
+    for node in nodes {
+        writer.aabb(&node.b_box);
+        writer.u32(node.start);
+        writer.u32(node.count);
+    }
+    for face in order {
+        writer.u32(*face);
+    }
+    writer.0
+}
+
+/// Reads a tree written by [`cached_tree`], checking that it fits the triangles
+fn read_tree(bytes: &[u8], face_count: usize) -> Option<(Vec<MeshNode>, Vec<u32>)> {
+    let mut reader = CacheReader::new(bytes);
+    let node_count = reader.u32()? as usize;
+    let nodes = (0..node_count)
+        .map(|_| {
+            Some(MeshNode {
+                b_box: reader.aabb()?,
+                start: reader.u32()?,
+                count: reader.u32()?,
+            })
+        })
+        .collect::<Option<Vec<MeshNode>>>()?;
+    let order = (0..face_count)
+        .map(|_| reader.u32())
+        .collect::<Option<Vec<u32>>>()?;
+
+    let fits = nodes.iter().all(|node| {
+        node.count == 0 || node.start as usize + node.count as usize <= face_count
+    });
+    let in_range = order.iter().all(|face| (*face as usize) < face_count);
+    (reader.is_done() && fits && in_range && is_depth_first(&nodes)).then_some((nodes, order))
+}
+
+/// Checks that the nodes are laid out like [`build_nodes`] lays them out, with each left child
+/// following its parent and each right child following the left subtree. Every node is then
+/// visited once when walking the tree, so a corrupt tree can not make the walk loop forever
+fn is_depth_first(nodes: &[MeshNode]) -> bool {
+    if nodes.is_empty() {
+        return true;
+    }
+    let mut next = 0;
+    let mut stack = vec![0];
+    while let Some(n) = stack.pop() {
+        if n != next || n >= nodes.len() {
+            return false;
+        }
+        next += 1;
+        if nodes[n].count == 0 {
+            stack.push(nodes[n].start as usize);
+            stack.push(n + 1);
+        }
+    }
+    next == nodes.len()
+}
+
+/// Adds the nodes for the items to the tree, sorting the items in the order of the leaves.
+/// The start is the position of the first of the items among all items
+fn build_nodes(nodes: &mut Vec<MeshNode>, items: &mut [MeshItem], start: usize) {
+    let Some(b_box) = items.iter().map(|item| item.b_box.clone()).reduce(|a, b| a.combine(&b))
+    else {
+        return;
+    };
+    let position = nodes.len();
+    nodes.push(MeshNode {
+        b_box,
+        start: start as u32,
+        count: items.len() as u32,
+    });
+    if items.len() <= MAX_LEAF_TRIANGLES {
+        return;
+    }
+
+    // Splits at the middle of the most spread axis of the centers, like the bvh
+    let spread = |axis: u8| {
+        let (min, max) = items.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), i| {
+            let c = i.center.axis(axis);
+            (min.min(c), max.max(c))
+        });
+        (max - min, (min + max) * 0.5)
+    };
+    let (axis, (_, center)) = (0..3)
+        .map(|axis| (axis, spread(axis)))
+        .reduce(|a, b| if b.1 .0 > a.1 .0 { b } else { a })
+        .unwrap();
+    items.sort_unstable_by(|a, b| a.center.axis(axis).total_cmp(&b.center.axis(axis)));
+    let mut mid = items.partition_point(|item| item.center.axis(axis) < center);
+    if mid == 0 || mid == items.len() {
+        mid = items.len() / 2;
+    }
+
+    let (left, right) = items.split_at_mut(mid);
+    build_nodes(nodes, left, start);
+    let right_position = nodes.len();
+    build_nodes(nodes, right, start + mid);
+    nodes[position].start = right_position as u32;
+    nodes[position].count = 0;
+}
+
+impl Sampleable for TriangleMesh {
+    /// Sums the pdf for all triangles along the direction,
+    /// as any of them could have been sampled
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let mesh = &self.data;
+        let total_area = self.area();
+        let ray = Ray::new(origin, direction);
+
+        let mut pdf = 0.;
+        let mut ray_length = RAY_INTERVAL;
+        while let Some((face, t, _, _)) = mesh.closest_hit(&ray, &ray_length) {
+            let [v0, v1, v2] = mesh.vertices(face);
+            let normal = (v1 - v0).cross(v2 - v0).unit();
+            let distance_squared = t * t * direction.length_squared();
+            let cosine = (direction.dot(normal) / direction.length()).abs();
+            pdf += distance_squared / (cosine * total_area);
+            ray_length = Interval::new(t + RAY_INTERVAL.min, RAY_INTERVAL.max);
+        }
+        pdf
+    }
+
+    /// Chooses a triangle by its area, and a point on it
+    fn random_direction(&self, origin: Vec3) -> Vec3 {
+        let mesh = &self.data;
+        let r = random_normal_float() * self.area();
+        let face = mesh
+            .cumulative_areas
+            .partition_point(|a| *a <= r)
+            .min(mesh.faces.len() - 1);
+
+        let [v0, v1, v2] = mesh.vertices(face);
+        let (mut s, mut t) = next_2d();
+        // Fold points in the parallelogram outside the triangle back inside it
+        if s + t > 1. {
+            s = 1. - s;
+            t = 1. - t;
+        }
+        v0 + (v1 - v0) * s + (v2 - v0) * t - origin
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn as_sampleable(&self) -> Option<&dyn Sampleable> {
+        if self.data.cumulative_areas.is_empty() {
+            None
+        } else {
+            Some(self)
+        }
+    }
+
+    fn hit(&self, r: &Ray, ray_length: &Interval) -> Option<RayHit> {
+        let mesh = &self.data;
+        let (face, tt, b1, b2) = mesh.closest_hit(r, ray_length)?;
+
+        let [v0, v1, v2] = mesh.vertices(face);
+        let v0v1 = v1 - v0;
+        let v0v2 = v2 - v0;
+        let face_normal = v0v1.cross(v0v2).unit();
+        let uvs = mesh.tex_coords(face);
+        let (dp_du, dp_dv) = uv_tangents(v0v1, v0v2, uvs);
+
+        let intersection = r.at(tt);
+        let u = b1 as f32;
+        let v = b2 as f32;
+        let uv0 = 1. - u - v;
+        let uv = mesh.tex_coord(face, b1, b2);
+
+        let uv_derivatives = UvDerivatives::new(r, intersection, face_normal, dp_du, dp_dv);
+        let vertex_color = mesh.vertex_colors(face).map(|[c0, c1, c2]| {
+            c0 * uv0 as f64 + c1 * u as f64 + c2 * v as f64
+        });
+
+        let front_face = r.direction.dot(face_normal) < 0.;
+        let (tangent, bi_tangent) = (dp_du.unit(), dp_dv.unit());
+        let mut onb = match mesh.vertex_normals(face) {
+            Some(normals) => smooth_onb(
+                &normals,
+                [1. - b1 - b2, b1, b2],
+                face_normal,
+                tangent,
+                bi_tangent,
+            ),
+            None => Onb {
+                tangent,
+                bi_tangent,
+                normal: face_normal,
+            },
+        };
+        if !front_face {
+            onb.normal = onb.normal.neg()
+        }
+        Some(
+            RayHit::new(
+                intersection,
+                onb,
+                &mesh.mat,
+                tt,
+                uv,
+                front_face,
+                r.time,
+            )
+            .with_uv_derivatives(uv_derivatives)
+            .with_vertex_color(vertex_color),
+        )
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.data.b_box
+    }
+
+    fn get_lights(&self) -> Vec<Hittables> {
+        if self.as_sampleable().is_some() {
+            vec![TriangleMeshType(self.clone())]
+        } else {
+            vec![]
+        }
+    }
+
+    /// Searches the nearest nodes first, skipping nodes that are farther away
+    /// than the closest point found so far
+    fn closest_point(&self, p: Vec3) -> Option<Vec3> {
+        let mesh = &self.data;
+        let mut closest: Option<(Vec3, f64)> = None;
+        let mut stack = Vec::new();
+        if !mesh.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(n) = stack.pop() {
+            let node = &mesh.nodes[n];
+            if closest.is_some_and(|(_, d)| node.b_box.distance_squared(p) >= d) {
+                continue;
+            }
+            if node.count == 0 {
+                let (left, right) = (n + 1, node.start as usize);
+                let distance = |n: usize| mesh.nodes[n].b_box.distance_squared(p);
+                if distance(left) <= distance(right) {
+                    stack.extend([right, left]);
+                } else {
+                    stack.extend([left, right]);
+                }
+                continue;
+            }
+            let start = node.start as usize;
+            for face in start..start + node.count as usize {
+                let point = closest_point_on_triangle(mesh.vertices(face), p);
+                let distance_squared = (point - p).length_squared();
+                if closest.is_none_or(|(_, d)| distance_squared < d) {
+                    closest = Some((point, distance_squared));
+                }
+            }
+        }
+        closest.map(|(point, _)| point)
+    }
+
+    fn materials(&self) -> Vec<&Materials> {
+        vec![&self.data.mat]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::transformation::{NopTransformer, Translation};
+    use crate::material::texture::SolidColor;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::util::chi_squared::chi_squared_test;
+
+    use super::*;
+
+    /// A grid of quads in the xy plane, each made of two triangles
+    fn grid(size: u32, mat: Materials) -> (Hittables, Vec<Hittables>) {
+        let mut positions = Vec::new();
+        for y in 0..=size {
+            for x in 0..=size {
+                positions.push(Vec3::new(x as f64, y as f64, 0.));
+            }
+        }
+        let index = |x: u32, y: u32| y * (size + 1) + x;
+        let mut faces = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                faces.push([index(x, y), index(x + 1, y), index(x + 1, y + 1)]);
+                faces.push([index(x, y), index(x + 1, y + 1), index(x, y + 1)]);
+            }
+        }
+        let uvs: Vec<[Uv; 3]> = faces
+            .iter()
+            .map(|f| {
+                f.map(|i| {
+                    let p = positions[i as usize] / size as f64;
+                    Uv::new(p.x as f32, p.y as f32)
+                })
+            })
+            .collect();
+        let translation = Translation::new(Vec3::new(0., 0., -1.));
+
+        let triangles = faces
+            .iter()
+            .zip(&uvs)
+            .map(|(f, uv)| {
+                let [v0, v1, v2] = f.map(|i| positions[i as usize]);
+                Triangle::new_with_options(
+                    v0,
+                    v1,
+                    v2,
+                    TriangleOptions {
+                        uvs: *uv,
+                        ..Default::default()
+                    },
+                    mat.clone(),
+                    &translation,
+                )
+            })
+            .collect();
+        let mesh = TriangleMesh::new_with_options(
+            positions,
+            faces,
+            TriangleMeshOptions {
+                uvs: Some(uvs),
+                ..Default::default()
+            },
+            mat,
+            &translation,
+        );
+        (mesh, triangles)
+    }
+
+    #[test]
+    fn test_hit_matches_triangles() {
+        let (mesh, triangles) = grid(10, Lambertian::new(SolidColor::new(1., 1., 1.), None));
+        let TriangleMeshType(m) = &mesh else {
+            panic!("Should be a mesh");
+        };
+        assert_eq!(m.len(), 200);
+
+        for i in 0..50 {
+            let target = Vec3::new(i as f64 * 0.19 + 0.05, i as f64 * 0.13 + 1.01, -1.);
+            let ray = Ray::new(Vec3::new(5., 5., 5.), target - Vec3::new(5., 5., 5.));
+            let rec = mesh.hit(&ray, &RAY_INTERVAL).unwrap();
+            let expected = triangles
+                .iter()
+                .filter_map(|t| t.hit(&ray, &RAY_INTERVAL))
+                .min_by(|a, b| a.ray_length.total_cmp(&b.ray_length))
+                .unwrap();
+            // Points on the edge between two triangles can be hit on either of them
+            assert_eq!(rec.hit_point, expected.hit_point);
+            assert!((rec.uv.u - expected.uv.u).abs() < 1e-6);
+            assert!((rec.uv.v - expected.uv.v).abs() < 1e-6);
+            assert_eq!(rec.normal, expected.normal);
+            assert_eq!(rec.onb.tangent, expected.onb.tangent);
+        }
+
+        let miss = Ray::new(Vec3::new(11., 5., 5.), Vec3::new(0., 0., -1.));
+        assert!(mesh.hit(&miss, &RAY_INTERVAL).is_none());
+
+        let closest = mesh.closest_point(Vec3::new(3.3, 12., 1.)).unwrap();
+        assert!((closest - Vec3::new(3.3, 10., -1.)).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_pdf_matches_random_direction() {
+        let (mesh, _) = grid(3, DiffuseLight::new(1., 1., 1., None));
+        let lights = mesh.get_lights();
+        assert_eq!(lights.len(), 1);
+
+        let light = lights[0].as_sampleable().unwrap();
+        let origin = Vec3::new(0.5, 1.2, 2.);
+        let res = chi_squared_test(
+            || light.random_direction(origin),
+            |direction| light.pdf_value(origin, direction),
+        );
+        assert_eq!(res, Ok(()));
+    }
+
+    #[test]
+    fn test_vertex_normals() {
+        // A pyramid without a bottom, with normals pointing away from its tip
+        let positions = vec![
+            Vec3::new(0., 1., 0.),
+            Vec3::new(-1., 0., 1.),
+            Vec3::new(1., 0., 1.),
+            Vec3::new(1., 0., -1.),
+            Vec3::new(-1., 0., -1.),
+        ];
+        let faces = vec![[0, 1, 2], [0, 2, 3], [0, 3, 4], [0, 4, 1]];
+        let normals: Vec<Vec3> = positions
+            .iter()
+            .map(|p| (*p - Vec3::new(0., -1., 0.)).unit())
+            .collect();
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let mesh = TriangleMesh::new_with_options(
+            positions.clone(),
+            faces.clone(),
+            TriangleMeshOptions {
+                normals: Some((normals.clone(), faces.clone())),
+                ..Default::default()
+            },
+            mat.clone(),
+            &NopTransformer(),
+        );
+        let triangles: Vec<Hittables> = faces
+            .iter()
+            .map(|f| {
+                let [v0, v1, v2] = f.map(|i| positions[i as usize]);
+                Triangle::new_with_options(
+                    v0,
+                    v1,
+                    v2,
+                    TriangleOptions {
+                        normals: Some(f.map(|i| normals[i as usize])),
+                        ..Default::default()
+                    },
+                    mat.clone(),
+                    &NopTransformer(),
+                )
+            })
+            .collect();
+
+        for i in 0..20 {
+            let target = Vec3::new(i as f64 * 0.05 - 0.5, 0.2, 0.3);
+            let ray = Ray::new(Vec3::new(0., 0.5, 5.), target - Vec3::new(0., 0.5, 5.));
+            let rec = mesh.hit(&ray, &RAY_INTERVAL).unwrap();
+            let expected = triangles
+                .iter()
+                .filter_map(|t| t.hit(&ray, &RAY_INTERVAL))
+                .min_by(|a, b| a.ray_length.total_cmp(&b.ray_length))
+                .unwrap();
+            assert!((rec.normal - expected.normal).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_read_tree() {
+        let (mesh, _) = grid(10, Lambertian::new(SolidColor::new(1., 1., 1.), None));
+        let TriangleMeshType(m) = &mesh else {
+            panic!("Should be a mesh");
+        };
+        let (nodes, order) = build_tree(&m.data.positions, &m.data.faces);
+        let tree = read_tree(&write_tree(&nodes, &order), order.len()).unwrap();
+        assert_eq!(tree.1, order);
+
+        // Inner nodes pointing back up the tree would make the walk loop forever
+        let inner = nodes.iter().rposition(|node| node.count == 0).unwrap();
+        for start in [0, inner as u32, nodes[inner].start + 1] {
+            let mut corrupt = nodes.clone();
+            corrupt[inner].start = start;
+            assert!(read_tree(&write_tree(&corrupt, &order), order.len()).is_none());
+        }
+    }
+
+    #[test]
+    fn test_empty_mesh() {
+        let mesh = TriangleMesh::new(
+            vec![],
+            vec![],
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        );
+        let ray = Ray::new(Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.));
+        assert!(mesh.hit(&ray, &RAY_INTERVAL).is_none());
+        assert!(mesh.closest_point(Vec3::new(0., 0., 0.)).is_none());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::geo::transformation::Matrix4;
+use crate::hittable::Hittables::InstanceType;
+use crate::hittable::{Hittable, Hittables};
+
+/// Visits the hittables in a tree of hittables, for tools like outliners, statistics
+/// and exporters. Closures taking the same arguments as [`HittableVisitor::visit`] are visitors
+pub trait HittableVisitor {
+    /// Called for each hittable in the tree, with its depth in the tree and the transformation
+    /// from the space of the hittable to the scene, which is the identity outside of instances
+    fn visit(&mut self, hittable: &Hittables, depth: usize, transformation: &Matrix4);
+}
+
+impl<F: FnMut(&Hittables, usize, &Matrix4)> HittableVisitor for F {
+    fn visit(&mut self, hittable: &Hittables, depth: usize, transformation: &Matrix4) {
+        self(hittable, depth, transformation)
+    }
+}
+
+/// Walks the tree of hittables depth first, visiting each hittable before its children.
+/// Hittables shared by several [`crate::hittable::Instance`]s are visited once per instance
+pub fn visit(hittable: &Hittables, visitor: &mut dyn HittableVisitor) {
+    visit_at(hittable, 0, &Matrix4::identity(), visitor);
+}
+
+fn visit_at(
+    hittable: &Hittables,
+    depth: usize,
+    transformation: &Matrix4,
+    visitor: &mut dyn HittableVisitor,
+) {
+    visitor.visit(hittable, depth, transformation);
+    let transformation = match hittable {
+        InstanceType(instance) => instance.transformation().then(transformation),
+        _ => *transformation,
+    };
+    for child in hittable.children() {
+        visit_at(child, depth + 1, &transformation, visitor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::geo::transformation::{NopTransformer, Transformer, Translation};
+    use crate::geo::vec3::Vec3;
+    use crate::hittable::{Bvh, Instance, MaterialOverride, Quad, Sphere};
+    use crate::material::texture::SolidColor;
+    use crate::material::{Lambertian, MaterialOverrides};
+
+    use super::*;
+
+    #[test]
+    fn test_visit() {
+        let mat = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let quad = Arc::new(Quad::new(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            mat.clone(),
+            &NopTransformer(),
+        ));
+        let world = Bvh::new(vec![
+            Sphere::new(Vec3::new(0., 0., 0.), 1., mat.clone()),
+            MaterialOverride::new(
+                Instance::new(quad.clone(), &Translation::new(Vec3::new(0., 5., 0.))),
+                MaterialOverrides::default(),
+            ),
+            Instance::new(quad, &Translation::new(Vec3::new(0., -5., 0.))),
+        ]);
+
+        let mut visited = Vec::new();
+        let mut materials = 0;
+        visit(&world, &mut |h: &Hittables, depth, t: &Matrix4| {
+            materials += h.materials().len();
+            let origin = t.transform(Vec3::new(0., 0., 0.), false);
+            visited.push((depth, origin, matches!(h, Hittables::QuadType(_))));
+        });
+
+        assert_eq!(visited.len(), 7);
+        assert_eq!(materials, 3);
+        let quads: Vec<_> = visited.iter().filter(|(_, _, is_quad)| *is_quad).collect();
+        assert_eq!(quads.len(), 2);
+        assert!(quads.contains(&&(3, Vec3::new(0., 5., 0.), true)));
+        assert!(quads.contains(&&(2, Vec3::new(0., -5., 0.), true)));
+    }
+}
@@ -7,25 +7,29 @@
 //! * Soft shadows
 //!
 //! Additionally, the library has:
-//! * Loading of obj models with included materials
+//! * Loading of obj and glTF models with included materials
 //! * Multithreaded Bvh creation to greatly speed up rendering
 //! * Post-processing of rendered images by:
 //!   * [Open Image Denoise](https://www.openimagedenoise.org/)
 //!   * Bloom filter
+//!   * Tone mapping
+//!   * Film grain
 //! * Bump mapping
 //! * Light attenuation
+//! * HDR environment maps, that are importance sampled as lights
+//! * Sun and point lights
+//! * Alpha cutout textures
 //!
 //! ## Example:
 //! ```rust
-//! # use std::sync::mpsc::channel;
-//! # use std::thread;
+//! # use std::collections::HashMap;
 //! # use image::RgbImage;
 //! # use solstrale::camera::CameraConfig;
 //! # use solstrale::geo::vec3::Vec3;
 //! # use solstrale::hittable::{Bvh, Sphere, Hittable};
 //! # use solstrale::material::{DiffuseLight, Lambertian};
 //! # use solstrale::material::texture::SolidColor;
-//! # use solstrale::ray_trace;
+//! # use solstrale::RenderProgressIter;
 //! # use solstrale::renderer::{RenderConfig, Scene};
 //! # use solstrale::renderer::shader::PathTracingShader;
 //! let camera = CameraConfig {
@@ -34,27 +38,25 @@
 //!     look_from: Vec3::new(0., 0., 4.),
 //!     look_at: Vec3::new(0., 0., 0.),
 //!     up: Vec3::new(0., 1., 0.),
+//!     focus_distance: None,
 //! };
 //! let mut world = Vec::new();
 //! let yellow = Lambertian::new(SolidColor::new(1., 1., 0.), None);
 //! let light = DiffuseLight::new(10., 10., 10., None);
 //! world.push(Sphere::new(Vec3::new(0., 0., 0.), 0.5, yellow));
+//! world.push(Sphere::new(Vec3::new(0., 10., 0.), 3., light));
 //!
 //! let scene = Scene {
 //!     world: Bvh::new(world),
 //!     camera,
+//!     views: HashMap::new(),
 //!     background_color: Vec3::new(0.2, 0.3, 0.5),
+//!     environment_map: None,
+//!     background: None,
 //!     render_config: RenderConfig::default(),
 //! };
 //!
-//! let (output_sender, output_receiver) = channel();
-//! let (_, abort_receiver) = channel();
-//!
-//! thread::spawn(move || {
-//!     ray_trace(scene, &output_sender, &abort_receiver).unwrap();
-//! });
-//!
-//! for render_output in output_receiver {
+//! for render_output in RenderProgressIter::new(scene).unwrap() {
 //!     let _image = render_output.render_image;
 //! }
 //! ```
@@ -68,19 +70,35 @@
 //! ## Credits
 //! The ray tracing is inspired by the excellent [Ray Tracing in One Weekend Book Series](https://github.com/RayTracing/raytracing.github.io) by Peter Shirley
 
-use crate::renderer::{RenderProgress, Renderer, Scene};
+use crate::renderer::{RenderImage, RenderImageStrategy, RenderProgress, Renderer, Scene};
+use image::RgbImage;
+use simple_error::SimpleError;
 use std::error::Error;
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::thread::JoinHandle;
 
+pub mod bake;
 pub mod camera;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod geo;
+pub mod gpu;
 pub mod hittable;
 pub mod loader;
 pub mod material;
 pub mod pdf;
 pub mod post;
+pub mod probe;
+#[cfg(feature = "python")]
+mod python;
+pub mod query;
 pub mod random;
 pub mod renderer;
+pub mod sampler;
+pub mod scatter;
+#[cfg(all(test, feature = "statistical-tests"))]
+mod statistical_tests;
 pub mod util;
 
 /// Executes the ray tracing with the given [`Scene`] and reports [`RenderProgress`] on
@@ -97,3 +115,166 @@ pub fn ray_trace<'a>(
 ) -> Result<(), Box<dyn Error>> {
     Renderer::new(scene)?.render(output, abort)
 }
+
+/// Renders the given [`Scene`] with the given image size, and blocks until the final image
+/// is available. Useful when progress reporting and aborting are not needed.
+/// The image has 8 bit colors, whatever the image format of the render configuration
+///
+/// # Arguments
+/// * `scene` - A scene describing how, and what should be rendered.
+///   Width and height of its render configuration are replaced by the given size
+/// * `width` - Width in pixels of the rendered image
+/// * `height` - Height in pixels of the rendered image
+///
+/// # Examples:
+/// ```
+/// # use std::collections::HashMap;
+/// # use solstrale::camera::CameraConfig;
+/// # use solstrale::geo::vec3::Vec3;
+/// # use solstrale::hittable::{Bvh, Sphere};
+/// # use solstrale::material::{DiffuseLight, Lambertian};
+/// # use solstrale::material::texture::SolidColor;
+/// # use solstrale::render_image;
+/// # use solstrale::renderer::{RenderConfig, Scene};
+/// let scene = Scene {
+///     world: Bvh::new(vec![
+///         Sphere::new(Vec3::new(0., 0., 0.), 0.5, Lambertian::new(SolidColor::new(1., 1., 0.), None)),
+///         Sphere::new(Vec3::new(0., 10., 0.), 3., DiffuseLight::new(10., 10., 10., None)),
+///     ]),
+///     camera: CameraConfig {
+///         look_from: Vec3::new(0., 0., 4.),
+///         ..CameraConfig::default()
+///     },
+///     views: HashMap::new(),
+///     background_color: Vec3::new(0.2, 0.3, 0.5),
+///     environment_map: None,
+///     background: None,
+///     render_config: RenderConfig {
+///         samples_per_pixel: 2,
+///         ..RenderConfig::default()
+///     },
+/// };
+///
+/// let image = render_image(scene, 40, 20).unwrap();
+/// assert_eq!(image.dimensions(), (40, 20));
+/// ```
+pub fn render_image(
+    mut scene: Scene,
+    width: usize,
+    height: usize,
+) -> Result<RgbImage, Box<dyn Error>> {
+    scene.render_config.width = width;
+    scene.render_config.height = height;
+    scene.render_config.render_image_strategy = RenderImageStrategy::OnlyFinal;
+
+    let (output_sender, output_receiver) = channel();
+    let (_abort_sender, abort_receiver) = channel();
+
+    ray_trace(scene, &output_sender, &abort_receiver)?;
+    drop(output_sender);
+
+    output_receiver
+        .into_iter()
+        .filter_map(|render_progress| render_progress.render_image)
+        .last()
+        .map(RenderImage::into_rgb8)
+        .ok_or_else(|| Box::new(SimpleError::new("No image was rendered")).into())
+}
+
+/// Renders a [`Scene`] in a background thread, and iterates over the [`RenderProgress`]
+/// reported until the rendering is complete or aborted.
+/// Dropping the iterator aborts the rendering.
+///
+/// # Examples:
+/// ```
+/// # use std::collections::HashMap;
+/// # use solstrale::camera::CameraConfig;
+/// # use solstrale::geo::vec3::Vec3;
+/// # use solstrale::hittable::{Bvh, Sphere};
+/// # use solstrale::material::{DiffuseLight, Lambertian};
+/// # use solstrale::material::texture::SolidColor;
+/// # use solstrale::RenderProgressIter;
+/// # use solstrale::renderer::{RenderConfig, Scene};
+/// # let scene = Scene {
+/// #     world: Bvh::new(vec![
+/// #         Sphere::new(Vec3::new(0., 0., 0.), 0.5, Lambertian::new(SolidColor::new(1., 1., 0.), None)),
+/// #         Sphere::new(Vec3::new(0., 10., 0.), 3., DiffuseLight::new(10., 10., 10., None)),
+/// #     ]),
+/// #     camera: CameraConfig::default(),
+/// #     views: HashMap::new(),
+/// #     background_color: Vec3::new(0.2, 0.3, 0.5),
+/// #     environment_map: None,
+/// #     background: None,
+/// #     render_config: RenderConfig {
+/// #         width: 40,
+/// #         height: 20,
+/// #         samples_per_pixel: 100,
+/// #         ..RenderConfig::default()
+/// #     },
+/// # };
+/// let mut progress_iter = RenderProgressIter::new(scene).unwrap();
+///
+/// let first_progress = progress_iter.next().unwrap();
+/// if first_progress.estimated_time_left.as_secs() > 60 {
+///     progress_iter.abort();
+/// }
+/// let final_image = progress_iter.filter_map(|p| p.render_image).last();
+/// ```
+pub struct RenderProgressIter {
+    output: Receiver<RenderProgress>,
+    abort: Sender<bool>,
+    render_thread: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl RenderProgressIter {
+    /// Starts rendering the given [`Scene`] in a background thread.
+    /// Errors in the scene are reported directly, before rendering is started
+    pub fn new(scene: Scene) -> Result<RenderProgressIter, Box<dyn Error>> {
+        let renderer = Renderer::new(scene)?;
+        let (output_sender, output_receiver) = channel();
+        let (abort_sender, abort_receiver) = channel();
+
+        let render_thread = thread::spawn(move || {
+            renderer
+                .render(&output_sender, &abort_receiver)
+                .map_err(|e| e.to_string())
+        });
+
+        Ok(RenderProgressIter {
+            output: output_receiver,
+            abort: abort_sender,
+            render_thread: Some(render_thread),
+        })
+    }
+
+    /// Requests the rendering to stop as soon as possible,
+    /// after which the iterator ends
+    pub fn abort(&self) {
+        // The render thread might have finished already, in which case there is nothing to abort
+        let _ = self.abort.send(true);
+    }
+
+    /// Waits for the rendering to finish, discarding any remaining progress,
+    /// and returns the error that stopped the rendering, if any
+    pub fn join(mut self) -> Result<(), Box<dyn Error>> {
+        let render_thread = self.render_thread.take().expect("Render thread is only joined once");
+        match render_thread.join() {
+            Ok(res) => res.map_err(|e| Box::new(SimpleError::new(e)).into()),
+            Err(_) => Err(Box::new(SimpleError::new("Render thread panicked"))),
+        }
+    }
+}
+
+impl Iterator for RenderProgressIter {
+    type Item = RenderProgress;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.output.recv().ok()
+    }
+}
+
+impl Drop for RenderProgressIter {
+    fn drop(&mut self) {
+        self.abort();
+    }
+}
@@ -0,0 +1,266 @@
+//! On disk cache of the results of slow preprocessing of scenes, like building the trees of
+//! bvhs and meshes and decoding textures. Entries are files named by a hash of everything the
+//! result depends on, so changed inputs never get stale results, and the same scene rendered
+//! again, or by another process, skips the preprocessing.
+//!
+//! The entries start with the version of their format. Entries of other versions are ignored
+//! and replaced, so caches made by other versions of the crate are safe to keep.
+//! The cache is best effort, failing to read or write an entry only means it is recomputed.
+//! Nothing is ever removed from the directory, that is left to the user
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use image::RgbImage;
+use xxhash_rust::xxh3::Xxh3Default;
+
+use crate::geo::vec3::Vec3;
+use crate::geo::Aabb;
+
+/// Version of the format of the entries, changed whenever any format changes
+const CACHE_VERSION: u32 = 1;
+
+/// First bytes of each entry
+const MAGIC: &[u8; 4] = b"SLSC";
+
+/// A directory of cached preprocessing results, see the [module](self) documentation
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneCache {
+    dir: PathBuf,
+}
+
+impl SceneCache {
+    /// Creates a cache in the directory, which is created when the first entry is written
+    pub fn new(dir: impl Into<PathBuf>) -> SceneCache {
+        SceneCache { dir: dir.into() }
+    }
+
+    fn entry_path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.bin", key))
+    }
+
+    /// The content of the entry, if there is one of the current version
+    pub(crate) fn get(&self, key: u64) -> Option<Vec<u8>> {
+        let bytes = fs::read(self.entry_path(key)).ok()?;
+        let mut reader = CacheReader::new(&bytes);
+        if reader.bytes(MAGIC.len())? != MAGIC || reader.u32()? != CACHE_VERSION {
+            return None;
+        }
+        Some(bytes[reader.position..].to_vec())
+    }
+
+    /// Writes the entry. It is first written to a temporary file that is then renamed,
+    /// so other processes reading the cache never see half written entries
+    pub(crate) fn put(&self, key: u64, content: &[u8]) {
+        let mut bytes = Vec::with_capacity(content.len() + 8);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(content);
+
+        let path = self.entry_path(key);
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&temporary, bytes))
+            .and_then(|_| fs::rename(&temporary, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+    }
+
+    /// The image decoded by the function from the file at the path, taken from the cache
+    /// if the same file has been decoded before with the same kind of decoding
+    pub(crate) fn image(
+        &self,
+        kind: &str,
+        path: &str,
+        decode: impl FnOnce(&str) -> Result<RgbImage, Box<dyn Error>>,
+    ) -> Result<RgbImage, Box<dyn Error>> {
+        // Errors reading the file are reported by the decoding
+        let Ok(file) = fs::read(path) else {
+            return decode(path);
+        };
+        let key = CacheKey::new(kind).bytes(&file).finish();
+        let cached = self.get(key).and_then(|bytes| {
+            let mut reader = CacheReader::new(&bytes);
+            let (width, height) = (reader.u32()?, reader.u32()?);
+            let pixels = reader.bytes(width as usize * height as usize * 3)?;
+            RgbImage::from_raw(width, height, pixels.to_vec())
+        });
+        if let Some(image) = cached {
+            return Ok(image);
+        }
+
+        let image = decode(path)?;
+        let mut writer = CacheWriter::default();
+        writer.u32(image.width());
+        writer.u32(image.height());
+        writer.bytes(image.as_raw());
+        self.put(key, &writer.0);
+        Ok(image)
+    }
+}
+
+/// Hashes everything that a cached result depends on into the key of its entry
+pub(crate) struct CacheKey(Xxh3Default);
+
+impl CacheKey {
+    /// Starts a key for the kind of result
+    pub(crate) fn new(kind: &str) -> CacheKey {
+        CacheKey(Xxh3Default::new())
+            .bytes(&CACHE_VERSION.to_le_bytes())
+            .bytes(kind.as_bytes())
+    }
+
+    pub(crate) fn bytes(mut self, bytes: &[u8]) -> CacheKey {
+        self.0.update(&(bytes.len() as u64).to_le_bytes());
+        self.0.update(bytes);
+        self
+    }
+
+    pub(crate) fn f64s(self, values: impl IntoIterator<Item = f64>) -> CacheKey {
+        let bytes: Vec<u8> = values.into_iter().flat_map(f64::to_le_bytes).collect();
+        self.bytes(&bytes)
+    }
+
+    pub(crate) fn u32s(self, values: impl IntoIterator<Item = u32>) -> CacheKey {
+        let bytes: Vec<u8> = values.into_iter().flat_map(u32::to_le_bytes).collect();
+        self.bytes(&bytes)
+    }
+
+    pub(crate) fn finish(self) -> u64 {
+        self.0.digest()
+    }
+}
+
+/// The numbers of a box, for hashing it into a key
+pub(crate) fn aabb_values(b_box: &Aabb) -> [f64; 6] {
+    [
+        b_box.x.min,
+        b_box.x.max,
+        b_box.y.min,
+        b_box.y.max,
+        b_box.z.min,
+        b_box.z.max,
+    ]
+}
+
+/// Writes the content of an entry, with numbers in little endian
+#[derive(Default)]
+pub(crate) struct CacheWriter(pub(crate) Vec<u8>);
+
+impl CacheWriter {
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn f64(&mut self, value: f64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn aabb(&mut self, b_box: &Aabb) {
+        for value in aabb_values(b_box) {
+            self.f64(value);
+        }
+    }
+}
+
+/// Reads the content of an entry, giving none when the content ends early
+pub(crate) struct CacheReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> CacheReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> CacheReader<'a> {
+        CacheReader { bytes, position: 0 }
+    }
+
+    /// Whether all of the content has been read
+    pub(crate) fn is_done(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    pub(crate) fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position.checked_add(count)?)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    pub(crate) fn f64(&mut self) -> Option<f64> {
+        Some(f64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    /// Reads a box written by [`CacheWriter::aabb`]
+    pub(crate) fn aabb(&mut self) -> Option<Aabb> {
+        let [x_min, x_max, y_min, y_max, z_min, z_max] = [(); 6].map(|_| self.f64());
+        Some(Aabb::new_from_2_points(
+            Vec3::new(x_min?, y_min?, z_min?),
+            Vec3::new(x_max?, y_max?, z_max?),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+
+    fn temporary_cache(name: &str) -> SceneCache {
+        let dir = std::env::temp_dir().join(format!("solstrale-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        SceneCache::new(dir)
+    }
+
+    #[test]
+    fn test_entries() {
+        let cache = temporary_cache("entries");
+        let key = CacheKey::new("test").u32s([1, 2, 3]).finish();
+        assert_ne!(key, CacheKey::new("test").u32s([1, 2]).u32s([3]).finish());
+        assert_ne!(key, CacheKey::new("other").u32s([1, 2, 3]).finish());
+
+        assert_eq!(cache.get(key), None);
+        cache.put(key, &[4, 5, 6]);
+        assert_eq!(cache.get(key), Some(vec![4, 5, 6]));
+
+        // Entries of other versions are ignored
+        let mut old = MAGIC.to_vec();
+        old.extend_from_slice(&(CACHE_VERSION + 1).to_le_bytes());
+        fs::write(cache.entry_path(key), old).unwrap();
+        assert_eq!(cache.get(key), None);
+        let _ = fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn test_image() {
+        let cache = temporary_cache("image");
+        let path = "resources/textures/tex.jpg";
+        let decode = |path: &str| -> Result<RgbImage, Box<dyn Error>> {
+            Ok(image::open(path)?.into_rgb8())
+        };
+        let decoded = cache.image("texture", path, decode).unwrap();
+        let cached = cache
+            .image("texture", path, |_| Ok(RgbImage::from_pixel(1, 1, Rgb([0, 0, 0]))))
+            .unwrap();
+        assert_eq!(cached, decoded);
+
+        assert!(cache.image("texture", "resources/textures/missing.jpg", decode).is_err());
+        let _ = fs::remove_dir_all(&cache.dir);
+    }
+}
@@ -0,0 +1,352 @@
+//! Reads a glTF .gltf or .glb file and creates a bvh containing all triangles
+//! of the meshes in the scene, placed by the transforms of their nodes.
+//! Materials are lambertian, using the base color and normal map of the glTF material.
+//! Applies supplied default material to primitives without material.
+//! The second set of texture coordinates is used for laying out baked lightmaps
+use std::error::Error;
+use std::sync::Arc;
+
+use gltf::image::{Data, Format};
+use gltf::mesh::Mode;
+use gltf::Node;
+use image::{DynamicImage, GrayAlphaImage, GrayImage, RgbImage, RgbaImage};
+use simple_error::SimpleError;
+
+use crate::geo::transformation::Transformer;
+use crate::geo::unit::Unit;
+use crate::geo::vec3::Vec3;
+use crate::geo::Uv;
+use crate::hittable::{Bvh, BvhQuality, Hittables, Triangle, TriangleOptions};
+use crate::loader::cache::SceneCache;
+use crate::loader::Loader;
+use crate::material::texture::{ImageMap, Multiply, SolidColor, TextureFilter};
+use crate::material::{Lambertian, Materials};
+
+/// Column major 4x4 matrix, as stored in glTF
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1., 0., 0., 0.],
+    [0., 1., 0., 0.],
+    [0., 0., 1., 0.],
+    [0., 0., 0., 1.],
+];
+
+/// Options for how the triangles of the gltf are created
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GltfOptions {
+    /// Triangles are not hit from behind, which hides artifacts from inverted normals
+    pub cull_backfaces: bool,
+    /// Unit of the scene that the gltf is loaded into. glTF models are always in meters
+    pub scene_unit: Unit,
+    /// Filter used when sampling the base color textures
+    pub texture_filter: TextureFilter,
+    /// Cache of the tree of the bvh of the triangles
+    pub cache: Option<SceneCache>,
+}
+
+/// Contains file information about the gltf to load
+pub struct Gltf {
+    path: String,
+    filename: String,
+    options: GltfOptions,
+}
+
+impl Gltf {
+    /// Creates a new [`Gltf`] instance
+    pub fn new(path: &str, filename: &str) -> Gltf {
+        Gltf::new_with_options(path, filename, GltfOptions::default())
+    }
+
+    /// Creates a new [`Gltf`] instance with options for how the triangles are created
+    pub fn new_with_options(path: &str, filename: &str, options: GltfOptions) -> Gltf {
+        Gltf {
+            path: path.to_string(),
+            filename: filename.to_string(),
+            options,
+        }
+    }
+}
+
+impl Loader for Gltf {
+    fn load(
+        &self,
+        transformation: &dyn Transformer,
+        default_material: Option<Materials>,
+    ) -> Result<Hittables, Box<dyn Error>> {
+        let filepath = format!("{}{}", self.path, self.filename);
+        let (document, buffers, images) = gltf::import(&filepath).map_err(|err| {
+            SimpleError::new(format!("failed to load gltf model from {}: {}", &filepath, err))
+        })?;
+        let default_material =
+            default_material.unwrap_or_else(|| Lambertian::new(SolidColor::new(1., 1., 1.), None));
+
+        let images = images
+            .iter()
+            .map(|data| Ok(Arc::new(rgb_image(data)?)))
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        let materials: Vec<Materials> = document
+            .materials()
+            .map(|m| {
+                let pbr = m.pbr_metallic_roughness();
+                let [r, g, b, _] = pbr.base_color_factor().map(|c| c as f64);
+                let albedo = match pbr.base_color_texture() {
+                    None => SolidColor::new(r, g, b),
+                    Some(info) => {
+                        let image = images[info.texture().source().index()].clone();
+                        let texture = ImageMap::new_with_filter(image, self.options.texture_filter);
+                        if [r, g, b] == [1., 1., 1.] {
+                            texture
+                        } else {
+                            Multiply::new(texture, SolidColor::new(r, g, b))
+                        }
+                    }
+                };
+                let normal = m
+                    .normal_texture()
+                    .map(|n| ImageMap::new(images[n.texture().source().index()].clone()));
+                Lambertian::new(albedo, normal)
+            })
+            .collect();
+
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .ok_or_else(|| SimpleError::new(format!("no scene in gltf model {}", &filepath)))?;
+        let mut nodes: Vec<(Node, Matrix)> = scene.nodes().map(|n| (n, IDENTITY)).collect();
+        let scale = Unit::Meters.scale_to(self.options.scene_unit);
+
+        let mut triangles = Vec::new();
+        while let Some((node, parent_matrix)) = nodes.pop() {
+            let local_matrix = node.transform().matrix().map(|c| c.map(f64::from));
+            let matrix = multiply(&parent_matrix, &local_matrix);
+            nodes.extend(node.children().map(|child| (child, matrix)));
+            let Some(mesh) = node.mesh() else {
+                continue;
+            };
+            let node_transformation = NodeTransformer {
+                matrix,
+                scale,
+                transformation,
+            };
+            // Mirroring transforms reverses the winding of the triangles
+            let flip_winding = determinant(&matrix) < 0.;
+
+            for primitive in mesh.primitives().filter(|p| p.mode() == Mode::Triangles) {
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let Some(positions) = reader.read_positions() else {
+                    continue;
+                };
+                let positions: Vec<Vec3> = positions
+                    .map(|[x, y, z]| Vec3::new(x as f64, y as f64, z as f64))
+                    .collect();
+                // glTF texture coordinates starts at the top of the image
+                let uvs: Option<Vec<Uv>> = reader
+                    .read_tex_coords(0)
+                    .map(|t| t.into_f32().map(|[u, v]| Uv::new(u, 1. - v)).collect());
+                let lightmap_uvs: Option<Vec<Uv>> = reader
+                    .read_tex_coords(1)
+                    .map(|t| t.into_f32().map(|[u, v]| Uv::new(u, 1. - v)).collect());
+                let indices: Vec<usize> = match reader.read_indices() {
+                    None => (0..positions.len()).collect(),
+                    Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                };
+                if indices.iter().any(|i| *i >= positions.len()) {
+                    return Err(Box::new(SimpleError::new(format!(
+                        "vertex index out of range in gltf model {}",
+                        &filepath
+                    ))));
+                }
+                let material = match primitive.material().index() {
+                    None => default_material.clone(),
+                    Some(i) => materials[i].clone(),
+                };
+
+                for face in indices.chunks_exact(3) {
+                    let mut face = [face[0], face[1], face[2]];
+                    if flip_winding {
+                        face.swap(1, 2);
+                    }
+                    let [v0, v1, v2] = face.map(|i| positions[i]);
+                    let face_uvs = match &uvs {
+                        None => [Uv::default(); 3],
+                        Some(uvs) => face.map(|i| uvs[i]),
+                    };
+                    triangles.push(Triangle::new_with_options(
+                        v0,
+                        v1,
+                        v2,
+                        TriangleOptions {
+                            uvs: face_uvs,
+                            lightmap_uvs: lightmap_uvs.as_ref().map(|uvs| face.map(|i| uvs[i])),
+                            cull_backfaces: self.options.cull_backfaces,
+                            ..Default::default()
+                        },
+                        material.clone(),
+                        &node_transformation,
+                    ));
+                }
+            }
+        }
+
+        Ok(match &self.options.cache {
+            None => Bvh::new(triangles),
+            Some(cache) => Bvh::new_with_cache(triangles, BvhQuality::default(), cache),
+        })
+    }
+}
+
+/// Applies the transform of a node, then scales to the scene unit
+/// and lastly applies the transformation given to the loader
+struct NodeTransformer<'a> {
+    matrix: Matrix,
+    scale: f64,
+    transformation: &'a dyn Transformer,
+}
+
+impl Transformer for NodeTransformer<'_> {
+    fn transform(&self, vec: Vec3, skip_translation: bool) -> Vec3 {
+        let m = &self.matrix;
+        let w = if skip_translation { 0. } else { 1. };
+        let row = |r: usize| m[0][r] * vec.x + m[1][r] * vec.y + m[2][r] * vec.z + m[3][r] * w;
+        let v = Vec3::new(row(0), row(1), row(2)) * self.scale;
+        self.transformation.transform(v, skip_translation)
+    }
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.; 4]; 4];
+    for (c, column) in m.iter_mut().enumerate() {
+        for (r, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][r] * b[c][k]).sum();
+        }
+    }
+    m
+}
+
+/// Determinant of the upper left 3x3 part of the matrix
+fn determinant(m: &Matrix) -> f64 {
+    let x = Vec3::new(m[0][0], m[0][1], m[0][2]);
+    let y = Vec3::new(m[1][0], m[1][1], m[1][2]);
+    let z = Vec3::new(m[2][0], m[2][1], m[2][2]);
+    x.cross(y).dot(z)
+}
+
+fn rgb_image(data: &Data) -> Result<RgbImage, Box<dyn Error>> {
+    let (width, height, pixels) = (data.width, data.height, data.pixels.clone());
+    let image = match data.format {
+        Format::R8 => GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+        Format::R8G8 => {
+            GrayAlphaImage::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8)
+        }
+        Format::R8G8B8 => RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+        Format::R8G8B8A8 => {
+            RgbaImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+        }
+        format => {
+            return Err(Box::new(SimpleError::new(format!(
+                "unsupported gltf image format {:?}",
+                format
+            ))))
+        }
+    };
+    Ok(image
+        .ok_or_else(|| SimpleError::new("invalid gltf image data"))?
+        .into_rgb8())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::transformation::NopTransformer;
+    use crate::geo::Ray;
+    use crate::hittable::{Hittable, Sphere};
+    use crate::material::{DiffuseLight, Material, RayScatter};
+    use crate::util::interval::RAY_INTERVAL;
+
+    use super::*;
+
+    fn hit_color(model: &Hittables, ray: &Ray) -> Vec3 {
+        let rec = model.hit(ray, &RAY_INTERVAL).unwrap();
+        let lights = [Sphere::new(Vec3::new(0., 0., 5.), 1., DiffuseLight::new(1., 1., 1., None))];
+        match rec.material.scatter(ray, &rec, &lights) {
+            RayScatter::ScatterPdf(s) => s.color,
+            _ => panic!("Lambertian should scatter by pdf"),
+        }
+    }
+
+    #[test]
+    fn test_node_transforms() {
+        let model = Gltf::new("resources/gltf/", "triangle.gltf")
+            .load(&NopTransformer(), None)
+            .unwrap();
+
+        // The triangle is scaled by its node, and translated by the parent node
+        let ray = Ray::new(Vec3::new(1.5, 0.25, 1.), Vec3::new(0., 0., -1.));
+        let rec = model.hit(&ray, &RAY_INTERVAL).unwrap();
+        assert_eq!(rec.hit_point, Vec3::new(1.5, 0.25, -1.));
+        assert_eq!(hit_color(&model, &ray), Vec3::new(1., 0., 0.));
+    }
+
+    #[test]
+    fn test_lightmap_tex_coords() {
+        let model = Gltf::new("resources/gltf/", "triangle.gltf")
+            .load(&NopTransformer(), None)
+            .unwrap();
+        let Hittables::BvhType(bvh) = &model else {
+            panic!("Gltf should be loaded as a bvh");
+        };
+        let Hittables::TriangleType(triangle) = bvh.leaves()[0] else {
+            panic!("Gltf should contain a triangle");
+        };
+        let (_, uvs) = triangle.vertices_and_tex_coords();
+        assert_eq!(uvs, [Uv::new(0., 0.), Uv::new(1., 0.), Uv::new(0., 1.)]);
+        assert_eq!(
+            triangle.lightmap_tex_coords(),
+            [Uv::new(0.5, 0.5), Uv::new(1., 0.5), Uv::new(0.5, 0.)]
+        );
+    }
+
+    #[test]
+    fn test_glb_with_texture() {
+        let model = Gltf::new_with_options(
+            "resources/gltf/",
+            "textured.glb",
+            GltfOptions {
+                cull_backfaces: true,
+                ..GltfOptions::default()
+            },
+        )
+        .load(&NopTransformer(), None)
+        .unwrap();
+
+        // The quad is mirrored to negative x, and still faces positive z
+        let hit = |x, y| Ray::new(Vec3::new(x, y, 1.), Vec3::new(0., 0., -1.));
+        assert_eq!(hit_color(&model, &hit(-0.25, 0.75)), Vec3::new(1., 0., 0.));
+        let rec = model.hit(&hit(-0.75, 0.25), &RAY_INTERVAL).unwrap();
+        assert_eq!(rec.uv, Uv::new(0.75, 0.25));
+        let from_behind = Ray::new(Vec3::new(-0.5, 0.5, -1.), Vec3::new(0., 0., 1.));
+        assert!(model.hit(&from_behind, &RAY_INTERVAL).is_none());
+    }
+
+    #[test]
+    fn test_unit_scaling() {
+        let model = Gltf::new_with_options(
+            "resources/gltf/",
+            "triangle.gltf",
+            GltfOptions {
+                scene_unit: Unit::Centimeters,
+                ..GltfOptions::default()
+            },
+        )
+        .load(&NopTransformer(), None)
+        .unwrap();
+        assert!((model.bounding_box().x.max - 200.).abs() < 0.001);
+    }
+
+    #[test]
+    fn missing_file() {
+        let res = Gltf::new("resources/gltf/", "missing.gltf").load(&NopTransformer(), None);
+        assert!(format!("{}", res.err().unwrap())
+            .starts_with("failed to load gltf model from resources/gltf/missing.gltf"));
+    }
+}
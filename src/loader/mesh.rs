@@ -0,0 +1,496 @@
+//! Processing of triangle meshes while they are loaded
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+use crate::geo::vec3::Vec3;
+
+/// Extra weight of the planes keeping open borders of a mesh in place while decimating
+const BOUNDARY_WEIGHT: f64 = 1000.;
+
+/// How much meshes are simplified when decimated
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Decimation {
+    /// Collapses edges until each mesh has at most this number of triangles
+    TargetTriangleCount(usize),
+    /// Collapses edges as long as the quadric error is below this value,
+    /// which is roughly the squared distance from the original surface
+    MaxError(f64),
+}
+
+/// Finds the triangles that need to be flipped so each connected part of the mesh
+/// has a consistent winding, where the normals of the majority of the surface point outwards
+pub fn winding_flips(positions: &[Vec3], faces: &[[usize; 3]]) -> Vec<bool> {
+    let mut edge_faces: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (f, face) in faces.iter().enumerate() {
+        for (a, b) in oriented_edges(face, false) {
+            edge_faces.entry((a.min(b), a.max(b))).or_default().push(f);
+        }
+    }
+
+    let mut flips = vec![false; faces.len()];
+    let mut visited = vec![false; faces.len()];
+    for start in 0..faces.len() {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+
+        // Flood fill the connected part, flipping neighbours to match the winding
+        let mut component = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(f) = queue.pop_front() {
+            for (a, b) in oriented_edges(&faces[f], flips[f]) {
+                for &n in &edge_faces[&(a.min(b), a.max(b))] {
+                    if visited[n] {
+                        continue;
+                    }
+                    visited[n] = true;
+                    // A consistently wound neighbour has the shared edge in the other direction
+                    flips[n] = oriented_edges(&faces[n], false).contains(&(a, b));
+                    component.push(n);
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        let centroid = component
+            .iter()
+            .map(|&f| face_center(positions, &faces[f]))
+            .fold(Vec3::default(), |acc, c| acc + c)
+            / component.len() as f64;
+
+        // Area weighted vote on whether the normals point away from the center
+        let outwards: f64 = component
+            .iter()
+            .map(|&f| {
+                let n = face_normal(positions, &faces[f]);
+                let n = if flips[f] { n.neg() } else { n };
+                n.dot(face_center(positions, &faces[f]) - centroid)
+            })
+            .sum();
+        if outwards < 0. {
+            for f in component {
+                flips[f] = !flips[f];
+            }
+        }
+    }
+    flips
+}
+
+/// Computes normals for a mesh that has none, so it is shaded smoothly instead of faceted.
+/// The normal of each corner is the area weighted average of the faces around the vertex that
+/// are within the crease angle in degrees of the face, so edges sharper than it stay hard.
+/// Returns the normals, and the indices of the normals of the corners of each face
+pub fn smooth_normals(
+    positions: &[Vec3],
+    faces: &[[usize; 3]],
+    crease_angle: f64,
+) -> (Vec<Vec3>, Vec<[u32; 3]>) {
+    let cos_crease = crease_angle.clamp(0., 180.).to_radians().cos();
+    let weighted: Vec<Vec3> = faces.iter().map(|f| face_normal(positions, f)).collect();
+    // Degenerate faces have no direction, so they are smoothed with all their neighbours
+    let directions: Vec<Option<Vec3>> = weighted
+        .iter()
+        .map(|n| (!n.near_zero()).then(|| n.unit()))
+        .collect();
+    let mut vertex_faces = vec![Vec::new(); positions.len()];
+    for (f, face) in faces.iter().enumerate() {
+        for v in face {
+            vertex_faces[*v].push(f);
+        }
+    }
+
+    // Corners sharing a vertex and the same normal share the index
+    let mut indices: HashMap<(usize, [u64; 3]), u32> = HashMap::new();
+    let mut normals = Vec::new();
+    let normal_faces = faces
+        .iter()
+        .enumerate()
+        .map(|(f, face)| {
+            face.map(|v| {
+                let sum = vertex_faces[v]
+                    .iter()
+                    .filter(|g| match (directions[f], directions[**g]) {
+                        (Some(a), Some(b)) => a.dot(b) >= cos_crease,
+                        _ => true,
+                    })
+                    .fold(Vec3::default(), |sum, g| sum + weighted[*g]);
+                let normal = if sum.near_zero() {
+                    Vec3::new(0., 0., 1.)
+                } else {
+                    sum.unit()
+                };
+                *indices
+                    .entry((v, [normal.x, normal.y, normal.z].map(f64::to_bits)))
+                    .or_insert_with(|| {
+                        normals.push(normal);
+                        normals.len() as u32 - 1
+                    })
+            })
+        })
+        .collect();
+    (normals, normal_faces)
+}
+
+/// Simplifies the mesh by repeatedly collapsing the edge with the lowest quadric error,
+/// as described by Garland and Heckbert. Vertices are kept in place, so the returned faces
+/// refer to a subset of the given positions
+pub fn decimate(
+    positions: &[Vec3],
+    faces: &[[usize; 3]],
+    decimation: Decimation,
+) -> Vec<[usize; 3]> {
+    let mut faces = faces.to_vec();
+    let mut alive = vec![true; faces.len()];
+    let mut alive_count = faces.len();
+    let mut quadrics = vec![Quadric::default(); positions.len()];
+    let mut vertex_faces = vec![Vec::new(); positions.len()];
+    let mut edge_faces: HashMap<(usize, usize), usize> = HashMap::new();
+
+    for (f, face) in faces.iter().enumerate() {
+        let n = face_normal(positions, face);
+        for &i in face {
+            if !n.near_zero() {
+                quadrics[i].add(&Quadric::from_plane(n.unit(), positions[i], 1.));
+            }
+            vertex_faces[i].push(f);
+        }
+        for (a, b) in oriented_edges(face, false) {
+            *edge_faces.entry((a.min(b), a.max(b))).or_default() += 1;
+        }
+    }
+
+    // Edges with only one face are on the border, which are kept in place
+    // by a plane perpendicular to the face
+    for face in &faces {
+        let n = face_normal(positions, face);
+        for (a, b) in oriented_edges(face, false) {
+            let border_normal = (positions[b] - positions[a]).cross(n);
+            if edge_faces[&(a.min(b), a.max(b))] == 1 && !border_normal.near_zero() {
+                let q = Quadric::from_plane(border_normal.unit(), positions[a], BOUNDARY_WEIGHT);
+                quadrics[a].add(&q);
+                quadrics[b].add(&q);
+            }
+        }
+    }
+
+    // Queued collapses are outdated when the stamp of any of its vertices has changed
+    let mut stamps = vec![0; positions.len()];
+    let mut queue = BinaryHeap::new();
+    for &(a, b) in edge_faces.keys() {
+        queue.push(Collapse::new(positions, &quadrics, &stamps, a, b));
+    }
+
+    while let Some(c) = queue.pop() {
+        let done = match decimation {
+            Decimation::TargetTriangleCount(count) => alive_count <= count,
+            Decimation::MaxError(max_error) => c.cost > max_error,
+        };
+        if done {
+            break;
+        }
+        if stamps[c.from] != c.from_stamp || stamps[c.to] != c.to_stamp {
+            continue;
+        }
+
+        let moved_faces: Vec<usize> = vertex_faces[c.from]
+            .iter()
+            .copied()
+            .filter(|f| alive[*f] && !faces[*f].contains(&c.to))
+            .collect();
+        if moved_faces.iter().any(|f| {
+            let moved_face = faces[*f].map(|i| if i == c.from { c.to } else { i });
+            face_normal(positions, &moved_face).dot(face_normal(positions, &faces[*f])) <= 0.
+        }) {
+            // The collapse would fold the surface over itself
+            continue;
+        }
+
+        for f in std::mem::take(&mut vertex_faces[c.from]) {
+            if !alive[f] {
+                continue;
+            }
+            if faces[f].contains(&c.to) {
+                alive[f] = false;
+                alive_count -= 1;
+            } else {
+                faces[f] = faces[f].map(|i| if i == c.from { c.to } else { i });
+                vertex_faces[c.to].push(f);
+            }
+        }
+        vertex_faces[c.to].retain(|f| alive[*f]);
+        let from_quadric = quadrics[c.from];
+        quadrics[c.to].add(&from_quadric);
+        stamps[c.from] += 1;
+        stamps[c.to] += 1;
+
+        let mut neighbours: Vec<usize> = vertex_faces[c.to]
+            .iter()
+            .flat_map(|f| faces[*f])
+            .filter(|i| *i != c.to)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        for n in neighbours {
+            queue.push(Collapse::new(positions, &quadrics, &stamps, c.to, n));
+        }
+    }
+
+    faces
+        .into_iter()
+        .zip(alive)
+        .filter(|(_, a)| *a)
+        .map(|(f, _)| f)
+        .collect()
+}
+
+/// Symmetric matrix giving the sum of squared distances to a set of planes
+#[derive(Copy, Clone, Debug, Default)]
+struct Quadric([[f64; 4]; 4]);
+
+impl Quadric {
+    fn from_plane(normal: Vec3, point: Vec3, weight: f64) -> Quadric {
+        let plane = [normal.x, normal.y, normal.z, -normal.dot(point)];
+        let mut q = [[0.; 4]; 4];
+        for (i, row) in q.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = plane[i] * plane[j] * weight;
+            }
+        }
+        Quadric(q)
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (row, other_row) in self.0.iter_mut().zip(other.0.iter()) {
+            for (v, other_v) in row.iter_mut().zip(other_row.iter()) {
+                *v += other_v;
+            }
+        }
+    }
+
+    fn error(&self, p: Vec3) -> f64 {
+        let v = [p.x, p.y, p.z, 1.];
+        let mut error = 0.;
+        for (i, row) in self.0.iter().enumerate() {
+            for (j, q) in row.iter().enumerate() {
+                error += v[i] * q * v[j];
+            }
+        }
+        error
+    }
+}
+
+/// Collapse of an edge by moving one vertex onto the other
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    from_stamp: u32,
+    to_stamp: u32,
+}
+
+impl Collapse {
+    fn new(positions: &[Vec3], quadrics: &[Quadric], stamps: &[u32], a: usize, b: usize) -> Self {
+        let mut q = quadrics[a];
+        q.add(&quadrics[b]);
+        let (cost_a, cost_b) = (q.error(positions[a]), q.error(positions[b]));
+        let (cost, from, to) = if cost_a <= cost_b {
+            (cost_a, b, a)
+        } else {
+            (cost_b, a, b)
+        };
+        Collapse {
+            cost,
+            from,
+            to,
+            from_stamp: stamps[from],
+            to_stamp: stamps[to],
+        }
+    }
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    /// Reversed, so the collapse with the lowest cost is first in the queue
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+fn face_normal(positions: &[Vec3], face: &[usize; 3]) -> Vec3 {
+    let [p0, p1, p2] = face.map(|i| positions[i]);
+    (p1 - p0).cross(p2 - p0)
+}
+
+fn oriented_edges(face: &[usize; 3], flipped: bool) -> [(usize, usize); 3] {
+    let [i0, i1, i2] = if flipped {
+        [face[0], face[2], face[1]]
+    } else {
+        *face
+    };
+    [(i0, i1), (i1, i2), (i2, i0)]
+}
+
+fn face_center(positions: &[Vec3], face: &[usize; 3]) -> Vec3 {
+    (positions[face[0]] + positions[face[1]] + positions[face[2]]) / 3.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube() -> (Vec<Vec3>, Vec<[usize; 3]>) {
+        let positions = (0..8)
+            .map(|i| {
+                Vec3::new(
+                    (i & 1) as f64 - 0.5,
+                    ((i >> 1) & 1) as f64 - 0.5,
+                    ((i >> 2) & 1) as f64 - 0.5,
+                )
+            })
+            .collect();
+        let faces = vec![
+            [0, 2, 3],
+            [0, 3, 1],
+            [4, 5, 7],
+            [4, 7, 6],
+            [0, 4, 6],
+            [0, 6, 2],
+            [1, 3, 7],
+            [1, 7, 5],
+            [0, 1, 5],
+            [0, 5, 4],
+            [2, 6, 7],
+            [2, 7, 3],
+        ];
+        (positions, faces)
+    }
+
+    fn assert_outwards(positions: &[Vec3], faces: &[[usize; 3]], flips: &[bool]) {
+        for (face, flip) in faces.iter().zip(flips) {
+            let [p0, p1, p2] = face.map(|i| positions[i]);
+            let n = (p1 - p0).cross(p2 - p0);
+            let n = if *flip { n.neg() } else { n };
+            assert!(n.dot(face_center(positions, face)) > 0.);
+        }
+    }
+
+    #[test]
+    fn test_consistent_mesh_is_unchanged() {
+        let (positions, faces) = cube();
+        assert_eq!(winding_flips(&positions, &faces), vec![false; 12]);
+    }
+
+    #[test]
+    fn test_inconsistent_faces_are_flipped() {
+        let (positions, mut faces) = cube();
+        for f in [1, 4, 5, 10] {
+            faces[f].swap(1, 2);
+        }
+        let flips = winding_flips(&positions, &faces);
+        assert_outwards(&positions, &faces, &flips);
+        assert_eq!(flips.iter().filter(|f| **f).count(), 4);
+    }
+
+    #[test]
+    fn test_inverted_mesh_is_flipped() {
+        let (positions, mut faces) = cube();
+        for face in faces.iter_mut() {
+            face.swap(1, 2);
+        }
+        let flips = winding_flips(&positions, &faces);
+        assert_outwards(&positions, &faces, &flips);
+    }
+
+    #[test]
+    fn test_smooth_normals() {
+        let (positions, faces) = cube();
+        // The edges of a cube are sharper than the crease angle, so the sides stay flat
+        let (normals, normal_faces) = smooth_normals(&positions, &faces, 30.);
+        assert_eq!(normals.len(), 24);
+        for (face, normal_face) in faces.iter().zip(&normal_faces) {
+            let n = face_normal(&positions, face).unit();
+            assert!(normal_face.iter().all(|i| normals[*i as usize] == n));
+        }
+
+        // Otherwise each corner has one normal pointing away from the cube
+        let (normals, normal_faces) = smooth_normals(&positions, &faces, 100.);
+        assert_eq!(normals.len(), 8);
+        for (face, normal_face) in faces.iter().zip(&normal_faces) {
+            for c in 0..3 {
+                let n = normals[normal_face[c] as usize];
+                assert!((n.length() - 1.).abs() < 1e-9);
+                assert!(n.dot(positions[face[c]]) > 0.);
+            }
+        }
+    }
+
+    /// Flat square in the xy plane split into a grid of triangles
+    fn grid(size: usize) -> (Vec<Vec3>, Vec<[usize; 3]>) {
+        let mut positions = Vec::new();
+        for y in 0..=size {
+            for x in 0..=size {
+                positions.push(Vec3::new(x as f64, y as f64, 0.) / size as f64);
+            }
+        }
+        let mut faces = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let i = y * (size + 1) + x;
+                faces.push([i, i + 1, i + size + 2]);
+                faces.push([i, i + size + 2, i + size + 1]);
+            }
+        }
+        (positions, faces)
+    }
+
+    fn assert_same_square(positions: &[Vec3], faces: &[[usize; 3]]) {
+        let mut area = 0.;
+        for face in faces {
+            let n = face_normal(positions, face);
+            assert!(n.z > 0., "Face was flipped");
+            area += n.length() / 2.;
+        }
+        assert!((area - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_decimate_to_target_count() {
+        let (positions, faces) = grid(10);
+        let decimated = decimate(&positions, &faces, Decimation::TargetTriangleCount(50));
+        assert!(decimated.len() <= 50);
+        assert_same_square(&positions, &decimated);
+    }
+
+    #[test]
+    fn test_decimate_by_max_error() {
+        let (positions, faces) = grid(10);
+        let decimated = decimate(&positions, &faces, Decimation::MaxError(1e-9));
+        assert!(decimated.len() < 10);
+        assert_same_square(&positions, &decimated);
+
+        // A bent surface keeps its fold
+        let mut positions = positions;
+        for p in positions.iter_mut().filter(|p| p.x > 0.5) {
+            p.z = p.x - 0.5;
+        }
+        let decimated = decimate(&positions, &faces, Decimation::MaxError(1e-9));
+        assert!(decimated.len() < 20);
+        assert!(decimated.iter().any(|f| face_normal(&positions, f).x < 0.));
+        assert!(decimated.iter().any(|f| face_normal(&positions, f).x == 0.));
+    }
+}
@@ -5,7 +5,11 @@ use crate::hittable::Hittables;
 use crate::material::Materials;
 use std::error::Error;
 
+pub mod cache;
+pub mod gltf;
+mod mesh;
 pub mod obj;
+pub mod scene;
 
 /// Common trait for loading object models of different formats
 pub trait Loader {
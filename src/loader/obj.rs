@@ -1,35 +1,94 @@
 //! Reads a Wavefront .obj file and creates a bvh containing
-//! all triangles. It also read materials from the referred .mat file.
+//! a [`TriangleMesh`] for each model. It also read materials from the referred .mat file.
 //! Support for colored and textured lambertian materials.
-//! Applies supplied default material if none in model
+//! Materials with an emissive color `Ke` or texture `map_Ke` are lights.
+//! Opacity textures `map_d` cut out the transparent parts of the surfaces.
+//! Applies supplied default material if none in model.
+//! Vertex colors are multiplied with the colors of the materials, see [`VertexColor`].
+//! Meshes without texture coordinates get them generated by a [`UvProjection`]
 use std::collections::HashMap;
 use std::error::Error;
 
 use simple_error::SimpleError;
 use tobj::LoadOptions;
 
+use crate::geo::Aabb;
 use crate::geo::transformation::Transformer;
+use crate::geo::unit::Unit;
 use crate::geo::Uv;
-use crate::geo::vec3::Vec3;
+use crate::geo::vec3::{Vec3, ZERO_VECTOR};
 use crate::hittable::Bvh;
 use crate::hittable::Hittables;
-use crate::hittable::Triangle;
+use crate::hittable::sphere_uv;
+use crate::hittable::{TriangleMesh, TriangleMeshOptions};
+use crate::loader::cache::SceneCache;
 use crate::loader::Loader;
-use crate::material::{Lambertian, Materials, texture};
-use crate::material::texture::{ImageMap, SolidColor};
+use crate::loader::mesh;
+pub use crate::loader::mesh::Decimation;
+use crate::material::{DiffuseLight, Lambertian, Materials, texture};
+use crate::material::texture::{
+    ImageMap, Multiply, SolidColor, TextureFilter, Textures, VertexColor, WrapMode,
+};
+
+/// How texture coordinates are generated for meshes that have none
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum UvProjection {
+    /// All texture coordinates are zero
+    None,
+    /// Projects each triangle along the axis its normal is most aligned with
+    #[default]
+    Box,
+    /// Projects onto a sphere around the center of the mesh
+    Sphere,
+}
+
+/// Options for how the triangles of the obj are created
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObjOptions {
+    /// Projection used for meshes without texture coordinates
+    pub uv_projection: UvProjection,
+    /// Triangles are not hit from behind, which hides artifacts from inverted normals
+    pub cull_backfaces: bool,
+    /// Flips triangles so connected parts of each mesh have a consistent winding,
+    /// with the normals of the majority of the surface pointing outwards
+    pub repair_winding: bool,
+    /// Simplifies the meshes, which is useful for quickly previewing very detailed models
+    pub decimation: Option<Decimation>,
+    /// Unit that the obj is modelled in
+    pub unit: Unit,
+    /// Unit of the scene that the obj is loaded into, the obj is scaled to match it
+    pub scene_unit: Unit,
+    /// Filter used when sampling the diffuse textures
+    pub texture_filter: TextureFilter,
+    /// Strength of bump mapping done at shade time for bump textures that are height maps,
+    /// see [`texture::HeightBump`]. If none, height maps are converted to normal maps
+    pub bump_strength: Option<f64>,
+    /// Crease angle in degrees for computing smooth normals of meshes without normals,
+    /// where edges sharper than it stay hard. If none, such meshes are flat shaded
+    pub smoothing_angle: Option<f64>,
+    /// Cache of the decoded textures and the trees of the meshes
+    pub cache: Option<SceneCache>,
+}
 
 /// Contains file information about the obj to load
 pub struct Obj {
     path: String,
     filename: String,
+    options: ObjOptions,
 }
 
 impl Obj {
     /// Creates a new [`Obj`] instance
     pub fn new(path: &str, filename: &str) -> Obj {
+        Obj::new_with_options(path, filename, ObjOptions::default())
+    }
+
+    /// Creates a new [`Obj`] instance with options for how the triangles are created
+    pub fn new_with_options(path: &str, filename: &str, options: ObjOptions) -> Obj {
         Obj {
             path: path.to_string(),
             filename: filename.to_string(),
+            options,
         }
     }
 }
@@ -40,8 +99,6 @@ impl Loader for Obj {
         transformation: &dyn Transformer,
         default_material: Option<Materials>,
     ) -> Result<Hittables, Box<dyn Error>> {
-        let default_material =
-            default_material.unwrap_or(Lambertian::new(SolidColor::new(1., 1., 1.), None));
         let load_options = LoadOptions {
             triangulate: true,
             ..Default::default()
@@ -53,7 +110,17 @@ impl Loader for Obj {
         })?;
         let materials =
             materials.map_err(|_| format!("failed to load MTL file for {}", &filepath))?;
+        let has_vertex_colors = models.iter().any(|m| !m.mesh.vertex_color.is_empty());
+        let with_vertex_colors = |texture| match has_vertex_colors {
+            true => Multiply::new(texture, VertexColor::new()),
+            false => texture,
+        };
 
+        let default_material = default_material.unwrap_or_else(|| {
+            Lambertian::new(with_vertex_colors(SolidColor::new(1., 1., 1.)), None)
+        });
+
+        let cache = self.options.cache.as_ref();
         let mut mat_map = HashMap::from([(-1, default_material.clone())]);
         for (i, m) in materials.iter().enumerate() {
             let albedo_texture = match &m.diffuse_texture {
@@ -62,77 +129,232 @@ impl Loader for Obj {
                     Some(c) => SolidColor::new_from_f32_array(c),
                 },
                 Some(diffuse_texture_filename) => {
-                    ImageMap::load(&format!("{}{}", self.path, diffuse_texture_filename))?
+                    let texture_path = format!("{}{}", self.path, diffuse_texture_filename);
+                    ImageMap::load_with_cache(
+                        &texture_path,
+                        self.options.texture_filter,
+                        WrapMode::Repeat,
+                        cache,
+                    )?
                 }
             };
             let normal_texture = match &m.normal_texture {
                 None => None,
                 Some(bump_texture_filename) => {
                     let bump_texture_path = format!("{}{}", self.path, bump_texture_filename);
-                    Some(texture::load_normal_texture(&bump_texture_path)?)
+                    Some(match self.options.bump_strength {
+                        None => texture::load_normal_texture_with_cache(&bump_texture_path, cache)?,
+                        Some(strength) => texture::load_bump_texture_with_cache(
+                            &bump_texture_path,
+                            strength,
+                            cache,
+                        )?,
+                    })
+                }
+            };
+            let alpha_texture = match &m.dissolve_texture {
+                None => None,
+                Some(alpha_texture_filename) => {
+                    let alpha_texture_path = format!("{}{}", self.path, alpha_texture_filename);
+                    Some(ImageMap::load_opacity_with_cache(
+                        &alpha_texture_path,
+                        self.options.texture_filter,
+                        cache,
+                    )?)
+                }
+            };
+            let albedo_texture = with_vertex_colors(albedo_texture);
+            let material = match (self.emission_texture(m)?, alpha_texture) {
+                (Some(emission), _) => DiffuseLight::new_from_texture(emission),
+                (None, None) => Lambertian::new(albedo_texture, normal_texture),
+                (None, Some(alpha)) => {
+                    Lambertian::new_with_alpha(albedo_texture, normal_texture, alpha)
                 }
             };
-            mat_map.insert(i as i8, Lambertian::new(albedo_texture, normal_texture));
+            mat_map.insert(i as i8, material);
         }
 
-        let mut triangles = Vec::new();
+        let mut meshes = Vec::new();
+        let scale = self.options.unit.scale_to(self.options.scene_unit);
 
         for m in models {
             let mesh = &m.mesh;
-            for i in (0..mesh.indices.len()).step_by(3) {
-                let mut pos_offset = (mesh.indices[i] * 3) as usize;
-
-                let v0 = vec3_from_mesh_vec(&mesh.positions, pos_offset);
-                pos_offset = (mesh.indices[i + 1] * 3) as usize;
-                let v1 = vec3_from_mesh_vec(&mesh.positions, pos_offset);
-                pos_offset = (mesh.indices[i + 2] * 3) as usize;
-                let v2 = vec3_from_mesh_vec(&mesh.positions, pos_offset);
-
-                let (uv0, uv1, uv2) = if mesh.texcoords.is_empty() {
-                    (Uv::default(), Uv::default(), Uv::default())
+            let positions: Vec<Vec3> = (0..mesh.positions.len())
+                .step_by(3)
+                .map(|offset| vec3_from_mesh_vec(&mesh.positions, offset) * scale)
+                .collect();
+            let bounds = mesh_bounds(&positions);
+            let colors: Option<Vec<Vec3>> = if mesh.vertex_color.is_empty() {
+                None
+            } else {
+                Some(
+                    (0..mesh.vertex_color.len())
+                        .step_by(3)
+                        .map(|offset| vec3_from_mesh_vec(&mesh.vertex_color, offset))
+                        .collect(),
+                )
+            };
+            let mut faces: Vec<[usize; 3]> = mesh
+                .indices
+                .chunks_exact(3)
+                .map(|f| [f[0] as usize, f[1] as usize, f[2] as usize])
+                .collect();
+            let mut face_uvs: Option<Vec<[Uv; 3]>> = if mesh.texcoords.is_empty() {
+                None
+            } else {
+                let uv = |i: u32| uv_from_mesh_vec(&mesh.texcoords, i as usize * 2);
+                Some(
+                    mesh.texcoord_indices
+                        .chunks_exact(3)
+                        .map(|f| [uv(f[0]), uv(f[1]), uv(f[2])])
+                        .collect(),
+                )
+            };
+            // Normals are only used if all the faces have them
+            let mut face_normals: Option<Vec<[u32; 3]>> =
+                if mesh.normals.is_empty() || mesh.normal_indices.len() != mesh.indices.len() {
+                    None
                 } else {
-                    let tex_offset1 = (mesh.texcoord_indices[i] * 2) as usize;
-                    let tex_offset2 = (mesh.texcoord_indices[i + 1] * 2) as usize;
-                    let tex_offset3 = (mesh.texcoord_indices[i + 2] * 2) as usize;
-                    (
-                        Uv {
-                            u: mesh.texcoords[tex_offset1],
-                            v: mesh.texcoords[tex_offset1 + 1],
-                        },
-                        Uv {
-                            u: mesh.texcoords[tex_offset2],
-                            v: mesh.texcoords[tex_offset2 + 1],
-                        },
-                        Uv {
-                            u: mesh.texcoords[tex_offset3],
-                            v: mesh.texcoords[tex_offset3 + 1],
-                        },
+                    Some(
+                        mesh.normal_indices
+                            .chunks_exact(3)
+                            .map(|f| [f[0], f[1], f[2]])
+                            .collect(),
                     )
                 };
 
-                let material_id = match mesh.material_id {
-                    None => -1,
-                    Some(id) => id as i8,
-                };
-                let material = match mat_map.get(&material_id) {
-                    None => default_material.to_owned(),
-                    Some(m) => m.to_owned(),
-                };
+            if self.options.repair_winding {
+                let flips = mesh::winding_flips(&positions, &faces);
+                for (f, flip) in flips.iter().enumerate() {
+                    if *flip {
+                        faces[f].swap(1, 2);
+                        if let Some(uvs) = face_uvs.as_mut() {
+                            uvs[f].swap(1, 2);
+                        }
+                        if let Some(normals) = face_normals.as_mut() {
+                            normals[f].swap(1, 2);
+                        }
+                    }
+                }
+            }
 
-                triangles.push(Triangle::new_with_tex_coords(
-                    v0,
-                    v1,
-                    v2,
-                    uv0,
-                    uv1,
-                    uv2,
-                    material,
-                    transformation,
-                ));
+            if let Some(decimation) = self.options.decimation {
+                // Texture coordinates follow the vertices, so seams are not kept
+                let mut vertex_uvs = vec![Uv::default(); positions.len()];
+                if let Some(uvs) = &face_uvs {
+                    for (face, uv) in faces.iter().zip(uvs).rev() {
+                        for c in 0..3 {
+                            vertex_uvs[face[c]] = uv[c];
+                        }
+                    }
+                }
+                faces = mesh::decimate(&positions, &faces, decimation);
+                // The normals do not fit the simplified surface, but may be smoothed again
+                face_normals = None;
+                face_uvs =
+                    face_uvs.map(|_| faces.iter().map(|f| f.map(|i| vertex_uvs[i])).collect());
             }
+
+            let normals = match (face_normals, self.options.smoothing_angle) {
+                (Some(normal_faces), _) => Some((
+                    (0..mesh.normals.len())
+                        .step_by(3)
+                        .map(|offset| vec3_from_mesh_vec(&mesh.normals, offset))
+                        .collect(),
+                    normal_faces,
+                )),
+                (None, Some(angle)) => Some(mesh::smooth_normals(&positions, &faces, angle)),
+                (None, None) => None,
+            };
+
+            let material_id = match mesh.material_id {
+                None => -1,
+                Some(id) => id as i8,
+            };
+            let material = match mat_map.get(&material_id) {
+                None => default_material.to_owned(),
+                Some(m) => m.to_owned(),
+            };
+
+            let face_uvs = face_uvs.unwrap_or_else(|| {
+                faces
+                    .iter()
+                    .map(|face| {
+                        let [v0, v1, v2] = face.map(|i| positions[i]);
+                        project_uvs(self.options.uv_projection, v0, v1, v2, &bounds)
+                    })
+                    .collect()
+            });
+
+            let mesh_options = TriangleMeshOptions {
+                uvs: Some(face_uvs),
+                colors,
+                normals,
+                cull_backfaces: self.options.cull_backfaces,
+            };
+            meshes.push(TriangleMesh::new_with_cache(
+                positions,
+                faces.iter().map(|face| face.map(|i| i as u32)).collect(),
+                mesh_options,
+                material,
+                transformation,
+                cache,
+            ));
         }
 
-        Ok(Bvh::new(triangles))
+        // Emissive triangles are sampled as one light
+        Ok(Bvh::new_light(meshes))
+    }
+}
+
+impl Obj {
+    /// Texture of the light emitted by the material, if it emits any
+    fn emission_texture(&self, m: &tobj::Material) -> Result<Option<Textures>, Box<dyn Error>> {
+        let color = match m.unknown_param.get("Ke") {
+            None => None,
+            Some(ke) => Some(parse_color(ke).ok_or_else(|| {
+                SimpleError::new(format!("invalid Ke '{}' in material {}", ke, m.name))
+            })?),
+        };
+        if color == Some(ZERO_VECTOR) {
+            return Ok(None);
+        }
+
+        // Texture options are ignored, the file name is last
+        let texture_filename = m
+            .unknown_param
+            .get("map_Ke")
+            .and_then(|map| map.split_whitespace().last());
+        Ok(match (texture_filename, color) {
+            (None, None) => None,
+            (None, Some(c)) => Some(SolidColor::new_from_vec3(c)),
+            (Some(filename), c) => {
+                let texture_path = format!("{}{}", self.path, filename);
+                let texture = ImageMap::load_with_cache(
+                    &texture_path,
+                    self.options.texture_filter,
+                    WrapMode::Repeat,
+                    self.options.cache.as_ref(),
+                )?;
+                Some(match c {
+                    None => texture,
+                    Some(c) => Multiply::new(texture, SolidColor::new_from_vec3(c)),
+                })
+            }
+        })
+    }
+}
+
+/// Parses a color of three numbers, or one number for gray
+fn parse_color(s: &str) -> Option<Vec3> {
+    let values: Vec<f64> = s
+        .split_whitespace()
+        .map(|v| v.parse().ok())
+        .collect::<Option<_>>()?;
+    match values[..] {
+        [v] => Some(Vec3::new(v, v, v)),
+        [r, g, b] => Some(Vec3::new(r, g, b)),
+        _ => None,
     }
 }
 
@@ -144,12 +366,121 @@ fn vec3_from_mesh_vec(positions: &[f32], offset: usize) -> Vec3 {
     )
 }
 
+fn uv_from_mesh_vec(texcoords: &[f32], offset: usize) -> Uv {
+    Uv::new(texcoords[offset], texcoords[offset + 1])
+}
+
+fn mesh_bounds(positions: &[Vec3]) -> Aabb {
+    positions
+        .iter()
+        .fold(None, |bounds: Option<Aabb>, p| {
+            let point_bounds = Aabb::new_from_2_points(*p, *p);
+            Some(match bounds {
+                None => point_bounds,
+                Some(b) => b.combine(&point_bounds),
+            })
+        })
+        .unwrap_or_default()
+}
+
+/// Generates texture coordinates for a triangle, relative to the bounds of its mesh
+fn project_uvs(
+    projection: UvProjection,
+    v0: Vec3,
+    v1: Vec3,
+    v2: Vec3,
+    bounds: &Aabb,
+) -> [Uv; 3] {
+    match projection {
+        UvProjection::None => [Uv::default(); 3],
+        UvProjection::Box => {
+            // Same scale on all axes, so the texture is not stretched
+            let size = bounds.x.size().max(bounds.y.size()).max(bounds.z.size());
+            let min = Vec3::new(bounds.x.min, bounds.y.min, bounds.z.min);
+            let n = (v1 - v0).cross(v2 - v0);
+            let (nx, ny, nz) = (n.x.abs(), n.y.abs(), n.z.abs());
+            let project = |p: Vec3| {
+                let p = (p - min) / size;
+                if nx >= ny && nx >= nz {
+                    Uv::new(p.z as f32, p.y as f32)
+                } else if ny >= nz {
+                    Uv::new(p.x as f32, p.z as f32)
+                } else {
+                    Uv::new(p.x as f32, p.y as f32)
+                }
+            };
+            [project(v0), project(v1), project(v2)]
+        }
+        UvProjection::Sphere => {
+            let center = bounds.center();
+            let mut uvs = [v0, v1, v2].map(|p| sphere_uv((p - center).unit()));
+            // Triangles crossing the seam wraps around instead of spanning the whole texture
+            let max_u = uvs.iter().map(|uv| uv.u).fold(f32::MIN, f32::max);
+            for uv in uvs.iter_mut() {
+                if max_u - uv.u > 0.5 {
+                    uv.u += 1.;
+                }
+            }
+            uvs
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::geo::transformation::NopTransformer;
+    use crate::geo::Ray;
+    use crate::hittable::Hittable;
+    use crate::material::{Material, RayScatter};
+    use crate::util::interval::RAY_INTERVAL;
 
     use super::*;
 
+    fn load_box(options: ObjOptions) -> Hittables {
+        Obj::new_with_options("resources/obj/", "box.obj", options)
+            .load(&NopTransformer(), None)
+            .unwrap()
+    }
+
+    fn hit_box_uv(uv_projection: UvProjection) -> Uv {
+        let model = load_box(ObjOptions {
+            uv_projection,
+            ..ObjOptions::default()
+        });
+        let ray = Ray::new(Vec3::new(0.1, 0.2, 2.), Vec3::new(0., 0., -1.));
+        model.hit(&ray, &RAY_INTERVAL).unwrap().uv
+    }
+
+    #[test]
+    fn test_uv_projection() {
+        assert_eq!(hit_box_uv(UvProjection::None), Uv::default());
+
+        let uv = hit_box_uv(UvProjection::Box);
+        assert!((uv.u - 0.6).abs() < 1e-5);
+        assert!((uv.v - 0.7).abs() < 1e-5);
+
+        let uv = hit_box_uv(UvProjection::Sphere);
+        assert!(uv.u > 0. && uv.v > 0.);
+    }
+
+    #[test]
+    fn test_smoothing_angle() {
+        let hit_normal = |smoothing_angle| {
+            let model = load_box(ObjOptions {
+                smoothing_angle,
+                ..ObjOptions::default()
+            });
+            let ray = Ray::new(Vec3::new(0.3, 0.4, 2.), Vec3::new(0., 0., -1.));
+            model.hit(&ray, &RAY_INTERVAL).unwrap().normal
+        };
+        let flat = Vec3::new(0., 0., 1.);
+        assert!((hit_normal(None) - flat).length() < 1e-9);
+        assert!((hit_normal(Some(30.)) - flat).length() < 1e-9);
+        // Normals are blended towards the corners of the box
+        let smooth = hit_normal(Some(100.));
+        assert!(smooth.x > 0.01 && smooth.y > 0.01 && smooth.z < 1., "{:?}", smooth);
+    }
+
     #[test]
     fn missing_file() {
         let res = Obj::new("resources/obj/", "missing.obj").load(&NopTransformer(), None);
@@ -182,4 +513,127 @@ mod tests {
         assert!(format!("{}", res.err().unwrap())
             .contains("Failed to decode image texture resources/obj/invalidImage.mtl"));
     }
+
+    #[test]
+    fn test_cull_backfaces() {
+        let ray_from_inside = Ray::new(Vec3::new(0., 0., 0.), Vec3::new(0.1, 0.2, 1.));
+
+        let model = load_box(ObjOptions::default());
+        assert!(model.hit(&ray_from_inside, &RAY_INTERVAL).is_some());
+
+        let model = load_box(ObjOptions {
+            cull_backfaces: true,
+            ..ObjOptions::default()
+        });
+        assert!(model.hit(&ray_from_inside, &RAY_INTERVAL).is_none());
+    }
+
+    #[test]
+    fn test_decimation() {
+        let res = Obj::new_with_options(
+            "resources/spider/",
+            "spider.obj",
+            ObjOptions {
+                decimation: Some(Decimation::TargetTriangleCount(100)),
+                ..ObjOptions::default()
+            },
+        )
+        .load(&NopTransformer(), None);
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_vertex_colors() {
+        let model = Obj::new("resources/obj/", "triWithVertexColors.obj")
+            .load(&NopTransformer(), None)
+            .unwrap();
+        let ray = Ray::new(Vec3::new(0., 0.5, 1.), Vec3::new(0., 0., -1.));
+        let rec = model.hit(&ray, &RAY_INTERVAL).unwrap();
+
+        assert_eq!(rec.vertex_color, Some(Vec3::new(0., 0., 1.)));
+    }
+
+    #[test]
+    fn test_alpha_texture() {
+        let model = Obj::new("resources/obj/", "quadWithAlpha.obj")
+            .load(&NopTransformer(), None)
+            .unwrap();
+        let hit = |x| {
+            let ray = Ray::new(Vec3::new(x, 0.3, 1.), Vec3::new(0., 0., -1.));
+            model.hit(&ray, &RAY_INTERVAL).is_some()
+        };
+
+        // The left half of the texture is opaque and the right half transparent
+        assert!(hit(-0.5));
+        assert!(!hit(0.5));
+    }
+
+    #[test]
+    fn test_emissive_materials() {
+        let model = Obj::new("resources/obj/", "triWithEmission.obj")
+            .load(&NopTransformer(), None)
+            .unwrap();
+        let emission = |x| {
+            let ray = Ray::new(Vec3::new(x, 0., 1.), Vec3::new(0., 0., -1.));
+            let rec = model.hit(&ray, &RAY_INTERVAL).unwrap();
+            match rec.material.scatter(&ray, &rec, &[]) {
+                RayScatter::ScatterEmission(e) => e.color,
+                _ => panic!("Emissive material should be a light"),
+            }
+        };
+
+        assert_eq!(emission(0.), Vec3::new(4., 4., 2.));
+        let screen = emission(2.);
+        assert!(screen.x > 0. && screen.x <= 0.5);
+        // All the emissive triangles are sampled as one light
+        let lights = model.get_lights();
+        assert_eq!(lights.len(), 1);
+        assert!(lights[0].as_sampleable().is_some());
+    }
+
+    #[test]
+    fn test_unit_scaling() {
+        let model = load_box(ObjOptions {
+            unit: Unit::Centimeters,
+            scene_unit: Unit::Millimeters,
+            ..ObjOptions::default()
+        });
+        // Bounding boxes of flat triangles are slightly padded
+        assert!((model.bounding_box().x.min + 5.).abs() < 0.001);
+        assert!((model.bounding_box().x.max - 5.).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_cache() {
+        let dir = std::env::temp_dir().join(format!("solstrale-obj-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let load = || {
+            let options = ObjOptions {
+                cache: Some(SceneCache::new(&dir)),
+                ..ObjOptions::default()
+            };
+            Obj::new_with_options("resources/spider/", "spider.obj", options)
+                .load(&NopTransformer(), None)
+                .unwrap()
+        };
+        let uncached = Obj::new("resources/spider/", "spider.obj")
+            .load(&NopTransformer(), None)
+            .unwrap();
+
+        let first = load();
+        let entries = std::fs::read_dir(&dir).unwrap().count();
+        assert!(entries > 0);
+        let second = load();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), entries);
+
+        for i in 0..20 {
+            let target = Vec3::new(i as f64 * 10. - 100., 0., 0.);
+            let ray = Ray::new(Vec3::new(0., 200., 200.), target - Vec3::new(0., 200., 200.));
+            let hits = [&uncached, &first, &second]
+                .map(|model| model.hit(&ray, &RAY_INTERVAL).map(|rec| (rec.hit_point, rec.uv)));
+            assert_eq!(hits[1], hits[0]);
+            assert_eq!(hits[2], hits[0]);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,783 @@
+//! Parses a plain text scene description into a [`Scene`].
+//! The same scenes can be built from code with a [`SceneBuilder`].
+//!
+//! The description is line based, where each line starts with a keyword
+//! followed by whitespace separated arguments. Empty lines and lines starting
+//! with `#` are ignored. Vectors are written as three consecutive numbers.
+//! Obj models are scaled from the latest declared `asset_unit` to the scene `unit`,
+//! which both default to meters. glTF models are always in meters.
+//! Obj models without normals declared after `smoothing_angle` get smooth normals,
+//! keeping edges sharper than the angle in degrees hard, until `smoothing_angle off`.
+//! Image paths containing `<UDIM>` load all existing UDIM tiles.
+//! Image textures declared after `texture_filter ewa` are sampled with anisotropic filtering,
+//! and after `bilinear` or `trilinear` with the corresponding filtering.
+//! Clipping planes cut away the whole scene on the side the normal points to.
+//! With a `cache` directory, the bvh of the scene and the decoded textures and trees of
+//! the models are kept between renders, see [`crate::loader::cache`].
+//!
+//! ```text
+//! size <width> <height>
+//! region <x> <y> <width> <height>
+//! samples <samples_per_pixel>
+//! max_depth <max_depth>
+//! preview_interval <milliseconds>
+//! time <time>
+//! unit <m|cm|mm|in|ft>
+//! asset_unit <m|cm|mm|in|ft>
+//! texture_filter <nearest|bilinear|trilinear|ewa>
+//! smoothing_angle <degrees|off>
+//! color_space <srgb|acescg>
+//! cache <directory>
+//! background <r> <g> <b>
+//! environment <image_path> [<intensity>]
+//! camera <vertical_fov_degrees> <aperture_size> <look_from> <look_at> <up> [<focus_distance>]
+//! view <name> <vertical_fov_degrees> <aperture_size> <look_from> <look_at> <up> [<focus_distance>]
+//! material <name> lambertian <r> <g> <b>
+//! material <name> lambertian_image <image_path>
+//! material <name> translucent <r> <g> <b> <transmission>
+//! material <name> metal <r> <g> <b> <fuzz>
+//! material <name> dielectric <r> <g> <b> <index_of_refraction>
+//! material <name> principled <r> <g> <b> <metallic> <roughness>
+//! material <name> measured <merl_path>
+//! material <name> hair <eumelanin> <pheomelanin> <longitudinal_roughness> <azimuthal_roughness>
+//! material <name> diffraction <r> <g> <b> <groove_spacing_nm> <groove_depth_nm>
+//! material <name> glint <r> <g> <b> <density> <flake_size> <roughness>
+//! material <name> light <r> <g> <b> [<attenuation_half_length>]
+//! sphere <center> <radius> <material>
+//! ellipsoid <center> <radii> <material>
+//! quad <q> <u> <v> <material>
+//! box <a> <b> <material>
+//! plane <point> <normal> <material>
+//! disc <center> <normal> <radius> <material>
+//! triangle <v0> <v1> <v2> <material>
+//! sun <direction> <r> <g> <b> [<angular_diameter_degrees>]
+//! point_light <position> <r> <g> <b> [<radius>]
+//! obj <path> <filename> [<default_material>]
+//! gltf <path> <filename> [<default_material>]
+//! clip <point> <normal> [<cap_material>]
+//! ```
+//!
+//! ## Example:
+//! ```
+//! # use solstrale::loader::scene::parse_scene;
+//! let scene = parse_scene(
+//!     "size 40 20
+//!      camera 30 0 0 1 5 0 0 0 0 1 0
+//!      material yellow lambertian 1 1 0
+//!      material lamp light 10 10 10
+//!      sphere 0 0 0 0.5 yellow
+//!      sphere 0 10 0 3 lamp",
+//! )
+//! .unwrap();
+//! assert_eq!(scene.render_config.width, 40);
+//! ```
+use std::collections::HashMap;
+use std::error::Error;
+use std::str::SplitWhitespace;
+use std::time::Duration;
+
+use simple_error::SimpleError;
+
+use crate::camera::CameraConfig;
+use crate::geo::transformation::NopTransformer;
+use crate::geo::unit::Unit;
+use crate::geo::vec3::Vec3;
+use crate::hittable::{
+    Bvh, BvhQuality, ClipPlane, Clipped, DirectionalLight, Disc, Ellipsoid, EnvironmentMap,
+    Hittables, Plane, PointLight, Quad, Sphere, Triangle,
+};
+use crate::loader::cache::SceneCache;
+use crate::loader::gltf::{Gltf, GltfOptions};
+use crate::loader::obj::{Obj, ObjOptions};
+use crate::loader::Loader;
+use crate::material::texture::{ImageMap, SolidColor, TextureFilter, Textures};
+use crate::material::{
+    Dielectric, DiffractionGrating, DiffuseLight, Glint, Hair, Lambertian, Materials,
+    MeasuredMaterial, Metal, PrincipledMaterial, Translucent,
+};
+use crate::renderer::shader::PathTracingShader;
+use crate::renderer::{ImageRegion, RenderConfig, RenderImageStrategy, Scene};
+use crate::util::color_space::ColorSpace;
+
+/// Angular diameter in degrees of suns without one, which is that of the sun seen from earth
+const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+/// Radius of point lights without one
+const POINT_LIGHT_RADIUS: f64 = 0.01;
+
+/// Builds a [`Scene`] from materials registered by name and the hittables using them.
+/// Used when parsing scene descriptions, and by code that builds scenes piece by piece.
+/// Models are loaded when the scene is built, scaled from the asset unit at the time
+/// they were added to the scene unit at the time of building
+#[derive(Clone, Default)]
+pub struct SceneBuilder {
+    /// Configuration of the render
+    pub render_config: RenderConfig,
+    /// Camera of the scene
+    pub camera: CameraConfig,
+    /// Named cameras that can be rendered instead of the main camera
+    pub views: HashMap<String, CameraConfig>,
+    /// Color of rays not hitting anything
+    pub background_color: Vec3,
+    /// Environment map that lights the scene instead of the background color
+    pub environment_map: Option<EnvironmentMap>,
+    /// Unit that the models are scaled to
+    pub scene_unit: Unit,
+    /// Unit of the obj models that are added
+    pub asset_unit: Unit,
+    /// Filtering of the image textures that are loaded
+    pub texture_filter: TextureFilter,
+    /// Angle in degrees below which the obj models that are added get smooth normals
+    pub smoothing_angle: Option<f64>,
+    /// Where the bvh, textures and trees of the models are kept between renders
+    pub cache: Option<SceneCache>,
+    materials: HashMap<String, Materials>,
+    world: Vec<Hittables>,
+    objs: Vec<ObjModel>,
+    gltfs: Vec<GltfModel>,
+    clip_planes: Vec<ClipPlane>,
+    clip_cap: Option<Materials>,
+}
+
+#[derive(Clone)]
+struct ObjModel {
+    path: String,
+    filename: String,
+    unit: Unit,
+    texture_filter: TextureFilter,
+    smoothing_angle: Option<f64>,
+    default_material: Option<Materials>,
+}
+
+#[derive(Clone)]
+struct GltfModel {
+    path: String,
+    filename: String,
+    texture_filter: TextureFilter,
+    default_material: Option<Materials>,
+}
+
+impl SceneBuilder {
+    /// Registers the material under the name, replacing any earlier one with the same name
+    pub fn add_material(&mut self, name: &str, material: Materials) {
+        self.materials.insert(name.to_string(), material);
+    }
+
+    /// The registered material with the name
+    pub fn material(&self, name: &str) -> Option<Materials> {
+        self.materials.get(name).cloned()
+    }
+
+    /// Adds the hittable to the scene
+    pub fn add(&mut self, hittable: Hittables) {
+        self.world.push(hittable);
+    }
+
+    /// Adds an obj model, using the default material where the model has none
+    pub fn add_obj(&mut self, path: &str, filename: &str, default_material: Option<Materials>) {
+        self.objs.push(ObjModel {
+            path: path.to_string(),
+            filename: filename.to_string(),
+            unit: self.asset_unit,
+            texture_filter: self.texture_filter,
+            smoothing_angle: self.smoothing_angle,
+            default_material,
+        });
+    }
+
+    /// Adds a glTF model, using the default material where the model has none
+    pub fn add_gltf(&mut self, path: &str, filename: &str, default_material: Option<Materials>) {
+        self.gltfs.push(GltfModel {
+            path: path.to_string(),
+            filename: filename.to_string(),
+            texture_filter: self.texture_filter,
+            default_material,
+        });
+    }
+
+    /// Cuts away the whole scene on the side of the plane that the normal points to.
+    /// The cut surfaces are capped with the latest given cap material
+    pub fn add_clip_plane(&mut self, plane: ClipPlane, cap: Option<Materials>) {
+        self.clip_planes.push(plane);
+        if cap.is_some() {
+            self.clip_cap = cap;
+        }
+    }
+
+    /// Loads the models and builds the scene
+    pub fn build(self) -> Result<Scene, Box<dyn Error>> {
+        let mut world = self.world;
+        for obj in self.objs {
+            let options = ObjOptions {
+                unit: obj.unit,
+                scene_unit: self.scene_unit,
+                texture_filter: obj.texture_filter,
+                smoothing_angle: obj.smoothing_angle,
+                cache: self.cache.clone(),
+                ..ObjOptions::default()
+            };
+            world.push(
+                Obj::new_with_options(&obj.path, &obj.filename, options)
+                    .load(&NopTransformer(), obj.default_material)?,
+            );
+        }
+        for gltf in self.gltfs {
+            let options = GltfOptions {
+                scene_unit: self.scene_unit,
+                texture_filter: gltf.texture_filter,
+                cache: self.cache.clone(),
+                ..GltfOptions::default()
+            };
+            world.push(
+                Gltf::new_with_options(&gltf.path, &gltf.filename, options)
+                    .load(&NopTransformer(), gltf.default_material)?,
+            );
+        }
+
+        let mut world = match &self.cache {
+            None => Bvh::new(world),
+            Some(cache) => Bvh::new_with_cache(world, BvhQuality::default(), cache),
+        };
+        if !self.clip_planes.is_empty() {
+            world = Clipped::new(world, self.clip_planes, self.clip_cap);
+        }
+
+        Ok(Scene {
+            world,
+            camera: self.camera,
+            views: self.views,
+            background_color: self.background_color,
+            environment_map: self.environment_map,
+            background: None,
+            render_config: self.render_config,
+        })
+    }
+}
+
+/// Parses the given scene description into a [`Scene`]
+pub fn parse_scene(description: &str) -> Result<Scene, Box<dyn Error>> {
+    let mut scene = SceneBuilder::default();
+
+    for (i, line) in description.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut args = Args {
+            line_number: i + 1,
+            tokens: line.split_whitespace(),
+        };
+        let keyword = args.string()?;
+        let render_config = &mut scene.render_config;
+
+        match keyword.as_str() {
+            "size" => {
+                render_config.width = args.number()? as usize;
+                render_config.height = args.number()? as usize;
+            }
+            "region" => {
+                render_config.region = Some(ImageRegion {
+                    x: args.number()? as usize,
+                    y: args.number()? as usize,
+                    width: args.number()? as usize,
+                    height: args.number()? as usize,
+                })
+            }
+            "samples" => render_config.samples_per_pixel = args.number()? as u32,
+            "max_depth" => render_config.shader = PathTracingShader::new(args.number()? as u32),
+            "preview_interval" => {
+                render_config.render_image_strategy =
+                    RenderImageStrategy::Interval(Duration::from_millis(args.number()? as u64))
+            }
+            "time" => render_config.time = args.number()?,
+            "unit" => scene.scene_unit = args.unit()?,
+            "asset_unit" => scene.asset_unit = args.unit()?,
+            "texture_filter" => scene.texture_filter = args.texture_filter()?,
+            "smoothing_angle" => scene.smoothing_angle = args.smoothing_angle()?,
+            "color_space" => render_config.color_space = args.color_space()?,
+            "cache" => scene.cache = Some(SceneCache::new(args.string()?)),
+            "background" => scene.background_color = args.vec3()?,
+            "environment" => {
+                let path = args.string()?;
+                let intensity = if args.has_more() { args.number()? } else { 1. };
+                scene.environment_map = Some(EnvironmentMap::load(&path, intensity)?);
+            }
+            "camera" => scene.camera = args.camera()?,
+            "view" => {
+                let name = args.string()?;
+                scene.views.insert(name, args.camera()?);
+            }
+            "material" => {
+                let name = args.string()?;
+                let material = parse_material(&mut args, scene.texture_filter)?;
+                scene.add_material(&name, material);
+            }
+            "sphere" => scene.add(Sphere::new(
+                args.vec3()?,
+                args.number()?,
+                args.material(&scene)?,
+            )),
+            "ellipsoid" => scene.add(Ellipsoid::new(
+                args.vec3()?,
+                args.vec3()?,
+                args.material(&scene)?,
+                &NopTransformer(),
+            )),
+            "quad" => scene.add(Quad::new(
+                args.vec3()?,
+                args.vec3()?,
+                args.vec3()?,
+                args.material(&scene)?,
+                &NopTransformer(),
+            )),
+            "box" => {
+                let (a, b) = (args.vec3()?, args.vec3()?);
+                let material = args.material(&scene)?;
+                for side in Quad::new_box(a, b, material, &NopTransformer()) {
+                    scene.add(side);
+                }
+            }
+            "plane" => scene.add(Plane::new(
+                args.vec3()?,
+                args.vec3()?,
+                args.material(&scene)?,
+            )),
+            "disc" => scene.add(Disc::new(
+                args.vec3()?,
+                args.vec3()?,
+                args.number()?,
+                args.material(&scene)?,
+            )),
+            "sun" => {
+                let direction = args.vec3()?;
+                let color = args.vec3()?;
+                let angular_diameter = if args.has_more() {
+                    args.number()?
+                } else {
+                    SUN_ANGULAR_DIAMETER
+                };
+                scene.add(DirectionalLight::new(direction, color, angular_diameter))
+            }
+            "point_light" => {
+                let position = args.vec3()?;
+                let color = args.vec3()?;
+                let radius = if args.has_more() {
+                    args.number()?
+                } else {
+                    POINT_LIGHT_RADIUS
+                };
+                scene.add(PointLight::new(position, color, radius))
+            }
+            "triangle" => scene.add(Triangle::new(
+                args.vec3()?,
+                args.vec3()?,
+                args.vec3()?,
+                args.material(&scene)?,
+                &NopTransformer(),
+            )),
+            "obj" => {
+                let path = args.string()?;
+                let filename = args.string()?;
+                let default_material = if args.has_more() {
+                    Some(args.material(&scene)?)
+                } else {
+                    None
+                };
+                scene.add_obj(&path, &filename, default_material);
+            }
+            "gltf" => {
+                let path = args.string()?;
+                let filename = args.string()?;
+                let default_material = if args.has_more() {
+                    Some(args.material(&scene)?)
+                } else {
+                    None
+                };
+                scene.add_gltf(&path, &filename, default_material);
+            }
+            "clip" => {
+                let plane = ClipPlane {
+                    point: args.vec3()?,
+                    normal: args.vec3()?,
+                };
+                let cap = if args.has_more() {
+                    Some(args.material(&scene)?)
+                } else {
+                    None
+                };
+                scene.add_clip_plane(plane, cap);
+            }
+            _ => return Err(args.error(&format!("unknown keyword '{}'", keyword))),
+        }
+
+        if args.has_more() {
+            return Err(args.error("unexpected trailing arguments"));
+        }
+    }
+
+    // Models are loaded last, as the scene unit can be declared after them
+    scene.build()
+}
+
+fn parse_material(
+    args: &mut Args,
+    texture_filter: TextureFilter,
+) -> Result<Materials, Box<dyn Error>> {
+    let material_type = args.string()?;
+    match material_type.as_str() {
+        "lambertian" => Ok(Lambertian::new(
+            SolidColor::new_from_vec3(args.vec3()?),
+            None,
+        )),
+        "lambertian_image" => Ok(Lambertian::new(
+            ImageMap::load_with_filter(&args.string()?, texture_filter)?,
+            None,
+        )),
+        "translucent" => Ok(Translucent::new(
+            SolidColor::new_from_vec3(args.vec3()?),
+            None,
+            args.number()?,
+        )),
+        "metal" => Ok(Metal::new(
+            SolidColor::new_from_vec3(args.vec3()?),
+            None,
+            args.number()?,
+        )),
+        "dielectric" => Ok(Dielectric::new(
+            SolidColor::new_from_vec3(args.vec3()?),
+            None,
+            args.number()?,
+        )),
+        "principled" => Ok(PrincipledMaterial::new(
+            SolidColor::new_from_vec3(args.vec3()?),
+            None,
+            grey(args.number()?),
+            grey(args.number()?),
+            grey(0.5),
+            1.5,
+        )),
+        "measured" => MeasuredMaterial::load_merl(&args.string()?),
+        "hair" => Ok(Hair::new(
+            args.number()?,
+            args.number()?,
+            args.number()?,
+            args.number()?,
+        )),
+        "diffraction" => Ok(DiffractionGrating::new(
+            SolidColor::new_from_vec3(args.vec3()?),
+            None,
+            args.number()?,
+            args.number()?,
+            0.,
+        )),
+        "glint" => {
+            let color = args.vec3()?;
+            Ok(Glint::new(
+                Lambertian::new(SolidColor::new_from_vec3(color), None),
+                SolidColor::new_from_vec3(color),
+                args.number()?,
+                args.number()?,
+                args.number()?,
+            ))
+        }
+        "light" => {
+            let color = args.vec3()?;
+            let attenuation_half_length = if args.has_more() {
+                Some(args.number()?)
+            } else {
+                None
+            };
+            Ok(DiffuseLight::new(
+                color.x,
+                color.y,
+                color.z,
+                attenuation_half_length,
+            ))
+        }
+        _ => Err(args.error(&format!("unknown material type '{}'", material_type))),
+    }
+}
+
+fn grey(value: f64) -> Textures {
+    SolidColor::new(value, value, value)
+}
+
+/// The arguments of a single line in the scene description
+struct Args<'a> {
+    line_number: usize,
+    tokens: SplitWhitespace<'a>,
+}
+
+impl Args<'_> {
+    fn error(&self, message: &str) -> Box<dyn Error> {
+        Box::new(SimpleError::new(format!(
+            "line {}: {}",
+            self.line_number, message
+        )))
+    }
+
+    fn has_more(&self) -> bool {
+        self.tokens.clone().next().is_some()
+    }
+
+    fn string(&mut self) -> Result<String, Box<dyn Error>> {
+        self.tokens
+            .next()
+            .map(|t| t.to_string())
+            .ok_or_else(|| self.error("missing argument"))
+    }
+
+    fn number(&mut self) -> Result<f64, Box<dyn Error>> {
+        let token = self.string()?;
+        token
+            .parse()
+            .map_err(|_| self.error(&format!("'{}' is not a number", token)))
+    }
+
+    fn unit(&mut self) -> Result<Unit, Box<dyn Error>> {
+        let token = self.string()?;
+        token.parse().map_err(|e: SimpleError| self.error(e.as_str()))
+    }
+
+    fn texture_filter(&mut self) -> Result<TextureFilter, Box<dyn Error>> {
+        let token = self.string()?;
+        match token.as_str() {
+            "nearest" => Ok(TextureFilter::Nearest),
+            "bilinear" => Ok(TextureFilter::Bilinear),
+            "trilinear" => Ok(TextureFilter::Trilinear),
+            "ewa" => Ok(TextureFilter::Ewa),
+            _ => Err(self.error(&format!("unknown texture filter '{}'", token))),
+        }
+    }
+
+    fn smoothing_angle(&mut self) -> Result<Option<f64>, Box<dyn Error>> {
+        let token = self.string()?;
+        if token == "off" {
+            return Ok(None);
+        }
+        token
+            .parse()
+            .map(Some)
+            .map_err(|_| self.error(&format!("'{}' is not a number or off", token)))
+    }
+
+    fn color_space(&mut self) -> Result<ColorSpace, Box<dyn Error>> {
+        let token = self.string()?;
+        match token.as_str() {
+            "srgb" => Ok(ColorSpace::LinearSrgb),
+            "acescg" => Ok(ColorSpace::AcesCg),
+            _ => Err(self.error(&format!("unknown color space '{}'", token))),
+        }
+    }
+
+    fn camera(&mut self) -> Result<CameraConfig, Box<dyn Error>> {
+        Ok(CameraConfig {
+            vertical_fov_degrees: self.number()?,
+            aperture_size: self.number()?,
+            look_from: self.vec3()?,
+            look_at: self.vec3()?,
+            up: self.vec3()?,
+            focus_distance: if self.has_more() {
+                Some(self.number()?)
+            } else {
+                None
+            },
+        })
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, Box<dyn Error>> {
+        Ok(Vec3::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    fn material(&mut self, scene: &SceneBuilder) -> Result<Materials, Box<dyn Error>> {
+        let name = self.string()?;
+        scene
+            .material(&name)
+            .ok_or_else(|| self.error(&format!("unknown material '{}'", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::Ray;
+    use crate::hittable::Hittable;
+    use crate::util::interval::RAY_INTERVAL;
+
+    use super::*;
+
+    #[test]
+    fn parse_render_settings() {
+        let scene = parse_scene(
+            "# A comment
+            size 30 20
+            region 5 4 10 8
+            samples 7
+            time 1.5
+
+            background 0.1 0.2 0.3
+            camera 40 0.5 1 2 3 0 0 0 0 1 0 2.5
+            view top 30 0 0 10 0 0 0 0 0 0 -1",
+        )
+        .unwrap();
+
+        assert_eq!(scene.render_config.width, 30);
+        assert_eq!(scene.render_config.height, 20);
+        assert_eq!(
+            scene.render_config.region,
+            Some(ImageRegion {
+                x: 5,
+                y: 4,
+                width: 10,
+                height: 8
+            })
+        );
+        assert_eq!(scene.render_config.samples_per_pixel, 7);
+        assert_eq!(scene.render_config.time, 1.5);
+        assert_eq!(scene.background_color, Vec3::new(0.1, 0.2, 0.3));
+        assert_eq!(scene.camera.vertical_fov_degrees, 40.);
+        assert_eq!(scene.camera.look_from, Vec3::new(1., 2., 3.));
+        assert_eq!(scene.camera.focus_distance, Some(2.5));
+        assert_eq!(scene.views["top"].look_from, Vec3::new(0., 10., 0.));
+        assert_eq!(scene.views["top"].focus_distance, None);
+    }
+
+    #[test]
+    fn environment_map() {
+        let scene = parse_scene("environment resources/textures/sky.hdr 2").unwrap();
+        let sky = scene.environment_map.unwrap().color(Vec3::new(0., 1., 0.));
+        assert!((sky - Vec3::new(0.8, 1.2, 2.)).length() < 0.05);
+    }
+
+    #[test]
+    fn obj_scaled_to_scene_unit() {
+        let scene = parse_scene(
+            "asset_unit cm
+            obj resources/obj/ box.obj
+            unit mm",
+        )
+        .unwrap();
+        assert!((scene.world.bounding_box().y.max - 5.).abs() < 0.001);
+    }
+
+    #[test]
+    fn obj_smoothing_angle() {
+        let hit_normal = |description: &str| {
+            let scene = parse_scene(description).unwrap();
+            let ray = Ray::new(Vec3::new(0.3, 0.4, 2.), Vec3::new(0., 0., -1.));
+            scene.world.hit(&ray, &RAY_INTERVAL).unwrap().normal
+        };
+        let is_flat = |description| (hit_normal(description) - Vec3::new(0., 0., 1.)).near_zero();
+        assert!(is_flat("obj resources/obj/ box.obj"));
+        assert!(!is_flat("smoothing_angle 100\nobj resources/obj/ box.obj"));
+        assert!(is_flat(
+            "smoothing_angle 100\nsmoothing_angle off\nobj resources/obj/ box.obj"
+        ));
+    }
+
+    #[test]
+    fn gltf_scaled_to_scene_unit() {
+        let scene = parse_scene(
+            "gltf resources/gltf/ triangle.gltf
+            unit cm",
+        )
+        .unwrap();
+        assert!((scene.world.bounding_box().y.max - 200.).abs() < 0.001);
+    }
+
+    #[test]
+    fn clipping_plane() {
+        let scene = parse_scene(
+            "material white lambertian 1 1 1
+            material red lambertian 1 0 0
+            sphere 0 0 0 1 white
+            clip 0 0 0 0 0 1 red",
+        )
+        .unwrap();
+        let ray = Ray::new(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+        let rec = scene.world.hit(&ray, &RAY_INTERVAL).unwrap();
+        assert_eq!(rec.ray_length, 5.);
+    }
+
+    #[test]
+    fn plane_and_disc_light() {
+        let scene = parse_scene(
+            "material white lambertian 1 1 1
+            material lamp light 10 10 10
+            plane 0 0 0 0 1 0 white
+            disc 0 5 0 0 -1 0 2 lamp",
+        )
+        .unwrap();
+        let lights = scene.world.get_lights();
+        assert_eq!(lights.len(), 1);
+        assert!(lights[0].as_sampleable().is_some());
+
+        let ray = Ray::new(Vec3::new(100., 1., 100.), Vec3::new(0., -1., 0.));
+        let rec = scene.world.hit(&ray, &RAY_INTERVAL).unwrap();
+        assert_eq!(rec.ray_length, 1.);
+    }
+
+    #[test]
+    fn cached_models() {
+        let dir = std::env::temp_dir().join(format!("solstrale-scene-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let description = format!(
+            "cache {}
+            obj resources/obj/ box.obj
+            gltf resources/gltf/ triangle.gltf",
+            dir.display()
+        );
+        let first = parse_scene(&description).unwrap();
+        let entries = std::fs::read_dir(&dir).unwrap().count();
+        assert!(entries > 0);
+
+        let second = parse_scene(&description).unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), entries);
+        assert_eq!(second.world.bounding_box(), first.world.bounding_box());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unknown_unit() {
+        let res = parse_scene("unit furlong");
+        assert_eq!(
+            "line 1: unknown unit 'furlong'",
+            format!("{}", res.err().unwrap())
+        );
+    }
+
+    #[test]
+    fn unknown_keyword() {
+        let res = parse_scene("size 1 1\ncylinder 0 0 0 1");
+        assert_eq!(
+            "line 2: unknown keyword 'cylinder'",
+            format!("{}", res.err().unwrap())
+        );
+    }
+
+    #[test]
+    fn unknown_material() {
+        let res = parse_scene("sphere 0 0 0 1 gold");
+        assert_eq!(
+            "line 1: unknown material 'gold'",
+            format!("{}", res.err().unwrap())
+        );
+    }
+
+    #[test]
+    fn invalid_number() {
+        let res = parse_scene("size ten 1");
+        assert_eq!(
+            "line 1: 'ten' is not a number",
+            format!("{}", res.err().unwrap())
+        );
+    }
+
+    #[test]
+    fn trailing_arguments() {
+        let res = parse_scene("samples 1 2");
+        assert_eq!(
+            "line 1: unexpected trailing arguments",
+            format!("{}", res.err().unwrap())
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Material for surfaces with fine parallel grooves, that split light into rainbow colors
+use std::f64::consts::PI;
+
+use crate::geo::vec3::{random_in_unit_sphere, Vec3, ZERO_VECTOR};
+use crate::geo::{Onb, Ray, Uv};
+use crate::hittable::Hittables;
+use crate::material::texture::Textures;
+use crate::material::{
+    transform_normal_by_map, Material, Materials, RayHit, RayScatter, ScatterBasic,
+};
+use crate::random::random_normal_float;
+
+/// Wavelengths in nanometers that the red, green and blue channels are diffracted as
+const CHANNEL_WAVELENGTHS: [f64; 3] = [630., 532., 465.];
+/// Highest diffraction order that is considered, higher orders carry almost no light
+const MAX_ORDER: i32 = 8;
+
+/// A reflective diffraction grating, like the surface of a CD, holographic foil or the wings
+/// of some insects. Light is reflected into several directions, the diffraction orders,
+/// that are different for each wavelength so that the reflections are split into rainbow
+/// colors. Each color channel is diffracted as a single wavelength. The grooves are
+/// sinusoidal and run across the tangent of the hittable, and how much light goes into each
+/// order is given analytically by the depth of the grooves. Shallow grooves are mostly a mirror
+#[derive(Clone, Debug)]
+pub struct DiffractionGrating {
+    albedo: Textures,
+    normal: Option<Textures>,
+    groove_spacing: f64,
+    groove_depth: f64,
+    fuzz: f64,
+}
+
+impl DiffractionGrating {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new diffraction grating material
+    ///
+    /// # Arguments
+    /// * `albedo` - Color of the reflections
+    /// * `normal` - Normal map of the surface
+    /// * `groove_spacing` - Distance between the grooves in nanometers, about 1600 for a CD
+    /// * `groove_depth` - Depth of the grooves in nanometers, from top to bottom
+    /// * `fuzz` - Randomness applied to the reflections, like for [`crate::material::Metal`]
+    pub fn new(
+        albedo: Textures,
+        normal: Option<Textures>,
+        groove_spacing: f64,
+        groove_depth: f64,
+        fuzz: f64,
+    ) -> Materials {
+        Materials::from(DiffractionGrating {
+            albedo,
+            normal,
+            groove_spacing: groove_spacing.max(1.),
+            groove_depth: groove_depth.max(0.),
+            fuzz,
+        })
+    }
+
+    /// The directions of the diffraction orders of the wavelength that leave the surface,
+    /// and the fraction of the light that goes into each
+    fn orders(
+        &self,
+        reflected: Vec3,
+        normal: Vec3,
+        grating: Vec3,
+        wavelength: f64,
+    ) -> Vec<(Vec3, f64)> {
+        let cos_incoming = reflected.dot(normal);
+        let tangential = reflected - normal * cos_incoming;
+        (-MAX_ORDER..=MAX_ORDER)
+            .filter_map(|order| {
+                let shifted =
+                    tangential + grating * (order as f64 * wavelength / self.groove_spacing);
+                let cos_outgoing_squared = 1. - shifted.length_squared();
+                if cos_outgoing_squared <= 0. {
+                    return None;
+                }
+                let cos_outgoing = cos_outgoing_squared.sqrt();
+                let phase = PI * self.groove_depth * (cos_incoming + cos_outgoing) / wavelength;
+                let efficiency = bessel_j(order.unsigned_abs(), phase).powi(2);
+                Some((shifted + normal * cos_outgoing, efficiency))
+            })
+            .collect()
+    }
+}
+
+impl Material for DiffractionGrating {
+    /// Reflects the ray into a diffraction order of one of the color channels,
+    /// chosen by how much light goes into each order
+    fn scatter(&self, ray: &Ray, rec: &RayHit, _lights: &[Hittables]) -> RayScatter {
+        let normal = rec.normal;
+        let reflected = ray.direction.unit().reflect(normal);
+        let grating = rec.onb.tangent - normal * rec.onb.tangent.dot(normal);
+        let grating = if grating.near_zero() {
+            Onb::new(normal).tangent
+        } else {
+            grating.unit()
+        };
+
+        let channel = ((random_normal_float() * 3.) as usize).min(2);
+        let orders = self.orders(reflected, normal, grating, CHANNEL_WAVELENGTHS[channel]);
+        let total: f64 = orders.iter().map(|(_, efficiency)| efficiency).sum();
+
+        let mut choice = random_normal_float() * total;
+        let direction = orders
+            .iter()
+            .find(|(_, efficiency)| {
+                choice -= efficiency;
+                choice < 0.
+            })
+            .or(orders.last())
+            .map_or(reflected, |(direction, _)| *direction);
+
+        // Only the chosen channel is reflected, so it carries the light of all three
+        let albedo = rec.texture_color(&self.albedo);
+        let color = match channel {
+            0 => Vec3::new(albedo.x, 0., 0.),
+            1 => Vec3::new(0., albedo.y, 0.),
+            _ => Vec3::new(0., 0., albedo.z),
+        } * (3. * total);
+
+        RayScatter::ScatterBasic(ScatterBasic {
+            color: if total > 0. { color } else { ZERO_VECTOR },
+            ray: Ray::new_at_time(
+                rec.hit_point,
+                direction
+                    + random_in_unit_sphere() * self.fuzz * rec.overrides.roughness_multiplier,
+                rec.time,
+            ),
+        })
+    }
+
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
+        self.normal
+            .as_ref()
+            .map_or(onb.normal, |n| transform_normal_by_map(n, onb, uv, time))
+    }
+}
+
+/// Bessel function of the first kind of the order, from its power series
+fn bessel_j(order: u32, x: f64) -> f64 {
+    let half_x = x / 2.;
+    let mut term = (1..=order).fold(1., |term, i| term * half_x / i as f64);
+    let mut sum = 0.;
+    for k in 1..40 {
+        sum += term;
+        term *= -half_x * half_x / (k as f64 * (k + order) as f64);
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::material::texture::SolidColor;
+
+    use super::*;
+
+    #[test]
+    fn test_bessel_j() {
+        assert!((bessel_j(0, 0.) - 1.).abs() < 1e-12);
+        assert!((bessel_j(0, 2.404825557695773)).abs() < 1e-9);
+        assert!((bessel_j(1, 1.) - 0.440050585744933).abs() < 1e-12);
+        assert!((bessel_j(3, 5.) - 0.364831230613667).abs() < 1e-12);
+        // The squares of all orders sum to one
+        let sum: f64 = (-30..=30_i32)
+            .map(|m| bessel_j(m.unsigned_abs(), 4.).powi(2))
+            .sum();
+        assert!((sum - 1.).abs() < 1e-9, "sum was {}", sum);
+    }
+
+    fn grating(groove_depth: f64) -> DiffractionGrating {
+        match DiffractionGrating::new(SolidColor::new(1., 1., 1.), None, 1600., groove_depth, 0.) {
+            Materials::DiffractionGratingType(grating) => grating,
+            _ => panic!("Expected diffraction grating"),
+        }
+    }
+
+    #[test]
+    fn test_orders() {
+        let normal = Vec3::new(0., 0., 1.);
+        let tangent = Vec3::new(1., 0., 0.);
+        let reflected = Vec3::new(0., 0., 1.);
+
+        // Without grooves all light is reflected like a mirror
+        let orders = grating(0.).orders(reflected, normal, tangent, 532.);
+        let mirror: Vec<_> = orders.iter().filter(|(_, e)| *e > 0.).collect();
+        assert_eq!(mirror, vec![&(reflected, 1.)]);
+
+        // The first order follows the grating equation, and is further out for red than blue
+        let first_order = |wavelength| {
+            let orders = grating(200.).orders(reflected, normal, tangent, wavelength);
+            orders.iter().map(|(d, _)| d.x).find(|x| *x > 0.).unwrap()
+        };
+        assert!((first_order(630.) - 630. / 1600.).abs() < 1e-12);
+        assert!(first_order(630.) > first_order(465.));
+
+        let orders = grating(200.).orders(reflected, normal, tangent, 532.);
+        assert_eq!(orders.len(), 7);
+        let total: f64 = orders.iter().map(|(_, e)| e).sum();
+        assert!(total <= 1. + 1e-12);
+        assert!(orders
+            .iter()
+            .all(|(d, _)| (d.length() - 1.).abs() < 1e-12 && d.z > 0.));
+    }
+}
@@ -0,0 +1,207 @@
+//! Material with discrete mirror flakes, that sparkle when they reflect a light
+use std::f64::consts::PI;
+
+use crate::geo::vec3::Vec3;
+use crate::geo::{Onb, Ray, Uv};
+use crate::hittable::Hittables;
+use crate::material::texture::{hash_to_unit, Textures};
+use crate::material::{Material, Materials, RayHit, RayScatter, ScatterBasic};
+
+/// Flakes on top of an underlying base material, for glitter, car paint flakes and snow.
+/// The surface is divided into cells of the flake size, and each cell holds a flake
+/// with the probability of the density. The flakes are round mirrors tilted randomly
+/// away from the normal, and as their positions and tilts are given by the position in the
+/// scene instead of random sampling, each flake sparkles in the same place in every frame
+/// when the camera moves. Rays hitting between the flakes are scattered by the base material
+#[derive(Clone, Debug)]
+pub struct Glint {
+    base: Box<Materials>,
+    color: Textures,
+    density: f64,
+    flake_size: f64,
+    roughness: f64,
+}
+
+impl Glint {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new glint material
+    ///
+    /// # Arguments
+    /// * `base` - The material between the flakes, which keeps its own normal map
+    /// * `color` - Color of the reflections of the flakes
+    /// * `density` - Between zero and one, the fraction of the cells that hold a flake
+    /// * `flake_size` - Diameter of the flakes, in the units of the scene
+    /// * `roughness` - Between zero and one, how much the flakes are tilted away from the
+    ///   normal. Flat flakes only sparkle close to the mirror direction of a light
+    pub fn new(
+        base: Materials,
+        color: Textures,
+        density: f64,
+        flake_size: f64,
+        roughness: f64,
+    ) -> Materials {
+        Materials::from(Glint {
+            base: Box::new(base),
+            color,
+            density: density.clamp(0., 1.),
+            flake_size: flake_size.max(f64::EPSILON),
+            roughness: roughness.clamp(0., 1.),
+        })
+    }
+
+    /// The normal of the flake that covers the point, if any.
+    /// The flakes of the neighbouring cells are also checked, as they reach into the cell
+    fn flake_normal(&self, point: Vec3, normal: Vec3) -> Option<Vec3> {
+        let cell = point / self.flake_size;
+        let (x, y, z) = (
+            cell.x.floor() as i32,
+            cell.y.floor() as i32,
+            cell.z.floor() as i32,
+        );
+        let mut closest: Option<(f64, [i32; 3])> = None;
+        for neighbour in 0..27 {
+            let c = [
+                x + neighbour % 3 - 1,
+                y + neighbour / 3 % 3 - 1,
+                z + neighbour / 9 - 1,
+            ];
+            if cell_random(c, 0) >= self.density {
+                continue;
+            }
+            let center = Vec3::new(
+                c[0] as f64 + cell_random(c, 1),
+                c[1] as f64 + cell_random(c, 2),
+                c[2] as f64 + cell_random(c, 3),
+            );
+            let distance_squared = (cell - center).length_squared();
+            if distance_squared < 0.25 && closest.is_none_or(|(d, _)| distance_squared < d) {
+                closest = Some((distance_squared, c));
+            }
+        }
+
+        closest.map(|(_, c)| {
+            let radius = self.roughness * cell_random(c, 4).sqrt();
+            let angle = 2. * PI * cell_random(c, 5);
+            let onb = Onb::new(normal);
+            (normal
+                + onb.tangent * (radius * angle.cos())
+                + onb.bi_tangent * (radius * angle.sin()))
+            .unit()
+        })
+    }
+}
+
+impl Material for Glint {
+    fn is_light(&self) -> bool {
+        self.base.is_light()
+    }
+
+    /// Reflects the ray like a mirror if it hits a flake that faces it,
+    /// otherwise the base material scatters it
+    fn scatter(&self, ray: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
+        let direction = ray.direction.unit();
+        let reflected = self
+            .flake_normal(rec.hit_point, rec.normal)
+            .map(|flake_normal| direction.reflect(flake_normal))
+            .filter(|reflected| reflected.dot(rec.normal) > 0.);
+
+        match reflected {
+            Some(reflected) => RayScatter::ScatterBasic(ScatterBasic {
+                color: rec.texture_color(&self.color),
+                ray: Ray::new_at_time(rec.hit_point, reflected, rec.time),
+            }),
+            None => self.base.scatter(ray, rec, lights),
+        }
+    }
+
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
+        self.base.get_transformed_normal(onb, uv, time)
+    }
+}
+
+/// A pseudo random number between 0 and 1 that is the same every time for a cell and seed
+fn cell_random(cell: [i32; 3], seed: u32) -> f64 {
+    hash_to_unit(
+        (cell[0] as u32).wrapping_mul(0x8da6b343)
+            ^ (cell[1] as u32).wrapping_mul(0xd8163841)
+            ^ (cell[2] as u32).wrapping_mul(0xcb1ab31f)
+            ^ seed.wrapping_mul(0x9e3779b9),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::material::texture::SolidColor;
+    use crate::material::Lambertian;
+
+    use super::*;
+
+    fn glint(density: f64, roughness: f64) -> Glint {
+        let white = || SolidColor::new(1., 1., 1.);
+        match Glint::new(
+            Lambertian::new(white(), None),
+            white(),
+            density,
+            0.1,
+            roughness,
+        ) {
+            Materials::GlintType(glint) => glint,
+            _ => panic!("Expected glint"),
+        }
+    }
+
+    #[test]
+    fn test_flakes_are_stable() {
+        let normal = Vec3::new(0., 0., 1.);
+        let material = glint(0.5, 0.5);
+        let points: Vec<Vec3> = (0..1000)
+            .map(|i| Vec3::new((i % 40) as f64 * 0.013, (i / 40) as f64 * 0.017, 0.))
+            .collect();
+        let flakes: Vec<_> = points
+            .iter()
+            .map(|p| material.flake_normal(*p, normal))
+            .collect();
+        let again: Vec<_> = points
+            .iter()
+            .map(|p| material.flake_normal(*p, normal))
+            .collect();
+        assert_eq!(flakes, again);
+
+        // Some points are covered by flakes, which are tilted less than the roughness allows
+        let covered = flakes.iter().flatten().count();
+        assert!(covered > 100 && covered < 900, "covered was {}", covered);
+        assert!(flakes
+            .iter()
+            .flatten()
+            .all(|n| n.dot(normal) >= 1. / 1.25_f64.sqrt() - 1e-12));
+        // Flat flakes face the normal
+        let flat = glint(0.5, 0.);
+        assert!(points
+            .iter()
+            .filter_map(|p| flat.flake_normal(*p, normal))
+            .all(|n| (n - normal).near_zero()));
+    }
+
+    #[test]
+    fn test_density() {
+        let normal = Vec3::new(0., 0., 1.);
+        let point = Vec3::new(0.55, 0.37, 0.);
+        assert_eq!(glint(0., 0.5).flake_normal(point, normal), None);
+
+        let material = glint(1., 0.5);
+        let ray = Ray::new(Vec3::new(0., 0., 1.), Vec3::new(0.55, 0.37, -1.));
+        let onb = Onb::new(normal);
+        let flake = Materials::from(material.clone());
+        let rec = RayHit::new(point, onb, &flake, 1., Uv::default(), true, 0.);
+        match (
+            material.flake_normal(point, normal),
+            material.scatter(&ray, &rec, &[]),
+        ) {
+            (Some(n), RayScatter::ScatterBasic(s)) => {
+                assert_eq!(s.ray.direction, ray.direction.unit().reflect(n))
+            }
+            (None, RayScatter::ScatterPdf(_)) => {}
+            _ => panic!("Expected the flake or the base to scatter"),
+        }
+    }
+}
@@ -0,0 +1,490 @@
+//! Material for hair and fur, where light is scattered by thin fibers
+use std::f64::consts::{LN_2, PI};
+
+use crate::geo::vec3::{Vec3, ONE_VECTOR, ZERO_VECTOR};
+use crate::geo::Ray;
+use crate::hittable::Hittables;
+use crate::material::texture::brightness;
+use crate::material::{reflectance, Material, Materials, RayHit, RayScatter, ScatterPdf};
+use crate::pdf::{ContainerPdf, Pdf};
+use crate::random::random_normal_float;
+
+/// Number of paths through the fiber that are modelled separately,
+/// the rest are summed up in a single term
+const P_MAX: usize = 3;
+/// Index of refraction of the keratin that hair is made of
+const HAIR_INDEX_OF_REFRACTION: f64 = 1.55;
+/// Angle in degrees that the scales on the surface of hair are tilted by
+const SCALE_ANGLE: f64 = 2.;
+
+/// Absorption of eumelanin, the pigment that makes hair brown to black
+const EUMELANIN_ABSORPTION: Vec3 = Vec3 {
+    x: 0.419,
+    y: 0.697,
+    z: 1.37,
+};
+/// Absorption of pheomelanin, the pigment that makes hair red
+const PHEOMELANIN_ABSORPTION: Vec3 = Vec3 {
+    x: 0.187,
+    y: 0.4,
+    z: 1.05,
+};
+
+/// Hair and fur, using the fiber scattering model of Chiang et al. Light is reflected off the
+/// surface of the fibers and transmitted through them, colored by the pigments inside.
+/// The fibers run along the tangent of the hittable, so thin hittables like long quads and
+/// ellipsoids stretched along the hair work as strands
+#[derive(Clone, Debug)]
+pub struct Hair {
+    absorption: Vec3,
+    longitudinal_variance: [f64; P_MAX + 1],
+    azimuthal_scale: f64,
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+impl Hair {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates hair colored by the concentrations of the pigments eumelanin, which makes hair
+    /// brown to black, and pheomelanin, which makes it red. Blond hair has an eumelanin
+    /// concentration around 0.3, brown around 1.3 and black around 8.
+    /// The roughnesses along and around the fibers are between zero and one,
+    /// with 0.3 being typical for human hair
+    pub fn new(
+        eumelanin: f64,
+        pheomelanin: f64,
+        longitudinal_roughness: f64,
+        azimuthal_roughness: f64,
+    ) -> Materials {
+        let absorption =
+            EUMELANIN_ABSORPTION * eumelanin.max(0.) + PHEOMELANIN_ABSORPTION * pheomelanin.max(0.);
+        Hair::new_from_absorption(absorption, longitudinal_roughness, azimuthal_roughness)
+    }
+
+    /// Creates hair that has approximately the given color after multiple scattering,
+    /// for colors that are not natural hair colors
+    pub fn new_from_color(
+        color: Vec3,
+        longitudinal_roughness: f64,
+        azimuthal_roughness: f64,
+    ) -> Materials {
+        let b = azimuthal_roughness.clamp(0., 1.);
+        let divisor = 5.969 - 0.215 * b + 2.532 * b.powi(2) - 10.73 * b.powi(3)
+            + 5.574 * b.powi(4)
+            + 0.245 * b.powi(5);
+        let channel = |c: f64| (c.clamp(1e-4, 1.).ln() / divisor).powi(2);
+        let absorption = Vec3::new(channel(color.x), channel(color.y), channel(color.z));
+        Hair::new_from_absorption(absorption, longitudinal_roughness, azimuthal_roughness)
+    }
+
+    fn new_from_absorption(
+        absorption: Vec3,
+        longitudinal_roughness: f64,
+        azimuthal_roughness: f64,
+    ) -> Materials {
+        let beta_m = longitudinal_roughness.clamp(0.01, 1.);
+        let beta_n = azimuthal_roughness.clamp(0.01, 1.);
+
+        let v = (0.726 * beta_m + 0.812 * beta_m.powi(2) + 3.7 * beta_m.powi(20)).powi(2);
+        let mut longitudinal_variance = [4. * v; P_MAX + 1];
+        longitudinal_variance[0] = v;
+        longitudinal_variance[1] = 0.25 * v;
+
+        let azimuthal_scale =
+            (PI / 8.).sqrt() * (0.265 * beta_n + 1.194 * beta_n.powi(2) + 5.372 * beta_n.powi(22));
+
+        let mut sin_2k_alpha = [SCALE_ANGLE.to_radians().sin(); 3];
+        let mut cos_2k_alpha = [safe_sqrt(1. - sin_2k_alpha[0].powi(2)); 3];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2. * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+
+        Materials::from(Hair {
+            absorption,
+            longitudinal_variance,
+            azimuthal_scale,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        })
+    }
+
+    /// The sine and cosine of the outgoing angle, rotated by the tilt of the scales
+    /// for the path through the fiber
+    fn tilted(&self, p: usize, sin_theta: f64, cos_theta: f64) -> (f64, f64) {
+        let (sin, cos) = match p {
+            0 => (
+                sin_theta * self.cos_2k_alpha[1] - cos_theta * self.sin_2k_alpha[1],
+                cos_theta * self.cos_2k_alpha[1] + sin_theta * self.sin_2k_alpha[1],
+            ),
+            1 => (
+                sin_theta * self.cos_2k_alpha[0] + cos_theta * self.sin_2k_alpha[0],
+                cos_theta * self.cos_2k_alpha[0] - sin_theta * self.sin_2k_alpha[0],
+            ),
+            2 => (
+                sin_theta * self.cos_2k_alpha[2] + cos_theta * self.sin_2k_alpha[2],
+                cos_theta * self.cos_2k_alpha[2] - sin_theta * self.sin_2k_alpha[2],
+            ),
+            _ => (sin_theta, cos_theta),
+        };
+        (sin, cos.abs())
+    }
+}
+
+/// Scattering of light leaving the fiber in a direction, in the space of the fiber
+/// where x runs along the fiber and z points towards the outgoing direction
+struct FiberScatter {
+    sin_theta_outgoing: f64,
+    cos_theta_outgoing: f64,
+    phi_outgoing: f64,
+    gamma_outgoing: f64,
+    gamma_transmitted: f64,
+    attenuation: [Vec3; P_MAX + 1],
+}
+
+impl FiberScatter {
+    /// Where the fiber is hit is given by the offset across it, from -1 to 1
+    fn new(hair: &Hair, outgoing: Vec3, h: f64) -> FiberScatter {
+        let sin_theta_outgoing = outgoing.x;
+        let cos_theta_outgoing = safe_sqrt(1. - sin_theta_outgoing.powi(2));
+        let eta = HAIR_INDEX_OF_REFRACTION;
+
+        let sin_theta_transmitted = sin_theta_outgoing / eta;
+        let cos_theta_transmitted = safe_sqrt(1. - sin_theta_transmitted.powi(2));
+        let eta_projected = (eta * eta - sin_theta_outgoing.powi(2)).sqrt() / cos_theta_outgoing;
+        let sin_gamma_transmitted = h / eta_projected;
+        let cos_gamma_transmitted = safe_sqrt(1. - sin_gamma_transmitted.powi(2));
+
+        // Light absorbed by the pigments during one pass through the fiber
+        let path_length = 2. * cos_gamma_transmitted / cos_theta_transmitted;
+        let transmittance = Vec3::new(
+            (-hair.absorption.x * path_length).exp(),
+            (-hair.absorption.y * path_length).exp(),
+            (-hair.absorption.z * path_length).exp(),
+        );
+
+        let fresnel = reflectance(cos_theta_outgoing * safe_sqrt(1. - h * h), eta);
+        let mut attenuation = [ZERO_VECTOR; P_MAX + 1];
+        attenuation[0] = ONE_VECTOR * fresnel;
+        attenuation[1] = transmittance * (1. - fresnel).powi(2);
+        for p in 2..P_MAX {
+            attenuation[p] = attenuation[p - 1] * transmittance * fresnel;
+        }
+        let remaining = transmittance * fresnel;
+        attenuation[P_MAX] = attenuation[P_MAX - 1] * remaining / (ONE_VECTOR - remaining);
+
+        FiberScatter {
+            sin_theta_outgoing,
+            cos_theta_outgoing,
+            phi_outgoing: outgoing.y.atan2(outgoing.z),
+            gamma_outgoing: safe_asin(h),
+            gamma_transmitted: safe_asin(sin_gamma_transmitted),
+            attenuation,
+        }
+    }
+
+    /// The probabilities of choosing each path through the fiber when sampling
+    fn path_probabilities(&self) -> [f64; P_MAX + 1] {
+        let weights = self.attenuation.map(brightness);
+        let total: f64 = weights.iter().sum();
+        if total > 0. {
+            weights.map(|w| w / total)
+        } else {
+            [1. / (P_MAX + 1) as f64; P_MAX + 1]
+        }
+    }
+
+    /// Sum of the contributions of each path through the fiber, either the reflected light
+    /// or the pdf depending on the weights of the paths
+    fn sum<T: Copy + std::ops::Mul<f64, Output = T> + std::ops::Add<Output = T>>(
+        &self,
+        hair: &Hair,
+        incoming: Vec3,
+        weights: [T; P_MAX + 1],
+        zero: T,
+    ) -> T {
+        let sin_theta_incoming = incoming.x;
+        let cos_theta_incoming = safe_sqrt(1. - sin_theta_incoming.powi(2));
+        let phi = incoming.y.atan2(incoming.z) - self.phi_outgoing;
+
+        let mut sum = zero;
+        for (p, weight) in weights.iter().enumerate().take(P_MAX) {
+            let (sin_theta_outgoing, cos_theta_outgoing) =
+                hair.tilted(p, self.sin_theta_outgoing, self.cos_theta_outgoing);
+            let longitudinal = longitudinal_scattering(
+                cos_theta_incoming,
+                cos_theta_outgoing,
+                sin_theta_incoming,
+                sin_theta_outgoing,
+                hair.longitudinal_variance[p],
+            );
+            let azimuthal = azimuthal_scattering(
+                phi,
+                p,
+                hair.azimuthal_scale,
+                self.gamma_outgoing,
+                self.gamma_transmitted,
+            );
+            sum = sum + *weight * (longitudinal * azimuthal);
+        }
+        let longitudinal = longitudinal_scattering(
+            cos_theta_incoming,
+            self.cos_theta_outgoing,
+            sin_theta_incoming,
+            self.sin_theta_outgoing,
+            hair.longitudinal_variance[P_MAX],
+        );
+        sum + weights[P_MAX] * (longitudinal / (2. * PI))
+    }
+
+    /// Samples an incoming direction by first choosing a path through the fiber
+    fn sample(&self, hair: &Hair) -> Vec3 {
+        let probabilities = self.path_probabilities();
+        let mut u = random_normal_float();
+        let mut p = 0;
+        while p < P_MAX && u >= probabilities[p] {
+            u -= probabilities[p];
+            p += 1;
+        }
+
+        let (sin_theta_outgoing, cos_theta_outgoing) =
+            hair.tilted(p, self.sin_theta_outgoing, self.cos_theta_outgoing);
+        let v = hair.longitudinal_variance[p];
+        let u_theta = random_normal_float().max(1e-5);
+        let cos_theta = 1. + v * (u_theta + (1. - u_theta) * (-2. / v).exp()).ln();
+        let sin_theta = safe_sqrt(1. - cos_theta.powi(2));
+        let cos_phi = (2. * PI * random_normal_float()).cos();
+        let sin_theta_incoming =
+            -cos_theta * sin_theta_outgoing + sin_theta * cos_phi * cos_theta_outgoing;
+        let cos_theta_incoming = safe_sqrt(1. - sin_theta_incoming.powi(2));
+
+        let phi = if p < P_MAX {
+            azimuth(p, self.gamma_outgoing, self.gamma_transmitted)
+                + sample_trimmed_logistic(random_normal_float(), hair.azimuthal_scale)
+        } else {
+            2. * PI * random_normal_float()
+        };
+        let phi_incoming = self.phi_outgoing + phi;
+        Vec3::new(
+            sin_theta_incoming,
+            cos_theta_incoming * phi_incoming.cos(),
+            cos_theta_incoming * phi_incoming.sin(),
+        )
+    }
+}
+
+impl Material for Hair {
+    /// Samples either the lights or the scattering of the fiber, and weights
+    /// the scattered ray by the scattered light over the combined pdf of the two
+    fn scatter(&self, ray: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
+        let outgoing = ray.direction.unit().neg();
+        let normal = rec.normal;
+
+        // The fiber space, where z points towards the outgoing direction across the fiber
+        let along = rec.onb.tangent - normal * rec.onb.tangent.dot(normal);
+        let along = if along.near_zero() {
+            rec.onb.tangent
+        } else {
+            along.unit()
+        };
+        let across = outgoing - along * outgoing.dot(along);
+        let towards = if across.near_zero() {
+            normal
+        } else {
+            across.unit()
+        };
+        let side = towards.cross(along);
+        let to_fiber = |v: Vec3| Vec3::new(v.dot(along), v.dot(side), v.dot(towards));
+        let from_fiber = |v: Vec3| along * v.x + side * v.y + towards * v.z;
+        let h = (-normal.dot(side)).clamp(-1., 1.);
+
+        let fiber = FiberScatter::new(self, to_fiber(outgoing), h);
+        let light_pdf = ContainerPdf::new(lights, rec.hit_point);
+        let direction = if random_normal_float() < 0.5 {
+            light_pdf.generate()
+        } else {
+            from_fiber(fiber.sample(self))
+        };
+
+        let incoming = to_fiber(direction.unit());
+        let pdf_value = 0.5 * light_pdf.value(direction)
+            + 0.5 * fiber.sum(self, incoming, fiber.path_probabilities(), 0.);
+        let color = if pdf_value > 0. {
+            fiber.sum(self, incoming, fiber.attenuation, ZERO_VECTOR) * rec.overrides.tint
+                / pdf_value
+        } else {
+            ZERO_VECTOR
+        };
+
+        RayScatter::ScatterPdf(ScatterPdf {
+            color,
+            ray: Ray::new_at_time(rec.hit_point, direction, rec.time),
+            probability: 1.,
+        })
+    }
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.).sqrt()
+}
+
+fn safe_asin(x: f64) -> f64 {
+    x.clamp(-1., 1.).asin()
+}
+
+/// Modified Bessel function of the first kind and order zero
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 0.;
+    let mut term = 1.;
+    let x_squared_quarter = x * x / 4.;
+    for i in 1..=10 {
+        sum += term;
+        term *= x_squared_quarter / (i * i) as f64;
+    }
+    sum
+}
+
+fn log_bessel_i0(x: f64) -> f64 {
+    if x > 12. {
+        x + 0.5 * (-(2. * PI).ln() + (1. / x).ln() + 1. / (8. * x))
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+/// How much light is scattered along the fiber between the angles
+fn longitudinal_scattering(
+    cos_theta_incoming: f64,
+    cos_theta_outgoing: f64,
+    sin_theta_incoming: f64,
+    sin_theta_outgoing: f64,
+    variance: f64,
+) -> f64 {
+    let a = cos_theta_incoming * cos_theta_outgoing / variance;
+    let b = sin_theta_incoming * sin_theta_outgoing / variance;
+    if variance <= 0.1 {
+        (log_bessel_i0(a) - b - 1. / variance + LN_2 + (1. / (2. * variance)).ln()).exp()
+    } else {
+        (-b).exp() * bessel_i0(a) / ((1. / variance).sinh() * 2. * variance)
+    }
+}
+
+/// The azimuthal angle that light leaves the fiber at after the path through the fiber
+fn azimuth(p: usize, gamma_outgoing: f64, gamma_transmitted: f64) -> f64 {
+    let p = p as f64;
+    2. * p * gamma_transmitted - 2. * gamma_outgoing + p * PI
+}
+
+/// How much light is scattered around the fiber at the azimuthal angle
+fn azimuthal_scattering(
+    phi: f64,
+    p: usize,
+    scale: f64,
+    gamma_outgoing: f64,
+    gamma_transmitted: f64,
+) -> f64 {
+    let mut phi_difference = phi - azimuth(p, gamma_outgoing, gamma_transmitted);
+    while phi_difference > PI {
+        phi_difference -= 2. * PI;
+    }
+    while phi_difference < -PI {
+        phi_difference += 2. * PI;
+    }
+    trimmed_logistic(phi_difference, scale)
+}
+
+fn logistic(x: f64, scale: f64) -> f64 {
+    let e = (-x.abs() / scale).exp();
+    e / (scale * (1. + e).powi(2))
+}
+
+fn logistic_cdf(x: f64, scale: f64) -> f64 {
+    1. / (1. + (-x / scale).exp())
+}
+
+/// The logistic distribution normalized over `-PI..PI`
+fn trimmed_logistic(x: f64, scale: f64) -> f64 {
+    logistic(x, scale) / (logistic_cdf(PI, scale) - logistic_cdf(-PI, scale))
+}
+
+fn sample_trimmed_logistic(u: f64, scale: f64) -> f64 {
+    let k = logistic_cdf(PI, scale) - logistic_cdf(-PI, scale);
+    let x = -scale * (1. / (u * k + logistic_cdf(-PI, scale)) - 1.).ln();
+    x.clamp(-PI, PI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hair(material: Materials) -> Hair {
+        match material {
+            Materials::HairType(hair) => hair,
+            _ => panic!("Expected hair"),
+        }
+    }
+
+    fn uniform_sphere_direction() -> Vec3 {
+        let z = 1. - 2. * random_normal_float();
+        let r = safe_sqrt(1. - z * z);
+        let phi = 2. * PI * random_normal_float();
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    #[test]
+    fn test_pdf_integrates_to_one() {
+        let hair = hair(Hair::new(1.3, 0., 0.3, 0.3));
+        let outgoing = Vec3::new(0.3, 0.2, 0.9).unit();
+        let fiber = FiberScatter::new(&hair, outgoing, 0.4);
+
+        let samples = 200000;
+        let integral: f64 = (0..samples)
+            .map(|_| {
+                let incoming = uniform_sphere_direction();
+                fiber.sum(&hair, incoming, fiber.path_probabilities(), 0.) * 4. * PI
+            })
+            .sum::<f64>()
+            / samples as f64;
+        assert!((integral - 1.).abs() < 0.05, "integral was {}", integral);
+    }
+
+    #[test]
+    fn test_energy_is_conserved() {
+        for roughness in [0.2, 0.5, 0.9] {
+            let hair = hair(Hair::new(0., 0., roughness, roughness));
+            let outgoing = Vec3::new(-0.5, 0., 0.8).unit();
+            let fiber = FiberScatter::new(&hair, outgoing, -0.2);
+
+            let samples = 100000;
+            let reflected = (0..samples)
+                .map(|_| {
+                    let incoming = uniform_sphere_direction();
+                    fiber.sum(&hair, incoming, fiber.attenuation, ZERO_VECTOR) * 4. * PI
+                })
+                .fold(ZERO_VECTOR, |a, b| a + b)
+                / samples as f64;
+            // Without pigments nothing is absorbed
+            assert!(
+                (reflected.x - 1.).abs() < 0.1,
+                "reflected was {}",
+                reflected
+            );
+        }
+    }
+
+    #[test]
+    fn test_pigments_color_the_hair() {
+        let brown = hair(Hair::new(1.3, 0., 0.3, 0.3));
+        let red = hair(Hair::new(0.3, 2., 0.3, 0.3));
+        let outgoing = Vec3::new(0., 0., 1.);
+        let brown = FiberScatter::new(&brown, outgoing, 0.).attenuation[1];
+        let red = FiberScatter::new(&red, outgoing, 0.).attenuation[1];
+        assert!(brown.x > brown.y && brown.y > brown.z);
+        assert!(red.x / red.z > brown.x / brown.z);
+
+        let color = Vec3::new(0.8, 0.4, 0.2);
+        let colored = hair(Hair::new_from_color(color, 0.3, 0.3));
+        assert!(colored.absorption.z > colored.absorption.y);
+        assert!(colored.absorption.y > colored.absorption.x);
+    }
+}
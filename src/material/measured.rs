@@ -0,0 +1,323 @@
+//! Materials that reflect light as measured from real world samples
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs;
+use std::sync::Arc;
+
+use simple_error::SimpleError;
+
+use crate::geo::vec3::{Vec3, ALMOST_ZERO, ZERO_VECTOR};
+use crate::geo::{Onb, Ray, Uv};
+use crate::hittable::Hittables;
+use crate::material::texture::Textures;
+use crate::material::{
+    transform_normal_by_map, Material, Materials, RayHit, RayScatter, ScatterPdf,
+};
+use crate::pdf::{mix_generate, mix_value, ContainerPdf, CosinePdf};
+
+const THETA_HALF_RESOLUTION: usize = 90;
+const THETA_DIFFERENCE_RESOLUTION: usize = 90;
+const PHI_DIFFERENCE_RESOLUTION: usize = 180;
+const MERL_VALUE_COUNT: usize =
+    THETA_HALF_RESOLUTION * THETA_DIFFERENCE_RESOLUTION * PHI_DIFFERENCE_RESOLUTION;
+
+/// Factors that the stored values of each channel are multiplied with
+const MERL_SCALE: [f64; 3] = [1. / 1500., 1.15 / 1500., 1.66 / 1500.];
+
+/// An isotropic brdf measured from a real material, in the binary format of the
+/// MERL BRDF database. The values are tabulated over the half angle and difference
+/// angle parametrization, and are interpolated between the measured angles
+#[derive(Clone, PartialEq)]
+pub struct MeasuredBrdf {
+    values: Vec<[f32; 3]>,
+}
+
+impl MeasuredBrdf {
+    /// Loads a brdf from a file in the MERL binary format
+    pub fn load_merl(path: &str) -> Result<MeasuredBrdf, Box<dyn Error>> {
+        let bytes = fs::read(path).map_err(|err| {
+            SimpleError::new(format!(
+                "Failed to load measured brdf from {}: {}",
+                path, err
+            ))
+        })?;
+        MeasuredBrdf::from_merl_bytes(&bytes)
+            .map_err(|err| format!("Invalid measured brdf {}: {}", path, err).into())
+    }
+
+    /// Reads a brdf in the MERL binary format: three 32 bit sizes followed by the values
+    /// of the red, green and blue channels as 64 bit floats, all little endian
+    pub fn from_merl_bytes(bytes: &[u8]) -> Result<MeasuredBrdf, Box<dyn Error>> {
+        let header_len = 12;
+        if bytes.len() < header_len {
+            return Err(Box::new(SimpleError::new("Missing header")));
+        }
+        let sizes: Vec<usize> = bytes[..header_len]
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .collect();
+        let expected = [
+            THETA_HALF_RESOLUTION,
+            THETA_DIFFERENCE_RESOLUTION,
+            PHI_DIFFERENCE_RESOLUTION,
+        ];
+        if sizes != expected {
+            return Err(Box::new(SimpleError::new(format!(
+                "Unsupported sizes {:?}, expected {:?}",
+                sizes, expected
+            ))));
+        }
+        let data = &bytes[header_len..];
+        if data.len() != MERL_VALUE_COUNT * 3 * 8 {
+            return Err(Box::new(SimpleError::new(format!(
+                "Expected {} values, got {} bytes",
+                MERL_VALUE_COUNT * 3,
+                data.len()
+            ))));
+        }
+
+        let value = |channel: usize, i: usize| {
+            let offset = (channel * MERL_VALUE_COUNT + i) * 8;
+            let stored = f64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+            // Angles that could not be measured are stored as negative values
+            (stored * MERL_SCALE[channel]).max(0.) as f32
+        };
+        let values = (0..MERL_VALUE_COUNT)
+            .map(|i| [value(0, i), value(1, i), value(2, i)])
+            .collect();
+        Ok(MeasuredBrdf { values })
+    }
+
+    fn value_at(&self, theta_half: usize, theta_difference: usize, phi_difference: usize) -> Vec3 {
+        let i = (theta_half * THETA_DIFFERENCE_RESOLUTION + theta_difference)
+            * PHI_DIFFERENCE_RESOLUTION
+            + phi_difference;
+        let [r, g, b] = self.values[i];
+        Vec3::new(r as f64, g as f64, b as f64)
+    }
+
+    /// The reflected light from the incoming to the outgoing direction, both unit vectors
+    /// in the local space of the surface where the normal is the z axis
+    pub fn evaluate(&self, incoming: Vec3, outgoing: Vec3) -> Vec3 {
+        if incoming.z <= 0. || outgoing.z <= 0. {
+            return ZERO_VECTOR;
+        }
+        let half = (incoming + outgoing).unit();
+        let theta_half = half.z.clamp(-1., 1.).acos();
+        let phi_half = half.y.atan2(half.x);
+
+        // The incoming direction in the space where the half vector is the z axis
+        let difference = rotate(
+            rotate(incoming, Vec3::new(0., 0., 1.), -phi_half),
+            Vec3::new(0., 1., 0.),
+            -theta_half,
+        );
+        let theta_difference = difference.z.clamp(-1., 1.).acos();
+        let mut phi_difference = difference.y.atan2(difference.x);
+        // Reciprocity makes the brdf repeat every half turn of the difference angle
+        if phi_difference < 0. {
+            phi_difference += PI;
+        }
+
+        // The theta half angles are measured more densely near the normal
+        let theta_half_index =
+            (theta_half / (PI / 2.)).max(0.).sqrt() * THETA_HALF_RESOLUTION as f64;
+        let theta_difference_index =
+            theta_difference / (PI / 2.) * THETA_DIFFERENCE_RESOLUTION as f64;
+        let phi_difference_index = phi_difference / PI * PHI_DIFFERENCE_RESOLUTION as f64;
+
+        let (th0, th1, th_t) = clamped_neighbours(theta_half_index, THETA_HALF_RESOLUTION);
+        let (td0, td1, td_t) =
+            clamped_neighbours(theta_difference_index, THETA_DIFFERENCE_RESOLUTION);
+        let pd0 = (phi_difference_index.floor() as usize).min(PHI_DIFFERENCE_RESOLUTION - 1);
+        let pd1 = (pd0 + 1) % PHI_DIFFERENCE_RESOLUTION;
+        let pd_t = (phi_difference_index - pd0 as f64).clamp(0., 1.);
+
+        let lerp = |a: Vec3, b: Vec3, t: f64| a * (1. - t) + b * t;
+        let along_phi = |th, td| lerp(self.value_at(th, td, pd0), self.value_at(th, td, pd1), pd_t);
+        let along_theta_difference = |th| lerp(along_phi(th, td0), along_phi(th, td1), td_t);
+        lerp(
+            along_theta_difference(th0),
+            along_theta_difference(th1),
+            th_t,
+        )
+    }
+}
+
+impl fmt::Debug for MeasuredBrdf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MeasuredBrdf")
+            .field("values", &self.values.len())
+            .finish()
+    }
+}
+
+/// The indices on both sides of the continuous index and the fraction between them,
+/// clamped to the measured range
+fn clamped_neighbours(index: f64, resolution: usize) -> (usize, usize, f64) {
+    let index = index.clamp(0., (resolution - 1) as f64);
+    let low = index.floor() as usize;
+    let high = (low + 1).min(resolution - 1);
+    (low, high, index - low as f64)
+}
+
+/// Rotates the vector around the unit axis by the angle in radians
+fn rotate(v: Vec3, axis: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    v * cos + axis * (axis.dot(v) * (1. - cos)) + axis.cross(v) * sin
+}
+
+/// A material that reflects light as given by a [`MeasuredBrdf`],
+/// for comparisons with real materials and high fidelity product renders
+#[derive(Clone, Debug)]
+pub struct MeasuredMaterial {
+    brdf: Arc<MeasuredBrdf>,
+    normal: Option<Textures>,
+}
+
+impl MeasuredMaterial {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new measured material. The brdf is shared, as measured data is large
+    pub fn new(brdf: Arc<MeasuredBrdf>, normal: Option<Textures>) -> Materials {
+        Materials::from(MeasuredMaterial { brdf, normal })
+    }
+
+    /// Create a new measured material from a file in the MERL binary format
+    pub fn load_merl(path: &str) -> Result<Materials, Box<dyn Error>> {
+        Ok(MeasuredMaterial::new(
+            Arc::new(MeasuredBrdf::load_merl(path)?),
+            None,
+        ))
+    }
+}
+
+impl Material for MeasuredMaterial {
+    /// Samples either the lights or the cosine weighted hemisphere,
+    /// and weights the scattered ray by the measured brdf over the combined pdf
+    fn scatter(&self, ray: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
+        let onb = Onb::new(rec.normal);
+        let to_surface =
+            |v: Vec3| Vec3::new(v.dot(onb.tangent), v.dot(onb.bi_tangent), v.dot(onb.normal));
+
+        let light_pdf = ContainerPdf::new(lights, rec.hit_point);
+        let cosine_pdf = CosinePdf::new(rec.normal);
+        let direction = mix_generate(&light_pdf, &cosine_pdf);
+        let pdf_value = mix_value(&light_pdf, &cosine_pdf, direction);
+
+        let incoming = direction.unit();
+        let outgoing = ray.direction.unit().neg();
+        let cos_incoming = incoming.dot(rec.normal);
+        let color = if cos_incoming <= 0. || pdf_value <= ALMOST_ZERO {
+            ZERO_VECTOR
+        } else {
+            self.brdf
+                .evaluate(to_surface(incoming), to_surface(outgoing))
+                * rec.overrides.tint
+                * (cos_incoming / pdf_value)
+        };
+
+        RayScatter::ScatterPdf(ScatterPdf {
+            color,
+            ray: Ray::new_at_time(rec.hit_point, direction, rec.time),
+            probability: 1.,
+        })
+    }
+
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
+        self.normal
+            .as_ref()
+            .map_or(onb.normal, |n| transform_normal_by_map(n, onb, uv, time))
+    }
+}
+
+/// A brdf in the MERL format where the value of each channel is given by the function
+/// of the channel and the theta half, theta difference and phi difference indices.
+/// Negative values are missing measurements
+#[cfg(test)]
+pub(crate) fn merl_bytes(value: impl Fn(usize, usize, usize, usize) -> f64) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(12 + MERL_VALUE_COUNT * 24);
+    for size in [90, 90, 180_i32] {
+        bytes.extend_from_slice(&size.to_le_bytes());
+    }
+    for (channel, scale) in MERL_SCALE.iter().enumerate() {
+        for th in 0..THETA_HALF_RESOLUTION {
+            for td in 0..THETA_DIFFERENCE_RESOLUTION {
+                for pd in 0..PHI_DIFFERENCE_RESOLUTION {
+                    let stored = value(channel, th, td, pd) / scale;
+                    bytes.extend_from_slice(&stored.to_le_bytes());
+                }
+            }
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hittable::Sphere;
+    use crate::material::DiffuseLight;
+
+    use super::*;
+
+    /// Unit vector in the xz plane at the angle in degrees from the normal
+    fn at_degrees(degrees: f64) -> Vec3 {
+        let radians = degrees.to_radians();
+        Vec3::new(radians.sin(), 0., radians.cos())
+    }
+
+    #[test]
+    fn test_evaluate_interpolates() {
+        let bytes = merl_bytes(|_, _, td, _| td as f64);
+        let brdf = MeasuredBrdf::from_merl_bytes(&bytes).unwrap();
+
+        // Mirrored directions have the normal as half vector,
+        // so the difference angle is their angle to the normal
+        let value = brdf.evaluate(at_degrees(-10.5), at_degrees(10.5));
+        assert!((value.x - 10.5).abs() < 1e-6, "value was {}", value);
+        assert!((value.z - 10.5).abs() < 1e-6, "value was {}", value);
+        let value = brdf.evaluate(at_degrees(-30.), at_degrees(30.));
+        assert!((value.y - 30.).abs() < 1e-6, "value was {}", value);
+
+        assert_eq!(
+            brdf.evaluate(at_degrees(-100.), at_degrees(30.)),
+            ZERO_VECTOR
+        );
+    }
+
+    #[test]
+    fn test_invalid_merl() {
+        assert!(MeasuredBrdf::from_merl_bytes(&[0, 1]).is_err());
+        let mut bytes = merl_bytes(|_, _, _, _| 1.);
+        bytes.truncate(bytes.len() - 8);
+        assert!(MeasuredBrdf::from_merl_bytes(&bytes).is_err());
+        assert!(MeasuredMaterial::load_merl("resources/missing.binary").is_err());
+    }
+
+    #[test]
+    fn test_scatter() {
+        // Negative values are missing measurements
+        let bytes = merl_bytes(|_, _, _, pd| if pd == 0 { -1. } else { 0.1 });
+        let material = MeasuredMaterial::new(
+            Arc::new(MeasuredBrdf::from_merl_bytes(&bytes).unwrap()),
+            None,
+        );
+        let lights = [Sphere::new(
+            Vec3::new(0., 0., 5.),
+            1.,
+            DiffuseLight::new(1., 1., 1., None),
+        )];
+        let ray = Ray::new(Vec3::new(-1., 0., 1.), Vec3::new(1., 0., -1.));
+        let onb = Onb::new(Vec3::new(0., 0., 1.));
+        let rec = RayHit::new(ZERO_VECTOR, onb, &material, 1., Uv::default(), true, 0.);
+        for _ in 0..100 {
+            match material.scatter(&ray, &rec, &lights) {
+                RayScatter::ScatterPdf(s) => {
+                    let channels = [s.color.x, s.color.y, s.color.z];
+                    assert!(channels.iter().all(|c| c.is_finite() && *c >= 0.));
+                }
+                _ => panic!("Measured materials should scatter with pdfs"),
+            }
+        }
+    }
+}
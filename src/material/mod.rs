@@ -5,17 +5,40 @@ use std::f64::consts::PI;
 use enum_dispatch::enum_dispatch;
 
 use crate::geo::{Onb, Ray};
-use crate::geo::Uv;
-use crate::geo::vec3::{ONE_VECTOR, random_in_unit_sphere, Vec3, ZERO_VECTOR};
+use crate::geo::{Uv, UvDerivatives};
+use crate::geo::vec3::{ALMOST_ZERO, ONE_VECTOR, random_in_unit_sphere, Vec3, ZERO_VECTOR};
 use crate::hittable::Hittables;
-use crate::material::Materials::{BlendType, DielectricType, DiffuseLightType, IsotropicType, LambertianType, MetalType};
-use crate::material::texture::{SolidColor, Texture};
+use crate::material::Materials::{
+    BlendType, CoatType, DielectricType, DiffractionGratingType, DiffuseLightType, DoubleSidedType,
+    GlintType, HairType, HoldoutType, IsotropicType, LambertianType, MeasuredMaterialType,
+    MetalType, ParallaxType, PrincipledMaterialType, SheenType, TranslucentType,
+};
+use crate::material::texture::{brightness, SolidColor, Texture};
 use crate::material::texture::Textures;
-use crate::pdf::{ContainerPdf, CosinePdf, mix_generate, mix_value, SpherePdf};
+use crate::pdf::{
+    ContainerPdf, CosinePdf, ggx_distribution, GgxPdf, mix_generate, mix_value, Pdf, SpherePdf,
+    TwoSidedCosinePdf,
+};
 use crate::random::random_normal_float;
+use crate::util::color_space::ColorSpace;
 
+mod diffraction;
+mod glint;
+mod hair;
+mod measured;
+pub mod presets;
+mod sheen;
 pub mod texture;
 
+pub use crate::material::diffraction::DiffractionGrating;
+pub use crate::material::glint::Glint;
+pub use crate::material::hair::Hair;
+pub use crate::material::measured::{MeasuredBrdf, MeasuredMaterial};
+pub use crate::material::sheen::Sheen;
+
+#[cfg(all(test, feature = "statistical-tests"))]
+pub(crate) use crate::material::measured::merl_bytes;
+
 /// A collection of all interesting properties from
 /// when a ray hits a hittable object
 #[derive(Clone, Debug)]
@@ -33,6 +56,18 @@ pub struct RayHit<'a> {
     pub uv: Uv,
     /// Whether the hit point is inside or outside the hittable
     pub front_face: bool,
+    /// Point in time of the ray that hit
+    pub time: f64,
+    /// How much the texture coordinate changes per pixel, if known
+    pub uv_derivatives: Option<UvDerivatives>,
+    /// Tangent space of the hittable at the hit point, before any normal mapping
+    pub(crate) onb: Onb,
+    /// Material parameter overrides of the hittable that was hit
+    pub overrides: MaterialOverrides,
+    /// Color interpolated from the vertices of the hittable, if it has vertex colors
+    pub vertex_color: Option<Vec3>,
+    /// Color space that the texture colors are converted to, the one that is rendered in
+    pub color_space: ColorSpace,
 }
 
 impl<'a> RayHit<'a> {
@@ -44,14 +79,92 @@ impl<'a> RayHit<'a> {
         ray_length: f64,
         uv: Uv,
         front_face: bool,
+        time: f64,
     ) -> RayHit<'a> {
         RayHit {
             hit_point,
-            normal: material.get_transformed_normal(onb, uv),
+            normal: material.get_transformed_normal(onb.clone(), uv, time),
             material,
             ray_length,
             uv,
             front_face,
+            time,
+            uv_derivatives: None,
+            onb,
+            overrides: MaterialOverrides::default(),
+            vertex_color: None,
+            color_space: ColorSpace::default(),
+        }
+    }
+
+    /// Returns the hit with the given texture coordinate derivatives
+    pub fn with_uv_derivatives(self, uv_derivatives: Option<UvDerivatives>) -> RayHit<'a> {
+        RayHit {
+            uv_derivatives,
+            ..self
+        }
+    }
+
+    /// Returns the hit with the given vertex color
+    pub fn with_vertex_color(self, vertex_color: Option<Vec3>) -> RayHit<'a> {
+        RayHit {
+            vertex_color,
+            ..self
+        }
+    }
+
+    /// Returns the hit with the texture colors converted to the given color space
+    pub fn with_color_space(self, color_space: ColorSpace) -> RayHit<'a> {
+        RayHit {
+            color_space,
+            ..self
+        }
+    }
+
+    /// Color of the texture at the hit, tinted by the overrides
+    pub fn texture_color(&self, texture: &Textures) -> Vec3 {
+        self.color_space
+            .convert_srgb(texture.hit_color(self) * self.overrides.tint)
+    }
+}
+
+/// Parameters that vary the materials of a hittable without changing the materials themselves,
+/// like giving each copy of a model its own shade. See [`crate::hittable::MaterialOverride`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MaterialOverrides {
+    /// Color that the texture colors are multiplied with
+    pub tint: Vec3,
+    /// Factor that the roughness of the materials are multiplied with
+    pub roughness_multiplier: f64,
+    /// Offset that is added to the texture coordinates
+    pub uv_offset: Uv,
+    /// Identifies the hittable for the [`texture::ObjectId`] texture, zero if not set
+    pub object_id: u32,
+}
+
+impl Default for MaterialOverrides {
+    fn default() -> Self {
+        MaterialOverrides {
+            tint: ONE_VECTOR,
+            roughness_multiplier: 1.,
+            uv_offset: Uv::default(),
+            object_id: 0,
+        }
+    }
+}
+
+impl MaterialOverrides {
+    /// Combines with overrides of an enclosing hittable. If both have object ids,
+    /// they are mixed so each copy of a part in a model gets an id of its own
+    pub fn combine(&self, outer: &MaterialOverrides) -> MaterialOverrides {
+        MaterialOverrides {
+            tint: self.tint * outer.tint,
+            roughness_multiplier: self.roughness_multiplier * outer.roughness_multiplier,
+            uv_offset: self.uv_offset + outer.uv_offset,
+            object_id: match (self.object_id, outer.object_id) {
+                (0, id) | (id, 0) => id,
+                (inner, outer) => inner.wrapping_mul(0x9e3779b9) ^ outer,
+            },
         }
     }
 }
@@ -78,8 +191,8 @@ pub struct ScatterBasic {
 pub struct ScatterEmission {
     /// The emitted color from the ray hit
     pub color: Vec3,
-    /// The attenuation factor of the light source
-    pub attenuation_factor: Option<f64>,
+    /// How the light from the light source falls off with distance
+    pub attenuation: Attenuation,
 }
 
 /// An enum of scatter types
@@ -101,13 +214,72 @@ pub trait Material {
         false
     }
 
+    /// Is the material a holdout, that the camera sees as the background
+    fn is_holdout(&self) -> bool {
+        false
+    }
+
     /// Calculate scattering of the ray
     fn scatter(&self, _ray: &Ray, _rec: &RayHit, _lights: &[Hittables]) -> RayScatter;
 
     /// Get normal transformed by the material, implementations typically uses a normal texture map
-    fn get_transformed_normal(&self, onb: Onb, _uv: Uv) -> Vec3 {
+    fn get_transformed_normal(&self, onb: Onb, _uv: Uv, _time: f64) -> Vec3 {
         onb.normal
     }
+
+    /// Texture where the brightness is the opacity of the surface. Triangles and quads
+    /// are not hit where the material is transparent, see [`is_cut_out`]
+    fn alpha(&self) -> Option<&Textures> {
+        None
+    }
+}
+
+/// Is the surface cut out by the alpha texture at the texture coordinate, so rays pass through.
+/// Partly transparent surfaces let rays through randomly, in proportion to the transparency
+pub(crate) fn is_cut_out(alpha: &Textures, uv: Uv, time: f64) -> bool {
+    brightness(alpha.color(uv, time)) <= random_normal_float()
+}
+
+/// How the light from a light source falls off with the distance it travels
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Attenuation {
+    /// The light does not fall off
+    #[default]
+    None,
+    /// Falls off as `1 / (1 + d / half_length)`, so it is halved at the given distance
+    Linear(f64),
+    /// Physically based `1 / d²` falloff, where the light has its full strength at distance 1
+    InverseSquare,
+    /// Falls off as `1 / (1 + (d / half_length)^exponent)`,
+    /// so it is halved at the given distance
+    Exponent {
+        /// Distance at which the light has half its strength
+        half_length: f64,
+        /// How quickly the light falls off beyond the half length
+        exponent: f64,
+    },
+}
+
+impl Attenuation {
+    /// The factor that light is multiplied with after travelling the given distance
+    /// # Examples:
+    /// ```
+    /// # use solstrale::material::Attenuation;
+    /// assert_eq!(1., Attenuation::None.factor(10.));
+    /// assert_eq!(0.5, Attenuation::Linear(10.).factor(10.));
+    /// assert_eq!(0.25, Attenuation::InverseSquare.factor(2.));
+    /// ```
+    pub fn factor(&self, distance: f64) -> f64 {
+        match self {
+            Attenuation::None => 1.,
+            Attenuation::Linear(half_length) => 1. / (1. + distance / half_length),
+            Attenuation::InverseSquare => 1. / (distance * distance),
+            Attenuation::Exponent {
+                half_length,
+                exponent,
+            } => 1. / (1. + (distance / half_length).powf(*exponent)),
+        }
+    }
 }
 
 #[derive(Default)]
@@ -115,8 +287,8 @@ pub trait Material {
 pub struct AttenuatedColor {
     /// Color value before attenuation
     pub color: Vec3,
-    /// Factor for calculating amount of attenuation
-    pub attenuation_factor: Option<f64>,
+    /// How the color falls off with distance
+    pub attenuation: Attenuation,
     /// Distance the light has travelled
     pub accumulated_ray_length: f64,
 }
@@ -125,9 +297,7 @@ impl AttenuatedColor {
     /// Calculate the actual color based on the original color
     /// and the attenuation information
     pub fn get_attenuated_color(&self) -> Vec3 {
-        self.attenuation_factor.map_or(self.color, |af| {
-            self.color * 1. / (1. + af * self.accumulated_ray_length)
-        })
+        self.color * self.attenuation.factor(self.accumulated_ray_length)
     }
 }
 
@@ -147,6 +317,28 @@ pub enum Materials {
     IsotropicType(Isotropic),
     /// [`Material`] of type [`Blend`]
     BlendType(Blend),
+    /// [`Material`] of type [`Parallax`]
+    ParallaxType(Parallax),
+    /// [`Material`] of type [`Translucent`]
+    TranslucentType(Translucent),
+    /// [`Material`] of type [`DoubleSided`]
+    DoubleSidedType(DoubleSided),
+    /// [`Material`] of type [`Coat`]
+    CoatType(Coat),
+    /// [`Material`] of type [`Holdout`]
+    HoldoutType(Holdout),
+    /// [`Material`] of type [`PrincipledMaterial`]
+    PrincipledMaterialType(PrincipledMaterial),
+    /// [`Material`] of type [`MeasuredMaterial`]
+    MeasuredMaterialType(MeasuredMaterial),
+    /// [`Material`] of type [`Hair`]
+    HairType(Hair),
+    /// [`Material`] of type [`Sheen`]
+    SheenType(Sheen),
+    /// [`Material`] of type [`DiffractionGrating`]
+    DiffractionGratingType(DiffractionGrating),
+    /// [`Material`] of type [`Glint`]
+    GlintType(Glint),
 }
 
 impl Clone for Materials {
@@ -157,7 +349,18 @@ impl Clone for Materials {
             DielectricType(m) => DielectricType(m.clone()),
             DiffuseLightType(m) => DiffuseLightType(m.clone()),
             IsotropicType(m) => IsotropicType(m.clone()),
-            BlendType(m) => BlendType(m.clone())
+            BlendType(m) => BlendType(m.clone()),
+            ParallaxType(m) => ParallaxType(m.clone()),
+            TranslucentType(m) => TranslucentType(m.clone()),
+            DoubleSidedType(m) => DoubleSidedType(m.clone()),
+            CoatType(m) => CoatType(m.clone()),
+            HoldoutType(m) => HoldoutType(m.clone()),
+            PrincipledMaterialType(m) => PrincipledMaterialType(m.clone()),
+            MeasuredMaterialType(m) => MeasuredMaterialType(m.clone()),
+            HairType(m) => HairType(m.clone()),
+            SheenType(m) => SheenType(m.clone()),
+            DiffractionGratingType(m) => DiffractionGratingType(m.clone()),
+            GlintType(m) => GlintType(m.clone()),
         }
     }
 }
@@ -167,13 +370,33 @@ impl Clone for Materials {
 pub struct Lambertian {
     albedo: Textures,
     normal: Option<Textures>,
+    alpha: Option<Textures>,
 }
 
 impl Lambertian {
     #![allow(clippy::new_ret_no_self)]
     /// Create a new lambertian material
     pub fn new(albedo: Textures, normal: Option<Textures>) -> Materials {
-        Materials::from(Lambertian { albedo, normal })
+        Materials::from(Lambertian {
+            albedo,
+            normal,
+            alpha: None,
+        })
+    }
+
+    /// Create a new lambertian material with an alpha texture, where the brightness is the
+    /// opacity. Rays pass through the transparent parts, so leaves and fences can be
+    /// modelled as textured quads instead of rendering as solid rectangles
+    pub fn new_with_alpha(
+        albedo: Textures,
+        normal: Option<Textures>,
+        alpha: Textures,
+    ) -> Materials {
+        Materials::from(Lambertian {
+            albedo,
+            normal,
+            alpha: Some(alpha),
+        })
     }
 
     fn scattering_pdf_value(normal: Vec3, scatter_direction: Vec3) -> f64 {
@@ -189,13 +412,13 @@ impl Lambertian {
 impl Material for Lambertian {
 
     fn scatter(&self, _: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
-        let color = self.albedo.color(rec.uv);
+        let color = rec.texture_color(&self.albedo);
         let pdf = CosinePdf::new(rec.normal);
 
         let light_pdf = ContainerPdf::new(lights, rec.hit_point);
 
         let pdf_direction = mix_generate(&light_pdf, &pdf);
-        let scattered = Ray::new(rec.hit_point, pdf_direction);
+        let scattered = Ray::new_at_time(rec.hit_point, pdf_direction, rec.time);
         let light_pdf_value = mix_value(&light_pdf, &pdf, scattered.direction);
         let scattering_pdf_value = Lambertian::scattering_pdf_value(rec.normal, scattered.direction.unit());
 
@@ -206,10 +429,62 @@ impl Material for Lambertian {
         })
     }
 
-    fn get_transformed_normal(&self, onb: Onb, uv: Uv) -> Vec3 {
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
         self.normal
             .as_ref()
-            .map_or(onb.normal, |n| transform_normal_by_map(n, onb, uv))
+            .map_or(onb.normal, |n| transform_normal_by_map(n, onb, uv, time))
+    }
+
+    fn alpha(&self) -> Option<&Textures> {
+        self.alpha.as_ref()
+    }
+}
+
+/// Translucent is a diffuse material for thin surfaces like leaves, paper and lampshades.
+/// Part of the light is transmitted diffusely through the surface, without any refraction
+#[derive(Clone, Debug)]
+pub struct Translucent {
+    albedo: Textures,
+    normal: Option<Textures>,
+    transmission: f64,
+}
+
+impl Translucent {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new translucent material,
+    /// where transmission [0..1] is the fraction of the light that passes through the surface
+    pub fn new(albedo: Textures, normal: Option<Textures>, transmission: f64) -> Materials {
+        Materials::from(Translucent {
+            albedo,
+            normal,
+            transmission: transmission.clamp(0., 1.),
+        })
+    }
+}
+
+impl Material for Translucent {
+    fn scatter(&self, _: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
+        let color = rec.texture_color(&self.albedo);
+        let pdf = TwoSidedCosinePdf::new(rec.normal, self.transmission);
+
+        let light_pdf = ContainerPdf::new(lights, rec.hit_point);
+
+        let pdf_direction = mix_generate(&light_pdf, &pdf);
+        let scattered = Ray::new_at_time(rec.hit_point, pdf_direction, rec.time);
+        let light_pdf_value = mix_value(&light_pdf, &pdf, scattered.direction);
+        let scattering_pdf_value = pdf.value(scattered.direction);
+
+        RayScatter::ScatterPdf(ScatterPdf {
+            color,
+            ray: scattered,
+            probability: scattering_pdf_value / light_pdf_value,
+        })
+    }
+
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
+        self.normal
+            .as_ref()
+            .map_or(onb.normal, |n| transform_normal_by_map(n, onb, uv, time))
     }
 }
 
@@ -219,6 +494,7 @@ pub struct Metal {
     albedo: Textures,
     normal: Option<Textures>,
     fuzz: f64,
+    fuzz_map: Option<Textures>,
 }
 
 impl Metal {
@@ -229,6 +505,23 @@ impl Metal {
             albedo,
             normal,
             fuzz,
+            fuzz_map: None,
+        })
+    }
+
+    /// Creates a metal material where the fuzz varies over the surface,
+    /// as the brightness of the fuzz map multiplied by the fuzz
+    pub fn new_with_fuzz_map(
+        albedo: Textures,
+        normal: Option<Textures>,
+        fuzz: f64,
+        fuzz_map: Textures,
+    ) -> Materials {
+        Materials::from(Metal {
+            albedo,
+            normal,
+            fuzz,
+            fuzz_map: Some(fuzz_map),
         })
     }
 }
@@ -238,23 +531,160 @@ impl Material for Metal {
     /// The Fuzz property of the metal defines the randomness applied to the reflection
     fn scatter(&self, ray: &Ray, rec: &RayHit, _lights: &[Hittables]) -> RayScatter {
         let reflected = ray.direction.unit().reflect(rec.normal);
+        let fuzz = match &self.fuzz_map {
+            None => self.fuzz,
+            Some(fuzz_map) => self.fuzz * brightness(fuzz_map.hit_color(rec)),
+        };
 
         RayScatter::ScatterBasic(ScatterBasic {
-            color: self.albedo.color(rec.uv),
-            ray: Ray::new(
+            color: rec.texture_color(&self.albedo),
+            ray: Ray::new_at_time(
                 rec.hit_point,
-                reflected + random_in_unit_sphere() * self.fuzz,
+                reflected + random_in_unit_sphere() * fuzz * rec.overrides.roughness_multiplier,
+                rec.time,
             ),
         })
     }
 
-    fn get_transformed_normal(&self, onb: Onb, uv: Uv) -> Vec3 {
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
+        self.normal
+            .as_ref()
+            .map_or(onb.normal, |n| transform_normal_by_map(n, onb, uv, time))
+    }
+}
+
+/// A physically based material with the metallic-roughness parameters used by assets made
+/// in tools like Blender and Substance. It is a diffuse base below a glossy GGX reflection,
+/// where metals only have the glossy reflection, tinted by the base color.
+/// The metallic, roughness and specular parameters are the brightness of their textures
+#[derive(Clone, Debug)]
+pub struct PrincipledMaterial {
+    base_color: Textures,
+    normal: Option<Textures>,
+    // Boxed to keep the size of the materials enum down
+    metallic: Box<Textures>,
+    roughness: Box<Textures>,
+    specular: Box<Textures>,
+    index_of_refraction: f64,
+}
+
+/// Smallest width of the GGX distribution, as a perfectly smooth surface can not be sampled
+const MIN_ALPHA: f64 = 1e-3;
+
+impl PrincipledMaterial {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new principled material
+    ///
+    /// # Arguments
+    /// * `base_color` - Color of the diffuse base, and of the reflection of metals
+    /// * `normal` - Normal map of the surface
+    /// * `metallic` - From 0 for dielectrics like plastic to 1 for metals
+    /// * `roughness` - From 0 for mirror like reflections to 1 for diffuse like reflections
+    /// * `specular` - Scales the reflectance of dielectrics, where 0.5 is the reflectance
+    ///   given by the index of refraction
+    /// * `index_of_refraction` - Index of refraction of dielectrics, typically 1.5
+    pub fn new(
+        base_color: Textures,
+        normal: Option<Textures>,
+        metallic: Textures,
+        roughness: Textures,
+        specular: Textures,
+        index_of_refraction: f64,
+    ) -> Materials {
+        Materials::from(PrincipledMaterial {
+            base_color,
+            normal,
+            metallic: Box::new(metallic),
+            roughness: Box::new(roughness),
+            specular: Box::new(specular),
+            index_of_refraction,
+        })
+    }
+}
+
+impl Material for PrincipledMaterial {
+    /// Samples either the lights, the diffuse base or the GGX distribution, and weights
+    /// the scattered ray by the reflected light over the combined pdf of the three
+    fn scatter(&self, ray: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
+        let base_color = rec.texture_color(&self.base_color);
+        let metallic = brightness(self.metallic.hit_color(rec)).clamp(0., 1.);
+        let roughness =
+            brightness(self.roughness.hit_color(rec)) * rec.overrides.roughness_multiplier;
+        let alpha = (roughness * roughness).clamp(MIN_ALPHA, 1.);
+        let specular = brightness(self.specular.hit_color(rec)).max(0.);
+
+        let normal = rec.normal;
+        let outgoing = ray.direction.unit().neg();
+        let cos_outgoing = outgoing.dot(normal).max(ALMOST_ZERO);
+
+        // Reflectance at normal incidence, which for metals is their color
+        let dielectric_f0 =
+            ONE_VECTOR * (reflectance(1., self.index_of_refraction) * 2. * specular).min(1.);
+        let f0 = dielectric_f0 * (1. - metallic) + base_color * metallic;
+        let diffuse_color = base_color
+            * (1. - metallic)
+            * (ONE_VECTOR - schlick_fresnel(dielectric_f0, cos_outgoing));
+
+        let specular_weight = brightness(schlick_fresnel(f0, cos_outgoing));
+        let total_weight = specular_weight + brightness(diffuse_color);
+        let specular_probability = if total_weight > 0. {
+            specular_weight / total_weight
+        } else {
+            0.5
+        };
+
+        let light_pdf = ContainerPdf::new(lights, rec.hit_point);
+        let cosine_pdf = CosinePdf::new(normal);
+        let ggx_pdf = GgxPdf::new(normal, outgoing, alpha);
+        let direction = if random_normal_float() < 0.5 {
+            light_pdf.generate()
+        } else if random_normal_float() < specular_probability {
+            ggx_pdf.generate()
+        } else {
+            cosine_pdf.generate()
+        };
+        let pdf_value = 0.5 * light_pdf.value(direction)
+            + 0.5 * specular_probability * ggx_pdf.value(direction)
+            + 0.5 * (1. - specular_probability) * cosine_pdf.value(direction);
+
+        let incoming = direction.unit();
+        let cos_incoming = incoming.dot(normal);
+        let reflected = if cos_incoming <= 0. || pdf_value <= 0. {
+            ZERO_VECTOR
+        } else {
+            let half = (incoming + outgoing).unit();
+            let fresnel = schlick_fresnel(f0, outgoing.dot(half).max(0.));
+            let distribution = ggx_distribution(half.dot(normal).max(0.), alpha);
+            let shadowing = smith_g1(cos_outgoing, alpha) * smith_g1(cos_incoming, alpha);
+            diffuse_color * (cos_incoming / PI)
+                + fresnel * (distribution * shadowing / (4. * cos_outgoing))
+        };
+
+        RayScatter::ScatterPdf(ScatterPdf {
+            color: if pdf_value > 0. { reflected / pdf_value } else { ZERO_VECTOR },
+            ray: Ray::new_at_time(rec.hit_point, direction, rec.time),
+            probability: 1.,
+        })
+    }
+
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
         self.normal
             .as_ref()
-            .map_or(onb.normal, |n| transform_normal_by_map(n, onb, uv))
+            .map_or(onb.normal, |n| transform_normal_by_map(n, onb, uv, time))
     }
 }
 
+/// Schlick's approximation of the reflectance per color channel
+fn schlick_fresnel(f0: Vec3, cosine: f64) -> Vec3 {
+    f0 + (ONE_VECTOR - f0) * (1. - cosine).powi(5)
+}
+
+/// The fraction of the microfacets seen from a direction that are not hidden by others
+fn smith_g1(cosine: f64, alpha: f64) -> f64 {
+    let alpha_squared = alpha * alpha;
+    2. * cosine / (cosine + (alpha_squared + (1. - alpha_squared) * cosine * cosine).sqrt())
+}
+
 /// A glass type material with an index of refraction
 #[derive(Clone, Debug)]
 pub struct Dielectric {
@@ -296,15 +726,77 @@ impl Material for Dielectric {
             };
 
         RayScatter::ScatterBasic(ScatterBasic {
-            color: self.albedo.color(rec.uv),
-            ray: Ray::new(rec.hit_point, direction),
+            color: rec.texture_color(&self.albedo),
+            ray: Ray::new_at_time(rec.hit_point, direction, rec.time),
         })
     }
 
-    fn get_transformed_normal(&self, onb: Onb, uv: Uv) -> Vec3 {
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
         self.normal
             .as_ref()
-            .map_or(onb.normal, |n| transform_normal_by_map(n, onb, uv))
+            .map_or(onb.normal, |n| transform_normal_by_map(n, onb, uv, time))
+    }
+}
+
+/// A clear coat layer on top of an underlying base material, like varnish or car paint.
+/// The coat has a normal map of its own, so it can have another surface structure than
+/// the base, e.g. an orange peel coat on top of a smooth base
+#[derive(Clone, Debug)]
+pub struct Coat {
+    base: Box<Materials>,
+    normal: Option<Textures>,
+    index_of_refraction: f64,
+}
+
+impl Coat {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new coat material
+    ///
+    /// # Arguments
+    /// * `base` - The material below the coat, which keeps its own normal map
+    /// * `normal` - Normal map of the coat
+    /// * `index_of_refraction` - Index of refraction of the coat, higher is more reflective
+    pub fn new(base: Materials, normal: Option<Textures>, index_of_refraction: f64) -> Materials {
+        Materials::from(Coat {
+            base: Box::new(base),
+            normal,
+            index_of_refraction,
+        })
+    }
+
+    fn coat_normal(&self, rec: &RayHit) -> Vec3 {
+        self.normal.as_ref().map_or(rec.onb.normal, |n| {
+            transform_normal_by_map(n, rec.onb.clone(), rec.uv, rec.time)
+        })
+    }
+}
+
+impl Material for Coat {
+    fn is_light(&self) -> bool {
+        self.base.is_light()
+    }
+
+    /// Reflects off the coat by the fresnel reflectance, otherwise the base material scatters
+    fn scatter(&self, ray: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
+        let normal = self.coat_normal(rec);
+        let unit_direction = ray.direction.unit();
+        let cos_theta = unit_direction.neg().dot(normal).clamp(0., 1.);
+
+        if reflectance(cos_theta, self.index_of_refraction) > random_normal_float() {
+            let direction = unit_direction.reflect(normal);
+            // Reflections that would go below the surface are scattered by the base instead
+            if direction.dot(rec.onb.normal) > 0. {
+                return RayScatter::ScatterBasic(ScatterBasic {
+                    color: ONE_VECTOR,
+                    ray: Ray::new_at_time(rec.hit_point, direction, rec.time),
+                });
+            }
+        }
+        self.base.scatter(ray, rec, lights)
+    }
+
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
+        self.base.get_transformed_normal(onb, uv, time)
     }
 }
 
@@ -319,7 +811,7 @@ fn reflectance(cosine: f64, index_of_refraction: f64) -> f64 {
 #[derive(Clone, Debug)]
 pub struct DiffuseLight {
     tex: Textures,
-    attenuation_factor: Option<f64>,
+    attenuation: Attenuation,
 }
 
 impl DiffuseLight {
@@ -333,9 +825,31 @@ impl DiffuseLight {
     /// * `b` - The blue component of the light
     /// * `attenuation_half_length` - The distance at which the light is attenuated to half its strength
     pub fn new(r: f64, g: f64, b: f64, attenuation_half_length: Option<f64>) -> Materials {
+        DiffuseLight::new_with_attenuation(
+            Vec3::new(r, g, b),
+            attenuation_half_length.map_or(Attenuation::None, Attenuation::Linear),
+        )
+    }
+
+    /// Creates a new diffuse light material
+    ///
+    /// # Arguments
+    /// * `color` - The color of the light
+    /// * `attenuation` - How the light falls off with distance
+    pub fn new_with_attenuation(color: Vec3, attenuation: Attenuation) -> Materials {
         Materials::from(DiffuseLight {
-            tex: SolidColor::new(r, g, b),
-            attenuation_factor: attenuation_half_length.map(|a| 1. / a),
+            tex: SolidColor::new_from_vec3(color),
+            attenuation,
+        })
+    }
+
+    /// Creates a new diffuse light material where the color of the light is from a texture,
+    /// like an image of a screen or a sign. Images can be made brighter than their colors
+    /// by multiplying with a [`texture::SolidColor`] in a [`texture::Multiply`]
+    pub fn new_from_texture(tex: Textures) -> Materials {
+        Materials::from(DiffuseLight {
+            tex,
+            attenuation: Attenuation::None,
         })
     }
 
@@ -346,7 +860,7 @@ impl DiffuseLight {
     pub fn new_from_vec3(v: Vec3) -> Materials {
         DiffuseLightType(DiffuseLight {
             tex: SolidColor::new_from_vec3(v),
-            attenuation_factor: None,
+            attenuation: Attenuation::None,
         })
     }
 }
@@ -359,11 +873,11 @@ impl Material for DiffuseLight {
     fn scatter(&self, _ray: &Ray, rec: &RayHit, _lights: &[Hittables]) -> RayScatter {
         RayScatter::ScatterEmission(ScatterEmission {
             color: if rec.front_face {
-                self.tex.color(rec.uv)
+                rec.texture_color(&self.tex)
             } else {
                 ZERO_VECTOR
             },
-            attenuation_factor: self.attenuation_factor,
+            attenuation: self.attenuation,
         })
     }
 }
@@ -383,8 +897,8 @@ impl Isotropic {
     }
 }
 
-fn transform_normal_by_map(normal_map: &Textures, onb: Onb, uv: Uv) -> Vec3 {
-    let n: Vec3 = normal_map.color(uv) * 2. - ONE_VECTOR;
+fn transform_normal_by_map(normal_map: &Textures, onb: Onb, uv: Uv, time: f64) -> Vec3 {
+    let n: Vec3 = normal_map.color(uv, time) * 2. - ONE_VECTOR;
     onb.local(n)
 }
 
@@ -394,12 +908,12 @@ impl Material for Isotropic {
 
     /// Returns a randomly scattered ray in any direction
     fn scatter(&self, _: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
-        let color = self.tex.color(rec.uv);
+        let color = rec.texture_color(&self.tex);
 
         let pdf = SpherePdf::new();
         let light_pdf = ContainerPdf::new(lights, rec.hit_point);
         let pdf_direction = mix_generate(&light_pdf, &pdf);
-        let scattered = Ray::new(rec.hit_point, pdf_direction);
+        let scattered = Ray::new_at_time(rec.hit_point, pdf_direction, rec.time);
         let light_pdf_value = mix_value(&light_pdf, &pdf, scattered.direction);
 
         RayScatter::ScatterPdf(ScatterPdf {
@@ -416,42 +930,248 @@ pub struct Blend {
     material_1: Box<Materials>,
     material_2: Box<Materials>,
     blend_factor: f64,
+    mask: Option<Textures>,
 }
 
 impl Blend {
     #![allow(clippy::new_ret_no_self)]
     /// Create a new blend material from two underlying material and a blend factor [0..1]
     pub fn new(material_1: Materials, material_2: Materials, blend_factor: f64) -> Materials {
-        Materials::from(Blend { material_1: Box::new(material_1), material_2: Box::new(material_2), blend_factor })
+        Materials::from(Blend {
+            material_1: Box::new(material_1),
+            material_2: Box::new(material_2),
+            blend_factor,
+            mask: None,
+        })
+    }
+
+    /// Create a new blend material where the brightness of a mask texture is the blend factor,
+    /// so black gives the first material and white the second
+    pub fn new_with_mask(
+        material_1: Materials,
+        material_2: Materials,
+        mask: Textures,
+    ) -> Materials {
+        Materials::from(Blend {
+            material_1: Box::new(material_1),
+            material_2: Box::new(material_2),
+            blend_factor: 1.,
+            mask: Some(mask),
+        })
+    }
+
+    fn blend_factor(&self, mask_color: impl FnOnce(&Textures) -> Vec3) -> f64 {
+        self.mask
+            .as_ref()
+            .map_or(self.blend_factor, |mask| brightness(mask_color(mask)))
     }
 }
 
 impl Material for Blend {
     fn scatter(&self, ray: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
-        if random_normal_float() > self.blend_factor {
+        if random_normal_float() > self.blend_factor(|mask| mask.hit_color(rec)) {
             self.material_1.scatter(ray, rec, lights)
         } else {
             self.material_2.scatter(ray, rec, lights)
         }
     }
 
-    fn get_transformed_normal(&self, onb: Onb, uv: Uv) -> Vec3 {
-        if random_normal_float() > self.blend_factor {
-            self.material_1.get_transformed_normal(onb, uv)
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
+        if random_normal_float() > self.blend_factor(|mask| mask.color(uv, time)) {
+            self.material_1.get_transformed_normal(onb, uv, time)
         } else {
-            self.material_2.get_transformed_normal(onb, uv)
+            self.material_2.get_transformed_normal(onb, uv, time)
         }
     }
 }
 
+/// Makes the back side of a surface shade the same as the front side, for single sided
+/// geometry like a light quad that should emit light in both directions
+#[derive(Clone, Debug)]
+pub struct DoubleSided {
+    material: Box<Materials>,
+}
+
+impl DoubleSided {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new double sided material from an underlying material
+    pub fn new(material: Materials) -> Materials {
+        Materials::from(DoubleSided {
+            material: Box::new(material),
+        })
+    }
+}
+
+impl Material for DoubleSided {
+    fn is_light(&self) -> bool {
+        self.material.is_light()
+    }
+
+    fn scatter(&self, ray: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
+        if rec.front_face {
+            return self.material.scatter(ray, rec, lights);
+        }
+        let front_rec = RayHit {
+            front_face: true,
+            ..rec.clone()
+        };
+        self.material.scatter(ray, &front_rec, lights)
+    }
+
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
+        self.material.get_transformed_normal(onb, uv, time)
+    }
+}
+
+/// Holdout of an underlying material, for objects that stand in for things in a background plate.
+/// The camera sees the background where the holdout is, darkened by the shadows that fall on
+/// the underlying material from the lights of the scene, so they can be composited onto the plate.
+/// For all other rays it is the underlying material, so it still hides what is behind it
+/// and casts shadows and reflections
+#[derive(Clone, Debug)]
+pub struct Holdout {
+    material: Box<Materials>,
+}
+
+impl Holdout {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new holdout material from an underlying material
+    pub fn new(material: Materials) -> Materials {
+        Materials::from(Holdout {
+            material: Box::new(material),
+        })
+    }
+}
+
+impl Material for Holdout {
+    fn is_light(&self) -> bool {
+        self.material.is_light()
+    }
+
+    fn is_holdout(&self) -> bool {
+        true
+    }
+
+    fn scatter(&self, ray: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
+        self.material.scatter(ray, rec, lights)
+    }
+
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
+        self.material.get_transformed_normal(onb, uv, time)
+    }
+}
+
+/// Parallax occlusion mapping of an underlying material, where the texture coordinates
+/// are shifted as if the surface had the depth of a height texture
+#[derive(Clone, Debug)]
+pub struct Parallax {
+    material: Box<Materials>,
+    height: Textures,
+    depth: f64,
+    steps: u32,
+}
+
+impl Parallax {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new parallax material
+    ///
+    /// # Arguments
+    /// * `material` - The material that is shifted
+    /// * `height` - Texture where the brightness is the height, white being the surface level
+    /// * `depth` - Depth of black in the height texture, measured in texture coordinate units
+    /// * `steps` - Number of height texture samples along the view ray,
+    ///   more steps gives less artifacts at grazing angles
+    pub fn new(material: Materials, height: Textures, depth: f64, steps: u32) -> Materials {
+        Materials::from(Parallax {
+            material: Box::new(material),
+            height,
+            depth,
+            steps: steps.max(1),
+        })
+    }
+
+    /// Depth below the surface from 0 to 1 at the texture coordinate
+    fn depth_at(&self, uv: Uv, time: f64) -> f64 {
+        1. - brightness(self.height.color(uv, time))
+    }
+
+    /// Marches along the view ray in tangent space until it is below the height field,
+    /// and returns the texture coordinate where it enters
+    fn parallax_uv(&self, ray: &Ray, rec: &RayHit) -> Uv {
+        let view = ray.direction.unit().neg();
+        let view_z = view.dot(rec.onb.normal);
+        if !rec.front_face || view_z <= 0. {
+            return rec.uv;
+        }
+
+        let step = 1. / self.steps as f64;
+        let step_shift = Uv::new(
+            (view.dot(rec.onb.tangent) / view_z * self.depth * step) as f32,
+            (view.dot(rec.onb.bi_tangent) / view_z * self.depth * step) as f32,
+        );
+
+        let mut uv = rec.uv;
+        let mut layer = 0.;
+        let mut depth = self.depth_at(uv, rec.time);
+        let (mut previous_uv, mut previous_layer, mut previous_depth) = (uv, layer, depth);
+        while layer < depth {
+            (previous_uv, previous_layer, previous_depth) = (uv, layer, depth);
+            uv = uv - step_shift;
+            layer += step;
+            depth = self.depth_at(uv, rec.time);
+        }
+        if layer == 0. {
+            return uv;
+        }
+
+        // Interpolate between the last step above and the first step below the height field
+        let above = previous_depth - previous_layer;
+        let below = layer - depth;
+        let t = (above / (above + below)) as f32;
+        Uv::new(
+            previous_uv.u + (uv.u - previous_uv.u) * t,
+            previous_uv.v + (uv.v - previous_uv.v) * t,
+        )
+    }
+}
+
+impl Material for Parallax {
+    fn is_light(&self) -> bool {
+        self.material.is_light()
+    }
+
+    fn scatter(&self, ray: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
+        let uv = self.parallax_uv(ray, rec);
+        let shifted_rec = RayHit {
+            normal: self
+                .material
+                .get_transformed_normal(rec.onb.clone(), uv, rec.time),
+            uv,
+            ..rec.clone()
+        };
+        self.material.scatter(ray, &shifted_rec, lights)
+    }
+
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
+        self.material.get_transformed_normal(onb, uv, time)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::ops::Sub;
+    use std::sync::Arc;
 
-    use crate::geo::{Onb, Uv};
+    use image::{Rgb, RgbImage};
+
+    use crate::geo::{Onb, Ray, Uv};
     use crate::geo::vec3::Vec3;
-    use crate::material::texture::SolidColor;
-    use crate::material::transform_normal_by_map;
+    use crate::material::texture::{ImageMap, Multiply, SolidColor};
+    use crate::material::{
+        transform_normal_by_map, Blend, Coat, DiffuseLight, DoubleSided, Lambertian, Material,
+        Materials, Metal, Parallax, PrincipledMaterial, RayHit, RayScatter,
+    };
+    use crate::hittable::Sphere;
 
     #[test]
     fn test_transform_normal_by_map() {
@@ -463,8 +1183,164 @@ mod tests {
                 normal: Vec3::new(1., 0., 0.)
             },
             Uv::default(),
+            0.,
         );
 
         assert!(Vec3::new(0., 1., 0.).sub(n).near_zero(), "n was {}", n);
     }
+
+    #[test]
+    fn test_parallax_uv() {
+        let onb = Onb {
+            tangent: Vec3::new(1., 0., 0.),
+            bi_tangent: Vec3::new(0., 1., 0.),
+            normal: Vec3::new(0., 0., 1.),
+        };
+        let lambertian = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+        let ray = Ray::new(Vec3::new(-1., 0., 1.), Vec3::new(1., 0., -1.));
+        let uv = Uv::new(0.5, 0.5);
+        let parallax_uv = |height| {
+            let material = Parallax::new(lambertian.clone(), height, 0.1, 10);
+            let rec = RayHit::new(Vec3::new(0., 0., 0.), onb.clone(), &material, 1., uv, true, 0.);
+            match &material {
+                Materials::ParallaxType(p) => p.parallax_uv(&ray, &rec),
+                _ => panic!("Should be a parallax material"),
+            }
+        };
+
+        // Nothing is shifted when the whole surface is at the top
+        assert_eq!(parallax_uv(SolidColor::new(1., 1., 1.)), uv);
+
+        // At 45 degrees the uv is shifted as much as the depth of the surface
+        let shifted = parallax_uv(SolidColor::new(0., 0., 0.));
+        assert!((shifted.u - 0.6).abs() < 0.001, "uv was {:?}", shifted);
+        assert_eq!(shifted.v, 0.5);
+    }
+
+    #[test]
+    fn test_double_sided() {
+        let emitted = |material: &Materials, front_face| {
+            let onb = Onb::new(Vec3::new(0., 0., 1.));
+            let uv = Uv::default();
+            let rec = RayHit::new(Vec3::default(), onb, material, 1., uv, front_face, 0.);
+            let ray = Ray::new(Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.));
+            match material.scatter(&ray, &rec, &[]) {
+                RayScatter::ScatterEmission(s) => s.color,
+                _ => panic!("Light should emit"),
+            }
+        };
+        let light = DiffuseLight::new(1., 1., 1., None);
+        let double_sided = DoubleSided::new(light.clone());
+
+        assert_eq!(emitted(&light, false), Vec3::default());
+        assert_eq!(emitted(&double_sided, false), Vec3::new(1., 1., 1.));
+        assert!(double_sided.is_light());
+    }
+
+    #[test]
+    fn test_light_from_texture() {
+        let image = Arc::new(RgbImage::from_fn(2, 1, |x, _| Rgb([x as u8 * 255, 0, 0])));
+        let texture = Multiply::new(ImageMap::new(image), SolidColor::new(5., 5., 5.));
+        let light = DiffuseLight::new_from_texture(texture);
+        let onb = Onb::new(Vec3::new(0., 0., 1.));
+        let ray = Ray::new(Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.));
+
+        let emitted = |u| {
+            let uv = Uv::new(u, 0.5);
+            let rec = RayHit::new(Vec3::default(), onb.clone(), &light, 1., uv, true, 0.);
+            match light.scatter(&ray, &rec, &[]) {
+                RayScatter::ScatterEmission(s) => s.color,
+                _ => panic!("Light should emit"),
+            }
+        };
+        assert_eq!(emitted(0.25), Vec3::new(0., 0., 0.));
+        assert_eq!(emitted(0.75), Vec3::new(5., 0., 0.));
+    }
+
+    #[test]
+    fn test_coat_normal() {
+        let onb = Onb::new(Vec3::new(0., 0., 1.));
+        let base_normal = SolidColor::new(0.5, 0.5, 1.);
+        let coat_normal = SolidColor::new(0.75, 0.5, 0.933);
+        let base = Lambertian::new(SolidColor::new(1., 1., 1.), Some(base_normal));
+        // The coat reflects everything with a very high index of refraction
+        let material = Coat::new(base, Some(coat_normal.clone()), 1e9);
+        let rec = RayHit::new(Vec3::default(), onb.clone(), &material, 1., Uv::default(), true, 0.);
+        let ray = Ray::new(Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.));
+
+        assert!((rec.normal - Vec3::new(0., 0., 1.)).near_zero());
+        let expected = ray
+            .direction
+            .reflect(transform_normal_by_map(&coat_normal, onb, Uv::default(), 0.));
+        match material.scatter(&ray, &rec, &[]) {
+            RayScatter::ScatterBasic(s) => assert!((s.ray.direction - expected).near_zero()),
+            _ => panic!("Coat should reflect"),
+        }
+    }
+
+    #[test]
+    fn test_texture_driven_parameters() {
+        let onb = Onb::new(Vec3::new(0., 0., 1.));
+        let ray = Ray::new(Vec3::new(-1., 0., 1.), Vec3::new(1., 0., -1.));
+        let scatter = |material: &Materials| {
+            let uv = Uv::default();
+            let rec = RayHit::new(Vec3::default(), onb.clone(), material, 1., uv, true, 0.);
+            material.scatter(&ray, &rec, &[])
+        };
+
+        // A black fuzz map makes the metal a perfect mirror
+        let metal = Metal::new_with_fuzz_map(
+            SolidColor::new(1., 1., 1.),
+            None,
+            1.,
+            SolidColor::new(0., 0., 0.),
+        );
+        match scatter(&metal) {
+            RayScatter::ScatterBasic(s) => {
+                assert!((s.ray.direction.unit() - Vec3::new(1., 0., 1.).unit()).near_zero())
+            }
+            _ => panic!("Metal should scatter basic"),
+        }
+
+        // A white mask always picks the second material
+        let blend = Blend::new_with_mask(
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            metal,
+            SolidColor::new(1., 1., 1.),
+        );
+        for _ in 0..10 {
+            assert!(matches!(scatter(&blend), RayScatter::ScatterBasic(_)));
+        }
+    }
+
+    #[test]
+    fn test_principled_reflection_color() {
+        let onb = Onb::new(Vec3::new(0., 0., 1.));
+        let ray = Ray::new(Vec3::new(-1., 0., 1.), Vec3::new(1., 0., -1.));
+        let lights = [Sphere::new(Vec3::new(0., 0., 5.), 1., DiffuseLight::new(1., 1., 1., None))];
+        let red = SolidColor::new(1., 0., 0.);
+        let grey = |v| SolidColor::new(v, v, v);
+        let scattered_colors = |material: &Materials| {
+            let uv = Uv::default();
+            let rec = RayHit::new(Vec3::default(), onb.clone(), material, 1., uv, true, 0.);
+            (0..100)
+                .map(|_| match material.scatter(&ray, &rec, &lights) {
+                    RayScatter::ScatterPdf(s) => s.color,
+                    _ => panic!("Principled material should scatter with a pdf"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Metals reflect their color, apart from a faint white rim at grazing angles
+        let metal = PrincipledMaterial::new(red.clone(), None, grey(1.), grey(0.3), grey(0.5), 1.5);
+        for color in scattered_colors(&metal) {
+            assert!(color.y == color.z && color.y <= 0.1 * color.x, "color was {}", color);
+        }
+
+        // The reflection of dielectrics is white on top of the colored diffuse base
+        let plastic = PrincipledMaterial::new(red, None, grey(0.), grey(0.3), grey(0.5), 1.5);
+        let colors = scattered_colors(&plastic);
+        assert!(colors.iter().any(|color| color.y > 0. && color.y == color.z));
+        assert!(colors.iter().all(|color| color.x >= color.y));
+    }
 }
@@ -0,0 +1,141 @@
+//! Ready to use materials with measured parameters of common real world materials,
+//! so that plausible looks do not require looking up colors and indices of refraction.
+//! Opaque materials are built on [`PrincipledMaterial`]. It has no transmission,
+//! so the transparent materials are [`Dielectric`]s
+use crate::geo::vec3::Vec3;
+use crate::material::texture::SolidColor;
+use crate::material::{Coat, Dielectric, Materials, PrincipledMaterial};
+
+/// Index of refraction of BK7, the most common optical glass
+pub const BK7_INDEX_OF_REFRACTION: f64 = 1.5168;
+/// Index of refraction of water at room temperature
+pub const WATER_INDEX_OF_REFRACTION: f64 = 1.333;
+/// Index of refraction of the clear coat of car paint
+pub const CAR_PAINT_COAT_INDEX_OF_REFRACTION: f64 = 1.5;
+
+/// Reflectance of gold at normal incidence, in linear colors
+const GOLD_COLOR: Vec3 = Vec3 {
+    x: 1.,
+    y: 0.766,
+    z: 0.336,
+};
+/// Reflectance of copper at normal incidence, in linear colors
+const COPPER_COLOR: Vec3 = Vec3 {
+    x: 0.955,
+    y: 0.638,
+    z: 0.538,
+};
+
+fn principled(color: Vec3, metallic: f64, roughness: f64, index_of_refraction: f64) -> Materials {
+    let grey = |v| SolidColor::new(v, v, v);
+    PrincipledMaterial::new(
+        SolidColor::new_from_vec3(color),
+        None,
+        grey(metallic),
+        grey(roughness),
+        grey(0.5),
+        index_of_refraction,
+    )
+}
+
+/// Polished gold
+pub fn gold() -> Materials {
+    principled(GOLD_COLOR, 1., 0.2, 1.5)
+}
+
+/// Slightly worn copper
+pub fn copper() -> Materials {
+    principled(COPPER_COLOR, 1., 0.3, 1.5)
+}
+
+/// Clear BK7 glass
+pub fn glass_bk7() -> Materials {
+    Dielectric::new(SolidColor::new(1., 1., 1.), None, BK7_INDEX_OF_REFRACTION)
+}
+
+/// Clear water
+pub fn water() -> Materials {
+    Dielectric::new(SolidColor::new(1., 1., 1.), None, WATER_INDEX_OF_REFRACTION)
+}
+
+/// Skin of the given color, with the soft sheen of its oily surface.
+/// Light scattering below the surface is not simulated
+pub fn skin(color: Vec3) -> Materials {
+    principled(color, 0., 0.45, 1.4)
+}
+
+/// Metallic car paint of the given color, below a smooth clear coat
+pub fn car_paint(color: Vec3) -> Materials {
+    Coat::new(
+        principled(color, 0.5, 0.35, 1.5),
+        None,
+        CAR_PAINT_COAT_INDEX_OF_REFRACTION,
+    )
+}
+
+/// Black rubber, like the rubber of tires
+pub fn rubber() -> Materials {
+    principled(Vec3::new(0.02, 0.02, 0.02), 0., 0.9, 1.52)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::{Onb, Ray, Uv};
+    use crate::hittable::Sphere;
+    use crate::material::{DiffuseLight, Material, RayHit, RayScatter};
+
+    use super::*;
+
+    #[test]
+    fn test_presets_scatter() {
+        let onb = Onb::new(Vec3::new(0., 0., 1.));
+        let ray = Ray::new(Vec3::new(-1., 0., 1.), Vec3::new(1., 0., -1.));
+        let lights = [Sphere::new(
+            Vec3::new(0., 0., 5.),
+            1.,
+            DiffuseLight::new(1., 1., 1., None),
+        )];
+        let scattered_colors = |material: &Materials| {
+            let uv = Uv::default();
+            let rec = RayHit::new(Vec3::default(), onb.clone(), material, 1., uv, true, 0.);
+            (0..100)
+                .map(|_| match material.scatter(&ray, &rec, &lights) {
+                    RayScatter::ScatterPdf(s) => s.color,
+                    RayScatter::ScatterBasic(s) => s.color,
+                    RayScatter::ScatterEmission(_) => panic!("Presets should not emit"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let skin_color = Vec3::new(0.8, 0.5, 0.4);
+        let red = Vec3::new(0.8, 0.05, 0.05);
+        let presets = [
+            gold(),
+            copper(),
+            glass_bk7(),
+            water(),
+            skin(skin_color),
+            car_paint(red),
+            rubber(),
+        ];
+        for preset in &presets {
+            for color in scattered_colors(preset) {
+                let channels = [color.x, color.y, color.z];
+                assert!(
+                    channels.iter().all(|c| c.is_finite() && *c >= 0.),
+                    "color {}",
+                    color
+                );
+            }
+        }
+
+        // The reflections of the metals have their color
+        for color in scattered_colors(&gold()) {
+            assert!(
+                color.x >= color.y && color.y >= color.z,
+                "color was {}",
+                color
+            );
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Sheen layer for cloth, where light is scattered by the fibers standing up from the surface
+use std::f64::consts::PI;
+
+use crate::geo::vec3::{Vec3, ONE_VECTOR, ZERO_VECTOR};
+use crate::geo::{Onb, Ray, Uv};
+use crate::hittable::Hittables;
+use crate::material::texture::{brightness, Textures};
+use crate::material::{Material, Materials, RayHit, RayScatter, ScatterPdf};
+use crate::pdf::{mix_generate, mix_value, ContainerPdf, CosinePdf};
+use crate::random::random_normal_float;
+
+/// Smallest roughness of the sheen, below it the distribution gets too narrow to sample
+const MIN_ROUGHNESS: f64 = 0.07;
+/// Number of outgoing angles that the reflectance of the sheen is tabulated for
+const ALBEDO_TABLE_SIZE: usize = 32;
+/// Number of steps in each dimension of the integration of the reflectance
+const ALBEDO_INTEGRATION_STEPS: usize = 64;
+
+/// A sheen layer on top of an underlying base material, for velvet and woven fabrics,
+/// using the Charlie distribution of Estevez and Kulla. Light that is not reflected
+/// by the sheen reaches the base, so the layer never adds energy
+#[derive(Clone, Debug)]
+pub struct Sheen {
+    base: Box<Materials>,
+    color: Textures,
+    alpha: f64,
+    albedo_table: Box<[f64; ALBEDO_TABLE_SIZE]>,
+}
+
+impl Sheen {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new sheen material
+    ///
+    /// # Arguments
+    /// * `base` - The material below the sheen, which keeps its own normal map
+    /// * `color` - Color of the sheen
+    /// * `roughness` - Between zero and one, where low values give a sheen
+    ///   only at grazing angles like velvet and high values a broader sheen
+    pub fn new(base: Materials, color: Textures, roughness: f64) -> Materials {
+        let roughness = roughness.clamp(MIN_ROUGHNESS, 1.);
+        let alpha = roughness * roughness;
+        Materials::from(Sheen {
+            base: Box::new(base),
+            color,
+            alpha,
+            albedo_table: Box::new(albedo_table(alpha)),
+        })
+    }
+
+    /// Fraction of the light leaving in the direction that is reflected by a white sheen
+    fn albedo(&self, cos_outgoing: f64) -> f64 {
+        let position = (cos_outgoing.clamp(0., 1.) * ALBEDO_TABLE_SIZE as f64 - 0.5)
+            .clamp(0., (ALBEDO_TABLE_SIZE - 1) as f64);
+        let low = position.floor() as usize;
+        let high = (low + 1).min(ALBEDO_TABLE_SIZE - 1);
+        let t = position - low as f64;
+        self.albedo_table[low] * (1. - t) + self.albedo_table[high] * t
+    }
+}
+
+impl Material for Sheen {
+    fn is_light(&self) -> bool {
+        self.base.is_light()
+    }
+
+    /// Either scatters off the sheen, or lets the base material scatter the light that
+    /// passes the sheen, choosing by how much of the light the sheen reflects
+    fn scatter(&self, ray: &Ray, rec: &RayHit, lights: &[Hittables]) -> RayScatter {
+        let normal = rec.normal;
+        let outgoing = ray.direction.unit().neg();
+        let cos_outgoing = outgoing.dot(normal);
+        let sheen_albedo = rec.texture_color(&self.color) * self.albedo(cos_outgoing);
+        let sheen_probability = brightness(sheen_albedo).clamp(0., 1.);
+
+        if random_normal_float() >= sheen_probability {
+            let weight = (ONE_VECTOR - sheen_albedo) / (1. - sheen_probability);
+            return match self.base.scatter(ray, rec, lights) {
+                RayScatter::ScatterPdf(s) => RayScatter::ScatterPdf(ScatterPdf {
+                    color: s.color * weight,
+                    ..s
+                }),
+                RayScatter::ScatterBasic(mut s) => {
+                    s.color = s.color * weight;
+                    RayScatter::ScatterBasic(s)
+                }
+                emission => emission,
+            };
+        }
+
+        let light_pdf = ContainerPdf::new(lights, rec.hit_point);
+        let cosine_pdf = CosinePdf::new(normal);
+        let direction = mix_generate(&light_pdf, &cosine_pdf);
+        let pdf_value = mix_value(&light_pdf, &cosine_pdf, direction);
+        let incoming = direction.unit();
+        let cos_incoming = incoming.dot(normal);
+
+        let color = if cos_incoming <= 0. || cos_outgoing <= 0. || pdf_value <= 0. {
+            ZERO_VECTOR
+        } else {
+            let cos_half = (incoming + outgoing).unit().dot(normal);
+            let reflected = sheen_reflectance(self.alpha, cos_incoming, cos_outgoing, cos_half);
+            rec.texture_color(&self.color) * (reflected * cos_incoming / pdf_value)
+                / sheen_probability
+        };
+
+        RayScatter::ScatterPdf(ScatterPdf {
+            color,
+            ray: Ray::new_at_time(rec.hit_point, direction, rec.time),
+            probability: 1.,
+        })
+    }
+
+    fn get_transformed_normal(&self, onb: Onb, uv: Uv, time: f64) -> Vec3 {
+        self.base.get_transformed_normal(onb, uv, time)
+    }
+}
+
+/// The brdf of a white sheen, with the Charlie distribution of the fibers
+/// and the visibility term of Neubelt and Pettineo
+fn sheen_reflectance(alpha: f64, cos_incoming: f64, cos_outgoing: f64, cos_half: f64) -> f64 {
+    let inverse_alpha = 1. / alpha;
+    let sin_half_squared = (1. - cos_half * cos_half).max(1. / 128.);
+    let distribution =
+        (2. + inverse_alpha) * sin_half_squared.powf(inverse_alpha * 0.5) / (2. * PI);
+    let visibility = 1. / (4. * (cos_incoming + cos_outgoing - cos_incoming * cos_outgoing));
+    distribution * visibility
+}
+
+/// Reflectance of a white sheen for evenly spaced cosines of the outgoing angle,
+/// integrated over the hemisphere of incoming directions
+fn albedo_table(alpha: f64) -> [f64; ALBEDO_TABLE_SIZE] {
+    let steps = ALBEDO_INTEGRATION_STEPS;
+    let solid_angle_step = (1. / steps as f64) * (2. * PI / steps as f64);
+    let mut table = [0.; ALBEDO_TABLE_SIZE];
+    for (i, albedo) in table.iter_mut().enumerate() {
+        let cos_outgoing = (i as f64 + 0.5) / ALBEDO_TABLE_SIZE as f64;
+        let outgoing = Vec3::new((1. - cos_outgoing * cos_outgoing).sqrt(), 0., cos_outgoing);
+        let mut sum = 0.;
+        for j in 0..steps {
+            let cos_incoming = (j as f64 + 0.5) / steps as f64;
+            let sin_incoming = (1. - cos_incoming * cos_incoming).sqrt();
+            for k in 0..steps {
+                let phi = 2. * PI * (k as f64 + 0.5) / steps as f64;
+                let incoming = Vec3::new(
+                    sin_incoming * phi.cos(),
+                    sin_incoming * phi.sin(),
+                    cos_incoming,
+                );
+                let cos_half = (incoming + outgoing).unit().z;
+                sum +=
+                    sheen_reflectance(alpha, cos_incoming, cos_outgoing, cos_half) * cos_incoming;
+            }
+        }
+        *albedo = (sum * solid_angle_step).min(1.);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_albedo_table() {
+        for roughness in [0.1, 0.5, 1.] {
+            let table = albedo_table(roughness * roughness);
+            assert!(table.iter().all(|a| (0. ..=1.).contains(a)), "{:?}", table);
+            // The sheen is strongest at grazing angles
+            assert!(table[0] > table[ALBEDO_TABLE_SIZE - 1], "{:?}", table);
+        }
+    }
+}
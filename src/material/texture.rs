@@ -1,25 +1,47 @@
 //! Contains textures to be used by materials
+use std::collections::HashMap;
 use std::error::Error;
+use std::path::Path;
 use std::sync::Arc;
 
 use enum_dispatch::enum_dispatch;
+use image::imageops::FilterType;
 use image::ImageReader;
-use image::RgbImage;
+use image::{DynamicImage, Rgb, RgbImage};
 use simple_error::SimpleError;
 
-use crate::geo::Uv;
-use crate::geo::vec3::Vec3;
+use crate::geo::{Uv, UvDerivatives};
+use crate::geo::vec3::{ONE_VECTOR, Vec3, ZERO_VECTOR};
+use crate::loader::cache::SceneCache;
 use crate::material::texture::BumpMap::{Height, Normal};
-use crate::material::texture::Textures::{ImageMapType, SolidColorType};
+use crate::material::texture::Textures::{
+    HeightBumpType, ImageMapType, MixType, MultiplyType, ObjectIdType, RampType, ScrollType,
+    SolidColorType, StochasticTilingType, VertexColorType,
+};
+use crate::material::RayHit;
+use crate::util::gaussian::{inverse_normal_cdf, normal_cdf};
 use crate::util::height_map;
 use crate::util::rgb_color::rgb_to_vec3;
 
 /// Describes the color of a material.
-/// The color can vary by the uv coordinates of the hittable
+/// The color can vary by the uv coordinates of the hittable, and by time for animated textures
 #[enum_dispatch]
 pub trait Texture {
     /// Return the color of the texture at a given hit
-    fn color(&self, uv: Uv) -> Vec3;
+    fn color(&self, uv: Uv, time: f64) -> Vec3;
+
+    /// Return the color of the texture at a given hit, averaged over the area
+    /// covered by the pixel if the texture supports filtering and the uv derivatives are known
+    fn filtered_color(&self, uv: Uv, uv_derivatives: Option<UvDerivatives>, time: f64) -> Vec3 {
+        let _ = uv_derivatives;
+        self.color(uv, time)
+    }
+
+    /// Return the color of the texture at a given hit. Textures that depend on more
+    /// than the texture coordinate of the hit, like [`VertexColor`], overrides this
+    fn hit_color(&self, rec: &RayHit) -> Vec3 {
+        self.filtered_color(rec.uv, rec.uv_derivatives, rec.time)
+    }
 }
 
 #[enum_dispatch(Texture)]
@@ -30,6 +52,22 @@ pub enum Textures {
     SolidColorType(SolidColor),
     /// [`Texture`] of the type [`ImageMap`]
     ImageMapType(ImageMap),
+    /// [`Texture`] of the type [`Scroll`]
+    ScrollType(Scroll),
+    /// [`Texture`] of the type [`HeightBump`]
+    HeightBumpType(HeightBump),
+    /// [`Texture`] of the type [`StochasticTiling`]
+    StochasticTilingType(StochasticTiling),
+    /// [`Texture`] of the type [`VertexColor`]
+    VertexColorType(VertexColor),
+    /// [`Texture`] of the type [`Multiply`]
+    MultiplyType(Multiply),
+    /// [`Texture`] of the type [`ObjectId`]
+    ObjectIdType(ObjectId),
+    /// [`Texture`] of the type [`Mix`]
+    MixType(Mix),
+    /// [`Texture`] of the type [`Ramp`]
+    RampType(Ramp),
 }
 
 impl Clone for Textures {
@@ -37,6 +75,14 @@ impl Clone for Textures {
         match self {
             SolidColorType(t) => SolidColorType(t.clone()),
             ImageMapType(t) => ImageMapType(t.clone()),
+            ScrollType(t) => ScrollType(t.clone()),
+            HeightBumpType(t) => HeightBumpType(t.clone()),
+            StochasticTilingType(t) => StochasticTilingType(t.clone()),
+            VertexColorType(t) => VertexColorType(t.clone()),
+            MultiplyType(t) => MultiplyType(t.clone()),
+            ObjectIdType(t) => ObjectIdType(t.clone()),
+            MixType(t) => MixType(t.clone()),
+            RampType(t) => RampType(t.clone()),
         }
     }
 }
@@ -50,20 +96,11 @@ pub enum BumpMap {
 }
 
 /// Load a bump map image texture and detect if it is a normal or height map
-fn load_bump_map(path: &str) -> Result<BumpMap, Box<dyn Error>> {
-    let mut reader = ImageReader::open(path).map_err(|err| {
-        SimpleError::new(format!("Failed to open bump texture {}: {}", path, err))
-    })?;
-    reader.no_limits();
-    reader = reader.with_guessed_format().map_err(|err| {
-        SimpleError::new(format!("Failed to load bump texture {}: {}", path, err))
-    })?;
-    let image = reader
-        .decode()
-        .map_err(|err| {
-            SimpleError::new(format!("Failed to decode bump texture {}: {}", path, err))
-        })?
-        .into_rgb8();
+fn load_bump_map(path: &str, cache: Option<&SceneCache>) -> Result<BumpMap, Box<dyn Error>> {
+    let image = match cache {
+        Some(cache) => cache.image("bump", path, decode_bump_image)?,
+        None => decode_bump_image(path)?,
+    };
 
     let mut num_normal = 0;
     let mut num_height = 0;
@@ -85,14 +122,72 @@ fn load_bump_map(path: &str) -> Result<BumpMap, Box<dyn Error>> {
     }
 }
 
-/// Load a normal map texture. Source image can either be a normal or height map
+fn decode_bump_image(path: &str) -> Result<RgbImage, Box<dyn Error>> {
+    let mut reader = ImageReader::open(path).map_err(|err| {
+        SimpleError::new(format!("Failed to open bump texture {}: {}", path, err))
+    })?;
+    reader.no_limits();
+    reader = reader.with_guessed_format().map_err(|err| {
+        SimpleError::new(format!("Failed to load bump texture {}: {}", path, err))
+    })?;
+    Ok(reader
+        .decode()
+        .map_err(|err| {
+            SimpleError::new(format!("Failed to decode bump texture {}: {}", path, err))
+        })?
+        .into_rgb8())
+}
+
+/// Load a normal map texture. Source image can either be a normal or height map.
+/// If the path contains [`UDIM_TOKEN`], all the existing UDIM tiles are loaded
 pub fn load_normal_texture(path: &str) -> Result<Textures, Box<dyn Error>> {
-    match load_bump_map(path)? {
+    load_normal_texture_with_cache(path, None)
+}
+
+/// Load a normal map texture like [`load_normal_texture`], taking the normal maps made from
+/// the images from the cache if they are there
+pub(crate) fn load_normal_texture_with_cache(
+    path: &str,
+    cache: Option<&SceneCache>,
+) -> Result<Textures, Box<dyn Error>> {
+    let load = |path: &str| match cache {
+        Some(cache) => cache.image("normal", path, |path| load_normal_image(path, None)),
+        None => load_normal_image(path, None),
+    };
+    if path.contains(UDIM_TOKEN) {
+        let tiles = udim_tile_paths(path)?
+            .into_iter()
+            .map(|(tile, tile_path)| Ok((tile, Arc::new(load(&tile_path)?))))
+            .collect::<Result<_, Box<dyn Error>>>()?;
+        Ok(ImageMap::new_udim(tiles))
+    } else {
+        Ok(ImageMap::new(Arc::new(load(path)?)))
+    }
+}
+
+/// Load a bump map texture. Height maps are used for bump mapping at shade time
+/// with the given strength, see [`HeightBump`]. Normal maps are used as is
+pub fn load_bump_texture(path: &str, strength: f64) -> Result<Textures, Box<dyn Error>> {
+    load_bump_texture_with_cache(path, strength, None)
+}
+
+/// Load a bump map texture like [`load_bump_texture`], taking the decoded image from the cache
+/// if it is there
+pub(crate) fn load_bump_texture_with_cache(
+    path: &str,
+    strength: f64,
+    cache: Option<&SceneCache>,
+) -> Result<Textures, Box<dyn Error>> {
+    match load_bump_map(path, cache)? {
         Normal(n) => Ok(ImageMap::new(Arc::new(n))),
-        Height(h) => {
-            let n = height_map::to_normal_map(h);
-            Ok(ImageMap::new(Arc::new(n)))
-        }
+        Height(h) => Ok(HeightBump::new(ImageMap::new(Arc::new(h)), strength)),
+    }
+}
+
+fn load_normal_image(path: &str, cache: Option<&SceneCache>) -> Result<RgbImage, Box<dyn Error>> {
+    match load_bump_map(path, cache)? {
+        Normal(n) => Ok(n),
+        Height(h) => Ok(height_map::to_normal_map(h)),
     }
 }
 
@@ -118,74 +213,1056 @@ impl SolidColor {
 }
 
 impl Texture for SolidColor {
-    fn color(&self, _: Uv) -> Vec3 {
+    fn color(&self, _: Uv, _: f64) -> Vec3 {
         self.0
     }
 }
 
+/// Token in an image path that is replaced by the UDIM tile number, like 1001, 1002 and so on
+pub const UDIM_TOKEN: &str = "<UDIM>";
+
+/// How an [`ImageMap`] samples its image
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum TextureFilter {
+    /// The single nearest pixel is used
+    #[default]
+    Nearest,
+    /// The four nearest pixels are blended, which gives smooth magnified textures
+    Bilinear,
+    /// Bilinear filtering of the two mip maps closest to the area covered by the pixel,
+    /// blended together. Removes aliasing of minified textures, but blurs surfaces
+    /// seen at grazing angles
+    Trilinear,
+    /// Elliptical weighted average over the area covered by the pixel, using mip maps.
+    /// Removes moiré patterns on surfaces seen at grazing angles, but is slower
+    Ewa,
+}
+
+/// How an [`ImageMap`] is sampled outside of the texture coordinates 0 to 1.
+/// Filters that blend several pixels use it for the pixels beyond the edges of the image
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum WrapMode {
+    /// The image is repeated
+    #[default]
+    Repeat,
+    /// The image is repeated, with every other repetition mirrored
+    Mirror,
+    /// The pixels at the edges of the image are stretched outwards
+    Clamp,
+    /// The given color is used outside of the image
+    Border(Vec3),
+    /// For images that wrap around a sphere, like the texture coordinates of a
+    /// [`crate::hittable::Sphere`]. The image is repeated horizontally, and continues
+    /// over the top and bottom edges on the opposite side of the image, like across a pole
+    Spherical,
+}
+
+impl WrapMode {
+    /// Texture coordinates wrapped into the range 0 to 1, or none if outside of a border.
+    /// Coordinates that are not finite are outside of a border, and 0 for the other modes
+    fn uv(&self, u: f32, v: f32) -> Option<(f32, f32)> {
+        if !u.is_finite() || !v.is_finite() {
+            return match self {
+                WrapMode::Border(_) => None,
+                _ => Some((0., 0.)),
+            };
+        }
+        if *self == WrapMode::Spherical {
+            let v = v.rem_euclid(2.);
+            return if v > 1. {
+                Some((repeat(u + 0.5), 2. - v))
+            } else {
+                Some((repeat(u), v))
+            };
+        }
+        Some((self.coordinate(u)?, self.coordinate(v)?))
+    }
+
+    fn coordinate(&self, c: f32) -> Option<f32> {
+        match self {
+            WrapMode::Repeat | WrapMode::Spherical => Some(repeat(c)),
+            WrapMode::Mirror => {
+                let c = c.rem_euclid(2.);
+                Some(if c > 1. { 2. - c } else { c })
+            }
+            WrapMode::Clamp => Some(c.clamp(0., 1.)),
+            WrapMode::Border(_) => (0. ..=1.).contains(&c).then_some(c),
+        }
+    }
+
+    /// Pixel wrapped into an image of the given size, or none if outside of a border
+    fn pixel(&self, x: i64, y: i64, width: i64, height: i64) -> Option<(u32, u32)> {
+        let (x, y) = if *self == WrapMode::Spherical {
+            let y = y.rem_euclid(2 * height);
+            if y >= height {
+                ((x + width / 2).rem_euclid(width), 2 * height - 1 - y)
+            } else {
+                (x.rem_euclid(width), y)
+            }
+        } else {
+            (self.index(x, width)?, self.index(y, height)?)
+        };
+        Some((x as u32, y as u32))
+    }
+
+    fn index(&self, i: i64, size: i64) -> Option<i64> {
+        match self {
+            WrapMode::Repeat | WrapMode::Spherical => Some(i.rem_euclid(size)),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                Some(if i >= size { 2 * size - 1 - i } else { i })
+            }
+            WrapMode::Clamp => Some(i.clamp(0, size - 1)),
+            WrapMode::Border(_) => (0..size).contains(&i).then_some(i),
+        }
+    }
+
+    fn border_color(&self) -> Vec3 {
+        match self {
+            WrapMode::Border(color) => *color,
+            _ => ZERO_VECTOR,
+        }
+    }
+}
+
+/// Color of the pixel, where the indices may be outside of the image
+fn texel(image: &RgbImage, x: i64, y: i64, wrap_mode: WrapMode) -> Vec3 {
+    match wrap_mode.pixel(x, y, image.width() as i64, image.height() as i64) {
+        Some((x, y)) => rgb_to_vec3(image.get_pixel(x, y)),
+        None => wrap_mode.border_color(),
+    }
+}
+
+/// Coordinate repeated into the range 0 to 1
+fn repeat(c: f32) -> f32 {
+    c - c.floor()
+}
+
+/// Maximum ratio between the axes of the filter ellipse, longer ellipses are widened
+const MAX_ANISOTROPY: f64 = 8.;
+/// Falloff of the gaussian filter weights
+const EWA_ALPHA: f64 = 2.;
+
 /// Texture that uses image data for color by loading the image from the path
 #[derive(Clone, Debug)]
 pub struct ImageMap {
+    tiles: ImageTiles,
+}
+
+#[derive(Clone, Debug)]
+enum ImageTiles {
+    /// One image that is repeated over the whole uv space
+    Single(ImageTile),
+    /// UDIM tiles by tile number, where tile 1001 covers uv 0 to 1,
+    /// tile 1002 covers u 1 to 2 and tile 1011 covers v 1 to 2
+    Udim(Arc<HashMap<u32, ImageTile>>),
+}
+
+#[derive(Clone, Debug)]
+struct ImageTile {
     image: Arc<RgbImage>,
-    max_x: f32,
-    max_y: f32,
+    filter: TextureFilter,
+    wrap_mode: WrapMode,
+    /// Successively halved versions of the image, only used by [`TextureFilter::Trilinear`]
+    /// and [`TextureFilter::Ewa`]
+    mip_levels: Option<Arc<Vec<RgbImage>>>,
+}
+
+impl ImageTile {
+    fn new(image: Arc<RgbImage>, filter: TextureFilter, wrap_mode: WrapMode) -> ImageTile {
+        // Empty images are black, instead of having no pixels to sample
+        let image = if image.width() == 0 || image.height() == 0 {
+            Arc::new(RgbImage::new(1, 1))
+        } else {
+            image
+        };
+        let mip_levels = match filter {
+            TextureFilter::Nearest | TextureFilter::Bilinear => None,
+            TextureFilter::Trilinear | TextureFilter::Ewa => {
+                Some(Arc::new(create_mip_levels(&image)))
+            }
+        };
+        ImageTile {
+            image,
+            filter,
+            wrap_mode,
+            mip_levels,
+        }
+    }
+
+    /// Color of the pixel at u and v in the range 0 to 1, where v goes from the bottom
+    /// of the image. Each pixel covers an equal part of the range, so u is scaled by the
+    /// width rather than the width minus one
+    fn color(&self, u: f32, v: f32) -> Vec3 {
+        let (w, h) = (self.image.width() as i64, self.image.height() as i64);
+        let x = ((u as f64 * w as f64) as i64).min(w - 1);
+        let y = (((1. - v as f64) * h as f64) as i64).min(h - 1);
+        texel(&self.image, x, y, self.wrap_mode)
+    }
+
+    fn filtered_color(&self, u: f32, v: f32, uv_derivatives: Option<UvDerivatives>) -> Vec3 {
+        match (self.filter, &self.mip_levels, uv_derivatives) {
+            (TextureFilter::Nearest, _, _) => self.color(u, v),
+            (TextureFilter::Trilinear, Some(mip_levels), Some(d)) => {
+                self.trilinear(mip_levels, u, v, d)
+            }
+            (TextureFilter::Ewa, Some(mip_levels), Some(d)) => self.ewa(mip_levels, u, v, d),
+            (TextureFilter::Ewa, _, _) => self.color(u, v),
+            _ => bilinear_level(&self.image, u, v, self.wrap_mode),
+        }
+    }
+
+    fn level<'a>(&'a self, mip_levels: &'a [RgbImage], level: usize) -> &'a RgbImage {
+        if level == 0 {
+            &self.image
+        } else {
+            &mip_levels[level - 1]
+        }
+    }
+
+    /// Bilinear filtering of the two mip levels, where the longest side of the area
+    /// covered by the pixel is closest to one pixel
+    fn trilinear(&self, mip_levels: &[RgbImage], u: f32, v: f32, d: UvDerivatives) -> Vec3 {
+        let (w, h) = (self.image.width() as f64, self.image.height() as f64);
+        let texels = |d: Uv| ((d.u as f64 * w).powi(2) + (d.v as f64 * h).powi(2)).sqrt();
+        let width = texels(d.duv_dx).max(texels(d.duv_dy));
+
+        let num_levels = mip_levels.len() + 1;
+        let lod = width.log2().clamp(0., (num_levels - 1) as f64);
+        if !lod.is_finite() {
+            return bilinear_level(&self.image, u, v, self.wrap_mode);
+        }
+        let level = (lod as usize).min(num_levels - 1);
+        if level == num_levels - 1 {
+            return bilinear_level(self.level(mip_levels, level), u, v, self.wrap_mode);
+        }
+
+        let t = lod - level as f64;
+        bilinear_level(self.level(mip_levels, level), u, v, self.wrap_mode) * (1. - t)
+            + bilinear_level(self.level(mip_levels, level + 1), u, v, self.wrap_mode) * t
+    }
+
+    /// Elliptical weighted average, as described in Physically Based Rendering.
+    /// The mip level is chosen so the minor axis of the ellipse covers a few pixels
+    fn ewa(&self, mip_levels: &[RgbImage], u: f32, v: f32, d: UvDerivatives) -> Vec3 {
+        // Image rows go downwards, so the v derivatives change sign
+        let mut major = (d.duv_dx.u as f64, -d.duv_dx.v as f64);
+        let mut minor = (d.duv_dy.u as f64, -d.duv_dy.v as f64);
+        let length = |a: (f64, f64)| (a.0 * a.0 + a.1 * a.1).sqrt();
+        if length(major) < length(minor) {
+            std::mem::swap(&mut major, &mut minor);
+        }
+        let major_length = length(major);
+        let mut minor_length = length(minor);
+
+        // When magnified there is no aliasing to filter away, and the filter would only blur
+        let texels = self.image.width().max(self.image.height()) as f64;
+        if major_length * texels <= 1. {
+            return self.color(u, v);
+        }
+
+        if minor_length * MAX_ANISOTROPY < major_length && minor_length > 0. {
+            let scale = major_length / (minor_length * MAX_ANISOTROPY);
+            minor = (minor.0 * scale, minor.1 * scale);
+            minor_length *= scale;
+        }
+        if minor_length == 0. || !minor_length.is_finite() {
+            return self.color(u, v);
+        }
+
+        let num_levels = mip_levels.len() + 1;
+        let lod = (num_levels as f64 - 1. + minor_length.log2()).max(0.);
+        let level = lod as usize;
+        let filter_level =
+            |l| ewa_level(self.level(mip_levels, l), u, v, major, minor, self.wrap_mode);
+        if level >= num_levels - 1 {
+            return filter_level(num_levels - 1);
+        }
+
+        let t = lod - level as f64;
+        filter_level(level) * (1. - t) + filter_level(level + 1) * t
+    }
+}
+
+/// Halves the image repeatedly down to a single pixel
+fn create_mip_levels(image: &RgbImage) -> Vec<RgbImage> {
+    let mut levels: Vec<RgbImage> = Vec::new();
+    let (mut w, mut h) = image.dimensions();
+    while w > 1 || h > 1 {
+        w = (w / 2).max(1);
+        h = (h / 2).max(1);
+        let previous = levels.last().unwrap_or(image);
+        levels.push(image::imageops::resize(previous, w, h, FilterType::Triangle));
+    }
+    levels
+}
+
+/// Blend of the four pixels closest to the uv coordinate
+fn bilinear_level(image: &RgbImage, u: f32, v: f32, wrap_mode: WrapMode) -> Vec3 {
+    let (w, h) = (image.width() as f64, image.height() as f64);
+    let s = u as f64 * w - 0.5;
+    let t = (1. - v as f64) * h - 0.5;
+    let (s0, t0) = (s.floor(), t.floor());
+    let (fs, ft) = (s - s0, t - t0);
+    let pixel = |is: i64, it: i64| texel(image, is, it, wrap_mode);
+
+    let (s0, t0) = (s0 as i64, t0 as i64);
+    (pixel(s0, t0) * (1. - fs) + pixel(s0 + 1, t0) * fs) * (1. - ft)
+        + (pixel(s0, t0 + 1) * (1. - fs) + pixel(s0 + 1, t0 + 1) * fs) * ft
+}
+
+/// Gaussian weighted average of the pixels within the ellipse with the given axes
+fn ewa_level(
+    image: &RgbImage,
+    u: f32,
+    v: f32,
+    major: (f64, f64),
+    minor: (f64, f64),
+    wrap_mode: WrapMode,
+) -> Vec3 {
+    let (w, h) = (image.width() as f64, image.height() as f64);
+    let s = u as f64 * w - 0.5;
+    let t = (1. - v as f64) * h - 0.5;
+    let (d0s, d0t) = (major.0 * w, major.1 * h);
+    let (d1s, d1t) = (minor.0 * w, minor.1 * h);
+
+    // Implicit ellipse equation a*s^2 + b*s*t + c*t^2 < 1, widened by one pixel
+    let mut a = d0t * d0t + d1t * d1t + 1.;
+    let mut b = -2. * (d0s * d0t + d1s * d1t);
+    let mut c = d0s * d0s + d1s * d1s + 1.;
+    let inv_f = 1. / (a * c - b * b * 0.25);
+    a *= inv_f;
+    b *= inv_f;
+    c *= inv_f;
+
+    let det = -b * b + 4. * a * c;
+    let s_extent = 2. * (det * c).sqrt() / det;
+    let t_extent = 2. * (det * a).sqrt() / det;
+    let s0 = (s - s_extent).ceil() as i64;
+    let s1 = (s + s_extent).floor() as i64;
+    let t0 = (t - t_extent).ceil() as i64;
+    let t1 = (t + t_extent).floor() as i64;
+
+    let mut sum = ZERO_VECTOR;
+    let mut weight_sum = 0.;
+    for it in t0..=t1 {
+        let tt = it as f64 - t;
+        for is in s0..=s1 {
+            let ss = is as f64 - s;
+            let r2 = a * ss * ss + b * ss * tt + c * tt * tt;
+            if r2 < 1. {
+                let weight = (-EWA_ALPHA * r2).exp() - (-EWA_ALPHA).exp();
+                sum += texel(image, is, it, wrap_mode) * weight;
+                weight_sum += weight;
+            }
+        }
+    }
+
+    if weight_sum > 0. {
+        sum / weight_sum
+    } else {
+        texel(image, s.round() as i64, t.round() as i64, wrap_mode)
+    }
 }
 
 impl ImageMap {
     #![allow(clippy::new_ret_no_self)]
-    /// Creates a new image texture from a file path
+    /// Creates a new image texture from a file path.
+    /// If the path contains [`UDIM_TOKEN`], all the existing UDIM tiles are loaded
     pub fn load(path: &str) -> Result<Textures, Box<dyn Error>> {
-        let mut reader = ImageReader::open(path).map_err(|err| {
-            SimpleError::new(format!("Failed to open image texture {}: {}", path, err))
-        })?;
-        reader.no_limits();
-        reader = reader.with_guessed_format().map_err(|err| {
-            SimpleError::new(format!("Failed to load image texture {}: {}", path, err))
-        })?;
-        let image = reader
-            .decode()
-            .map_err(|err| {
-                SimpleError::new(format!("Failed to decode image texture {}: {}", path, err))
-            })?
-            .into_rgb8();
-
-        Ok(Self::new(Arc::new(image)))
+        Self::load_with_filter(path, TextureFilter::Nearest)
+    }
+
+    /// Creates a new image texture from a file path, that is sampled with the given filter.
+    /// If the path contains [`UDIM_TOKEN`], all the existing UDIM tiles are loaded
+    pub fn load_with_filter(path: &str, filter: TextureFilter) -> Result<Textures, Box<dyn Error>> {
+        Self::load_with_wrap_mode(path, filter, WrapMode::Repeat)
+    }
+
+    /// Creates a new image texture from a file path, that is sampled with the given filter
+    /// and wrap mode. If the path contains [`UDIM_TOKEN`], all the existing UDIM tiles are
+    /// loaded, and the wrap mode only applies to the filtering at the edges of the tiles
+    pub fn load_with_wrap_mode(
+        path: &str,
+        filter: TextureFilter,
+        wrap_mode: WrapMode,
+    ) -> Result<Textures, Box<dyn Error>> {
+        Self::load_with_cache(path, filter, wrap_mode, None)
+    }
+
+    /// Creates a new image texture like [`ImageMap::load_with_wrap_mode`], taking the decoded
+    /// images from the cache if they are there
+    pub(crate) fn load_with_cache(
+        path: &str,
+        filter: TextureFilter,
+        wrap_mode: WrapMode,
+        cache: Option<&SceneCache>,
+    ) -> Result<Textures, Box<dyn Error>> {
+        if path.contains(UDIM_TOKEN) {
+            let tiles = udim_tile_paths(path)?
+                .into_iter()
+                .map(|(tile, tile_path)| Ok((tile, Arc::new(read_image(&tile_path, cache)?))))
+                .collect::<Result<_, Box<dyn Error>>>()?;
+            Ok(Self::udim(tiles, filter, wrap_mode))
+        } else {
+            let image = read_image(path, cache)?;
+            Ok(Self::new_with_wrap_mode(Arc::new(image), filter, wrap_mode))
+        }
+    }
+
+    /// Loads an image file as two textures, one for the color and one for the alpha channel.
+    /// The alpha texture is white for images without alpha
+    pub fn load_with_alpha(
+        path: &str,
+        filter: TextureFilter,
+    ) -> Result<(Textures, Textures), Box<dyn Error>> {
+        let image = decode_image(path)?;
+        let rgba = image.to_rgba8();
+        let alpha = RgbImage::from_fn(image.width(), image.height(), |x, y| {
+            let a = rgba.get_pixel(x, y)[3];
+            Rgb([a, a, a])
+        });
+        Ok((
+            Self::new_with_filter(Arc::new(image.into_rgb8()), filter),
+            Self::new_with_filter(Arc::new(alpha), filter),
+        ))
+    }
+
+    /// Loads an image file as an opacity texture, from the alpha channel of images that
+    /// have one and otherwise from the brightness, taking the decoded image from the cache
+    /// if it is there
+    pub(crate) fn load_opacity_with_cache(
+        path: &str,
+        filter: TextureFilter,
+        cache: Option<&SceneCache>,
+    ) -> Result<Textures, Box<dyn Error>> {
+        let decode = |path: &str| -> Result<RgbImage, Box<dyn Error>> {
+            let image = decode_image(path)?;
+            if !image.color().has_alpha() {
+                return Ok(image.into_rgb8());
+            }
+            let rgba = image.to_rgba8();
+            Ok(RgbImage::from_fn(image.width(), image.height(), |x, y| {
+                let a = rgba.get_pixel(x, y)[3];
+                Rgb([a, a, a])
+            }))
+        };
+        let image = match cache {
+            Some(cache) => cache.image("opacity", path, decode)?,
+            None => decode(path)?,
+        };
+        Ok(Self::new_with_filter(Arc::new(image), filter))
     }
 
     /// Creates a texture that uses image data for color
     pub fn new(image: Arc<RgbImage>) -> Textures {
-        let w = image.width();
-        let h = image.height();
+        Self::new_with_filter(image, TextureFilter::Nearest)
+    }
+
+    /// Creates a texture that uses image data for color, sampled with the given filter
+    pub fn new_with_filter(image: Arc<RgbImage>, filter: TextureFilter) -> Textures {
+        Self::new_with_wrap_mode(image, filter, WrapMode::Repeat)
+    }
+
+    /// Creates a texture that uses image data for color, sampled with the given filter
+    /// and wrap mode. Empty images are black
+    pub fn new_with_wrap_mode(
+        image: Arc<RgbImage>,
+        filter: TextureFilter,
+        wrap_mode: WrapMode,
+    ) -> Textures {
         Textures::from(ImageMap {
-            image,
-            max_x: w as f32 - 1.,
-            max_y: h as f32 - 1.,
+            tiles: ImageTiles::Single(ImageTile::new(image, filter, wrap_mode)),
+        })
+    }
+
+    /// Creates a texture from UDIM tiles by tile number.
+    /// Uv coordinates that fall on a missing tile are black
+    pub fn new_udim(tiles: HashMap<u32, Arc<RgbImage>>) -> Textures {
+        Self::new_udim_with_filter(tiles, TextureFilter::Nearest)
+    }
+
+    /// Creates a texture from UDIM tiles by tile number, sampled with the given filter.
+    /// Uv coordinates that fall on a missing tile are black
+    pub fn new_udim_with_filter(
+        tiles: HashMap<u32, Arc<RgbImage>>,
+        filter: TextureFilter,
+    ) -> Textures {
+        Self::udim(tiles, filter, WrapMode::Repeat)
+    }
+
+    fn udim(
+        tiles: HashMap<u32, Arc<RgbImage>>,
+        filter: TextureFilter,
+        wrap_mode: WrapMode,
+    ) -> Textures {
+        let tiles = tiles
+            .into_iter()
+            .map(|(tile, image)| (tile, ImageTile::new(image, filter, wrap_mode)))
+            .collect();
+        Textures::from(ImageMap {
+            tiles: ImageTiles::Udim(Arc::new(tiles)),
+        })
+    }
+
+    /// Size of a pixel in texture coordinates
+    fn texel_size(&self) -> f32 {
+        let image = match &self.tiles {
+            ImageTiles::Single(tile) => Some(&tile.image),
+            ImageTiles::Udim(tiles) => tiles.values().next().map(|tile| &tile.image),
+        };
+        image.map_or(DEFAULT_BUMP_UV_STEP, |image| {
+            1. / image.width().max(image.height()) as f32
         })
     }
 }
 
 impl Texture for ImageMap {
     /// Returns the color in the image data that corresponds to the UV coordinate of the hittable
-    /// If UV coordinates from hit record is <0 or >1 texture wraps
-    fn color(&self, uv: Uv) -> Vec3 {
-        let u = uv.u.abs() % 1.;
-        let v = 1. - uv.v.abs() % 1.;
+    /// If UV coordinates from hit record is <0 or >1 the wrap mode decides the color,
+    /// unless it has UDIM tiles
+    fn color(&self, uv: Uv, time: f64) -> Vec3 {
+        self.filtered_color(uv, None, time)
+    }
+
+    fn filtered_color(&self, uv: Uv, uv_derivatives: Option<UvDerivatives>, _: f64) -> Vec3 {
+        match &self.tiles {
+            ImageTiles::Single(tile) => {
+                let wrap_mode = tile.wrap_mode;
+                match wrap_mode.uv(uv.u, uv.v) {
+                    Some((u, v)) => tile.filtered_color(u, v, uv_derivatives),
+                    None => wrap_mode.border_color(),
+                }
+            }
+            ImageTiles::Udim(tiles) => match udim_tile(uv).and_then(|t| tiles.get(&t)) {
+                Some(tile) => tile.filtered_color(uv.u.fract(), uv.v.fract(), uv_derivatives),
+                None => ZERO_VECTOR,
+            },
+        }
+    }
+}
+
+/// The UDIM tile number for the uv coordinate, if within the 10 tiles wide UDIM range
+fn udim_tile(uv: Uv) -> Option<u32> {
+    if !(0. ..10.).contains(&uv.u) || uv.v < 0. {
+        return None;
+    }
+    Some(1001 + uv.u as u32 + 10 * uv.v as u32)
+}
+
+/// Finds the existing files for the UDIM tiles of the path containing [`UDIM_TOKEN`]
+fn udim_tile_paths(path: &str) -> Result<Vec<(u32, String)>, SimpleError> {
+    let tile_paths: Vec<(u32, String)> = (1001..=1100)
+        .map(|tile| (tile, path.replace(UDIM_TOKEN, &tile.to_string())))
+        .filter(|(_, tile_path)| Path::new(tile_path).is_file())
+        .collect();
 
-        let x = u * self.max_x;
-        let y = v * self.max_y;
+    if tile_paths.is_empty() {
+        return Err(SimpleError::new(format!("No UDIM tiles found for {}", path)));
+    }
+    Ok(tile_paths)
+}
 
-        let pixel = self.image.get_pixel(x as u32, y as u32);
-        rgb_to_vec3(pixel)
+fn read_image(path: &str, cache: Option<&SceneCache>) -> Result<RgbImage, Box<dyn Error>> {
+    let decode = |path: &str| -> Result<RgbImage, Box<dyn Error>> {
+        Ok(decode_image(path)?.into_rgb8())
+    };
+    match cache {
+        Some(cache) => cache.image("image", path, decode),
+        None => decode(path),
+    }
+}
+
+fn decode_image(path: &str) -> Result<DynamicImage, Box<dyn Error>> {
+    let mut reader = ImageReader::open(path).map_err(|err| {
+        SimpleError::new(format!("Failed to open image texture {}: {}", path, err))
+    })?;
+    reader.no_limits();
+    reader = reader.with_guessed_format().map_err(|err| {
+        SimpleError::new(format!("Failed to load image texture {}: {}", path, err))
+    })?;
+    Ok(reader.decode().map_err(|err| {
+        SimpleError::new(format!("Failed to decode image texture {}: {}", path, err))
+    })?)
+}
+
+/// Texture that moves another texture over the surface with a constant uv velocity over time
+#[derive(Clone, Debug)]
+pub struct Scroll {
+    texture: Box<Textures>,
+    velocity: Uv,
+}
+
+impl Scroll {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a texture scrolling the given texture by velocity uv units per time unit
+    pub fn new(texture: Textures, velocity: Uv) -> Textures {
+        Textures::from(Scroll {
+            texture: Box::new(texture),
+            velocity,
+        })
+    }
+}
+
+impl Texture for Scroll {
+    fn color(&self, uv: Uv, time: f64) -> Vec3 {
+        self.filtered_color(uv, None, time)
+    }
+
+    fn filtered_color(&self, uv: Uv, uv_derivatives: Option<UvDerivatives>, time: f64) -> Vec3 {
+        let offset = Uv::new(
+            self.velocity.u * time as f32,
+            self.velocity.v * time as f32,
+        );
+        self.texture.filtered_color(uv + offset, uv_derivatives, time)
+    }
+}
+
+/// Texture with the color interpolated from the vertices of the hittable that was hit,
+/// as loaded from scanned or vertex painted models. Is white for hittables without vertex colors
+#[derive(Clone, Debug)]
+pub struct VertexColor {}
+
+impl VertexColor {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a texture reading the vertex colors of the hittable
+    pub fn new() -> Textures {
+        Textures::from(VertexColor {})
+    }
+}
+
+impl Texture for VertexColor {
+    /// Vertex colors are not known from the texture coordinate alone
+    fn color(&self, _: Uv, _: f64) -> Vec3 {
+        ONE_VECTOR
+    }
+
+    fn hit_color(&self, rec: &RayHit) -> Vec3 {
+        rec.vertex_color.unwrap_or(ONE_VECTOR)
+    }
+}
+
+/// Texture that multiplies the colors of two textures,
+/// e.g. to shade an image texture with the vertex colors of a model
+#[derive(Clone, Debug)]
+pub struct Multiply {
+    a: Box<Textures>,
+    b: Box<Textures>,
+}
+
+impl Multiply {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a texture with the product of the colors of the given textures
+    pub fn new(a: Textures, b: Textures) -> Textures {
+        Textures::from(Multiply {
+            a: Box::new(a),
+            b: Box::new(b),
+        })
+    }
+}
+
+impl Texture for Multiply {
+    fn color(&self, uv: Uv, time: f64) -> Vec3 {
+        self.a.color(uv, time) * self.b.color(uv, time)
+    }
+
+    fn filtered_color(&self, uv: Uv, uv_derivatives: Option<UvDerivatives>, time: f64) -> Vec3 {
+        self.a.filtered_color(uv, uv_derivatives, time)
+            * self.b.filtered_color(uv, uv_derivatives, time)
+    }
+
+    fn hit_color(&self, rec: &RayHit) -> Vec3 {
+        self.a.hit_color(rec) * self.b.hit_color(rec)
+    }
+}
+
+/// Texture that mixes two textures by a factor texture, per color channel.
+/// Black factor gives the first texture and white the second, e.g. to use an image as mask
+#[derive(Clone, Debug)]
+pub struct Mix {
+    a: Box<Textures>,
+    b: Box<Textures>,
+    factor: Box<Textures>,
+}
+
+impl Mix {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a texture mixing the given textures by the factor texture
+    pub fn new(a: Textures, b: Textures, factor: Textures) -> Textures {
+        Textures::from(Mix {
+            a: Box::new(a),
+            b: Box::new(b),
+            factor: Box::new(factor),
+        })
+    }
+}
+
+fn mix(a: Vec3, b: Vec3, factor: Vec3) -> Vec3 {
+    a * (ONE_VECTOR - factor) + b * factor
+}
+
+impl Texture for Mix {
+    fn color(&self, uv: Uv, time: f64) -> Vec3 {
+        mix(
+            self.a.color(uv, time),
+            self.b.color(uv, time),
+            self.factor.color(uv, time),
+        )
+    }
+
+    fn filtered_color(&self, uv: Uv, uv_derivatives: Option<UvDerivatives>, time: f64) -> Vec3 {
+        mix(
+            self.a.filtered_color(uv, uv_derivatives, time),
+            self.b.filtered_color(uv, uv_derivatives, time),
+            self.factor.filtered_color(uv, uv_derivatives, time),
+        )
+    }
+
+    fn hit_color(&self, rec: &RayHit) -> Vec3 {
+        mix(
+            self.a.hit_color(rec),
+            self.b.hit_color(rec),
+            self.factor.hit_color(rec),
+        )
+    }
+}
+
+/// Texture that maps the brightness of another texture to a color gradient
+#[derive(Clone, Debug)]
+pub struct Ramp {
+    input: Box<Textures>,
+    stops: Vec<(f64, Vec3)>,
+}
+
+impl Ramp {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a texture with the color gradient given by stops of brightness and color.
+    /// Colors are interpolated between the stops, and clamped outside of them
+    pub fn new(input: Textures, mut stops: Vec<(f64, Vec3)>) -> Textures {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Textures::from(Ramp {
+            input: Box::new(input),
+            stops,
+        })
+    }
+
+    fn ramp(&self, input: Vec3) -> Vec3 {
+        let value = brightness(input);
+        let next = self.stops.partition_point(|(position, _)| *position < value);
+        match (self.stops.get(next.wrapping_sub(1)), self.stops.get(next)) {
+            (None, None) => ZERO_VECTOR,
+            (Some((_, color)), None) | (None, Some((_, color))) => *color,
+            (Some((p0, c0)), Some((p1, c1))) => {
+                let t = (value - p0) / (p1 - p0);
+                *c0 * (1. - t) + *c1 * t
+            }
+        }
+    }
+}
+
+impl Texture for Ramp {
+    fn color(&self, uv: Uv, time: f64) -> Vec3 {
+        self.ramp(self.input.color(uv, time))
+    }
+
+    fn filtered_color(&self, uv: Uv, uv_derivatives: Option<UvDerivatives>, time: f64) -> Vec3 {
+        self.ramp(self.input.filtered_color(uv, uv_derivatives, time))
+    }
+
+    fn hit_color(&self, rec: &RayHit) -> Vec3 {
+        self.ramp(self.input.hit_color(rec))
+    }
+}
+
+/// Brightness of a color as the average of the channels,
+/// for textures used as a single value like masks and heights
+pub fn brightness(color: Vec3) -> f64 {
+    (color.x + color.y + color.z) / 3.
+}
+
+/// Texture with a pseudo random color for each object id,
+/// see [`crate::material::MaterialOverrides`].
+/// Useful to tell instances apart when debugging, or to vary the look of objects
+/// that share a material by multiplying it with another texture
+#[derive(Clone, Debug)]
+pub struct ObjectId {
+    range: Option<(f64, f64)>,
+}
+
+impl ObjectId {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a texture with a random color for each object id
+    pub fn new() -> Textures {
+        Textures::from(ObjectId { range: None })
+    }
+
+    /// Creates a texture with a random gray value between min and max for each object id
+    pub fn new_scalar(min: f64, max: f64) -> Textures {
+        Textures::from(ObjectId {
+            range: Some((min, max)),
+        })
+    }
+
+    fn id_color(&self, object_id: u32) -> Vec3 {
+        let hash = |seed: u32| hash_to_unit(object_id.wrapping_mul(0x27d4eb2d) ^ seed);
+        match self.range {
+            None => Vec3::new(hash(0x68e31da4), hash(0xb5297a4d), hash(0x1b56c4e9)),
+            Some((min, max)) => ONE_VECTOR * (min + (max - min) * hash(0x68e31da4)),
+        }
+    }
+}
+
+impl Texture for ObjectId {
+    /// The object id is not known from the texture coordinate alone, so id zero is used
+    fn color(&self, _: Uv, _: f64) -> Vec3 {
+        self.id_color(0)
+    }
+
+    fn hit_color(&self, rec: &RayHit) -> Vec3 {
+        self.id_color(rec.overrides.object_id)
+    }
+}
+
+/// Number of entries in the lookup table from gaussian values back to colors
+const STOCHASTIC_LUT_SIZE: usize = 1024;
+/// Standard deviation of the gaussian values, chosen so they mostly stay within 0 to 1
+const STOCHASTIC_STD_DEV: f64 = 1. / 6.;
+/// Size of the triangle grid in texture coordinates, so each triangle is about a third of the image
+const STOCHASTIC_GRID_SCALE: f32 = 3.464;
+
+/// Repeats an image texture without visible tiling, by blending three randomly offset copies
+/// of the image over a triangle grid. The blending is done on the image with its histogram
+/// transformed to a gaussian distribution, which keeps the contrast and colors of the image.
+/// As described in "High-Performance By-Example Noise using a Histogram-Preserving
+/// Blending Operator" by Heitz and Neyret
+#[derive(Clone, Debug)]
+pub struct StochasticTiling {
+    data: Arc<StochasticTilingData>,
+}
+
+#[derive(Debug)]
+struct StochasticTilingData {
+    /// Each channel of each pixel of the image, transformed to a gaussian distribution
+    gaussian: Vec<[f32; 3]>,
+    width: u32,
+    height: u32,
+    /// Maps gaussian values back to the colors of the image
+    inverse_lut: Vec<Rgb<u8>>,
+}
+
+impl StochasticTiling {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a stochastically tiled texture from an [`ImageMap`] with a single image
+    pub fn new(image_map: Textures) -> Result<Textures, SimpleError> {
+        let image = match &image_map {
+            ImageMapType(ImageMap {
+                tiles: ImageTiles::Single(tile),
+            }) => &tile.image,
+            _ => {
+                return Err(SimpleError::new(
+                    "Stochastic tiling needs an image map with a single image",
+                ))
+            }
+        };
+
+        let num_pixels = (image.width() * image.height()) as f64;
+        let mut to_gaussian = [[0f32; 256]; 3];
+        let mut inverse_lut = vec![Rgb([0u8; 3]); STOCHASTIC_LUT_SIZE];
+        for channel in 0..3 {
+            let mut histogram = [0u32; 256];
+            for pixel in image.pixels() {
+                histogram[pixel[channel] as usize] += 1;
+            }
+
+            // Each value is mapped to the middle of its range of the cumulative distribution
+            let mut cumulative = [0f64; 256];
+            let mut count = 0;
+            for (value, &n) in histogram.iter().enumerate() {
+                let p = (count as f64 + n as f64 / 2.) / num_pixels;
+                to_gaussian[channel][value] =
+                    (0.5 + inverse_normal_cdf(p) * STOCHASTIC_STD_DEV) as f32;
+                count += n;
+                cumulative[value] = count as f64 / num_pixels;
+            }
+
+            for (i, entry) in inverse_lut.iter_mut().enumerate() {
+                let g = (i as f64 + 0.5) / STOCHASTIC_LUT_SIZE as f64;
+                let p = normal_cdf((g - 0.5) / STOCHASTIC_STD_DEV);
+                entry[channel] = cumulative.iter().position(|&c| c >= p).unwrap_or(255) as u8;
+            }
+        }
+
+        let gaussian = image
+            .pixels()
+            .map(|p| {
+                [
+                    to_gaussian[0][p[0] as usize],
+                    to_gaussian[1][p[1] as usize],
+                    to_gaussian[2][p[2] as usize],
+                ]
+            })
+            .collect();
+
+        Ok(Textures::from(StochasticTiling {
+            data: Arc::new(StochasticTilingData {
+                gaussian,
+                width: image.width(),
+                height: image.height(),
+                inverse_lut,
+            }),
+        }))
+    }
+
+    /// Gaussian pixel values of the image at the texture coordinate, repeating outside 0 to 1
+    fn gaussian_at(&self, uv: Uv) -> [f32; 3] {
+        let data = &self.data;
+        let x = (uv.u.rem_euclid(1.) * data.width as f32) as u32;
+        let y = ((1. - uv.v.rem_euclid(1.)) * data.height as f32) as u32;
+        let x = x.min(data.width - 1);
+        let y = y.min(data.height - 1);
+        data.gaussian[(y * data.width + x) as usize]
+    }
+}
+
+impl Texture for StochasticTiling {
+    fn color(&self, uv: Uv, _: f64) -> Vec3 {
+        let (vertices, weights) = triangle_grid(uv);
+
+        let mut g = [0f32; 3];
+        for (vertex, weight) in vertices.iter().zip(weights) {
+            let sample = self.gaussian_at(uv + random_offset(*vertex));
+            for c in 0..3 {
+                g[c] += sample[c] * weight;
+            }
+        }
+
+        // Blending changes the variance, which is restored to keep the histogram
+        let weight_length = weights.iter().map(|w| w * w).sum::<f32>().sqrt();
+        let mut color = Rgb([0u8; 3]);
+        for c in 0..3 {
+            let g = (g[c] - 0.5) / weight_length + 0.5;
+            let i = (g * STOCHASTIC_LUT_SIZE as f32) as usize;
+            color[c] = self.data.inverse_lut[i.min(STOCHASTIC_LUT_SIZE - 1)][c];
+        }
+        rgb_to_vec3(&color)
+    }
+}
+
+/// The vertices of the triangle in a grid of equilateral triangles that contains the
+/// texture coordinate, and the barycentric weights of the vertices
+fn triangle_grid(uv: Uv) -> ([(i32, i32); 3], [f32; 3]) {
+    let u = uv.u * STOCHASTIC_GRID_SCALE;
+    let v = uv.v * STOCHASTIC_GRID_SCALE;
+    let skewed_u = u - 0.577_350_27 * v;
+    let skewed_v = 1.154_700_5 * v;
+
+    let base = (skewed_u.floor() as i32, skewed_v.floor() as i32);
+    let (fu, fv) = (skewed_u - skewed_u.floor(), skewed_v - skewed_v.floor());
+    let fw = 1. - fu - fv;
+    if fw > 0. {
+        (
+            [base, (base.0, base.1 + 1), (base.0 + 1, base.1)],
+            [fw, fv, fu],
+        )
+    } else {
+        (
+            [(base.0 + 1, base.1 + 1), (base.0 + 1, base.1), (base.0, base.1 + 1)],
+            [-fw, 1. - fv, 1. - fu],
+        )
+    }
+}
+
+/// A pseudo random texture coordinate offset that is the same every time for a grid vertex
+fn random_offset(vertex: (i32, i32)) -> Uv {
+    let hash = |seed: u32| {
+        hash_to_unit(
+            (vertex.0 as u32).wrapping_mul(0x8da6b343)
+                ^ (vertex.1 as u32).wrapping_mul(0xd8163841)
+                ^ seed,
+        ) as f32
+    };
+    Uv::new(hash(0x9e3779b9), hash(0x85ebca6b))
+}
+
+/// Scrambles the bits of a value into a pseudo random number between 0 and 1
+pub(crate) fn hash_to_unit(value: u32) -> f64 {
+    let mut h = value;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846ca68b);
+    h ^= h >> 16;
+    (h >> 8) as f64 / (1 << 24) as f64
+}
+
+/// Normal map texture that perturbs the normal by the gradient of a height texture,
+/// sampled when shading instead of being converted to a normal map up front
+#[derive(Clone, Debug)]
+pub struct HeightBump {
+    height: Box<Textures>,
+    strength: f64,
+    uv_step: f32,
+}
+
+/// Distance in texture coordinates between height samples, for textures without pixels
+const DEFAULT_BUMP_UV_STEP: f32 = 0.001;
+
+impl HeightBump {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a normal map texture from a height texture where the brightness is the height.
+    /// Strength is the height of white, measured in texture coordinate units
+    pub fn new(height: Textures, strength: f64) -> Textures {
+        let uv_step = match &height {
+            ImageMapType(image_map) => image_map.texel_size(),
+            _ => DEFAULT_BUMP_UV_STEP,
+        };
+        Textures::from(HeightBump {
+            height: Box::new(height),
+            strength,
+            uv_step,
+        })
+    }
+
+    fn height(&self, uv: Uv, time: f64) -> f64 {
+        brightness(self.height.color(uv, time))
+    }
+}
+
+impl Texture for HeightBump {
+    /// Returns the normal in tangent space, encoded as a color like a normal map
+    fn color(&self, uv: Uv, time: f64) -> Vec3 {
+        let step = self.uv_step;
+        let dh_du = (self.height(uv + Uv::new(step, 0.), time)
+            - self.height(uv - Uv::new(step, 0.), time))
+            / (2. * step as f64);
+        let dh_dv = (self.height(uv + Uv::new(0., step), time)
+            - self.height(uv - Uv::new(0., step), time))
+            / (2. * step as f64);
+
+        let normal = Vec3::new(-self.strength * dh_du, -self.strength * dh_dv, 1.).unit();
+        (normal + ONE_VECTOR) / 2.
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::material::texture::{BumpMap, load_bump_map};
+    use std::sync::Arc;
+
+    use image::{Rgb, RgbImage};
+
+    use crate::geo::Uv;
+    use crate::geo::vec3::Vec3;
+    use std::collections::HashMap;
+
+    use crate::geo::UvDerivatives;
+    use crate::material::texture::{
+        BumpMap, HeightBump, ImageMap, load_bump_map, Mix, Ramp, Scroll, SolidColor,
+        StochasticTiling, Texture, TextureFilter, Textures, WrapMode,
+    };
 
     #[test]
     fn test_load_normal_bump_map() {
-        let res = load_bump_map("resources/textures/wall_n.png").unwrap();
+        let res = load_bump_map("resources/textures/wall_n.png", None).unwrap();
         match res {
             BumpMap::Normal(n) => assert!(n.width() > 0 && n.height() > 0),
             BumpMap::Height(_) => panic!("Should not be a height map"),
@@ -194,10 +1271,274 @@ mod tests {
 
     #[test]
     fn test_load_height_bump_map() {
-        let res = load_bump_map("resources/textures/sponza-h.jpg").unwrap();
+        let res = load_bump_map("resources/textures/sponza-h.jpg", None).unwrap();
         match res {
             BumpMap::Normal(_) => panic!("Should not be a height map"),
             BumpMap::Height(n) => assert!(n.width() > 0 && n.height() > 0),
         }
     }
+
+    #[test]
+    fn test_scroll() {
+        let mut image = RgbImage::new(4, 1);
+        image.put_pixel(0, 0, Rgb([255, 0, 0]));
+        image.put_pixel(2, 0, Rgb([0, 0, 255]));
+        let texture = Scroll::new(ImageMap::new(Arc::new(image)), Uv::new(0.5, 0.));
+
+        let uv = Uv::new(0.125, 0.5);
+        assert_eq!(texture.color(uv, 0.), Vec3::new(1., 0., 0.));
+        assert_eq!(texture.color(uv, 1.), Vec3::new(0., 0., 1.));
+    }
+
+    #[test]
+    fn test_udim_tiles() {
+        let tile = |r, g, b| Arc::new(RgbImage::from_pixel(2, 2, Rgb([r, g, b])));
+        let texture = ImageMap::new_udim(HashMap::from([
+            (1001, tile(255, 0, 0)),
+            (1002, tile(0, 255, 0)),
+            (1011, tile(0, 0, 255)),
+        ]));
+
+        assert_eq!(texture.color(Uv::new(0.5, 0.5), 0.), Vec3::new(1., 0., 0.));
+        assert_eq!(texture.color(Uv::new(1.5, 0.5), 0.), Vec3::new(0., 1., 0.));
+        assert_eq!(texture.color(Uv::new(0.5, 1.5), 0.), Vec3::new(0., 0., 1.));
+        assert_eq!(texture.color(Uv::new(1.5, 1.5), 0.), Vec3::new(0., 0., 0.));
+        assert_eq!(texture.color(Uv::new(-0.5, 0.5), 0.), Vec3::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn test_missing_udim_tiles() {
+        let res = ImageMap::load("resources/textures/missing.<UDIM>.png");
+        assert_eq!(
+            "No UDIM tiles found for resources/textures/missing.<UDIM>.png",
+            format!("{}", res.err().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_bilinear_filter() {
+        let image = Arc::new(RgbImage::from_fn(2, 1, |x, _| match x {
+            0 => Rgb([0, 0, 0]),
+            _ => Rgb([255, 255, 255]),
+        }));
+        let bilinear = ImageMap::new_with_filter(image, TextureFilter::Bilinear);
+
+        // Pixel centers give the pixel colors, and halfway between them the colors are blended
+        assert_eq!(bilinear.color(Uv::new(0.25, 0.5), 0.), Vec3::new(0., 0., 0.));
+        assert_eq!(bilinear.color(Uv::new(0.75, 0.5), 0.), Vec3::new(1., 1., 1.));
+        let color = bilinear.color(Uv::new(0.5, 0.5), 0.);
+        assert!((color - Vec3::new(0.5, 0.5, 0.5)).length() < 1e-6);
+        // The image wraps around at the edges
+        let color = bilinear.color(Uv::new(0., 0.5), 0.);
+        assert!((color - Vec3::new(0.5, 0.5, 0.5)).length() < 1e-6);
+    }
+
+    #[test]
+    fn test_wrap_modes() {
+        let image = Arc::new(RgbImage::from_fn(4, 1, |x, _| Rgb([x as u8 * 85, 0, 0])));
+        let border = Vec3::new(0., 1., 0.);
+        let red = |x: f64| Vec3::new(x * 85. / 255., 0., 0.);
+        let cases = [
+            (-0.125, [red(3.), red(0.), red(0.), border]),
+            (0., [red(0.), red(0.), red(0.), red(0.)]),
+            (1., [red(0.), red(3.), red(3.), red(3.)]),
+            (1.125, [red(0.), red(3.), red(3.), border]),
+            (2.875, [red(3.), red(3.), red(3.), border]),
+            (f32::NAN, [red(0.), red(0.), red(0.), border]),
+            (f32::INFINITY, [red(0.), red(0.), red(0.), border]),
+        ];
+        let wrap_modes = [
+            WrapMode::Repeat,
+            WrapMode::Mirror,
+            WrapMode::Clamp,
+            WrapMode::Border(border),
+        ];
+
+        for (u, expected) in cases {
+            for (wrap_mode, expected) in wrap_modes.iter().zip(expected) {
+                let texture =
+                    ImageMap::new_with_wrap_mode(image.clone(), TextureFilter::Nearest, *wrap_mode);
+                let color = texture.color(Uv::new(u, 0.5), 0.);
+                assert!(
+                    (color - expected).length() < 1e-9,
+                    "{:?} at u {} was {}",
+                    wrap_mode,
+                    u,
+                    color
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_image_is_black() {
+        let footprint = UvDerivatives {
+            duv_dx: Uv::new(0.1, 0.),
+            duv_dy: Uv::new(0., 0.1),
+        };
+        let filters = [
+            TextureFilter::Nearest,
+            TextureFilter::Bilinear,
+            TextureFilter::Trilinear,
+            TextureFilter::Ewa,
+        ];
+        for filter in filters {
+            let texture = ImageMap::new_with_filter(Arc::new(RgbImage::new(0, 0)), filter);
+            for uv in [Uv::new(0.5, 0.5), Uv::new(-3.2, 7.9), Uv::new(f32::NAN, 1.)] {
+                assert_eq!(texture.color(uv, 0.), Vec3::new(0., 0., 0.));
+                let color = texture.filtered_color(uv, Some(footprint), 0.);
+                assert_eq!(color, Vec3::new(0., 0., 0.), "{:?}", filter);
+            }
+        }
+    }
+
+    #[test]
+    fn test_clamped_bilinear_filter() {
+        let image = Arc::new(RgbImage::from_fn(2, 1, |x, _| match x {
+            0 => Rgb([0, 0, 0]),
+            _ => Rgb([255, 255, 255]),
+        }));
+        let clamped =
+            ImageMap::new_with_wrap_mode(image, TextureFilter::Bilinear, WrapMode::Clamp);
+
+        // The edge pixels are not blended with the opposite edge
+        assert_eq!(clamped.color(Uv::new(0., 0.5), 0.), Vec3::new(0., 0., 0.));
+        assert_eq!(clamped.color(Uv::new(1., 0.5), 0.), Vec3::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn test_spherical_wrap_mode() {
+        let spherical = WrapMode::Spherical;
+        assert_eq!(spherical.pixel(-1, 0, 4, 2), Some((3, 0)));
+        assert_eq!(spherical.pixel(0, -1, 4, 2), Some((2, 0)));
+        assert_eq!(spherical.pixel(3, 2, 4, 2), Some((1, 1)));
+        assert_eq!(spherical.uv(0.25, 1.25), Some((0.75, 0.75)));
+
+        let image = Arc::new(RgbImage::from_fn(4, 2, |x, y| {
+            Rgb([x as u8 * 85, y as u8 * 255, 0])
+        }));
+        let texture =
+            ImageMap::new_with_wrap_mode(image, TextureFilter::Bilinear, spherical);
+
+        // At the poles the edge rows are blended with the other side of the same row,
+        // instead of with the row at the opposite pole
+        for v in [0., 1.] {
+            let color = texture.color(Uv::new(0.125, v), 0.);
+            assert!((color.x - 85. / 255.).abs() < 1e-6, "color at v {} was {}", v, color);
+            assert!(color.y == 0. || color.y == 1., "color at v {} was {}", v, color);
+        }
+    }
+
+    #[test]
+    fn test_trilinear_filter() {
+        let checker = Arc::new(RgbImage::from_fn(64, 64, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        }));
+        let uv = Uv::new(0.5 / 64., 1. - 10.5 / 64.);
+        let footprint = |size| UvDerivatives {
+            duv_dx: Uv::new(size, 0.),
+            duv_dy: Uv::new(0., size),
+        };
+        let trilinear = ImageMap::new_with_filter(checker, TextureFilter::Trilinear);
+
+        // A footprint covering many pixels gives the average color
+        let color = trilinear.filtered_color(uv, Some(footprint(0.25)), 0.);
+        assert!((color - Vec3::new(0.5, 0.5, 0.5)).length() < 0.05);
+
+        // A footprint smaller than a pixel gives the color of the pixel
+        let color = trilinear.filtered_color(uv, Some(footprint(0.001)), 0.);
+        assert!((color - Vec3::new(1., 1., 1.)).length() < 0.05);
+    }
+
+    #[test]
+    fn test_ewa_filter() {
+        let checker = Arc::new(RgbImage::from_fn(64, 64, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        }));
+        let uv = Uv::new(0.5 / 64., 1. - 10.5 / 64.);
+        let footprint = |size| UvDerivatives {
+            duv_dx: Uv::new(size, 0.),
+            duv_dy: Uv::new(0., size / 4.),
+        };
+
+        // A footprint covering many pixels gives the average color
+        let ewa = ImageMap::new_with_filter(checker.clone(), TextureFilter::Ewa);
+        let color = ewa.filtered_color(uv, Some(footprint(0.5)), 0.);
+        assert!((color - Vec3::new(0.5, 0.5, 0.5)).length() < 0.05);
+
+        // A footprint smaller than a pixel gives the color of the pixel
+        let color = ewa.filtered_color(uv, Some(footprint(0.001)), 0.);
+        assert!((color - Vec3::new(1., 1., 1.)).length() < 0.05);
+
+        let nearest = ImageMap::new(checker);
+        let color = nearest.filtered_color(uv, Some(footprint(0.5)), 0.);
+        assert_eq!(color, Vec3::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn test_height_bump() {
+        let flat = HeightBump::new(SolidColor::new(0.5, 0.5, 0.5), 1.);
+        assert_eq!(flat.color(Uv::new(0.5, 0.5), 0.), Vec3::new(0.5, 0.5, 1.));
+
+        // Height increases along u, so the normal tilts towards negative u
+        let ramp = Arc::new(RgbImage::from_fn(64, 64, |x, _| Rgb([x as u8 * 4; 3])));
+        let bump = HeightBump::new(ImageMap::new(ramp.clone()), 0.5);
+        let color = bump.color(Uv::new(0.5, 0.5), 0.);
+        assert!(color.x < 0.35);
+        assert_eq!(color.y, 0.5);
+
+        let stronger = HeightBump::new(ImageMap::new(ramp), 1.);
+        assert!(stronger.color(Uv::new(0.5, 0.5), 0.).x < color.x);
+    }
+
+    #[test]
+    fn test_stochastic_tiling() {
+        let image = ImageMap::load("resources/textures/tex.jpg").unwrap();
+        let tiled = StochasticTiling::new(image.clone()).unwrap();
+
+        let samples: Vec<Uv> = (0..100)
+            .flat_map(|i| (0..100).map(move |j| Uv::new(i as f32 * 0.1003, j as f32 * 0.1007)))
+            .collect();
+        let mean = |texture: &Textures| {
+            let sum = samples
+                .iter()
+                .fold(Vec3::default(), |sum, uv| sum + texture.color(*uv, 0.));
+            sum / samples.len() as f64
+        };
+        assert!((mean(&image) - mean(&tiled)).length() < 0.05);
+
+        // The texture does not repeat with the image
+        let repeated = samples
+            .iter()
+            .filter(|uv| tiled.color(**uv, 0.) == tiled.color(**uv + Uv::new(1., 0.), 0.))
+            .count();
+        assert!(repeated < samples.len() / 10);
+
+        let udim = ImageMap::new_udim(HashMap::new());
+        assert!(StochasticTiling::new(udim).is_err());
+    }
+
+    #[test]
+    fn test_mix_and_ramp() {
+        let mask = SolidColor::new(0.25, 0.25, 0.25);
+        let mix = Mix::new(SolidColor::new(1., 0., 0.), SolidColor::new(0., 0., 1.), mask.clone());
+        assert_eq!(mix.color(Uv::default(), 0.), Vec3::new(0.75, 0., 0.25));
+
+        let ramp = Ramp::new(
+            mask,
+            vec![(0.5, Vec3::new(0., 1., 0.)), (0., Vec3::new(1., 0., 0.))],
+        );
+        assert_eq!(ramp.color(Uv::default(), 0.), Vec3::new(0.5, 0.5, 0.));
+
+        let clamped = Ramp::new(SolidColor::new(1., 1., 1.), vec![(0.5, Vec3::new(0., 1., 0.))]);
+        assert_eq!(clamped.color(Uv::default(), 0.), Vec3::new(0., 1., 0.));
+    }
 }
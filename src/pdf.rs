@@ -29,6 +29,10 @@ pub enum Pdfs<'a> {
     ContainerPdfType(ContainerPdf<'a>),
     /// [`Pdf`] of type [`SpherePdf`]
     SpherePdfType(SpherePdf),
+    /// [`Pdf`] of type [`TwoSidedCosinePdf`]
+    TwoSidedCosinePdfType(TwoSidedCosinePdf),
+    /// [`Pdf`] of type [`GgxPdf`]
+    GgxPdfType(GgxPdf),
 }
 
 /// Returns the pdf value for a given vector for the pdfs.
@@ -71,6 +75,103 @@ impl Pdf for CosinePdf {
     }
 }
 
+/// A probability density functions with a cosine distribution on both sides of a surface,
+/// where a fraction of the directions are on the back side
+pub struct TwoSidedCosinePdf {
+    uvw: Onb,
+    back_fraction: f64,
+}
+
+impl<'a> TwoSidedCosinePdf {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a new instance of a TwoSidedCosinePdf
+    pub fn new(w: Vec3, back_fraction: f64) -> Pdfs<'a> {
+        Pdfs::from(TwoSidedCosinePdf {
+            uvw: Onb::new(w),
+            back_fraction,
+        })
+    }
+}
+
+impl Pdf for TwoSidedCosinePdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let cosine_theta = direction.unit().dot(self.uvw.normal);
+        if cosine_theta < 0. {
+            self.back_fraction * -cosine_theta / PI
+        } else {
+            (1. - self.back_fraction) * cosine_theta / PI
+        }
+    }
+
+    fn generate(&self) -> Vec3 {
+        let mut direction = random_cosine_direction();
+        if random_normal_float() < self.back_fraction {
+            direction.z = -direction.z;
+        }
+        self.uvw.local(direction)
+    }
+}
+
+/// A probability density functions for reflections off a rough surface,
+/// where the normals of the microfacets of the surface have a GGX distribution
+pub struct GgxPdf {
+    uvw: Onb,
+    outgoing: Vec3,
+    alpha: f64,
+}
+
+impl<'a> GgxPdf {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a new instance of a GgxPdf, for light reflected towards the outgoing direction.
+    /// Alpha is the width of the distribution, typically the square of the roughness
+    pub fn new(w: Vec3, outgoing: Vec3, alpha: f64) -> Pdfs<'a> {
+        Pdfs::from(GgxPdf {
+            uvw: Onb::new(w),
+            outgoing: outgoing.unit(),
+            alpha,
+        })
+    }
+}
+
+/// The GGX distribution of microfacet normals at the given cosine to the surface normal
+pub(crate) fn ggx_distribution(cos_theta: f64, alpha: f64) -> f64 {
+    let alpha_squared = alpha * alpha;
+    let d = cos_theta * cos_theta * (alpha_squared - 1.) + 1.;
+    alpha_squared / (PI * d * d)
+}
+
+impl Pdf for GgxPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let half = self.outgoing + direction.unit();
+        if half.near_zero() {
+            return 0.;
+        }
+        let half = half.unit();
+        let cos_theta = half.dot(self.uvw.normal).abs();
+        let cos_outgoing = self.outgoing.dot(half).abs();
+        if cos_outgoing == 0. {
+            return 0.;
+        }
+        // The density of the microfacet normals changes by the reflection
+        ggx_distribution(cos_theta, self.alpha) * cos_theta / (4. * cos_outgoing)
+    }
+
+    fn generate(&self) -> Vec3 {
+        let r1 = random_normal_float();
+        let r2 = random_normal_float();
+        let cos_theta = (1. / (1. + self.alpha * self.alpha * r1 / (1. - r1))).sqrt();
+        let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+        let phi = 2. * PI * r2;
+
+        let half = self.uvw.local(Vec3::new(
+            phi.cos() * sin_theta,
+            phi.sin() * sin_theta,
+            cos_theta,
+        ));
+        self.outgoing.neg().reflect(half)
+    }
+}
+
 /// A wrapper for generating pdfs for a list of hittable objects
 pub struct ContainerPdf<'a> {
     objects: &'a [Hittables],
@@ -90,14 +191,18 @@ impl<'a> Pdf for ContainerPdf<'a> {
         let sum: f64 = self
             .objects
             .iter()
-            .map(|i| i.pdf_value(self.origin, direction))
+            .filter_map(|i| i.as_sampleable())
+            .map(|s| s.pdf_value(self.origin, direction))
             .sum();
         sum / self.objects.len() as f64
     }
 
     fn generate(&self) -> Vec3 {
         let idx = random_element_index(self.objects);
-        self.objects[idx].random_direction(self.origin)
+        self.objects[idx]
+            .as_sampleable()
+            .expect("Lights are validated to be sampleable when creating the renderer")
+            .random_direction(self.origin)
     }
 }
 
@@ -52,24 +52,19 @@ impl PostProcessor for BloomPostProcessor {
         pixel_colors: &[Vec3],
         albedo_colors: &[Vec3],
         normal_colors: &[Vec3],
+        standard_errors: &[f64],
         width: u32,
         height: u32,
-        num_samples: u32,
     ) -> Result<image::RgbImage, Box<dyn Error>> {
         let pixel_colors = self.intermediate_post_process(
             pixel_colors,
             albedo_colors,
             normal_colors,
+            standard_errors,
             width,
             height,
-            num_samples,
         )?;
-        Ok(pixel_colors_to_rgb_image(
-            &pixel_colors,
-            width,
-            height,
-            num_samples,
-        ))
+        Ok(pixel_colors_to_rgb_image(&pixel_colors, width, height))
     }
 
     #[allow(clippy::needless_range_loop)]
@@ -78,12 +73,12 @@ impl PostProcessor for BloomPostProcessor {
         pixel_colors: &[Vec3],
         _albedo_colors: &[Vec3],
         _normal_colors: &[Vec3],
+        _standard_errors: &[f64],
         width: u32,
         height: u32,
-        num_samples: u32,
     ) -> Result<Vec<Vec3>, Box<dyn Error>> {
-        let threshold = self.threshold * num_samples as f64;
-        let max_intensity = self.max_intensity * num_samples as f64;
+        let threshold = self.threshold;
+        let max_intensity = self.max_intensity;
         let kernel_size = (self.kernel_size_fraction * width as f64) as usize * 2 + 1;
         let half_kernel_size = (kernel_size / 2) as i32;
 
@@ -149,9 +144,21 @@ impl PostProcessor for BloomPostProcessor {
             .collect())
     }
 
-    fn needs_albedo_and_normal_colors(&self) -> bool {
+    fn wants_aovs(&self) -> bool {
         false
     }
+
+    fn supports_intermediate(&self) -> bool {
+        true
+    }
+
+    fn wants_hdr(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "Bloom"
+    }
 }
 
 fn get_pixel_safe(pixel_colors: &[Vec3], x: i32, y: i32, width: u32, height: u32) -> Vec3 {
@@ -0,0 +1,205 @@
+use std::error::Error;
+
+use image::RgbImage;
+
+use crate::geo::vec3::Vec3;
+use crate::material::texture::hash_to_unit;
+use crate::post::{pixel_colors_to_rgb_image, PostProcessor, PostProcessors};
+
+#[derive(Clone)]
+/// Adds the grain of photographic film to the image, for matching rendered shots to filmed
+/// footage. The grain is the same every time for the same seed, so give each frame of an
+/// animation its own seed for the grain to move like on film. The grain scales the colors,
+/// so it is most visible in the bright parts. Add it after tone mapping, as the last
+/// post processor, so that it is not compressed or removed by other post processors
+pub struct FilmGrainPostProcessor {
+    size: f64,
+    intensity: f64,
+    chromaticity: f64,
+    seed: u32,
+}
+
+impl FilmGrainPostProcessor {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new film grain post processor
+    /// # Arguments
+    /// * `size` Size of the grains in pixels, at least one
+    /// * `intensity` Standard deviation of the relative change of the colors by the grain
+    /// * `chromaticity` Between zero and one, where zero is grain of the same brightness
+    ///   in all color channels, like black and white film, and one is grain that is
+    ///   independent in each channel, like the dye clouds of color film
+    /// * `seed` Chooses the pattern of the grain
+    pub fn new(size: f64, intensity: f64, chromaticity: f64, seed: u32) -> PostProcessors {
+        PostProcessors::from(FilmGrainPostProcessor {
+            size: size.max(1.),
+            intensity: intensity.max(0.),
+            chromaticity: chromaticity.clamp(0., 1.),
+            seed,
+        })
+    }
+
+    /// Grain at the pixel for the channel, with zero mean and unit standard deviation.
+    /// Random values on a grid with the spacing of the grain size are blended smoothly
+    fn grain(&self, x: u32, y: u32, channel: u32) -> f64 {
+        let gx = (x as f64 + 0.5) / self.size - 0.5;
+        let gy = (y as f64 + 0.5) / self.size - 0.5;
+        let (x0, y0) = (gx.floor(), gy.floor());
+        let smooth = |t: f64| t * t * (3. - 2. * t);
+        let (tx, ty) = (smooth(gx - x0), smooth(gy - y0));
+
+        let mut value = 0.;
+        let mut squared_weights = 0.;
+        for (dx, dy, weight) in [
+            (0, 0, (1. - tx) * (1. - ty)),
+            (1, 0, tx * (1. - ty)),
+            (0, 1, (1. - tx) * ty),
+            (1, 1, tx * ty),
+        ] {
+            value += self.grid_value(x0 as i32 + dx, y0 as i32 + dy, channel) * weight;
+            squared_weights += weight * weight;
+        }
+        // Blending lowers the variance, which is restored to keep the intensity
+        value / squared_weights.sqrt()
+    }
+
+    /// A random value for the grid point, with zero mean and unit standard deviation
+    fn grid_value(&self, x: i32, y: i32, channel: u32) -> f64 {
+        let hash = |i: u32| {
+            hash_to_unit(
+                (x as u32).wrapping_mul(0x8da6b343)
+                    ^ (y as u32).wrapping_mul(0xd8163841)
+                    ^ self.seed.wrapping_mul(0xcb1ab31f)
+                    ^ (channel * 3 + i).wrapping_mul(0x9e3779b9),
+            )
+        };
+        // The sum of three uniform values is close to a normal distribution
+        (hash(0) + hash(1) + hash(2) - 1.5) * 2.
+    }
+}
+
+impl PostProcessor for FilmGrainPostProcessor {
+    fn post_process(
+        &self,
+        pixel_colors: &[Vec3],
+        albedo_colors: &[Vec3],
+        normal_colors: &[Vec3],
+        standard_errors: &[f64],
+        width: u32,
+        height: u32,
+    ) -> Result<RgbImage, Box<dyn Error>> {
+        let pixel_colors = self.intermediate_post_process(
+            pixel_colors,
+            albedo_colors,
+            normal_colors,
+            standard_errors,
+            width,
+            height,
+        )?;
+        Ok(pixel_colors_to_rgb_image(&pixel_colors, width, height))
+    }
+
+    fn intermediate_post_process(
+        &self,
+        pixel_colors: &[Vec3],
+        _albedo_colors: &[Vec3],
+        _normal_colors: &[Vec3],
+        _standard_errors: &[f64],
+        width: u32,
+        _height: u32,
+    ) -> Result<Vec<Vec3>, Box<dyn Error>> {
+        let c = self.chromaticity;
+        let normalization = ((1. - c) * (1. - c) + c * c).sqrt();
+        Ok(pixel_colors
+            .iter()
+            .enumerate()
+            .map(|(i, color)| {
+                let (x, y) = (i as u32 % width, i as u32 / width);
+                let monochrome = self.grain(x, y, 0);
+                let scale = |channel: u32| {
+                    let grain =
+                        ((1. - c) * monochrome + c * self.grain(x, y, channel + 1)) / normalization;
+                    (1. + self.intensity * grain).max(0.)
+                };
+                Vec3::new(color.x * scale(0), color.y * scale(1), color.z * scale(2))
+            })
+            .collect())
+    }
+
+    fn wants_aovs(&self) -> bool {
+        false
+    }
+
+    fn supports_intermediate(&self) -> bool {
+        true
+    }
+
+    fn wants_hdr(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "Film grain"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn film_grain(size: f64, intensity: f64, chromaticity: f64, seed: u32) -> Vec<Vec3> {
+        let grey = vec![Vec3::new(0.5, 0.5, 0.5); 64 * 64];
+        FilmGrainPostProcessor::new(size, intensity, chromaticity, seed)
+            .intermediate_post_process(&grey, &[], &[], &[], 64, 64)
+            .unwrap()
+    }
+
+    fn mean_and_deviation(values: impl Iterator<Item = f64> + Clone) -> (f64, f64) {
+        let count = values.clone().count() as f64;
+        let mean = values.clone().sum::<f64>() / count;
+        let variance = values.map(|v| (v - mean) * (v - mean)).sum::<f64>() / count;
+        (mean, variance.sqrt())
+    }
+
+    #[test]
+    fn test_intensity() {
+        assert!(film_grain(1., 0., 1., 0)
+            .iter()
+            .all(|c| *c == Vec3::new(0.5, 0.5, 0.5)));
+
+        for size in [1., 3.] {
+            let (mean, deviation) =
+                mean_and_deviation(film_grain(size, 0.1, 0., 0).iter().map(|c| c.x));
+            assert!((mean - 0.5).abs() < 0.01, "mean was {}", mean);
+            assert!(
+                (deviation - 0.05).abs() < 0.01,
+                "deviation was {}",
+                deviation
+            );
+        }
+    }
+
+    #[test]
+    fn test_seed_and_chromaticity() {
+        assert_eq!(film_grain(2., 0.1, 0.5, 1), film_grain(2., 0.1, 0.5, 1));
+        assert_ne!(film_grain(2., 0.1, 0.5, 1), film_grain(2., 0.1, 0.5, 2));
+
+        // Monochrome grain keeps greys grey
+        assert!(film_grain(2., 0.1, 0., 1)
+            .iter()
+            .all(|c| c.x == c.y && c.y == c.z));
+        assert!(film_grain(2., 0.1, 1., 1).iter().any(|c| c.x != c.y));
+    }
+
+    #[test]
+    fn test_size() {
+        // Larger grains make neighbouring pixels more alike
+        let neighbour_difference = |size| {
+            let grain = film_grain(size, 0.1, 0., 0);
+            grain
+                .windows(2)
+                .map(|w| (w[0].x - w[1].x).abs())
+                .sum::<f64>()
+        };
+        assert!(neighbour_difference(4.) < neighbour_difference(1.) * 0.5);
+    }
+}
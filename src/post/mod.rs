@@ -1,8 +1,11 @@
 //! Post processors for applying effects to the raw rendered image
 
 mod bloom;
+mod film_grain;
 mod nop;
 mod oidn;
+mod region;
+mod tone_map;
 
 use std::error::Error;
 
@@ -10,10 +13,15 @@ use enum_dispatch::enum_dispatch;
 
 use crate::geo::vec3::Vec3;
 pub use crate::post::bloom::BloomPostProcessor;
+pub use crate::post::film_grain::FilmGrainPostProcessor;
 pub use crate::post::nop::NopPostProcessor;
 pub use crate::post::oidn::OidnPostProcessor;
+pub use crate::post::region::RegionOfInterestPostProcessor;
+pub use crate::post::tone_map::{ToneMapOperator, ToneMapPostProcessor};
 
-/// Responsible for taking the rendered image and transforming it
+/// Responsible for taking the rendered image and transforming it.
+/// The colors are the mean of all samples taken so far, so they do not depend on the sample count.
+/// The standard errors are per pixel estimates of how noisy the luminance of the colors still is
 #[enum_dispatch]
 pub trait PostProcessor {
     /// Execute final postprocessing of the rendered image
@@ -22,9 +30,9 @@ pub trait PostProcessor {
         pixel_colors: &[Vec3],
         albedo_colors: &[Vec3],
         normal_colors: &[Vec3],
+        standard_errors: &[f64],
         width: u32,
         height: u32,
-        num_samples: u32,
     ) -> Result<image::RgbImage, Box<dyn Error>>;
 
     /// Execute intermediate postprocessing of the rendered image
@@ -33,13 +41,23 @@ pub trait PostProcessor {
         pixel_colors: &[Vec3],
         albedo_colors: &[Vec3],
         normal_colors: &[Vec3],
+        standard_errors: &[f64],
         width: u32,
         height: u32,
-        num_samples: u32,
     ) -> Result<Vec<Vec3>, Box<dyn Error>>;
 
-    /// Does this post-processor need albedo or normal colors
-    fn needs_albedo_and_normal_colors(&self) -> bool;
+    /// Does this post-processor need albedo and normal colors in addition to the pixel colors
+    fn wants_aovs(&self) -> bool;
+
+    /// Can this post-processor be followed by other post-processors
+    fn supports_intermediate(&self) -> bool;
+
+    /// Does this post-processor handle high dynamic range colors,
+    /// otherwise the colors are clamped between 0 and 1 before being passed to it
+    fn wants_hdr(&self) -> bool;
+
+    /// Name of the post-processor, as shown in error messages
+    fn name(&self) -> &'static str;
 }
 
 #[enum_dispatch(PostProcessor)]
@@ -52,24 +70,21 @@ pub enum PostProcessors {
     BloomPostProcessorType(BloomPostProcessor),
     /// [`PostProcessor`] of type [`NopPostProcessor`]
     NopPostProcessorType(NopPostProcessor),
+    /// [`PostProcessor`] of type [`RegionOfInterestPostProcessor`]
+    RegionOfInterestPostProcessorType(RegionOfInterestPostProcessor),
+    /// [`PostProcessor`] of type [`ToneMapPostProcessor`]
+    ToneMapPostProcessorType(ToneMapPostProcessor),
+    /// [`PostProcessor`] of type [`FilmGrainPostProcessor`]
+    FilmGrainPostProcessorType(FilmGrainPostProcessor),
 }
 
-fn pixel_colors_to_rgb_image(
-    pixel_colors: &[Vec3],
-    width: u32,
-    height: u32,
-    num_samples: u32,
-) -> image::RgbImage {
+fn pixel_colors_to_rgb_image(pixel_colors: &[Vec3], width: u32, height: u32) -> image::RgbImage {
     let mut img: image::RgbImage = image::ImageBuffer::new(width, height);
 
     for y in 0..height {
         for x in 0..width {
             let i = (y * width + x) as usize;
-            img.put_pixel(
-                x,
-                y,
-                crate::util::rgb_color::to_rgb_color(pixel_colors[i], num_samples),
-            )
+            img.put_pixel(x, y, crate::util::rgb_color::to_rgb_color(pixel_colors[i]))
         }
     }
 
@@ -21,16 +21,11 @@ impl PostProcessor for NopPostProcessor {
         pixel_colors: &[Vec3],
         _albedo_colors: &[Vec3],
         _normal_colors: &[Vec3],
+        _standard_errors: &[f64],
         width: u32,
         height: u32,
-        num_samples: u32,
     ) -> Result<RgbImage, Box<dyn Error>> {
-        Ok(pixel_colors_to_rgb_image(
-            pixel_colors,
-            width,
-            height,
-            num_samples,
-        ))
+        Ok(pixel_colors_to_rgb_image(pixel_colors, width, height))
     }
 
     fn intermediate_post_process(
@@ -38,14 +33,26 @@ impl PostProcessor for NopPostProcessor {
         pixel_colors: &[Vec3],
         _albedo_colors: &[Vec3],
         _normal_colors: &[Vec3],
+        _standard_errors: &[f64],
         _width: u32,
         _height: u32,
-        _num_samples: u32,
     ) -> Result<Vec<Vec3>, Box<dyn Error>> {
         Ok(Vec::from(pixel_colors))
     }
 
-    fn needs_albedo_and_normal_colors(&self) -> bool {
+    fn wants_aovs(&self) -> bool {
         false
     }
+
+    fn supports_intermediate(&self) -> bool {
+        true
+    }
+
+    fn wants_hdr(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "Nop"
+    }
 }
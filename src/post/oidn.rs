@@ -21,13 +21,13 @@ impl PostProcessor for OidnPostProcessor {
         pixel_colors: &[Vec3],
         albedo_colors: &[Vec3],
         normal_colors: &[Vec3],
+        _standard_errors: &[f64],
         width: u32,
         height: u32,
-        num_samples: u32,
     ) -> Result<image::RgbImage, Box<dyn Error>> {
-        let pixel_rgb = to_rgb_vec(pixel_colors, num_samples);
-        let albedo_rgb = to_rgb_vec(albedo_colors, num_samples);
-        let normal_rgb = to_rgb_vec(normal_colors, num_samples);
+        let pixel_rgb = to_rgb_vec(pixel_colors);
+        let albedo_rgb = to_rgb_vec(albedo_colors);
+        let normal_rgb = to_rgb_vec(normal_colors);
         let mut output = vec![0.0f32; pixel_rgb.len()];
 
         let device = oidn::Device::new();
@@ -68,18 +68,30 @@ impl PostProcessor for OidnPostProcessor {
         _pixel_colors: &[Vec3],
         _albedo_colors: &[Vec3],
         _normal_colors: &[Vec3],
+        _standard_errors: &[f64],
         _width: u32,
         _height: u32,
-        _num_samples: u32,
     ) -> Result<Vec<Vec3>, Box<dyn Error>> {
         Err(Box::new(simple_error::SimpleError::new(
             "Intel Open Image DeNoise can not be used as an intermediate post processor",
         )))
     }
 
-    fn needs_albedo_and_normal_colors(&self) -> bool {
+    fn wants_aovs(&self) -> bool {
         true
     }
+
+    fn supports_intermediate(&self) -> bool {
+        false
+    }
+
+    fn wants_hdr(&self) -> bool {
+        false
+    }
+
+    fn name(&self) -> &'static str {
+        "OIDN"
+    }
 }
 
 #[cfg(not(feature = "oidn-postprocessor"))]
@@ -89,17 +101,17 @@ impl PostProcessor for OidnPostProcessor {
         pixel_colors: &[Vec3],
         albedo_colors: &[Vec3],
         normal_colors: &[Vec3],
+        standard_errors: &[f64],
         width: u32,
         height: u32,
-        num_samples: u32,
     ) -> Result<image::RgbImage, Box<dyn Error>> {
         crate::post::nop::NopPostProcessor::new().post_process(
             pixel_colors,
             albedo_colors,
             normal_colors,
+            standard_errors,
             width,
             height,
-            num_samples,
         )
     }
 
@@ -108,30 +120,43 @@ impl PostProcessor for OidnPostProcessor {
         pixel_colors: &[Vec3],
         albedo_colors: &[Vec3],
         normal_colors: &[Vec3],
+        standard_errors: &[f64],
         width: u32,
         height: u32,
-        num_samples: u32,
     ) -> Result<Vec<Vec3>, Box<dyn Error>> {
         crate::post::nop::NopPostProcessor::new().intermediate_post_process(
             pixel_colors,
             albedo_colors,
             normal_colors,
+            standard_errors,
             width,
             height,
-            num_samples,
         )
     }
 
-    fn needs_albedo_and_normal_colors(&self) -> bool {
+    fn wants_aovs(&self) -> bool {
         false
     }
+
+    // Same as when the feature is enabled, so the scene is valid either way
+    fn supports_intermediate(&self) -> bool {
+        false
+    }
+
+    fn wants_hdr(&self) -> bool {
+        false
+    }
+
+    fn name(&self) -> &'static str {
+        "OIDN"
+    }
 }
 
 #[cfg(feature = "oidn-postprocessor")]
-fn to_rgb_vec(vec: &[Vec3], num_samples: u32) -> Vec<f32> {
+fn to_rgb_vec(vec: &[Vec3]) -> Vec<f32> {
     vec.iter()
         .flat_map(|v| {
-            let c = crate::util::rgb_color::to_float(*v, num_samples);
+            let c = crate::util::rgb_color::to_float(*v);
             vec![c.x as f32, c.y as f32, c.z as f32]
         })
         .collect()
@@ -0,0 +1,281 @@
+use std::error::Error;
+
+use image::RgbImage;
+
+use crate::geo::vec3::Vec3;
+use crate::post::{pixel_colors_to_rgb_image, PostProcessor, PostProcessors};
+
+#[derive(Clone)]
+/// Applies another post processor only on the tiles of the image that are still noisy,
+/// which saves time when denoising mostly converged images. Only denoisers can be applied,
+/// as the colors of the converged tiles are kept, so post processors that change the colors
+/// of the whole image, like tone mapping and bloom, would leave seams between the tiles
+pub struct RegionOfInterestPostProcessor {
+    post_processor: Box<PostProcessors>,
+    tile_size: u32,
+    max_standard_error: f64,
+}
+
+impl RegionOfInterestPostProcessor {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new region of interest post processor
+    /// # Arguments
+    /// * `post_processor` The denoiser to apply on the noisy tiles
+    /// * `tile_size` Width and height in pixels of the tiles
+    /// * `max_standard_error` Tiles where any pixel has a higher standard error are noisy
+    pub fn new(
+        post_processor: PostProcessors,
+        tile_size: u32,
+        max_standard_error: f64,
+    ) -> Result<PostProcessors, simple_error::SimpleError> {
+        if tile_size == 0 {
+            return Err(simple_error::SimpleError::new(
+                "tile_size must be greater than 0",
+            ));
+        }
+        match post_processor {
+            PostProcessors::OidnPostProcessorType(_)
+            | PostProcessors::NopPostProcessorType(_)
+            | PostProcessors::RegionOfInterestPostProcessorType(_) => {}
+            PostProcessors::BloomPostProcessorType(_)
+            | PostProcessors::ToneMapPostProcessorType(_)
+            | PostProcessors::FilmGrainPostProcessorType(_) => {
+                return Err(simple_error::SimpleError::new(
+                    "only denoisers can be applied to regions of the image, \
+                    other post processors would leave seams between the tiles",
+                ))
+            }
+        }
+
+        Ok(PostProcessors::from(RegionOfInterestPostProcessor {
+            post_processor: Box::new(post_processor),
+            tile_size,
+            max_standard_error,
+        }))
+    }
+
+    fn noisy_tiles(&self, standard_errors: &[f64], width: u32, height: u32) -> Vec<Region> {
+        let mut tiles = Vec::new();
+        for y in (0..height).step_by(self.tile_size as usize) {
+            for x in (0..width).step_by(self.tile_size as usize) {
+                let tile = Region {
+                    x,
+                    y,
+                    width: self.tile_size.min(width - x),
+                    height: self.tile_size.min(height - y),
+                };
+                let noisy = standard_errors.is_empty()
+                    || crop(standard_errors, width, &tile)
+                        .iter()
+                        .any(|e| *e > self.max_standard_error);
+                if noisy {
+                    tiles.push(tile);
+                }
+            }
+        }
+        tiles
+    }
+
+    /// The tile with a border of surrounding pixels,
+    /// so the post processor has some context at the edges of the tile
+    fn padded(&self, tile: &Region, width: u32, height: u32) -> Region {
+        let padding = self.tile_size / 2;
+        let x = tile.x.saturating_sub(padding);
+        let y = tile.y.saturating_sub(padding);
+        Region {
+            x,
+            y,
+            width: (tile.x + tile.width + padding).min(width) - x,
+            height: (tile.y + tile.height + padding).min(height) - y,
+        }
+    }
+}
+
+impl PostProcessor for RegionOfInterestPostProcessor {
+    fn post_process(
+        &self,
+        pixel_colors: &[Vec3],
+        albedo_colors: &[Vec3],
+        normal_colors: &[Vec3],
+        standard_errors: &[f64],
+        width: u32,
+        height: u32,
+    ) -> Result<RgbImage, Box<dyn Error>> {
+        let mut img = pixel_colors_to_rgb_image(pixel_colors, width, height);
+
+        for tile in self.noisy_tiles(standard_errors, width, height) {
+            let padded = self.padded(&tile, width, height);
+            let tile_img = self.post_processor.post_process(
+                &crop(pixel_colors, width, &padded),
+                &crop(albedo_colors, width, &padded),
+                &crop(normal_colors, width, &padded),
+                &crop(standard_errors, width, &padded),
+                padded.width,
+                padded.height,
+            )?;
+            for y in tile.y..tile.y + tile.height {
+                for x in tile.x..tile.x + tile.width {
+                    img.put_pixel(x, y, *tile_img.get_pixel(x - padded.x, y - padded.y));
+                }
+            }
+        }
+
+        Ok(img)
+    }
+
+    fn intermediate_post_process(
+        &self,
+        pixel_colors: &[Vec3],
+        albedo_colors: &[Vec3],
+        normal_colors: &[Vec3],
+        standard_errors: &[f64],
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<Vec3>, Box<dyn Error>> {
+        let mut colors = pixel_colors.to_vec();
+
+        for tile in self.noisy_tiles(standard_errors, width, height) {
+            let padded = self.padded(&tile, width, height);
+            let tile_colors = self.post_processor.intermediate_post_process(
+                &crop(pixel_colors, width, &padded),
+                &crop(albedo_colors, width, &padded),
+                &crop(normal_colors, width, &padded),
+                &crop(standard_errors, width, &padded),
+                padded.width,
+                padded.height,
+            )?;
+            for y in tile.y..tile.y + tile.height {
+                for x in tile.x..tile.x + tile.width {
+                    let padded_i = ((y - padded.y) * padded.width + x - padded.x) as usize;
+                    colors[(y * width + x) as usize] = tile_colors[padded_i];
+                }
+            }
+        }
+
+        Ok(colors)
+    }
+
+    fn wants_aovs(&self) -> bool {
+        self.post_processor.wants_aovs()
+    }
+
+    fn supports_intermediate(&self) -> bool {
+        self.post_processor.supports_intermediate()
+    }
+
+    fn wants_hdr(&self) -> bool {
+        self.post_processor.wants_hdr()
+    }
+
+    fn name(&self) -> &'static str {
+        self.post_processor.name()
+    }
+}
+
+/// A rectangular part of the image
+struct Region {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Copies the values of the region from an image with the given width.
+/// Empty values stays empty, as post processors only get the buffers they need
+fn crop<T: Copy>(values: &[T], width: u32, region: &Region) -> Vec<T> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let mut cropped = Vec::with_capacity((region.width * region.height) as usize);
+    for y in region.y..region.y + region.height {
+        let start = (y * width + region.x) as usize;
+        cropped.extend_from_slice(&values[start..start + region.width as usize]);
+    }
+    cropped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::post::{BloomPostProcessor, NopPostProcessor, OidnPostProcessor};
+    use crate::post::{FilmGrainPostProcessor, ToneMapOperator, ToneMapPostProcessor};
+
+    use super::*;
+
+    const WIDTH: u32 = 20;
+    const HEIGHT: u32 = 12;
+
+    fn pixel_colors() -> Vec<Vec3> {
+        (0..WIDTH * HEIGHT)
+            .map(|i| {
+                if i % 7 == 0 {
+                    Vec3::new(5., 5., 5.)
+                } else {
+                    Vec3::new(0.1, 0.2, 0.3)
+                }
+            })
+            .collect()
+    }
+
+    fn process(standard_errors: &[f64]) -> Vec<Vec3> {
+        // Bloom is not accepted by new, but shows clearly which pixels are processed
+        let bloom = BloomPostProcessor::new(0.1, None, None).unwrap();
+        RegionOfInterestPostProcessor {
+            post_processor: Box::new(bloom),
+            tile_size: 8,
+            max_standard_error: 0.1,
+        }
+        .intermediate_post_process(&pixel_colors(), &[], &[], standard_errors, WIDTH, HEIGHT)
+        .unwrap()
+    }
+
+    #[test]
+    fn test_only_noisy_tiles_are_processed() {
+        // Only the pixel at (10, 9) is noisy, which is in the tile from (8, 8) to (15, 11)
+        let mut standard_errors = vec![0.; (WIDTH * HEIGHT) as usize];
+        standard_errors[(9 * WIDTH + 10) as usize] = 1.;
+
+        let res = process(&standard_errors);
+        let original = pixel_colors();
+
+        let mut changed_in_tile = false;
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let i = (y * WIDTH + x) as usize;
+                if (8..16).contains(&x) && y >= 8 {
+                    changed_in_tile |= res[i] != original[i];
+                } else {
+                    assert_eq!(res[i], original[i], "Pixel ({}, {})", x, y);
+                }
+            }
+        }
+        assert!(changed_in_tile);
+    }
+
+    #[test]
+    fn test_converged_image_is_unchanged() {
+        let standard_errors = vec![0.; (WIDTH * HEIGHT) as usize];
+        assert_eq!(process(&standard_errors), pixel_colors());
+    }
+
+    #[test]
+    fn test_zero_tile_size() {
+        let res = RegionOfInterestPostProcessor::new(OidnPostProcessor::new(), 0, 0.1);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_only_denoisers_are_accepted() {
+        assert!(RegionOfInterestPostProcessor::new(OidnPostProcessor::new(), 8, 0.1).is_ok());
+        assert!(RegionOfInterestPostProcessor::new(NopPostProcessor::new(), 8, 0.1).is_ok());
+
+        let seamed = [
+            BloomPostProcessor::new(0.1, None, None).unwrap(),
+            ToneMapPostProcessor::new(ToneMapOperator::Reinhard, 0.),
+            FilmGrainPostProcessor::new(1., 0.1, 0., 0),
+        ];
+        for post_processor in seamed {
+            let res = RegionOfInterestPostProcessor::new(post_processor, 8, 0.1);
+            assert!(res.is_err_and(|e| e.as_str().contains("only denoisers")));
+        }
+    }
+}
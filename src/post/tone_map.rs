@@ -0,0 +1,155 @@
+use std::error::Error;
+
+use image::RgbImage;
+
+use crate::geo::vec3::Vec3;
+use crate::post::{pixel_colors_to_rgb_image, PostProcessor, PostProcessors};
+use crate::util::rgb_color::luminance;
+
+/// How high dynamic range colors are compressed into the range that can be displayed
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ToneMapOperator {
+    /// Colors brighter than white are clipped, which only makes sense together with exposure
+    Clamp,
+    /// Divides the colors by one plus their luminance, which keeps their hue
+    /// and never quite reaches white
+    #[default]
+    Reinhard,
+    /// Approximation of the filmic curve of the ACES reference rendering transform,
+    /// with more contrast than Reinhard and highlights that desaturate towards white
+    Aces,
+}
+
+impl ToneMapOperator {
+    fn map(&self, color: Vec3) -> Vec3 {
+        match self {
+            ToneMapOperator::Clamp => color,
+            ToneMapOperator::Reinhard => color / (1. + luminance(color).max(0.)),
+            ToneMapOperator::Aces => {
+                let curve = |x: f64| {
+                    let x = x.max(0.);
+                    (x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0., 1.)
+                };
+                Vec3::new(curve(color.x), curve(color.y), curve(color.z))
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+/// Compresses the high dynamic range colors of the image, so bright lights
+/// do not clip to white as harshly
+pub struct ToneMapPostProcessor {
+    operator: ToneMapOperator,
+    exposure_scale: f64,
+}
+
+impl ToneMapPostProcessor {
+    #![allow(clippy::new_ret_no_self)]
+    /// Create a new tone map post processor
+    /// # Arguments
+    /// * `operator` How the colors are compressed
+    /// * `exposure` Exposure adjustment in stops that is applied before the compression,
+    ///   where each stop doubles the brightness
+    pub fn new(operator: ToneMapOperator, exposure: f64) -> PostProcessors {
+        PostProcessors::from(ToneMapPostProcessor {
+            operator,
+            exposure_scale: exposure.exp2(),
+        })
+    }
+}
+
+impl PostProcessor for ToneMapPostProcessor {
+    fn post_process(
+        &self,
+        pixel_colors: &[Vec3],
+        albedo_colors: &[Vec3],
+        normal_colors: &[Vec3],
+        standard_errors: &[f64],
+        width: u32,
+        height: u32,
+    ) -> Result<RgbImage, Box<dyn Error>> {
+        let pixel_colors = self.intermediate_post_process(
+            pixel_colors,
+            albedo_colors,
+            normal_colors,
+            standard_errors,
+            width,
+            height,
+        )?;
+        Ok(pixel_colors_to_rgb_image(&pixel_colors, width, height))
+    }
+
+    fn intermediate_post_process(
+        &self,
+        pixel_colors: &[Vec3],
+        _albedo_colors: &[Vec3],
+        _normal_colors: &[Vec3],
+        _standard_errors: &[f64],
+        _width: u32,
+        _height: u32,
+    ) -> Result<Vec<Vec3>, Box<dyn Error>> {
+        Ok(pixel_colors
+            .iter()
+            .map(|c| self.operator.map(*c * self.exposure_scale))
+            .collect())
+    }
+
+    fn wants_aovs(&self) -> bool {
+        false
+    }
+
+    fn supports_intermediate(&self) -> bool {
+        true
+    }
+
+    fn wants_hdr(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "Tone mapping"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone_map(operator: ToneMapOperator, exposure: f64, color: Vec3) -> Vec3 {
+        ToneMapPostProcessor::new(operator, exposure)
+            .intermediate_post_process(&[color], &[], &[], &[], 1, 1)
+            .unwrap()[0]
+    }
+
+    #[test]
+    fn test_exposure() {
+        let color = Vec3::new(0.1, 0.2, 0.3);
+        let brighter = tone_map(ToneMapOperator::Clamp, 1., color);
+        assert_eq!(brighter, color * 2.);
+        let darker = tone_map(ToneMapOperator::Clamp, -2., color);
+        assert_eq!(darker, color * 0.25);
+    }
+
+    #[test]
+    fn test_bright_colors_are_compressed() {
+        for operator in [ToneMapOperator::Reinhard, ToneMapOperator::Aces] {
+            let dim = tone_map(operator, 0., Vec3::new(0.5, 0.5, 0.5));
+            let bright = tone_map(operator, 0., Vec3::new(10., 10., 10.));
+            let brighter = tone_map(operator, 0., Vec3::new(100., 100., 100.));
+            assert!(dim.x < bright.x && bright.x <= brighter.x, "{:?}", operator);
+            assert!(brighter.x <= 1., "{:?}", operator);
+        }
+        assert_eq!(
+            tone_map(ToneMapOperator::Aces, 0., Vec3::new(0., 0., 0.)),
+            Vec3::new(0., 0., 0.)
+        );
+    }
+
+    #[test]
+    fn test_reinhard_keeps_hue() {
+        let mapped = tone_map(ToneMapOperator::Reinhard, 0., Vec3::new(4., 2., 1.));
+        assert!((mapped.x / mapped.y - 2.).abs() < 1e-12);
+        assert!((mapped.y / mapped.z - 2.).abs() < 1e-12);
+    }
+}
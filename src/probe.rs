@@ -0,0 +1,180 @@
+//! Light probes, that store the light arriving at points in space from all directions
+//! as spherical harmonics. Engines can use them for lighting dynamic objects by light
+//! path traced in a static scene
+use std::error::Error;
+use std::f64::consts::PI;
+
+use rayon::prelude::*;
+use simple_error::SimpleError;
+
+use crate::geo::vec3::{random_unit_vector, Vec3, ZERO_VECTOR};
+use crate::renderer::{Renderer, Scene};
+use crate::sampler;
+
+/// Convolution of each band with the clamped cosine lobe, divided by pi
+const COSINE_LOBE: [f64; 3] = [1., 2. / 3., 1. / 4.];
+
+/// Spherical harmonics approximation of the light arriving at a point from all directions.
+/// Uses the real basis, ordered by band and then from -l to l, with the axes of the scene
+#[derive(Clone, Debug, PartialEq)]
+pub struct SphericalHarmonics {
+    /// Color coefficients of the basis functions, 4 for 2 bands and 9 for 3 bands
+    pub coefficients: Vec<Vec3>,
+}
+
+impl SphericalHarmonics {
+    /// Number of bands in the spherical harmonics
+    pub fn bands(&self) -> usize {
+        (self.coefficients.len() as f64).sqrt() as usize
+    }
+
+    /// Light arriving from the given direction
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        basis(direction.unit())
+            .iter()
+            .zip(&self.coefficients)
+            .fold(ZERO_VECTOR, |sum, (y, c)| sum + *c * *y)
+    }
+
+    /// Irradiance on a surface with the given normal, divided by pi like in
+    /// [`crate::bake::bake_irradiance`]
+    pub fn irradiance(&self, normal: Vec3) -> Vec3 {
+        basis(normal.unit())
+            .iter()
+            .zip(&self.coefficients)
+            .enumerate()
+            .fold(ZERO_VECTOR, |sum, (i, (y, c))| {
+                let band = (i as f64).sqrt() as usize;
+                sum + *c * (*y * COSINE_LOBE[band])
+            })
+    }
+}
+
+/// Path traces the light arriving at each of the positions in the scene, and projects it
+/// onto spherical harmonics with 2 or 3 bands. Each probe is sampled in the given number
+/// of uniformly distributed directions, which must be at least one
+pub fn bake_light_probes(
+    scene: Scene,
+    positions: &[Vec3],
+    bands: usize,
+    samples: u32,
+) -> Result<Vec<SphericalHarmonics>, Box<dyn Error>> {
+    if !(2..=3).contains(&bands) {
+        return Err(Box::new(SimpleError::new(format!(
+            "Light probes can have 2 or 3 bands, not {}",
+            bands
+        ))));
+    }
+    if samples == 0 {
+        return Err(Box::new(SimpleError::new(
+            "Light probes need at least one sample",
+        )));
+    }
+    let sampler = scene.render_config.sampler;
+    let renderer = Renderer::new(scene)?;
+
+    Ok(positions
+        .par_iter()
+        .enumerate()
+        .map(|(i, position)| {
+            let mut coefficients = vec![ZERO_VECTOR; bands * bands];
+            for sample in 0..samples {
+                sampler::start_pixel_sample(sampler, i, 0, sample, samples);
+                let direction = random_unit_vector();
+                let radiance = renderer.incident_radiance(*position, direction);
+                for (c, y) in coefficients.iter_mut().zip(basis(direction)) {
+                    *c += radiance * y;
+                }
+            }
+            // Monte Carlo estimate with the uniform sphere pdf
+            let weight = 4. * PI / samples as f64;
+            SphericalHarmonics {
+                coefficients: coefficients.into_iter().map(|c| c * weight).collect(),
+            }
+        })
+        .collect())
+}
+
+/// The real spherical harmonics basis functions of the first three bands
+fn basis(d: Vec3) -> [f64; 9] {
+    [
+        0.282095,
+        0.488603 * d.y,
+        0.488603 * d.z,
+        0.488603 * d.x,
+        1.092548 * d.x * d.y,
+        1.092548 * d.y * d.z,
+        0.315392 * (3. * d.z * d.z - 1.),
+        1.092548 * d.x * d.z,
+        0.546274 * (d.x * d.x - d.y * d.y),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use image::{Rgb, Rgb32FImage};
+
+    use crate::camera::CameraConfig;
+    use crate::geo::vec3::UNIT_Y;
+    use crate::hittable::{EnvironmentMap, Sphere};
+    use crate::material::texture::SolidColor;
+    use crate::material::Lambertian;
+    use crate::renderer::RenderConfig;
+
+    use super::*;
+
+    /// Scene lit by a sky above the horizon, and a small sphere far below
+    fn scene() -> Scene {
+        let image = Rgb32FImage::from_fn(8, 4, |_, y| match y {
+            0 | 1 => Rgb([1., 1., 1.]),
+            _ => Rgb([0., 0., 0.]),
+        });
+        Scene {
+            world: Sphere::new(
+                Vec3::new(0., -100., 0.),
+                1.,
+                Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            ),
+            camera: CameraConfig::default(),
+            views: HashMap::new(),
+            background_color: ZERO_VECTOR,
+            environment_map: Some(EnvironmentMap::new(image, 1.)),
+            background: None,
+            render_config: RenderConfig::default(),
+        }
+    }
+
+    #[test]
+    fn test_bake_light_probes() {
+        let probes = bake_light_probes(scene(), &[ZERO_VECTOR], 3, 20_000).unwrap();
+        assert_eq!(probes.len(), 1);
+        assert_eq!(probes[0].bands(), 3);
+
+        // A surface facing the sky gets all of its light
+        let up = probes[0].irradiance(UNIT_Y);
+        let down = probes[0].irradiance(UNIT_Y.neg());
+        assert!((up.x - 1.).abs() < 0.05, "irradiance up was {}", up);
+        assert!(down.x.abs() < 0.05, "irradiance down was {}", down);
+        assert!(probes[0].radiance(UNIT_Y).x > probes[0].radiance(UNIT_Y.neg()).x);
+    }
+
+    #[test]
+    fn test_invalid_bands() {
+        let res = bake_light_probes(scene(), &[ZERO_VECTOR], 4, 1);
+        assert_eq!(
+            format!("{}", res.err().unwrap()),
+            "Light probes can have 2 or 3 bands, not 4"
+        );
+    }
+
+    #[test]
+    fn test_no_samples() {
+        let res = bake_light_probes(scene(), &[ZERO_VECTOR], 3, 0);
+        assert_eq!(
+            format!("{}", res.err().unwrap()),
+            "Light probes need at least one sample"
+        );
+    }
+}
@@ -0,0 +1,451 @@
+//! Python bindings for building scenes and rendering them into numpy arrays.
+//!
+//! Enable the `python-extension` feature and build the crate as a `cdylib`
+//! (e.g. `cargo rustc --release --features python-extension --crate-type cdylib`).
+//! The resulting library, renamed to `solstrale.so` (`solstrale.pyd` on Windows),
+//! can then be imported from Python.
+//! The `python` feature alone links against the Python library instead,
+//! so the bindings can be tested with `cargo test --features python`.
+//!
+//! ```python
+//! import solstrale
+//!
+//! scene = solstrale.Scene()
+//! scene.set_camera(30., 0., (0., 1., 5.), (0., 0., 0.))
+//! scene.add_lambertian("yellow", (1., 1., 0.))
+//! scene.add_light("lamp", (10., 10., 10.))
+//! scene.add_sphere((0., 0., 0.), 0.5, "yellow")
+//! scene.add_sphere((0., 10., 0.), 3., "lamp")
+//!
+//! image = solstrale.render(
+//!     scene,
+//!     width=400,
+//!     height=200,
+//!     samples_per_pixel=50,
+//!     post_processors=[solstrale.PostProcessor.tone_map("aces")],
+//! )
+//! print(image.shape)  # (200, 400, 3)
+//! ```
+use std::error::Error;
+
+use numpy::ndarray::Array3;
+use numpy::IntoPyArray;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::camera::CameraConfig;
+use crate::geo::transformation::NopTransformer;
+use crate::geo::vec3::Vec3;
+use crate::hittable::{Quad, Sphere, Triangle};
+use crate::loader::scene::SceneBuilder;
+use crate::material::texture::{ImageMap, SolidColor};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Materials, Metal};
+use crate::post::{
+    BloomPostProcessor, FilmGrainPostProcessor, OidnPostProcessor, PostProcessors, ToneMapOperator,
+    ToneMapPostProcessor,
+};
+use crate::renderer::shader::PathTracingShader;
+use crate::renderer::{ImageFormat, ImageRegion, RenderConfig, RenderImage, RenderImageStrategy};
+use crate::sampler::Sampler;
+use crate::RenderProgressIter;
+
+type Tuple3 = (f64, f64, f64);
+
+fn vec3(t: Tuple3) -> Vec3 {
+    Vec3::new(t.0, t.1, t.2)
+}
+
+fn value_error(e: impl ToString) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+/// A scene under construction, materials are registered by name
+/// and then referred to when adding objects
+#[pyclass(name = "Scene")]
+#[derive(Default)]
+pub struct PyScene {
+    builder: SceneBuilder,
+}
+
+impl PyScene {
+    fn material(&self, name: &str) -> PyResult<Materials> {
+        self.builder
+            .material(name)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown material '{}'", name)))
+    }
+
+    /// Builds the scene and renders it with the given configuration,
+    /// returning the final image
+    fn render_image(&self, render_config: RenderConfig) -> Result<RenderImage, Box<dyn Error>> {
+        let mut builder = self.builder.clone();
+        builder.render_config = render_config;
+        let mut progress = RenderProgressIter::new(builder.build()?)?;
+        let image = progress.by_ref().filter_map(|p| p.render_image).last();
+        progress.join()?;
+        image.ok_or_else(|| "No image was rendered".into())
+    }
+}
+
+#[pymethods]
+impl PyScene {
+    /// Creates an empty scene
+    #[new]
+    fn new() -> Self {
+        PyScene::default()
+    }
+
+    /// Sets where the camera is located and where it is looking
+    #[pyo3(signature = (
+        vertical_fov_degrees,
+        aperture_size,
+        look_from,
+        look_at,
+        up = (0., 1., 0.),
+        focus_distance = None
+    ))]
+    fn set_camera(
+        &mut self,
+        vertical_fov_degrees: f64,
+        aperture_size: f64,
+        look_from: Tuple3,
+        look_at: Tuple3,
+        up: Tuple3,
+        focus_distance: Option<f64>,
+    ) {
+        self.builder.camera = CameraConfig {
+            vertical_fov_degrees,
+            aperture_size,
+            look_from: vec3(look_from),
+            look_at: vec3(look_at),
+            up: vec3(up),
+            focus_distance,
+        };
+    }
+
+    /// Sets the color of rays not hitting anything
+    fn set_background_color(&mut self, color: Tuple3) {
+        self.builder.background_color = vec3(color);
+    }
+
+    /// Registers a matte material with a solid color
+    fn add_lambertian(&mut self, name: &str, color: Tuple3) {
+        let material = Lambertian::new(SolidColor::new_from_vec3(vec3(color)), None);
+        self.builder.add_material(name, material);
+    }
+
+    /// Registers a matte material with a color from an image file
+    fn add_lambertian_image(&mut self, name: &str, image_path: &str) -> PyResult<()> {
+        let texture = ImageMap::load_with_filter(image_path, self.builder.texture_filter)
+            .map_err(value_error)?;
+        self.builder
+            .add_material(name, Lambertian::new(texture, None));
+        Ok(())
+    }
+
+    /// Registers a reflective material
+    fn add_metal(&mut self, name: &str, color: Tuple3, fuzz: f64) {
+        let material = Metal::new(SolidColor::new_from_vec3(vec3(color)), None, fuzz);
+        self.builder.add_material(name, material);
+    }
+
+    /// Registers a glass type material
+    fn add_dielectric(&mut self, name: &str, color: Tuple3, index_of_refraction: f64) {
+        let material = Dielectric::new(
+            SolidColor::new_from_vec3(vec3(color)),
+            None,
+            index_of_refraction,
+        );
+        self.builder.add_material(name, material);
+    }
+
+    /// Registers a light emitting material
+    #[pyo3(signature = (name, color, attenuation_half_length = None))]
+    fn add_light(&mut self, name: &str, color: Tuple3, attenuation_half_length: Option<f64>) {
+        let material = DiffuseLight::new(color.0, color.1, color.2, attenuation_half_length);
+        self.builder.add_material(name, material);
+    }
+
+    /// Adds a sphere with a previously registered material
+    fn add_sphere(&mut self, center: Tuple3, radius: f64, material: &str) -> PyResult<()> {
+        let material = self.material(material)?;
+        self.builder
+            .add(Sphere::new(vec3(center), radius, material));
+        Ok(())
+    }
+
+    /// Adds a flat rectangle with a previously registered material
+    fn add_quad(&mut self, q: Tuple3, u: Tuple3, v: Tuple3, material: &str) -> PyResult<()> {
+        let material = self.material(material)?;
+        self.builder.add(Quad::new(
+            vec3(q),
+            vec3(u),
+            vec3(v),
+            material,
+            &NopTransformer(),
+        ));
+        Ok(())
+    }
+
+    /// Adds a box between the two corners with a previously registered material
+    fn add_box(&mut self, a: Tuple3, b: Tuple3, material: &str) -> PyResult<()> {
+        let material = self.material(material)?;
+        for side in Quad::new_box(vec3(a), vec3(b), material, &NopTransformer()) {
+            self.builder.add(side);
+        }
+        Ok(())
+    }
+
+    /// Adds a triangle with a previously registered material
+    fn add_triangle(&mut self, v0: Tuple3, v1: Tuple3, v2: Tuple3, material: &str) -> PyResult<()> {
+        let material = self.material(material)?;
+        self.builder.add(Triangle::new(
+            vec3(v0),
+            vec3(v1),
+            vec3(v2),
+            material,
+            &NopTransformer(),
+        ));
+        Ok(())
+    }
+
+    /// Adds an obj model, using the given registered material where the model has none.
+    /// The model is loaded when rendering
+    #[pyo3(signature = (path, filename, default_material = None))]
+    fn add_obj(
+        &mut self,
+        path: &str,
+        filename: &str,
+        default_material: Option<&str>,
+    ) -> PyResult<()> {
+        let default_material = default_material.map(|m| self.material(m)).transpose()?;
+        self.builder.add_obj(path, filename, default_material);
+        Ok(())
+    }
+}
+
+/// A post processor of the rendered image, given to render
+#[pyclass(name = "PostProcessor", from_py_object)]
+#[derive(Clone)]
+pub struct PyPostProcessor(PostProcessors);
+
+#[pymethods]
+impl PyPostProcessor {
+    /// Removes the noise with Intel Open Image Denoise,
+    /// when built with the `oidn-postprocessor` feature
+    #[staticmethod]
+    fn denoise() -> Self {
+        PyPostProcessor(OidnPostProcessor::new())
+    }
+
+    /// Lets the bright parts of the image bleed light into their surroundings
+    #[staticmethod]
+    #[pyo3(signature = (kernel_size_fraction, threshold = None, max_intensity = None))]
+    fn bloom(
+        kernel_size_fraction: f64,
+        threshold: Option<f64>,
+        max_intensity: Option<f64>,
+    ) -> PyResult<Self> {
+        BloomPostProcessor::new(kernel_size_fraction, threshold, max_intensity)
+            .map(PyPostProcessor)
+            .map_err(value_error)
+    }
+
+    /// Maps colors brighter than white to the display, with the operator
+    /// "clamp", "reinhard" or "aces", after scaling them by two to the power of the exposure
+    #[staticmethod]
+    #[pyo3(signature = (operator = "reinhard", exposure = 0.))]
+    fn tone_map(operator: &str, exposure: f64) -> PyResult<Self> {
+        let operator = match operator {
+            "clamp" => ToneMapOperator::Clamp,
+            "reinhard" => ToneMapOperator::Reinhard,
+            "aces" => ToneMapOperator::Aces,
+            _ => {
+                return Err(value_error(format!(
+                    "Unknown tone map operator '{}'",
+                    operator
+                )))
+            }
+        };
+        Ok(PyPostProcessor(ToneMapPostProcessor::new(
+            operator, exposure,
+        )))
+    }
+
+    /// Adds the grain of photographic film
+    #[staticmethod]
+    #[pyo3(signature = (size, intensity, chromaticity = 0.5, seed = 0))]
+    fn film_grain(size: f64, intensity: f64, chromaticity: f64, seed: u32) -> Self {
+        PyPostProcessor(FilmGrainPostProcessor::new(
+            size,
+            intensity,
+            chromaticity,
+            seed,
+        ))
+    }
+}
+
+fn sampler(name: &str) -> PyResult<Sampler> {
+    match name {
+        "random" => Ok(Sampler::Random),
+        "stratified" => Ok(Sampler::Stratified),
+        _ => Err(value_error(format!("Unknown sampler '{}'", name))),
+    }
+}
+
+fn image_format(name: &str) -> PyResult<ImageFormat> {
+    match name {
+        "rgb8" => Ok(ImageFormat::Rgb8),
+        "rgb32f" => Ok(ImageFormat::Rgb32F),
+        _ => Err(value_error(format!("Unknown image format '{}'", name))),
+    }
+}
+
+/// Renders the scene and returns the final image as a numpy array with the shape
+/// (height, width, 3), of the region if one is given as (x, y, width, height).
+/// The image format "rgb8" gives gamma corrected 8-bit RGB values,
+/// and "rgb32f" linear 32-bit float values.
+/// The post processors are applied in the given order, and the sampler
+/// is either "stratified" or "random". The GIL is released while rendering.
+#[pyfunction]
+#[pyo3(signature = (
+    scene,
+    width = 300,
+    height = 200,
+    samples_per_pixel = 50,
+    max_depth = 50,
+    post_processors = Vec::new(),
+    sampler = "stratified",
+    image_format = "rgb8",
+    region = None
+))]
+#[allow(clippy::too_many_arguments)]
+fn render<'py>(
+    py: Python<'py>,
+    scene: &PyScene,
+    width: usize,
+    height: usize,
+    samples_per_pixel: u32,
+    max_depth: u32,
+    post_processors: Vec<PyPostProcessor>,
+    sampler: &str,
+    image_format: &str,
+    region: Option<(usize, usize, usize, usize)>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let render_config = RenderConfig {
+        width,
+        height,
+        samples_per_pixel,
+        shader: PathTracingShader::new(max_depth),
+        post_processors: post_processors.into_iter().map(|p| p.0).collect(),
+        sampler: self::sampler(sampler)?,
+        image_format: self::image_format(image_format)?,
+        region: region.map(|(x, y, width, height)| ImageRegion {
+            x,
+            y,
+            width,
+            height,
+        }),
+        render_image_strategy: RenderImageStrategy::OnlyFinal,
+        ..scene.builder.render_config.clone()
+    };
+
+    let image = py
+        .detach(|| scene.render_image(render_config).map_err(|e| e.to_string()))
+        .map_err(value_error)?;
+
+    let (width, height) = image.dimensions();
+    let shape = (height as usize, width as usize, 3);
+    let to_runtime_error = |e: numpy::ndarray::ShapeError| PyRuntimeError::new_err(e.to_string());
+    Ok(match image {
+        RenderImage::Rgb8(image) => Array3::from_shape_vec(shape, image.into_raw())
+            .map_err(to_runtime_error)?
+            .into_pyarray(py)
+            .into_any(),
+        RenderImage::Rgb32F(image) => Array3::from_shape_vec(shape, image.into_raw())
+            .map_err(to_runtime_error)?
+            .into_pyarray(py)
+            .into_any(),
+    })
+}
+
+/// The solstrale python module
+#[pymodule]
+fn solstrale(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyScene>()?;
+    m.add_class::<PyPostProcessor>()?;
+    m.add_function(wrap_pyfunction!(render, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene() -> PyScene {
+        let mut scene = PyScene::new();
+        scene.set_camera(30., 0., (0., 1., 5.), (0., 0., 0.), (0., 1., 0.), None);
+        scene.add_lambertian("yellow", (1., 1., 0.));
+        scene.add_light("lamp", (10., 10., 10.), None);
+        scene.add_sphere((0., 0., 0.), 0.5, "yellow").unwrap();
+        scene.add_sphere((0., 10., 0.), 3., "lamp").unwrap();
+        scene
+    }
+
+    fn render_config() -> RenderConfig {
+        RenderConfig {
+            width: 40,
+            height: 20,
+            samples_per_pixel: 5,
+            shader: PathTracingShader::new(10),
+            render_image_strategy: RenderImageStrategy::OnlyFinal,
+            ..RenderConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let image = match scene().render_image(render_config()).unwrap() {
+            RenderImage::Rgb8(image) => image,
+            RenderImage::Rgb32F(_) => panic!("Expected an 8-bit image"),
+        };
+        assert_eq!(image.dimensions(), (40, 20));
+        // The top of the yellow sphere in the middle of the image is lit by the lamp above,
+        // on the black background
+        let [r, g, b] = image.get_pixel(19, 7).0;
+        assert!(r > 0 && g > 0 && b == 0, "{:?}", (r, g, b));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_render_config() {
+        let render_config = RenderConfig {
+            post_processors: vec![PyPostProcessor::tone_map("aces", 1.).unwrap().0],
+            sampler: sampler("random").unwrap(),
+            image_format: image_format("rgb32f").unwrap(),
+            region: Some(ImageRegion {
+                x: 10,
+                y: 5,
+                width: 20,
+                height: 10,
+            }),
+            ..render_config()
+        };
+        match scene().render_image(render_config).unwrap() {
+            RenderImage::Rgb32F(image) => assert_eq!(image.dimensions(), (20, 10)),
+            RenderImage::Rgb8(_) => panic!("Expected a float image"),
+        }
+
+        assert!(sampler("sobol").is_err());
+        assert!(image_format("rgb16").is_err());
+        assert!(PyPostProcessor::tone_map("filmic", 0.).is_err());
+    }
+
+    #[test]
+    fn test_unknown_material() {
+        let mut scene = scene();
+        assert!(scene.add_sphere((0., 0., 0.), 1., "missing").is_err());
+        assert!(scene
+            .add_obj("resources/obj", "model.obj", Some("missing"))
+            .is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Geometric queries against the hittables of a scene, outside of rendering.
+//! Useful for host applications that implement tools like measuring and picking
+//! on top of the same [`crate::hittable::Bvh`] as is rendered
+use crate::camera::{Camera, CameraConfig};
+use crate::geo::vec3::Vec3;
+use crate::geo::Ray;
+use crate::hittable::{Hittable, Hittables};
+use crate::renderer::Scene;
+use crate::util::interval::Interval;
+
+/// Where a measurement ray hit a hittable
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Measurement {
+    /// Distance from the origin of the ray to the hit point
+    pub distance: f64,
+    /// The hit point
+    pub point: Vec3,
+    /// Geometric normal at the hit point, facing the origin of the ray
+    pub normal: Vec3,
+}
+
+/// Casts a ray from the origin in the given direction and returns the first hit, if any
+pub fn cast_ray(world: &Hittables, origin: Vec3, direction: Vec3) -> Option<Measurement> {
+    measure(world, &Ray::new(origin, direction), f64::INFINITY)
+}
+
+/// Returns the first hit on the line segment between the two points,
+/// or none if there is nothing in between them
+pub fn cast_ray_between(world: &Hittables, from: Vec3, to: Vec3) -> Option<Measurement> {
+    measure(world, &Ray::new(from, to - from), 1.)
+}
+
+/// Casts a ray from the camera of the scene through the center of the given pixel,
+/// where pixel 0, 0 is the top left corner of the rendered image. Depth of field is ignored
+pub fn cast_pixel_ray(scene: &Scene, x: usize, y: usize) -> Option<Measurement> {
+    let ray = pixel_camera(scene).get_pixel_ray(
+        x as f64 + 0.5,
+        y as f64 + 0.5,
+        scene.render_config.time,
+    );
+    measure(&scene.world, &ray, f64::INFINITY)
+}
+
+/// Projects a point onto the image rendered by the camera of the scene, where 0, 0 is
+/// the top left corner of the image. Useful for drawing labels over the rendered image.
+/// Returns none if the point is behind the camera
+pub fn project_point(scene: &Scene, point: Vec3) -> Option<(f64, f64)> {
+    pixel_camera(scene).world_to_pixel(point)
+}
+
+/// Camera of the scene for the rendered image, without depth of field
+fn pixel_camera(scene: &Scene) -> Camera {
+    Camera::new(
+        scene.render_config.width,
+        scene.render_config.height,
+        &CameraConfig {
+            aperture_size: 0.,
+            ..scene.camera.clone()
+        },
+    )
+}
+
+fn measure(world: &Hittables, ray: &Ray, max_length: f64) -> Option<Measurement> {
+    let rec = world.hit(ray, &Interval::new(0., max_length))?;
+    Some(Measurement {
+        distance: rec.ray_length * ray.direction.length(),
+        point: rec.hit_point,
+        normal: rec.onb.normal,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::hittable::{Bvh, Sphere};
+    use crate::material::texture::SolidColor;
+    use crate::material::Lambertian;
+    use crate::renderer::RenderConfig;
+
+    use super::*;
+
+    fn world() -> Hittables {
+        Bvh::new(vec![Sphere::new(
+            Vec3::new(0., 0., 0.),
+            1.,
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+        )])
+    }
+
+    #[test]
+    fn test_cast_ray() {
+        let m = cast_ray(&world(), Vec3::new(0., 0., 5.), Vec3::new(0., 0., -2.)).unwrap();
+        assert_eq!(m.distance, 4.);
+        assert_eq!(m.point, Vec3::new(0., 0., 1.));
+        assert_eq!(m.normal, Vec3::new(0., 0., 1.));
+
+        let from_inside = cast_ray(&world(), Vec3::new(0., 0., 0.), Vec3::new(1., 0., 0.)).unwrap();
+        assert_eq!(from_inside.normal, Vec3::new(-1., 0., 0.));
+
+        let from = Vec3::new(0., 0., 5.);
+        assert!(cast_ray_between(&world(), from, Vec3::new(0., 0., 2.)).is_none());
+        let m = cast_ray_between(&world(), from, Vec3::new(0., 0., -5.)).unwrap();
+        assert_eq!(m.distance, 4.);
+    }
+
+    #[test]
+    fn test_cast_pixel_ray() {
+        let scene = Scene {
+            world: world(),
+            camera: CameraConfig {
+                look_from: Vec3::new(0., 0., 5.),
+                aperture_size: 1.,
+                ..CameraConfig::default()
+            },
+            views: HashMap::new(),
+            background_color: Vec3::default(),
+            environment_map: None,
+            background: None,
+            render_config: RenderConfig {
+                width: 100,
+                height: 50,
+                ..RenderConfig::default()
+            },
+        };
+
+        let center = cast_pixel_ray(&scene, 49, 25).unwrap();
+        assert_eq!(center.distance, 4.);
+        assert!(cast_pixel_ray(&scene, 0, 0).is_none());
+
+        let (x, y) = project_point(&scene, center.point).unwrap();
+        assert!((x - 49.5).abs() < 1e-9 && (y - 25.5).abs() < 1e-9);
+    }
+}
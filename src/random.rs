@@ -1,5 +1,11 @@
 //! A wrapper for the random number generator to be used by ray tracer.
 
+/// Seeds the random number generator of the current thread,
+/// making the following sequence of random numbers reproducible
+pub fn seed(seed: u64) {
+    fastrand::seed(seed)
+}
+
 /// returns a random float 0 to <1
 pub fn random_normal_float() -> f64 {
     fastrand::f64()
@@ -32,6 +38,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_seed() {
+        seed(42);
+        let a: Vec<f64> = (0..10).map(|_| random_normal_float()).collect();
+        seed(42);
+        let b: Vec<f64> = (0..10).map(|_| random_normal_float()).collect();
+        assert_eq!(a, b)
+    }
+
     #[test]
     fn test_random_float() {
         for _ in 0..100 {
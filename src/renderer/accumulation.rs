@@ -0,0 +1,128 @@
+//! Buffers that the samples of each pixel are summed in while rendering
+use crate::geo::vec3::Vec3;
+
+/// Precision of the buffers that the samples of each pixel are summed in
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Accumulation {
+    /// 64 bit floats
+    #[default]
+    F64,
+    /// 32 bit floats, that use less memory for large images. The colors of the image are
+    /// summed with Kahan summation, so that they stay precise at high sample counts
+    F32,
+}
+
+/// Sums of the samples of each pixel, with one or more values per pixel
+pub(crate) struct SampleSums {
+    channels: usize,
+    sums: Sums,
+}
+
+enum Sums {
+    F64(Vec<f64>),
+    F32(Vec<f32>),
+    /// Sums with the rounding errors of the additions, that are compensated for in the next one
+    CompensatedF32(Vec<f32>, Vec<f32>),
+}
+
+impl SampleSums {
+    /// Creates sums for the given number of pixels, where compensated sums are only kept
+    /// with 32 bit precision
+    pub(crate) fn new(
+        accumulation: Accumulation,
+        compensated: bool,
+        channels: usize,
+        pixel_count: usize,
+    ) -> SampleSums {
+        let len = channels * pixel_count;
+        let sums = match accumulation {
+            Accumulation::F64 => Sums::F64(vec![0.; len]),
+            Accumulation::F32 if compensated => Sums::CompensatedF32(vec![0.; len], vec![0.; len]),
+            Accumulation::F32 => Sums::F32(vec![0.; len]),
+        };
+        SampleSums { channels, sums }
+    }
+
+    /// Adds the values of a sample to the sums of the pixel
+    pub(crate) fn add(&mut self, pixel: usize, values: &[f64]) {
+        let start = pixel * self.channels;
+        for (i, value) in (start..start + self.channels).zip(values) {
+            match &mut self.sums {
+                Sums::F64(sums) => sums[i] += value,
+                Sums::F32(sums) => sums[i] += *value as f32,
+                Sums::CompensatedF32(sums, compensations) => {
+                    let y = *value as f32 - compensations[i];
+                    let t = sums[i] + y;
+                    compensations[i] = (t - sums[i]) - y;
+                    sums[i] = t;
+                }
+            }
+        }
+    }
+
+    /// Adds a color sample to the sums of the pixel
+    pub(crate) fn add_color(&mut self, pixel: usize, color: Vec3) {
+        self.add(pixel, &[color.x, color.y, color.z]);
+    }
+
+    /// Sum of the given channel of the pixel
+    pub(crate) fn sum(&self, pixel: usize, channel: usize) -> f64 {
+        let i = pixel * self.channels + channel;
+        match &self.sums {
+            Sums::F64(sums) => sums[i],
+            Sums::F32(sums) | Sums::CompensatedF32(sums, _) => sums[i] as f64,
+        }
+    }
+
+    /// Mean colors of the pixels, for sums of colors
+    pub(crate) fn mean_colors(&self, num_samples: u32) -> Vec<Vec3> {
+        let scale = 1. / num_samples as f64;
+        (0..self.pixel_count())
+            .map(|p| Vec3::new(self.sum(p, 0), self.sum(p, 1), self.sum(p, 2)) * scale)
+            .collect()
+    }
+
+    /// Mean values of the pixels, for sums of single values
+    pub(crate) fn means(&self, num_samples: u32) -> Vec<f64> {
+        let scale = 1. / num_samples as f64;
+        (0..self.pixel_count()).map(|p| self.sum(p, 0) * scale).collect()
+    }
+
+    fn pixel_count(&self) -> usize {
+        let len = match &self.sums {
+            Sums::F64(sums) => sums.len(),
+            Sums::F32(sums) | Sums::CompensatedF32(sums, _) => sums.len(),
+        };
+        len / self.channels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mean_of_many_samples(accumulation: Accumulation, compensated: bool) -> f64 {
+        let mut sums = SampleSums::new(accumulation, compensated, 3, 1);
+        for _ in 0..1_000_000 {
+            sums.add_color(0, Vec3::new(0.1, 0.1, 0.1));
+        }
+        sums.mean_colors(1_000_000)[0].x
+    }
+
+    #[test]
+    fn test_compensated_summation() {
+        assert!((mean_of_many_samples(Accumulation::F64, true) - 0.1).abs() < 1e-9);
+        assert!((mean_of_many_samples(Accumulation::F32, true) - 0.1).abs() < 1e-6);
+        // Without compensation the rounding errors add up
+        assert!((mean_of_many_samples(Accumulation::F32, false) - 0.1).abs() > 1e-4);
+    }
+
+    #[test]
+    fn test_channels() {
+        let mut sums = SampleSums::new(Accumulation::F32, false, 1, 2);
+        sums.add(1, &[2.]);
+        sums.add(1, &[3.]);
+        assert_eq!(sums.sum(0, 0), 0.);
+        assert_eq!(sums.sum(1, 0), 5.);
+    }
+}
@@ -0,0 +1,137 @@
+//! Placement of the render threads on the cores and NUMA nodes of the machine
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+/// How the threads rendering the image are placed on the cores of the machine.
+/// Only used with [`crate::renderer::Parallelism::MultiThreaded`]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ThreadPlacement {
+    /// The operating system moves the threads between the cores
+    #[default]
+    Unpinned,
+    /// Each thread is pinned to a core of its own, which keeps its caches warm
+    Pinned,
+    /// Threads are pinned like with [`ThreadPlacement::Pinned`], and the tiles rendered by
+    /// the threads of each NUMA node are kept in the memory of the node until the end of
+    /// each sample. Avoids traffic between the nodes of machines with several processor
+    /// sockets. The nodes are only known on Linux, elsewhere all cores are on one node.
+    /// As the tiles are only added to the image at the end of each sample, the progress
+    /// and the tile updates of a sample are all reported once it is done
+    NumaAware,
+}
+
+/// The thread pool that renders the image, and the NUMA node of each of its threads
+pub(crate) struct RenderThreads {
+    pub(crate) pool: ThreadPool,
+    thread_nodes: Vec<usize>,
+    node_count: usize,
+}
+
+impl RenderThreads {
+    pub(crate) fn new(placement: ThreadPlacement) -> RenderThreads {
+        let cores = match placement {
+            ThreadPlacement::Unpinned => Vec::new(),
+            ThreadPlacement::Pinned | ThreadPlacement::NumaAware => {
+                core_affinity::get_core_ids().unwrap_or_default()
+            }
+        };
+        if cores.is_empty() {
+            return RenderThreads {
+                pool: ThreadPoolBuilder::new()
+                    .build()
+                    .expect("Failed to create thread pool"),
+                thread_nodes: Vec::new(),
+                node_count: 1,
+            };
+        }
+
+        let nodes = numa_nodes();
+        let thread_nodes: Vec<usize> = cores
+            .iter()
+            .map(|core| nodes.iter().position(|cpus| cpus.contains(&core.id)).unwrap_or(0))
+            .collect();
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(cores.len())
+            .start_handler(move |i| {
+                core_affinity::set_for_current(cores[i]);
+            })
+            .build()
+            .expect("Failed to create thread pool");
+
+        RenderThreads {
+            pool,
+            thread_nodes,
+            node_count: nodes.len().max(1),
+        }
+    }
+
+    /// Number of NUMA nodes that the threads are on
+    pub(crate) fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    /// NUMA node of the calling thread, which is zero outside of the pool
+    pub(crate) fn current_node(&self) -> usize {
+        rayon::current_thread_index()
+            .and_then(|i| self.thread_nodes.get(i))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// The cpus of each NUMA node of the machine
+#[cfg(target_os = "linux")]
+fn numa_nodes() -> Vec<Vec<usize>> {
+    let Ok(entries) = std::fs::read_dir("/sys/devices/system/node") else {
+        return Vec::new();
+    };
+    let mut nodes: Vec<(usize, Vec<usize>)> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let node = path.file_name()?.to_str()?.strip_prefix("node")?.parse().ok()?;
+            let cpus = std::fs::read_to_string(path.join("cpulist")).ok()?;
+            Some((node, parse_cpu_list(&cpus)))
+        })
+        .collect();
+    nodes.sort_by_key(|(node, _)| *node);
+    nodes.into_iter().map(|(_, cpus)| cpus).collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn numa_nodes() -> Vec<Vec<usize>> {
+    Vec::new()
+}
+
+/// Parses a list of cpus like `0-3,8-11`
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_cpu_list(list: &str) -> Vec<usize> {
+    list.trim()
+        .split(',')
+        .filter_map(|range| match range.split_once('-') {
+            Some((first, last)) => Some(first.parse().ok()?..=last.parse().ok()?),
+            None => {
+                let cpu = range.parse().ok()?;
+                Some(cpu..=cpu)
+            }
+        })
+        .flatten()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0-3,8,10-11\n"), vec![0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(parse_cpu_list("\n"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_threads_are_on_known_nodes() {
+        let threads = RenderThreads::new(ThreadPlacement::NumaAware);
+        let nodes: Vec<usize> = threads.pool.broadcast(|_| threads.current_node());
+        assert!(nodes.iter().all(|node| *node < threads.node_count()));
+        assert_eq!(threads.current_node(), 0);
+    }
+}
@@ -0,0 +1,108 @@
+//! Backgrounds seen by rays that do not hit anything in the world
+use std::fmt;
+use std::sync::Arc;
+
+use enum_dispatch::enum_dispatch;
+
+use crate::geo::vec3::Vec3;
+
+/// Calculates the color seen in a direction where rays do not hit anything.
+/// Unlike an [`crate::hittable::EnvironmentMap`], a background is not sampled as a light,
+/// but it still lights the scene through the rays that bounce off the world and miss it
+#[enum_dispatch]
+pub trait Background {
+    /// Color seen in the direction, which is a unit vector
+    fn color(&self, direction: Vec3) -> Vec3;
+}
+
+#[enum_dispatch(Background)]
+#[derive(Clone, Debug)]
+/// An enum of available backgrounds
+pub enum Backgrounds {
+    /// [`Background`] of type [`Gradient`]
+    GradientType(Gradient),
+    /// [`Background`] of type [`CustomBackground`]
+    CustomBackgroundType(CustomBackground),
+}
+
+/// A sky that blends from one color at the horizon to another straight up.
+/// Below the horizon it has the color of the ground
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    horizon: Vec3,
+    zenith: Vec3,
+    ground: Vec3,
+}
+
+impl Gradient {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a gradient background, with colors at the horizon, straight up and below the horizon
+    pub fn new(horizon: Vec3, zenith: Vec3, ground: Vec3) -> Backgrounds {
+        Backgrounds::from(Gradient {
+            horizon,
+            zenith,
+            ground,
+        })
+    }
+}
+
+impl Background for Gradient {
+    fn color(&self, direction: Vec3) -> Vec3 {
+        if direction.y < 0. {
+            return self.ground;
+        }
+        self.horizon * (1. - direction.y) + self.zenith * direction.y
+    }
+}
+
+/// A background calculated by a function of the direction, for procedural skies
+/// like star fields. The function is called from all the render threads
+#[derive(Clone)]
+pub struct CustomBackground(Arc<dyn Fn(Vec3) -> Vec3 + Send + Sync>);
+
+impl CustomBackground {
+    #![allow(clippy::new_ret_no_self)]
+    /// Creates a background from a function that gives the color seen in a direction,
+    /// which is a unit vector
+    pub fn new(color: impl Fn(Vec3) -> Vec3 + Send + Sync + 'static) -> Backgrounds {
+        Backgrounds::from(CustomBackground(Arc::new(color)))
+    }
+}
+
+impl fmt::Debug for CustomBackground {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomBackground")
+    }
+}
+
+impl Background for CustomBackground {
+    fn color(&self, direction: Vec3) -> Vec3 {
+        (self.0)(direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gradient() {
+        let (horizon, zenith, ground) = (
+            Vec3::new(1., 1., 1.),
+            Vec3::new(0., 0., 1.),
+            Vec3::new(0.5, 0.5, 0.5),
+        );
+        let gradient = Gradient::new(horizon, zenith, ground);
+        assert_eq!(gradient.color(Vec3::new(1., 0., 0.)), horizon);
+        assert_eq!(gradient.color(Vec3::new(0., 1., 0.)), zenith);
+        assert_eq!(gradient.color(Vec3::new(0., 0.5, 0.5_f64.sqrt())), Vec3::new(0.5, 0.5, 1.));
+        assert_eq!(gradient.color(Vec3::new(0., -1., 0.)), ground);
+    }
+
+    #[test]
+    fn test_custom_background() {
+        let background = CustomBackground::new(|direction: Vec3| direction * 2.);
+        let direction = Vec3::new(0., 0.6, 0.8);
+        assert_eq!(background.clone().color(direction), direction * 2.);
+    }
+}
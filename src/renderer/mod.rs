@@ -1,26 +1,58 @@
 //! The renderer takes a [`Scene`] as input, renders it and reports [`RenderProgress`]
 
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::error::Error;
-use std::ops::Deref;
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 
-use image::RgbImage;
+use image::{GenericImage, Rgb, Rgb32FImage, RgbImage};
 use simple_error::SimpleError;
+use xxhash_rust::xxh3::xxh3_64;
 
 use crate::camera::{Camera, CameraConfig};
 use crate::geo::vec3::{Vec3, ZERO_VECTOR};
 use crate::geo::{Ray, Uv};
-use crate::hittable::{Hittable, Hittables};
-use crate::material::AttenuatedColor;
+use crate::hittable::Hittables::{DirectionalLightType, EnvironmentMapType};
+use crate::hittable::{EnvironmentMap, Hittable, Hittables};
+use crate::material::{AttenuatedColor, Material, RayHit, RayScatter};
+use crate::post::PostProcessors::ToneMapPostProcessorType;
 use crate::post::{NopPostProcessor, PostProcessor, PostProcessors};
+use crate::random;
 use crate::random::random_normal_float;
+use crate::renderer::accumulation::SampleSums;
+use crate::renderer::affinity::RenderThreads;
+use crate::renderer::background::{Background, Backgrounds};
 use crate::renderer::shader::{AlbedoShader, NormalShader, PathTracingShader, Shader, Shaders};
-use crate::util::interval::RAY_INTERVAL;
+use crate::sampler;
+use crate::sampler::Sampler;
+use crate::util::color_space::ColorSpace;
+use crate::util::interval::{Interval, RAY_INTERVAL};
+use crate::util::rgb_color::{luminance, rgb_to_vec3, to_rgb_color};
 
+mod accumulation;
+mod affinity;
+pub mod background;
 pub mod shader;
 
+pub use accumulation::Accumulation;
+pub use affinity::ThreadPlacement;
+
+/// Width and height in pixels of the tiles that the image is rendered in
+const TILE_SIZE: usize = 32;
+/// How often an abort is checked for while tiles are rendered on the render threads
+const ABORT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Distance in pixels, horizontally and vertically, between the pixels rendered
+/// in the warm-up pass
+const WARM_UP_PIXEL_STEP: usize = 4;
+/// Distance to the background when calculating motion vectors
+const BACKGROUND_DISTANCE: f64 = 1e9;
+
 ///Input to the ray tracer for how the image should be rendered
 #[derive(Clone)]
 pub struct RenderConfig {
@@ -36,6 +68,45 @@ pub struct RenderConfig {
     pub post_processors: Vec<PostProcessors>,
     /// Describes at which points in time the render progress should contain an image
     pub render_image_strategy: RenderImageStrategy,
+    /// Describes how the rendering work is distributed among threads
+    pub parallelism: Parallelism,
+    /// Describes how points on area lights are distributed over the samples of a pixel
+    pub sampler: Sampler,
+    /// Point in time of the rendered frame, passed on to animated textures and materials
+    pub time: f64,
+    /// Only renders this region of the image, and outputs an image of the size of the region.
+    /// Regions rendered separately can be put together with [`stitch`]
+    pub region: Option<ImageRegion>,
+    /// Precision of the buffers that the samples of the pixels are summed in
+    pub accumulation: Accumulation,
+    /// Renders a low resolution sample before the first sample, measuring how long each tile
+    /// takes to render, so that the slowest tiles are started first in every sample.
+    /// Shortens the wait for the last tiles of each sample in scenes where some parts of
+    /// the image are much slower to render than others. Only used with
+    /// [`Parallelism::MultiThreaded`], as the order does not matter on a single thread
+    pub warm_up: bool,
+    /// How the render threads are placed on the cores of the machine
+    pub thread_placement: ThreadPlacement,
+    /// Renders the albedo and normal colors that post processors like the denoiser use in a
+    /// prepass with this many samples per pixel, instead of with every sample. They converge
+    /// after a few samples, so this makes every sample cheaper.
+    /// If not set, they are rendered with every sample
+    pub aov_samples: Option<u32>,
+    /// Reports a hash of the colors of each tile for each sample, see [`TileHash`]
+    pub tile_hashes: bool,
+    /// Reports the pixels of each tile as soon as it has been rendered for a sample,
+    /// see [`TileUpdate`]. With [`ThreadPlacement::NumaAware`] they are reported at the
+    /// end of the sample instead
+    pub tile_updates: bool,
+    /// Reports the albedo and normal colors along with each image, see [`Aovs`]
+    pub aov_output: bool,
+    /// Camera of the previous frame of an animation. If set, the [`Aovs`] also have
+    /// motion vectors from the rendered frame to the previous frame
+    pub previous_camera: Option<CameraConfig>,
+    /// Format of the images in the render progress
+    pub image_format: ImageFormat,
+    /// Color space that the colors of the scene are converted to and rendered in
+    pub color_space: ColorSpace,
 }
 
 impl Default for RenderConfig {
@@ -47,30 +118,131 @@ impl Default for RenderConfig {
             shader: PathTracingShader::new(50),
             post_processors: vec![],
             render_image_strategy: RenderImageStrategy::OnlyFinal,
+            parallelism: Parallelism::MultiThreaded,
+            sampler: Sampler::Stratified,
+            time: 0.,
+            region: None,
+            accumulation: Accumulation::F64,
+            warm_up: false,
+            thread_placement: ThreadPlacement::Unpinned,
+            aov_samples: None,
+            tile_hashes: false,
+            tile_updates: false,
+            aov_output: false,
+            previous_camera: None,
+            image_format: ImageFormat::Rgb8,
+            color_space: ColorSpace::LinearSrgb,
         }
     }
 }
 
 impl RenderConfig {
     fn needs_albedo_and_normal_colors(&self) -> bool {
-        self.post_processors
-            .iter()
-            .any(|p| p.needs_albedo_and_normal_colors())
+        self.aov_output || self.post_processors.iter().any(|p| p.wants_aovs())
+    }
+
+    /// Whether the albedo and normal colors are rendered with every sample,
+    /// rather than in a prepass
+    fn samples_albedo_and_normal_colors(&self) -> bool {
+        self.aov_samples.is_none() && self.needs_albedo_and_normal_colors()
+    }
+
+    /// The region of the image that is rendered
+    pub(crate) fn region(&self) -> ImageRegion {
+        self.region.unwrap_or(ImageRegion {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        })
     }
 }
 
+/// A rectangular region of an image in pixels, with the origin in the top left corner
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ImageRegion {
+    /// Left edge of the region
+    pub x: usize,
+    /// Top edge of the region
+    pub y: usize,
+    /// Width of the region
+    pub width: usize,
+    /// Height of the region
+    pub height: usize,
+}
+
+impl ImageRegion {
+    /// Splits an image of the given size into regions of at most the given size,
+    /// row by row from the top left corner
+    pub fn split(
+        image_width: usize,
+        image_height: usize,
+        region_width: usize,
+        region_height: usize,
+    ) -> Vec<ImageRegion> {
+        (0..image_height)
+            .step_by(region_height.max(1))
+            .flat_map(|y| {
+                (0..image_width).step_by(region_width.max(1)).map(move |x| ImageRegion {
+                    x,
+                    y,
+                    width: region_width.min(image_width - x),
+                    height: region_height.min(image_height - y),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Puts together images rendered for regions of an image into one image of the given size.
+/// Post processors that spread light over the image, like bloom, can give visible seams
+pub fn stitch(
+    image_width: usize,
+    image_height: usize,
+    parts: &[(ImageRegion, RgbImage)],
+) -> Result<RgbImage, Box<dyn Error>> {
+    let mut image = RgbImage::new(image_width as u32, image_height as u32);
+    for (region, part) in parts {
+        if part.dimensions() != (region.width as u32, region.height as u32)
+            || region.x + region.width > image_width
+            || region.y + region.height > image_height
+        {
+            return Err(Box::new(SimpleError::new(format!(
+                "Image of region {:?} does not fit in the stitched image",
+                region
+            ))));
+        }
+        image.copy_from(part, region.x as u32, region.y as u32)?;
+    }
+    Ok(image)
+}
+
 /// Contains all information needed to render an image
 pub struct Scene {
     /// World is the hittable objects in the scene
     pub world: Hittables,
     /// A camera for defining the view of the world
     pub camera: CameraConfig,
+    /// Additional named cameras, that the world can be rendered from with
+    /// [`Renderer::render_view`]
+    pub views: HashMap<String, CameraConfig>,
     /// Background color of the scene
     pub background_color: Vec3,
+    /// Environment seen instead of the background color, which also lights the scene
+    pub environment_map: Option<EnvironmentMap>,
+    /// Background seen instead of the background color, when there is no environment map
+    pub background: Option<Backgrounds>,
     /// Render configuration
     pub render_config: RenderConfig,
 }
 
+/// Command sent to a renderer while it renders, see [`Renderer::render_interactive`]
+#[derive(Clone)]
+pub enum RenderCommand {
+    /// Restarts the render from the given camera
+    MoveCamera(CameraConfig),
+}
+
 /// Progress reported back to the caller of the raytrace function
 pub struct RenderProgress {
     /// progress is reported between 0 -> 1 and represents a percentage of completion
@@ -79,8 +251,143 @@ pub struct RenderProgress {
     pub fps: Option<f64>,
     /// Estimated time left until rendering is complete
     pub estimated_time_left: Duration,
-    /// Output image so far, will be final when progress is 1
-    pub render_image: Option<RgbImage>,
+    /// Output image so far, will be final when progress is 1.
+    /// Its format is chosen by [`RenderConfig::image_format`]
+    pub render_image: Option<RenderImage>,
+    /// Standard error of the luminance of each pixel in the output image, row by row.
+    /// Shows how much noise is left, and is included whenever there is an output image
+    pub standard_errors: Option<Vec<f64>>,
+    /// Albedo and normal colors of the image, if [`RenderConfig::aov_output`] is set.
+    /// Included whenever the [`RenderImageStrategy`] calls for an image,
+    /// also when there are no post processors to make one
+    pub aovs: Option<Aovs>,
+    /// Tiles that panicked while rendering this sample. Their pixels are missing the
+    /// sample, but the rest of the image is still rendered
+    pub tile_errors: Vec<TileError>,
+    /// Hashes of the tiles rendered in this sample, from the top left of the image row by row,
+    /// if [`RenderConfig::tile_hashes`] is set
+    pub tile_hashes: Vec<TileHash>,
+    /// Pixels of a tile that has just been rendered, if [`RenderConfig::tile_updates`] is set.
+    /// Progress with a tile update has no image, and is reported in addition to the progress
+    /// of each sample, which comes after the updates of all the tiles of the sample
+    pub tile_update: Option<TileUpdate>,
+}
+
+/// The auxiliary colors of the rendered image that denoisers use to tell noise from details,
+/// for running another denoiser than the post processors or saving them as channels of
+/// their own. The colors are for the rendered region, row by row from the top
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aovs {
+    /// Mean color of the surfaces seen in each pixel, without any lighting.
+    /// Pixels that do not see anything have the background color
+    pub albedo_colors: Vec<Vec3>,
+    /// Mean world space normal of the surfaces seen in each pixel,
+    /// which is zero for pixels that do not see anything
+    pub normal_colors: Vec<Vec3>,
+    /// Offset in pixels from the center of each pixel to where the surface seen through it
+    /// is in the image of the previous frame, for temporal denoisers and frame interpolation.
+    /// Only the camera moves between the frames, the hittables are treated as static.
+    /// Zero for surfaces behind the previous camera, and empty if there is no
+    /// [`RenderConfig::previous_camera`]
+    pub motion_vectors: Vec<(f64, f64)>,
+    /// Fraction of the light of the directional lights, like the sun, that reaches the surface
+    /// seen in each pixel without being blocked by other hittables, for grading the density of
+    /// the shadows in compositing. Zero in full shadow and one where fully lit, which is also
+    /// what surfaces facing away from the lights and pixels that see nothing get.
+    /// Holdouts get their shadows too. Empty if the scene has no directional light
+    pub sun_visibility: Vec<f64>,
+}
+
+/// A tile of the image that could not be rendered for a sample,
+/// because a panic occurred while rendering it
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileError {
+    /// Part of the image covered by the tile
+    pub region: ImageRegion,
+    /// The sample that was rendered, starting from 1
+    pub sample: u32,
+    /// Message of the panic
+    pub message: String,
+}
+
+/// A hash of the colors of a tile rendered for a sample, for regression tests that detect
+/// changes to the rendered image without storing a reference image.
+/// With [`Parallelism::SingleThreaded`] the hashes are the same in every render of a scene
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TileHash {
+    /// Part of the image covered by the tile
+    pub region: ImageRegion,
+    /// The sample that was rendered, starting from 1
+    pub sample: u32,
+    /// XXH3 hash of the colors of the pixels of the tile, row by row from the bottom
+    pub hash: u64,
+}
+
+/// The pixels of a tile that has been rendered for a sample, so that applications showing
+/// the image while it is rendered can update only the tiles that have changed.
+/// The pixels are the mean of the samples so far, converted like the colors of
+/// [`NopPostProcessor`], as post processors can only be applied to the whole image
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileUpdate {
+    /// Part of the image covered by the tile
+    pub region: ImageRegion,
+    /// The sample that was rendered, starting from 1
+    pub sample: u32,
+    /// Pixels of the tile, of the size of the region
+    pub image: RgbImage,
+}
+
+/// An image of the rendering, in the format chosen by [`RenderConfig::image_format`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum RenderImage {
+    /// Gamma corrected 8 bit colors, where colors brighter than white are clipped
+    Rgb8(RgbImage),
+    /// Linear 32 bit float colors, with the raw radiance of bright colors kept
+    Rgb32F(Rgb32FImage),
+}
+
+impl RenderImage {
+    /// Width and height of the image
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            RenderImage::Rgb8(image) => image.dimensions(),
+            RenderImage::Rgb32F(image) => image.dimensions(),
+        }
+    }
+
+    /// The image as gamma corrected 8 bit colors, the same as an image rendered
+    /// with [`ImageFormat::Rgb8`]
+    pub fn into_rgb8(self) -> RgbImage {
+        match self {
+            RenderImage::Rgb8(image) => image,
+            RenderImage::Rgb32F(image) => RgbImage::from_fn(image.width(), image.height(), |x, y| {
+                let [r, g, b] = image.get_pixel(x, y).0;
+                to_rgb_color(Vec3::new(r as f64, g as f64, b as f64))
+            }),
+        }
+    }
+
+    /// Saves the image to the path, in the format given by its extension.
+    /// Float images can be saved as OpenEXR with the `exr` extension
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        match self {
+            RenderImage::Rgb8(image) => image.save(path)?,
+            RenderImage::Rgb32F(image) => image.save(path)?,
+        }
+        Ok(())
+    }
+}
+
+/// Format of the images in the render progress
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ImageFormat {
+    /// Gamma corrected 8 bit colors, see [`RenderImage::Rgb8`]
+    #[default]
+    Rgb8,
+    /// Linear 32 bit float colors for compositing, see [`RenderImage::Rgb32F`].
+    /// When the last post processor can only make 8 bit images, like the denoiser,
+    /// its image is converted back to linear colors, but brighter colors than white are lost
+    Rgb32F,
 }
 
 #[derive(Copy, Clone)]
@@ -118,12 +425,26 @@ impl RenderImageStrategy {
     }
 }
 
+#[derive(Copy, Clone)]
+/// How the rendering work is distributed among threads
+pub enum Parallelism {
+    /// Tiles of the image are rendered in parallel using all available cores
+    MultiThreaded,
+    /// Tiles of the image are rendered one after another on the calling thread.
+    /// The random number generator is seeded from the given value for every pixel,
+    /// so rendering the same scene twice produces the exact same image,
+    /// also when the image is rendered in separate regions
+    SingleThreaded(u64),
+}
+
 /// Renderer is a central part of the raytracer responsible for controlling the
 /// process reporting back progress to the caller
 pub struct Renderer {
     scene: Scene,
     /// All the light hittables in the world
     pub lights: Vec<Hittables>,
+    /// The directional lights among the lights
+    suns: Vec<Hittables>,
     albedo_shader: AlbedoShader,
     normal_shader: NormalShader,
 }
@@ -133,12 +454,16 @@ pub(crate) struct RayColorResult {
     pixel_color: AttenuatedColor,
     albedo_color: Vec3,
     normal_color: Vec3,
+    sun_visibility: f64,
 }
 
 impl Renderer {
     /// Creates a new renderer given a scene and channels for communicating with the caller
     pub fn new(mut scene: Scene) -> Result<Renderer, Box<dyn Error>> {
-        let light_list = scene.world.get_lights();
+        let mut light_list = scene.world.get_lights();
+        if let Some(environment_map) = &scene.environment_map {
+            light_list.push(Hittables::from(environment_map.clone()));
+        }
 
         if light_list.is_empty() {
             return Err(Box::new(SimpleError::new(
@@ -146,6 +471,23 @@ impl Renderer {
             )));
         }
 
+        if light_list.iter().any(|l| l.as_sampleable().is_none()) {
+            return Err(Box::new(SimpleError::new(
+                "Scene has a light that can not be sampled, a light bvh can only contain triangles, quads and discs as lights",
+            )));
+        }
+
+        let region = scene.render_config.region();
+        if region.width == 0
+            || region.height == 0
+            || region.x + region.width > scene.render_config.width
+            || region.y + region.height > scene.render_config.height
+        {
+            return Err(Box::new(SimpleError::new(
+                "Render region should be a non empty part of the image",
+            )));
+        }
+
         if scene.render_config.post_processors.is_empty() {
             scene
                 .render_config
@@ -153,17 +495,57 @@ impl Renderer {
                 .push(NopPostProcessor::new());
         }
 
+        let intermediate_count = scene.render_config.post_processors.len() - 1;
+        if let Some(p) = scene.render_config.post_processors[..intermediate_count]
+            .iter()
+            .find(|p| !p.supports_intermediate())
+        {
+            return Err(Box::new(SimpleError::new(format!(
+                "{} can only be used as the last post processor",
+                p.name()
+            ))));
+        }
+
+        if scene.render_config.color_space == ColorSpace::AcesCg
+            && scene
+                .render_config
+                .post_processors
+                .iter()
+                .any(|p| matches!(p, ToneMapPostProcessorType(_)))
+        {
+            return Err(Box::new(SimpleError::new(
+                "Tone mapping can not be used with the ACEScg color space, \
+                 which has its own tone mapping",
+            )));
+        }
+
+        let suns = light_list
+            .iter()
+            .filter(|l| matches!(l, DirectionalLightType(_)))
+            .cloned()
+            .collect();
+
         Ok(Renderer {
             scene,
             lights: light_list,
+            suns,
             albedo_shader: AlbedoShader {},
             normal_shader: NormalShader {},
         })
     }
 
+    /// The first hit of the ray in the world, shaded in the color space of the render
+    fn hit_world(&self, ray: &Ray) -> Option<RayHit<'_>> {
+        self.scene
+            .world
+            .hit(ray, &RAY_INTERVAL)
+            .map(|rec| rec.with_color_space(self.scene.render_config.color_space))
+    }
+
     fn ray_color(&self, ray: &Ray, depth: u32, accumulated_ray_length: f64) -> RayColorResult {
-        match self.scene.world.hit(ray, &RAY_INTERVAL) {
-            Some(rec) => {
+        match self.hit_world(ray) {
+            // Holdouts are seen as the background by the camera
+            Some(rec) if !(depth == 0 && rec.material.is_holdout()) => {
                 let attenuated_color = self.scene.render_config.shader.shade(
                     self,
                     &rec,
@@ -172,19 +554,13 @@ impl Renderer {
                     accumulated_ray_length,
                 );
 
-                if depth == 0 && self.scene.render_config.needs_albedo_and_normal_colors() {
-                    let albedo_color = self
-                        .albedo_shader
-                        .shade(self, &rec, ray, depth, accumulated_ray_length)
-                        .color;
-                    let normal_color = self
-                        .normal_shader
-                        .shade(self, &rec, ray, depth, accumulated_ray_length)
-                        .color;
+                if depth == 0 && self.scene.render_config.samples_albedo_and_normal_colors() {
+                    let (albedo_color, normal_color) = self.hit_aov_colors(&rec, ray);
                     return RayColorResult {
                         pixel_color: attenuated_color,
                         albedo_color,
                         normal_color,
+                        sun_visibility: self.sun_visibility(Some(&rec)),
                     };
                 }
 
@@ -192,107 +568,605 @@ impl Renderer {
                     pixel_color: attenuated_color,
                     albedo_color: ZERO_VECTOR,
                     normal_color: ZERO_VECTOR,
+                    sun_visibility: 1.,
+                }
+            }
+            rec => {
+                let background_color = self.background_color(ray);
+                // Holdouts receive the shadows that the underlying material would have
+                let pixel_color = match &rec {
+                    Some(rec) => background_color * self.light_visibility(rec, &self.lights),
+                    None => background_color,
+                };
+                let sun_visibility =
+                    if depth == 0 && self.scene.render_config.samples_albedo_and_normal_colors() {
+                        self.sun_visibility(rec.as_ref())
+                    } else {
+                        1.
+                    };
+                RayColorResult {
+                    pixel_color: AttenuatedColor {
+                        color: pixel_color,
+                        ..AttenuatedColor::default()
+                    },
+                    albedo_color: background_color,
+                    normal_color: ZERO_VECTOR,
+                    sun_visibility,
                 }
             }
-            None => RayColorResult {
-                pixel_color: AttenuatedColor {
-                    color: self.scene.background_color,
-                    ..AttenuatedColor::default()
+        }
+    }
+
+    /// Albedo and normal colors and sun visibility of the first hit of the camera ray
+    fn aov_colors(&self, ray: &Ray) -> (Vec3, Vec3, f64) {
+        let rec = self.hit_world(ray);
+        let sun_visibility = self.sun_visibility(rec.as_ref());
+        match rec {
+            Some(rec) if !rec.material.is_holdout() => {
+                let (albedo_color, normal_color) = self.hit_aov_colors(&rec, ray);
+                (albedo_color, normal_color, sun_visibility)
+            }
+            _ => (self.background_color(ray), ZERO_VECTOR, sun_visibility),
+        }
+    }
+
+    /// Whether the visibility of the directional lights is rendered along with
+    /// the albedo and normal colors
+    fn needs_sun_visibility(&self) -> bool {
+        self.scene.render_config.aov_output && !self.suns.is_empty()
+    }
+
+    /// One sample of the fraction of the light of the directional lights that reaches
+    /// the hit point, or one if there is no hit or the visibility is not rendered
+    fn sun_visibility(&self, rec: Option<&RayHit>) -> f64 {
+        match rec {
+            Some(rec) if self.needs_sun_visibility() => self.light_visibility(rec, &self.suns),
+            _ => 1.,
+        }
+    }
+
+    /// One sample of the fraction of the direct light from the lights that reaches the hit
+    /// point without being blocked by other hittables, with each light weighted by how much
+    /// light it gives. One if no light reaches the hit point. Traces one ray to each light
+    fn light_visibility(&self, rec: &RayHit, lights: &[Hittables]) -> f64 {
+        let (mut visible, mut total) = (0., 0.);
+        for light in lights {
+            let Some(sampleable) = light.as_sampleable() else {
+                continue;
+            };
+            let direction = sampleable.random_direction(rec.hit_point).unit();
+            let cosine = direction.dot(rec.normal);
+            let pdf = sampleable.pdf_value(rec.hit_point, direction);
+            if cosine <= 0. || pdf <= 0. {
+                continue;
+            }
+            let ray = Ray::new_at_time(rec.hit_point, direction, rec.time);
+            let (radiance, distance) = match light {
+                EnvironmentMapType(_) => (self.background_color(&ray), f64::INFINITY),
+                _ => match light.hit(&ray, &RAY_INTERVAL) {
+                    Some(light_rec) => {
+                        let light_rec =
+                            light_rec.with_color_space(self.scene.render_config.color_space);
+                        (emitted_color(&ray, &light_rec), light_rec.ray_length)
+                    }
+                    None => continue,
                 },
-                albedo_color: self.scene.background_color,
-                normal_color: ZERO_VECTOR,
-            },
+            };
+            let weight = luminance(radiance) * cosine / pdf;
+            if weight.is_nan() || weight <= 0. {
+                continue;
+            }
+            total += weight;
+            // Relative to the distance, as directional lights are hit very far away
+            let before_light = Interval::new(RAY_INTERVAL.min, distance * (1. - 1e-6));
+            if self.scene.world.hit(&ray, &before_light).is_none() {
+                visible += weight;
+            }
+        }
+        if total > 0. {
+            visible / total
+        } else {
+            1.
         }
     }
 
+    fn hit_aov_colors(&self, rec: &RayHit, ray: &Ray) -> (Vec3, Vec3) {
+        (
+            self.albedo_shader.shade(self, rec, ray, 0, 0.).color,
+            self.normal_shader.shade(self, rec, ray, 0, 0.).color,
+        )
+    }
+
+    /// Light arriving at the point from the given direction, path traced by the shader.
+    /// Unlike for camera rays, holdouts are not seen through
+    pub fn incident_radiance(&self, point: Vec3, direction: Vec3) -> Vec3 {
+        let ray = Ray::new_at_time(point, direction, self.scene.render_config.time);
+        self.ray_color(&ray, 1, 0.).pixel_color.get_attenuated_color()
+    }
+
+    /// Offsets in pixels from the center of each pixel in the region to where the surface seen
+    /// through it is seen by the previous camera, row by row from the top
+    fn motion_vectors(&self, camera: &CameraConfig, previous: &CameraConfig) -> Vec<(f64, f64)> {
+        let config = &self.scene.render_config;
+        // Without depth of field, so the rays go through the centers of the pixels
+        let pinhole = |c: &CameraConfig| {
+            let c = CameraConfig {
+                aperture_size: 0.,
+                ..c.clone()
+            };
+            Camera::new(config.width, config.height, &c)
+        };
+        let (camera, previous) = (pinhole(camera), pinhole(previous));
+        let region = config.region();
+        (0..region.width * region.height)
+            .map(|i| {
+                let x = (region.x + i % region.width) as f64 + 0.5;
+                let y = (region.y + i / region.width) as f64 + 0.5;
+                let ray = camera.get_pixel_ray(x, y, config.time);
+                let point = match self.scene.world.hit(&ray, &RAY_INTERVAL) {
+                    Some(rec) => rec.hit_point,
+                    // The background is so far away that only rotations move it
+                    None => ray.origin + ray.direction.unit() * BACKGROUND_DISTANCE,
+                };
+                previous
+                    .world_to_pixel(point)
+                    .map_or((0., 0.), |(px, py)| (px - x, py - y))
+            })
+            .collect()
+    }
+
+    /// Color seen by rays that do not hit anything
+    fn background_color(&self, ray: &Ray) -> Vec3 {
+        let color = match (&self.scene.environment_map, &self.scene.background) {
+            (Some(environment_map), _) => environment_map.color(ray.direction),
+            (None, Some(background)) => background.color(ray.direction.unit()),
+            (None, None) => self.scene.background_color,
+        };
+        self.scene.render_config.color_space.convert_srgb(color)
+    }
+
+    /// Traces a ray through a random point in the pixel, where y goes up from the bottom
+    fn trace_pixel(
+        &self,
+        camera: &Camera,
+        x: usize,
+        y: usize,
+        sample_index: u32,
+    ) -> RayColorResult {
+        self.ray_color(&self.pixel_ray(camera, x, y, sample_index), 0, 0.)
+    }
+
+    /// Camera ray through a random point in the pixel, where y goes up from the bottom
+    fn pixel_ray(&self, camera: &Camera, x: usize, y: usize, sample_index: u32) -> Ray {
+        let config = &self.scene.render_config;
+        sampler::start_pixel_sample(config.sampler, x, y, sample_index, config.samples_per_pixel);
+        let u = (x as f64 + random_normal_float()) / (config.width - 1) as f64;
+        let v = (y as f64 + random_normal_float()) / (config.height - 1) as f64;
+        camera.get_ray(Uv::new(u as f32, v as f32), config.time)
+    }
+
+    /// Renders the albedo and normal colors of the given number of samples for every pixel,
+    /// and adds them to the buffers. Returns false if aborted. Tiles that panic are left out,
+    /// as they are reported when rendering the first sample
+    fn render_aov_prepass(
+        &self,
+        tiles: &[Tile],
+        camera: &Camera,
+        threads: Option<&RenderThreads>,
+        samples: u32,
+        buffers: &ImageBuffers,
+        abort: &dyn Fn() -> bool,
+    ) -> bool {
+        let image_height = self.scene.render_config.height;
+        let region = self.scene.render_config.region();
+        let aborted = AtomicBool::new(false);
+        for sample_index in 0..samples {
+            // Tiles are added in the order they are done, so single threaded renders
+            // add them in the same order every time
+            for_each_tile(threads, tiles, abort, &aborted, |_, tile, is_aborted| {
+                let tile_sample = panic::catch_unwind(AssertUnwindSafe(|| {
+                    self.render_aov_tile(*tile, sample_index, camera, is_aborted)
+                }));
+                if let Ok(Some(tile_sample)) = tile_sample {
+                    buffers.add(&tile_sample, region, image_height);
+                }
+            });
+            if aborted.load(Ordering::Relaxed) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Renders one sample of the albedo and normal colors for every pixel in the given tile.
+    /// Returns nothing if aborted between two rows of the tile
+    fn render_aov_tile(
+        &self,
+        tile: Tile,
+        sample_index: u32,
+        camera: &Camera,
+        is_aborted: &dyn Fn() -> bool,
+    ) -> Option<TileSample> {
+        let mut albedo_colors = Vec::with_capacity(tile.width * tile.height);
+        let mut normal_colors = Vec::with_capacity(tile.width * tile.height);
+        let mut sun_visibility = Vec::new();
+        for y in tile.y..tile.y + tile.height {
+            if is_aborted() {
+                return None;
+            }
+            for x in tile.x..tile.x + tile.width {
+                if let Parallelism::SingleThreaded(seed) = self.scene.render_config.parallelism {
+                    // Differs from the seeds of the samples, so the prepass does not repeat them
+                    random::seed(!pixel_seed(seed, x, y, sample_index));
+                }
+                let (albedo_color, normal_color, visibility) =
+                    self.aov_colors(&self.pixel_ray(camera, x, y, sample_index));
+                albedo_colors.push(albedo_color);
+                normal_colors.push(normal_color);
+                if self.needs_sun_visibility() {
+                    sun_visibility.push(visibility);
+                }
+            }
+        }
+        Some(TileSample {
+            tile,
+            pixel_colors: Vec::new(),
+            albedo_colors,
+            normal_colors,
+            sun_visibility,
+            hash: None,
+        })
+    }
+
+    /// Renders one sample for a few pixels spread over the tile, without adding them
+    /// to the image, and returns how long it took. Stops early if aborted between two rows
+    fn warm_up_tile(&self, tile: Tile, camera: &Camera, is_aborted: &dyn Fn() -> bool) -> Duration {
+        let start = Instant::now();
+        // Tiles that panic are reported when rendering the first sample
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            for y in (tile.y..tile.y + tile.height).step_by(WARM_UP_PIXEL_STEP) {
+                if is_aborted() {
+                    return;
+                }
+                for x in (tile.x..tile.x + tile.width).step_by(WARM_UP_PIXEL_STEP) {
+                    self.trace_pixel(camera, x, y, 0);
+                }
+            }
+        }));
+        start.elapsed()
+    }
+
+    /// Renders one sample for every pixel in the given tile. Returns nothing if aborted
+    /// between two rows of the tile, and an error if rendering the tile panics
+    fn render_tile(
+        &self,
+        tile: Tile,
+        sample_index: u32,
+        camera: &Camera,
+        is_aborted: &dyn Fn() -> bool,
+        needs_albedo_and_normal_colors: bool,
+    ) -> Result<Option<TileSample>, TileError> {
+        let image_height = self.scene.render_config.height;
+        let parallelism = self.scene.render_config.parallelism;
+
+        let tile_pixel_count = tile.width * tile.height;
+        let mut tile_pixel_colors: Vec<Vec3> = vec![ZERO_VECTOR; tile_pixel_count];
+        let mut tile_albedo_colors: Vec<Vec3> = if needs_albedo_and_normal_colors {
+            vec![ZERO_VECTOR; tile_pixel_count]
+        } else {
+            Vec::new()
+        };
+        let mut tile_normal_colors: Vec<Vec3> = if needs_albedo_and_normal_colors {
+            vec![ZERO_VECTOR; tile_pixel_count]
+        } else {
+            Vec::new()
+        };
+        let needs_sun_visibility = needs_albedo_and_normal_colors && self.needs_sun_visibility();
+        let mut tile_sun_visibility: Vec<f64> = if needs_sun_visibility {
+            vec![1.; tile_pixel_count]
+        } else {
+            Vec::new()
+        };
+
+        // A panic in a material or texture only loses this sample of the tile
+        let rendered = panic::catch_unwind(AssertUnwindSafe(|| {
+            for ty in 0..tile.height {
+                if is_aborted() {
+                    return false;
+                }
+                let y = tile.y + ty;
+                for tx in 0..tile.width {
+                    let x = tile.x + tx;
+                    let ti = ty * tile.width + tx;
+                    if let Parallelism::SingleThreaded(seed) = parallelism {
+                        random::seed(pixel_seed(seed, x, y, sample_index));
+                    }
+                    let ray_color_res = self.trace_pixel(camera, x, y, sample_index);
+
+                    tile_pixel_colors[ti] = ray_color_res.pixel_color.get_attenuated_color();
+
+                    if needs_albedo_and_normal_colors {
+                        tile_albedo_colors[ti] = ray_color_res.albedo_color;
+                        tile_normal_colors[ti] = ray_color_res.normal_color;
+                    }
+                    if needs_sun_visibility {
+                        tile_sun_visibility[ti] = ray_color_res.sun_visibility;
+                    }
+                }
+            }
+            true
+        }));
+        match rendered {
+            Ok(true) => {}
+            Ok(false) => return Ok(None),
+            Err(payload) => {
+                return Err(TileError {
+                    region: tile.image_region(image_height),
+                    sample: sample_index + 1,
+                    message: panic_message(payload.as_ref()),
+                });
+            }
+        }
+
+        let hash = self.scene.render_config.tile_hashes.then(|| TileHash {
+            region: tile.image_region(image_height),
+            sample: sample_index + 1,
+            hash: hash_colors(&tile_pixel_colors),
+        });
+        Ok(Some(TileSample {
+            tile,
+            pixel_colors: tile_pixel_colors,
+            albedo_colors: tile_albedo_colors,
+            normal_colors: tile_normal_colors,
+            sun_visibility: tile_sun_visibility,
+            hash,
+        }))
+    }
+
     /// Executes the rendering of the image
     pub fn render(
         &self,
         output: &Sender<RenderProgress>,
         abort: &Receiver<bool>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.render_with_camera(&self.scene.camera, output, &|| abort.try_recv().is_ok())
+    }
+
+    /// Executes the rendering of the image from the named view of the scene.
+    /// The renderer can render several views without the world being created again
+    pub fn render_view(
+        &self,
+        view: &str,
+        output: &Sender<RenderProgress>,
+        abort: &Receiver<bool>,
+    ) -> Result<(), Box<dyn Error>> {
+        let camera = self
+            .scene
+            .views
+            .get(view)
+            .ok_or_else(|| SimpleError::new(format!("Scene has no view named '{}'", view)))?;
+        self.render_with_camera(camera, output, &|| abort.try_recv().is_ok())
+    }
+
+    /// Executes the rendering of the image, while following the commands sent to the
+    /// renderer. When the camera is moved the render starts over from the first sample,
+    /// without the world being created again. A finished render waits for more commands,
+    /// until it is aborted or the command channel is closed
+    pub fn render_interactive(
+        &self,
+        commands: &Receiver<RenderCommand>,
+        output: &Sender<RenderProgress>,
+        abort: &Receiver<bool>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut camera = self.scene.camera.clone();
+        loop {
+            let moved_camera = RefCell::new(None);
+            let aborted = Cell::new(false);
+            let should_stop = || {
+                // Only the last of several camera moves is rendered
+                while let Ok(RenderCommand::MoveCamera(camera)) = commands.try_recv() {
+                    *moved_camera.borrow_mut() = Some(camera);
+                }
+                if abort.try_recv().is_ok() {
+                    aborted.set(true);
+                }
+                aborted.get() || moved_camera.borrow().is_some()
+            };
+            self.render_with_camera(&camera, output, &should_stop)?;
+            if aborted.get() {
+                return Ok(());
+            }
+
+            camera = match moved_camera.into_inner() {
+                Some(camera) => camera,
+                None => loop {
+                    match commands.recv_timeout(ABORT_POLL_INTERVAL) {
+                        Ok(RenderCommand::MoveCamera(camera)) => break camera,
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                    }
+                    if abort.try_recv().is_ok() {
+                        return Ok(());
+                    }
+                },
+            };
+        }
+    }
+
+    fn render_with_camera(
+        &self,
+        camera: &CameraConfig,
+        output: &Sender<RenderProgress>,
+        abort: &dyn Fn() -> bool,
     ) -> Result<(), Box<dyn Error>> {
         let mut last_image_generated_time = SystemTime::UNIX_EPOCH;
         let render_start_time = SystemTime::now();
         let image_width = self.scene.render_config.width;
         let image_height = self.scene.render_config.height;
-        let pixel_count = image_width * image_height;
+        let region = self.scene.render_config.region();
+        let pixel_count = region.width * region.height;
         let samples_per_pixel = self.scene.render_config.samples_per_pixel;
         let needs_albedo_and_normal_colors =
-            !self.scene.render_config.needs_albedo_and_normal_colors();
-
-        let pixel_colors: Arc<Mutex<Vec<Vec3>>> =
-            Arc::new(Mutex::new(vec![ZERO_VECTOR; pixel_count]));
-        let albedo_colors: Arc<Mutex<Vec<Vec3>>> =
-            Arc::new(Mutex::new(vec![ZERO_VECTOR; pixel_count]));
-        let normal_colors: Arc<Mutex<Vec<Vec3>>> =
-            Arc::new(Mutex::new(vec![ZERO_VECTOR; pixel_count]));
+            self.scene.render_config.needs_albedo_and_normal_colors();
+        let samples_albedo_and_normal_colors =
+            self.scene.render_config.samples_albedo_and_normal_colors();
 
-        let camera = Arc::new(Camera::new(image_width, image_height, &self.scene.camera));
+        let accumulation = self.scene.render_config.accumulation;
+        let aov_pixel_count = if needs_albedo_and_normal_colors {
+            pixel_count
+        } else {
+            0
+        };
+        let buffers = ImageBuffers {
+            pixel_colors: Mutex::new(SampleSums::new(accumulation, true, 3, pixel_count)),
+            albedo_colors: Mutex::new(SampleSums::new(accumulation, false, 3, aov_pixel_count)),
+            normal_colors: Mutex::new(SampleSums::new(accumulation, false, 3, aov_pixel_count)),
+            sun_visibility: Mutex::new(SampleSums::new(
+                accumulation,
+                false,
+                1,
+                if self.needs_sun_visibility() { pixel_count } else { 0 },
+            )),
+            squared_luminances: Mutex::new(SampleSums::new(accumulation, false, 1, pixel_count)),
+        };
 
-        let pool = rayon::ThreadPoolBuilder::new()
-            .build()
-            .expect("Failed to create thread pool");
-
-        for sample in 1..=samples_per_pixel {
-            if abort.try_recv().is_ok() {
-                return Ok(());
+        let motion_vectors = match &self.scene.render_config.previous_camera {
+            Some(previous) if self.scene.render_config.aov_output => {
+                self.motion_vectors(camera, previous)
             }
+            _ => Vec::new(),
+        };
+        let camera = Camera::new(image_width, image_height, camera);
+        let tiles = tiles(region, image_height);
 
-            pool.scope(|s| {
-                for y in 0..image_height {
-                    let camera = camera.clone();
-                    let pixel_colors = pixel_colors.clone();
-                    let albedo_colors = albedo_colors.clone();
-                    let normal_colors = normal_colors.clone();
-
-                    s.spawn(move |_| {
-                        let mut row_pixel_colors: Vec<Vec3> = vec![ZERO_VECTOR; image_width];
-                        let mut row_albedo_colors: Vec<Vec3> = if needs_albedo_and_normal_colors {
-                            vec![ZERO_VECTOR; image_width]
-                        } else {
-                            Vec::new()
-                        };
-                        let mut row_normal_colors: Vec<Vec3> = if needs_albedo_and_normal_colors {
-                            vec![ZERO_VECTOR; image_width]
-                        } else {
-                            Vec::new()
-                        };
+        let threads = match self.scene.render_config.parallelism {
+            Parallelism::MultiThreaded => {
+                Some(RenderThreads::new(self.scene.render_config.thread_placement))
+            }
+            Parallelism::SingleThreaded(_) => None,
+        };
+        let tiles = match &threads {
+            Some(threads) if self.scene.render_config.warm_up => {
+                let durations = Mutex::new(vec![Duration::ZERO; tiles.len()]);
+                let aborted = AtomicBool::new(false);
+                for_each_tile(Some(threads), &tiles, abort, &aborted, |i, tile, is_aborted| {
+                    durations.lock().unwrap()[i] = self.warm_up_tile(*tile, &camera, is_aborted);
+                });
+                if aborted.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                slowest_first(tiles, durations.into_inner().unwrap())
+            }
+            _ => tiles,
+        };
 
-                        let yi = ((image_height - 1) - y) * image_width;
-                        for x in 0..image_width {
-                            let u = (x as f64 + random_normal_float()) / (image_width - 1) as f64;
-                            let v = (y as f64 + random_normal_float()) / (image_height - 1) as f64;
-                            let ray = camera.get_ray(Uv::new(u as f32, v as f32));
-                            let ray_color_res = self.ray_color(&ray, 0, 0.);
+        let aov_prepass_samples = match self.scene.render_config.aov_samples {
+            Some(aov_samples) if needs_albedo_and_normal_colors => aov_samples,
+            _ => 0,
+        };
+        if !self.render_aov_prepass(
+            &tiles,
+            &camera,
+            threads.as_ref(),
+            aov_prepass_samples,
+            &buffers,
+            abort,
+        ) {
+            return Ok(());
+        }
 
-                            row_pixel_colors[x] = ray_color_res.pixel_color.get_attenuated_color();
+        let tile_hashes = Mutex::new(Vec::new());
+        let tiles_done = AtomicUsize::new(0);
+        let add_tile_sample = |tile_sample: &TileSample, sample: u32| {
+            buffers.add(tile_sample, region, image_height);
+            if let Some(hash) = tile_sample.hash {
+                tile_hashes.lock().unwrap().push(hash);
+            }
+            if self.scene.render_config.tile_updates {
+                let done = tiles_done.fetch_add(1, Ordering::Relaxed) + 1;
+                let progress = ((sample - 1) as f64 + done as f64 / tiles.len() as f64)
+                    / samples_per_pixel as f64;
+                let tile = tile_sample.tile;
+                // The progress of the sample reports if the caller has stopped listening
+                let _ = output.send(RenderProgress {
+                    progress,
+                    fps: None,
+                    estimated_time_left: estimated_time_left_at(
+                        render_start_time,
+                        SystemTime::now(),
+                        progress,
+                    ),
+                    render_image: None,
+                    standard_errors: None,
+                    aovs: None,
+                    tile_errors: Vec::new(),
+                    tile_hashes: Vec::new(),
+                    tile_update: Some(TileUpdate {
+                        region: tile.image_region(image_height),
+                        sample,
+                        image: buffers.tile_image(
+                            tile,
+                            region,
+                            image_height,
+                            sample,
+                            self.scene.render_config.color_space,
+                        ),
+                    }),
+                });
+            }
+        };
 
-                            if needs_albedo_and_normal_colors {
-                                row_albedo_colors[x] = ray_color_res.albedo_color;
-                                row_normal_colors[x] = ray_color_res.normal_color;
-                            }
-                        }
+        for sample in 1..=samples_per_pixel {
+            if abort() {
+                return Ok(());
+            }
+            tiles_done.store(0, Ordering::Relaxed);
 
-                        add_row_data(yi, &mut pixel_colors.lock().unwrap(), &row_pixel_colors);
-                        if needs_albedo_and_normal_colors {
-                            add_row_data(
-                                yi,
-                                &mut albedo_colors.lock().unwrap(),
-                                &row_albedo_colors,
-                            );
-                            add_row_data(
-                                yi,
-                                &mut normal_colors.lock().unwrap(),
-                                &row_normal_colors,
-                            );
-                        }
-                    });
+            let aborted = AtomicBool::new(false);
+            let tile_errors = Mutex::new(Vec::new());
+            // Tiles are kept in the memory of the NUMA node of the thread that rendered them,
+            // and added to the shared buffers in one go at the end of the sample
+            let node_samples: Vec<Mutex<Vec<TileSample>>> = match &threads {
+                Some(threads)
+                    if self.scene.render_config.thread_placement
+                        == ThreadPlacement::NumaAware =>
+                {
+                    (0..threads.node_count())
+                        .map(|_| Mutex::new(Vec::new()))
+                        .collect()
+                }
+                _ => Vec::new(),
+            };
+            for_each_tile(threads.as_ref(), &tiles, abort, &aborted, |_, tile, is_aborted| {
+                let res = self.render_tile(
+                    *tile,
+                    sample - 1,
+                    &camera,
+                    is_aborted,
+                    samples_albedo_and_normal_colors,
+                );
+                let node = threads.as_ref().map_or(0, |threads| threads.current_node());
+                match res {
+                    Ok(Some(tile_sample)) => match node_samples.get(node) {
+                        Some(samples) => samples.lock().unwrap().push(tile_sample),
+                        None => add_tile_sample(&tile_sample, sample),
+                    },
+                    Ok(None) => {}
+                    Err(e) => tile_errors.lock().unwrap().push(e),
                 }
             });
+            if aborted.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            for samples in node_samples {
+                for tile_sample in samples.into_inner().unwrap() {
+                    add_tile_sample(&tile_sample, sample);
+                }
+            }
 
             {
                 let now = SystemTime::now();
-                let render_image = if self
+                let (render_image, standard_errors, aovs) = if self
                     .scene
                     .render_config
                     .render_image_strategy
@@ -304,43 +1178,119 @@ impl Renderer {
                     ) {
                     last_image_generated_time = now;
 
-                    if let Some((last_post_processor, intermediate_post_processors)) =
+                    let aov_samples = if samples_albedo_and_normal_colors {
+                        sample
+                    } else {
+                        aov_prepass_samples
+                    };
+                    let (albedo_colors, normal_colors) = if needs_albedo_and_normal_colors {
+                        (
+                            buffers.albedo_colors.lock().unwrap().mean_colors(aov_samples),
+                            buffers.normal_colors.lock().unwrap().mean_colors(aov_samples),
+                        )
+                    } else {
+                        (Vec::new(), Vec::new())
+                    };
+
+                    let (render_image, standard_errors) = if let Some((
+                        last_post_processor,
+                        intermediate_post_processors,
+                    )) =
                         self.scene.render_config.post_processors.split_last()
                     {
-                        if abort.try_recv().is_ok() {
+                        if abort() {
                             return Ok(());
                         }
 
-                        let mut intermediate_pixel_colors = pixel_colors.lock().unwrap().clone();
+                        // Post processors get the mean of the samples,
+                        // so they work the same regardless of how many samples are taken
+                        let mut intermediate_pixel_colors =
+                            buffers.pixel_colors.lock().unwrap().mean_colors(sample);
+                        let standard_errors = standard_errors(
+                            &intermediate_pixel_colors,
+                            &buffers.squared_luminances.lock().unwrap(),
+                            sample,
+                        );
 
                         for ipp in intermediate_post_processors {
                             let processed_pixel_colors = ipp.intermediate_post_process(
-                                &intermediate_pixel_colors,
-                                albedo_colors.lock().unwrap().deref(),
-                                normal_colors.lock().unwrap().deref(),
-                                image_width as u32,
-                                image_height as u32,
-                                sample,
+                                &colors_for(ipp, intermediate_pixel_colors),
+                                &albedo_colors,
+                                &normal_colors,
+                                &standard_errors,
+                                region.width as u32,
+                                region.height as u32,
                             )?;
 
                             intermediate_pixel_colors = processed_pixel_colors;
                         }
 
-                        Some(last_post_processor.post_process(
-                            &intermediate_pixel_colors,
-                            albedo_colors.lock().unwrap().deref(),
-                            normal_colors.lock().unwrap().deref(),
-                            image_width as u32,
-                            image_height as u32,
-                            sample,
-                        )?)
+                        let pixel_colors =
+                            colors_for(last_post_processor, intermediate_pixel_colors);
+                        let (width, height) = (region.width as u32, region.height as u32);
+                        let render_image = match self.scene.render_config.image_format {
+                            ImageFormat::Rgb32F if last_post_processor.supports_intermediate() => {
+                                RenderImage::Rgb32F(to_rgb32f_image(
+                                    &last_post_processor.intermediate_post_process(
+                                        &pixel_colors,
+                                        &albedo_colors,
+                                        &normal_colors,
+                                        &standard_errors,
+                                        width,
+                                        height,
+                                    )?,
+                                    width,
+                                    height,
+                                ))
+                            }
+                            format => {
+                                // Maps the scene referred colors to the display,
+                                // while float images are kept in the color space
+                                let color_space = self.scene.render_config.color_space;
+                                let pixel_colors = match (color_space, format) {
+                                    (ColorSpace::LinearSrgb, _) | (_, ImageFormat::Rgb32F) => {
+                                        pixel_colors
+                                    }
+                                    (ColorSpace::AcesCg, ImageFormat::Rgb8) => pixel_colors
+                                        .into_iter()
+                                        .map(|c| color_space.to_display(c))
+                                        .collect(),
+                                };
+                                let image = last_post_processor.post_process(
+                                    &pixel_colors,
+                                    &albedo_colors,
+                                    &normal_colors,
+                                    &standard_errors,
+                                    width,
+                                    height,
+                                )?;
+                                if format == ImageFormat::Rgb32F {
+                                    RenderImage::Rgb32F(linear_image(&image))
+                                } else {
+                                    RenderImage::Rgb8(image)
+                                }
+                            }
+                        };
+                        (Some(render_image), Some(standard_errors))
                     } else {
-                        None
-                    }
+                        (None, None)
+                    };
+
+                    let aovs = self.scene.render_config.aov_output.then(|| Aovs {
+                        albedo_colors,
+                        normal_colors,
+                        motion_vectors: motion_vectors.clone(),
+                        sun_visibility: buffers.sun_visibility.lock().unwrap().means(aov_samples),
+                    });
+                    (render_image, standard_errors, aovs)
                 } else {
-                    None
+                    (None, None, None)
                 };
 
+                // Tiles are done in any order, but reported in the same order every time
+                let mut sample_tile_hashes = std::mem::take(&mut *tile_hashes.lock().unwrap());
+                sample_tile_hashes.sort_by_key(|h| (h.region.y, h.region.x));
+
                 output.send(RenderProgress {
                     progress: sample as f64 / samples_per_pixel as f64,
                     fps: Some(calculate_fps(render_start_time, now, sample)),
@@ -351,6 +1301,11 @@ impl Renderer {
                         samples_per_pixel,
                     ),
                     render_image,
+                    standard_errors,
+                    aovs,
+                    tile_errors: tile_errors.into_inner().unwrap(),
+                    tile_hashes: sample_tile_hashes,
+                    tile_update: None,
                 })?
             }
         }
@@ -358,12 +1313,292 @@ impl Renderer {
     }
 }
 
-fn add_row_data(yi: usize, colors: &mut [Vec3], row_colors: &[Vec3]) {
-    for (x, c) in row_colors.iter().enumerate() {
-        colors[yi + x] += *c;
+/// A rectangular part of the image, with y going up from the bottom of the image
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Tile {
+    /// The tile as a region of the image, with y going down from the top of the image
+    fn image_region(&self, image_height: usize) -> ImageRegion {
+        ImageRegion {
+            x: self.x,
+            y: image_height - (self.y + self.height),
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+/// One sample of every pixel of a tile, row by row from the bottom
+struct TileSample {
+    tile: Tile,
+    pixel_colors: Vec<Vec3>,
+    albedo_colors: Vec<Vec3>,
+    normal_colors: Vec<Vec3>,
+    sun_visibility: Vec<f64>,
+    hash: Option<TileHash>,
+}
+
+/// The buffers that the samples of the pixels of the rendered region are summed in
+struct ImageBuffers {
+    pixel_colors: Mutex<SampleSums>,
+    albedo_colors: Mutex<SampleSums>,
+    normal_colors: Mutex<SampleSums>,
+    sun_visibility: Mutex<SampleSums>,
+    squared_luminances: Mutex<SampleSums>,
+}
+
+impl ImageBuffers {
+    /// Adds the sample of the tile to the sums of its pixels.
+    /// Only the colors that the tile has are added
+    fn add(&self, tile_sample: &TileSample, region: ImageRegion, image_height: usize) {
+        let tile = tile_sample.tile;
+        if !tile_sample.pixel_colors.is_empty() {
+            let mut pixel_colors = self.pixel_colors.lock().unwrap();
+            add_tile_data(tile, region, image_height, &tile_sample.pixel_colors, |i, c| {
+                pixel_colors.add_color(i, c)
+            });
+            let mut squared_luminances = self.squared_luminances.lock().unwrap();
+            add_tile_data(tile, region, image_height, &tile_sample.pixel_colors, |i, c| {
+                squared_luminances.add(i, &[luminance(c).powi(2)])
+            });
+        }
+        if !tile_sample.albedo_colors.is_empty() {
+            let mut albedo_colors = self.albedo_colors.lock().unwrap();
+            add_tile_data(tile, region, image_height, &tile_sample.albedo_colors, |i, c| {
+                albedo_colors.add_color(i, c)
+            });
+            let mut normal_colors = self.normal_colors.lock().unwrap();
+            add_tile_data(tile, region, image_height, &tile_sample.normal_colors, |i, c| {
+                normal_colors.add_color(i, c)
+            });
+        }
+        if !tile_sample.sun_visibility.is_empty() {
+            let mut sun_visibility = self.sun_visibility.lock().unwrap();
+            add_tile_data(tile, region, image_height, &tile_sample.sun_visibility, |i, v| {
+                sun_visibility.add(i, &[v])
+            });
+        }
+    }
+
+    /// Image of the mean colors of the pixels of the tile, after the given number of samples,
+    /// mapped to the display like the final image
+    fn tile_image(
+        &self,
+        tile: Tile,
+        region: ImageRegion,
+        image_height: usize,
+        samples: u32,
+        color_space: ColorSpace,
+    ) -> RgbImage {
+        let pixel_colors = self.pixel_colors.lock().unwrap();
+        let top = tile.image_region(image_height).y - region.y;
+        RgbImage::from_fn(tile.width as u32, tile.height as u32, |x, y| {
+            let i = (top + y as usize) * region.width + tile.x - region.x + x as usize;
+            let sum = |channel| pixel_colors.sum(i, channel);
+            to_rgb_color(color_space.to_display(
+                Vec3::new(sum(0), sum(1), sum(2)) / samples as f64,
+            ))
+        })
+    }
+}
+
+/// Light emitted by the light that the ray hit, with the falloff over the distance to it
+fn emitted_color(ray: &Ray, light_rec: &RayHit) -> Vec3 {
+    match light_rec.material.scatter(ray, light_rec, &[]) {
+        RayScatter::ScatterEmission(s) => AttenuatedColor {
+            color: s.color,
+            attenuation: s.attenuation,
+            accumulated_ray_length: light_rec.ray_length,
+        }
+        .get_attenuated_color(),
+        _ => ZERO_VECTOR,
+    }
+}
+
+/// The message that a panic was raised with
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|m| m.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Unknown panic".to_string())
+}
+
+/// Splits the region of the image into tiles of at most [`TILE_SIZE`] pixels
+/// in width and height
+fn tiles(region: ImageRegion, image_height: usize) -> Vec<Tile> {
+    let bottom = image_height - (region.y + region.height);
+    let top = image_height - region.y;
+    (bottom..top)
+        .step_by(TILE_SIZE)
+        .flat_map(|y| {
+            (region.x..region.x + region.width)
+                .step_by(TILE_SIZE)
+                .map(move |x| Tile {
+                    x,
+                    y,
+                    width: TILE_SIZE.min(region.x + region.width - x),
+                    height: TILE_SIZE.min(top - y),
+                })
+        })
+        .collect()
+}
+
+/// Renders each of the tiles with the function, which is given the position of the tile and
+/// a check of whether rendering has been aborted, to call between the rows of the tile.
+/// On the render threads idle threads steal the remaining tiles from the busy ones, while the
+/// calling thread polls for an abort and sets the flag that the check reads, so that
+/// an abort takes effect without waiting for all the tiles to be done. Without threads the
+/// tiles are rendered on the calling thread, and the check polls for an abort itself
+fn for_each_tile(
+    threads: Option<&RenderThreads>,
+    tiles: &[Tile],
+    abort: &dyn Fn() -> bool,
+    aborted: &AtomicBool,
+    render: impl Fn(usize, &Tile, &dyn Fn() -> bool) + Sync,
+) {
+    let Some(threads) = threads else {
+        let is_aborted = || {
+            if abort() {
+                aborted.store(true, Ordering::Relaxed);
+            }
+            aborted.load(Ordering::Relaxed)
+        };
+        for (i, tile) in tiles.iter().enumerate() {
+            if is_aborted() {
+                break;
+            }
+            render(i, tile, &is_aborted);
+        }
+        return;
+    };
+
+    threads.pool.in_place_scope(|s| {
+        let (done_sender, done_receiver) = channel();
+        for (i, tile) in tiles.iter().enumerate() {
+            let render = &render;
+            let done_sender = done_sender.clone();
+            s.spawn(move |_| {
+                render(i, tile, &|| aborted.load(Ordering::Relaxed));
+                let _ = done_sender.send(());
+            });
+        }
+
+        let mut tiles_left = tiles.len();
+        while tiles_left > 0 {
+            if abort() {
+                aborted.store(true, Ordering::Relaxed);
+            }
+            match done_receiver.recv_timeout(ABORT_POLL_INTERVAL) {
+                Ok(()) => tiles_left -= 1,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+}
+
+/// Orders the tiles by how long they took to render, with the slowest first. Tiles are started
+/// in order, so the last tiles of a sample are quick ones that keep all threads busy
+fn slowest_first(tiles: Vec<Tile>, durations: Vec<Duration>) -> Vec<Tile> {
+    let mut tiles: Vec<(Tile, Duration)> = tiles.into_iter().zip(durations).collect();
+    tiles.sort_by(|(_, a), (_, b)| b.cmp(a));
+    tiles.into_iter().map(|(tile, _)| tile).collect()
+}
+
+/// Adds the data of a tile to the data of the rendered region of the image,
+/// which is stored row by row from the top. Each value of the tile is passed to `add`
+/// with its index in the data of the region
+fn add_tile_data<T: Copy>(
+    tile: Tile,
+    region: ImageRegion,
+    image_height: usize,
+    tile_data: &[T],
+    mut add: impl FnMut(usize, T),
+) {
+    for (ty, row) in tile_data.chunks_exact(tile.width).enumerate() {
+        let region_row = image_height - 1 - (tile.y + ty) - region.y;
+        let i = region_row * region.width + tile.x - region.x;
+        for (tx, t) in row.iter().enumerate() {
+            add(i + tx, *t);
+        }
+    }
+}
+
+/// Seed of the random number generator for a sample of a pixel, so that the pixel
+/// gets the same samples regardless of which region of the image is rendered
+fn pixel_seed(seed: u64, x: usize, y: usize, sample_index: u32) -> u64 {
+    seed ^ (x as u64).wrapping_mul(0x9e3779b97f4a7c15)
+        ^ (y as u64).wrapping_mul(0xc2b2ae3d27d4eb4f)
+        ^ (sample_index as u64).wrapping_mul(0x165667b19e3779f9)
+}
+
+/// Hash of the bits of the colors, which is the same on all platforms
+fn hash_colors(colors: &[Vec3]) -> u64 {
+    let bytes: Vec<u8> = colors
+        .iter()
+        .flat_map(|c| [c.x, c.y, c.z])
+        .flat_map(f64::to_le_bytes)
+        .collect();
+    xxh3_64(&bytes)
+}
+
+/// Image of the linear colors, row by row
+fn to_rgb32f_image(colors: &[Vec3], width: u32, height: u32) -> Rgb32FImage {
+    Rgb32FImage::from_fn(width, height, |x, y| {
+        let c = colors[(y * width + x) as usize];
+        Rgb([c.x as f32, c.y as f32, c.z as f32])
+    })
+}
+
+/// Converts a gamma corrected image back to linear colors
+fn linear_image(image: &RgbImage) -> Rgb32FImage {
+    Rgb32FImage::from_fn(image.width(), image.height(), |x, y| {
+        let c = rgb_to_vec3(image.get_pixel(x, y));
+        Rgb([(c.x * c.x) as f32, (c.y * c.y) as f32, (c.z * c.z) as f32])
+    })
+}
+
+/// Clamps the colors for post processors that can not handle high dynamic range
+fn colors_for(post_processor: &PostProcessors, colors: Vec<Vec3>) -> Vec<Vec3> {
+    if post_processor.wants_hdr() {
+        colors
+    } else {
+        colors
+            .iter()
+            .map(|c| Vec3::new(c.x.clamp(0., 1.), c.y.clamp(0., 1.), c.z.clamp(0., 1.)))
+            .collect()
     }
 }
 
+/// Standard error of the mean luminance of each pixel, which is infinite until there are two samples
+fn standard_errors(
+    mean_colors: &[Vec3],
+    squared_luminances: &SampleSums,
+    num_samples: u32,
+) -> Vec<f64> {
+    if num_samples < 2 {
+        return vec![f64::INFINITY; mean_colors.len()];
+    }
+    let n = num_samples as f64;
+    mean_colors
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let squared_luminance = squared_luminances.sum(i, 0);
+            let mean = luminance(*c);
+            let variance = ((squared_luminance / n - mean * mean) * n / (n - 1.)).max(0.);
+            (variance / n).sqrt()
+        })
+        .collect()
+}
+
 fn calculate_fps(render_start_time: SystemTime, now: SystemTime, samples_done: u32) -> f64 {
     let time_since_start = now
         .duration_since(render_start_time)
@@ -372,6 +1607,18 @@ fn calculate_fps(render_start_time: SystemTime, now: SystemTime, samples_done: u
     samples_done as f64 / time_since_start.as_secs_f64()
 }
 
+/// Estimated time left until rendering is complete, when the given fraction of it is done
+fn estimated_time_left_at(
+    render_start_time: SystemTime,
+    now: SystemTime,
+    progress: f64,
+) -> Duration {
+    let time_since_start = now
+        .duration_since(render_start_time)
+        .unwrap_or(Duration::from_millis(1));
+    time_since_start.mul_f64((1. - progress) / progress)
+}
+
 fn calculate_estimated_time_left(
     render_start_time: SystemTime,
     now: SystemTime,
@@ -392,7 +1639,27 @@ fn calculate_estimated_time_left(
 mod test {
     use std::time::{Duration, SystemTime};
 
-    use crate::renderer::{calculate_estimated_time_left, calculate_fps};
+    use std::collections::HashMap;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::Mutex;
+
+    use image::{Rgb, Rgb32FImage};
+
+    use crate::camera::{Camera, CameraConfig};
+    use crate::geo::vec3::{Vec3, ZERO_VECTOR};
+    use crate::hittable::{Bvh, EnvironmentMap, Sphere};
+    use crate::material::texture::SolidColor;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::post::{PostProcessors, ToneMapOperator, ToneMapPostProcessor};
+    use crate::renderer::accumulation::SampleSums;
+    use crate::renderer::affinity::RenderThreads;
+    use crate::renderer::background::CustomBackground;
+    use crate::renderer::{
+        add_tile_data, calculate_estimated_time_left, calculate_fps, slowest_first, tiles,
+        Accumulation, ImageBuffers, ImageRegion, Parallelism, RenderCommand, RenderConfig,
+        RenderImage, RenderProgress, Renderer, Scene, ThreadPlacement, Tile, TILE_SIZE,
+    };
+    use crate::util::color_space::{srgb_to_acescg, ColorSpace};
 
     #[test]
     fn test_calculate_fps() {
@@ -417,4 +1684,310 @@ mod test {
         time_left = calculate_estimated_time_left(render_start, now, 100, 100);
         assert_eq!(time_left, Duration::from_secs(0));
     }
+
+    #[test]
+    fn test_tiles_cover_image() {
+        let (width, height) = (TILE_SIZE * 2 + 5, TILE_SIZE + 1);
+        let region = ImageRegion {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        let tiles = tiles(region, height);
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(
+            tiles[5],
+            Tile {
+                x: TILE_SIZE * 2,
+                y: TILE_SIZE,
+                width: 5,
+                height: 1
+            }
+        );
+
+        let mut counts = vec![0; width * height];
+        for tile in tiles {
+            add_tile_data(
+                tile,
+                region,
+                height,
+                &vec![1; tile.width * tile.height],
+                |i, c| counts[i] += c,
+            );
+        }
+        assert!(counts.iter().all(|c| *c == 1));
+    }
+
+    #[test]
+    fn test_tiles_cover_region() {
+        let region = ImageRegion {
+            x: 10,
+            y: 5,
+            width: TILE_SIZE + 3,
+            height: 7,
+        };
+        let tiles = tiles(region, 100);
+        assert_eq!(tiles.len(), 2);
+        assert_eq!(tiles[0].x, 10);
+        assert_eq!(tiles[0].y, 100 - 5 - 7);
+
+        let mut counts = vec![0; region.width * region.height];
+        for tile in tiles {
+            add_tile_data(
+                tile,
+                region,
+                100,
+                &vec![1; tile.width * tile.height],
+                |i, c| counts[i] += c,
+            );
+        }
+        assert!(counts.iter().all(|c| *c == 1));
+    }
+
+    #[test]
+    fn test_split_image_regions() {
+        let regions = ImageRegion::split(10, 5, 4, 4);
+        assert_eq!(regions.len(), 6);
+        assert_eq!(
+            regions[5],
+            ImageRegion {
+                x: 8,
+                y: 4,
+                width: 2,
+                height: 1
+            }
+        );
+        let area: usize = regions.iter().map(|r| r.width * r.height).sum();
+        assert_eq!(area, 50);
+    }
+
+    #[test]
+    fn test_slowest_tiles_first() {
+        let region = ImageRegion {
+            x: 0,
+            y: 0,
+            width: TILE_SIZE * 3,
+            height: TILE_SIZE,
+        };
+        let tiles = tiles(region, TILE_SIZE);
+        let durations = [2, 5, 1].map(Duration::from_millis).to_vec();
+
+        let ordered = slowest_first(tiles.clone(), durations);
+        assert_eq!(ordered, vec![tiles[1], tiles[0], tiles[2]]);
+    }
+
+    #[test]
+    fn test_aov_prepass() {
+        let color = Vec3::new(0.5, 0.25, 1.);
+        for parallelism in [Parallelism::SingleThreaded(1), Parallelism::MultiThreaded] {
+            // The camera is inside the sphere, so every pixel sees it
+            let scene = Scene {
+                world: Sphere::new(
+                    ZERO_VECTOR,
+                    1000.,
+                    Lambertian::new(SolidColor::new(color.x, color.y, color.z), None),
+                ),
+                camera: CameraConfig {
+                    look_at: Vec3::new(0., 0., -1.),
+                    ..CameraConfig::default()
+                },
+                views: HashMap::new(),
+                background_color: ZERO_VECTOR,
+                environment_map: Some(EnvironmentMap::new(
+                    Rgb32FImage::from_pixel(2, 2, Rgb([1., 1., 1.])),
+                    1.,
+                )),
+                background: None,
+                render_config: RenderConfig {
+                    width: TILE_SIZE + 3,
+                    height: 10,
+                    parallelism,
+                    ..RenderConfig::default()
+                },
+            };
+            let renderer = Renderer::new(scene).unwrap();
+            let config = &renderer.scene.render_config;
+            let camera = Camera::new(config.width, config.height, &renderer.scene.camera);
+            let tiles = tiles(config.region(), config.height);
+            let threads = match parallelism {
+                Parallelism::MultiThreaded => Some(RenderThreads::new(ThreadPlacement::Unpinned)),
+                Parallelism::SingleThreaded(_) => None,
+            };
+            let pixel_count = config.width * config.height;
+            let sums = || Mutex::new(SampleSums::new(Accumulation::F64, false, 3, pixel_count));
+            let buffers = ImageBuffers {
+                pixel_colors: sums(),
+                albedo_colors: sums(),
+                normal_colors: sums(),
+                sun_visibility: sums(),
+                squared_luminances: sums(),
+            };
+
+            let no_abort = || false;
+            let done = renderer.render_aov_prepass(
+                &tiles,
+                &camera,
+                threads.as_ref(),
+                3,
+                &buffers,
+                &no_abort,
+            );
+
+            assert!(done);
+            let albedo_colors = buffers.albedo_colors.lock().unwrap().mean_colors(3);
+            assert_eq!(albedo_colors.len(), pixel_count);
+            assert!(albedo_colors.iter().all(|c| (*c - color).length() < 1e-9));
+            let pixel_colors = buffers.pixel_colors.lock().unwrap().mean_colors(1);
+            assert!(pixel_colors.iter().all(|c| *c == ZERO_VECTOR));
+        }
+    }
+
+    #[test]
+    fn test_background() {
+        let scene = |environment_map| Scene {
+            // A light behind the camera, as the renderer needs one
+            world: Sphere::new(Vec3::new(0., 0., -10.), 1., DiffuseLight::new(1., 1., 1., None)),
+            camera: CameraConfig::default(),
+            views: HashMap::new(),
+            background_color: Vec3::new(1., 1., 1.),
+            environment_map,
+            background: Some(CustomBackground::new(|direction: Vec3| direction)),
+            render_config: RenderConfig::default(),
+        };
+        let renderer = Renderer::new(scene(None)).unwrap();
+        let color = renderer.incident_radiance(ZERO_VECTOR, Vec3::new(0., 3., 4.));
+        assert_eq!(color, Vec3::new(0., 0.6, 0.8));
+
+        // The environment map is seen instead of the background
+        let image = Rgb32FImage::from_pixel(2, 2, Rgb([0.5, 0.5, 0.5]));
+        let renderer = Renderer::new(scene(Some(EnvironmentMap::new(image, 1.)))).unwrap();
+        let color = renderer.incident_radiance(ZERO_VECTOR, Vec3::new(0., 3., 4.));
+        assert_eq!(color, Vec3::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn test_motion_vectors() {
+        let camera = CameraConfig {
+            look_from: Vec3::new(0., 0., 5.),
+            ..CameraConfig::default()
+        };
+        let render_config = RenderConfig {
+            width: 20,
+            height: 10,
+            ..RenderConfig::default()
+        };
+        let renderer = Renderer::new(Scene {
+            world: Sphere::new(Vec3::new(0., 0., 0.), 1., DiffuseLight::new(1., 1., 1., None)),
+            camera: camera.clone(),
+            views: HashMap::new(),
+            background_color: ZERO_VECTOR,
+            environment_map: None,
+            background: None,
+            render_config,
+        })
+        .unwrap();
+
+        // A camera that has not moved has no motion
+        let motion_vectors = renderer.motion_vectors(&camera, &camera);
+        assert_eq!(motion_vectors.len(), 20 * 10);
+        assert!(motion_vectors
+            .iter()
+            .all(|(x, y)| x.abs() < 1e-6 && y.abs() < 1e-6));
+
+        // When the previous camera was to the right, the sphere was further left in the image,
+        // while the background stays in place
+        let previous = CameraConfig {
+            look_from: Vec3::new(1., 0., 5.),
+            look_at: Vec3::new(1., 0., 0.),
+            ..camera.clone()
+        };
+        let motion_vectors = renderer.motion_vectors(&camera, &previous);
+        let (x, y) = motion_vectors[5 * 20 + 10];
+        assert!(x < -1. && y.abs() < 1e-6, "{} {}", x, y);
+        let (x, y) = motion_vectors[0];
+        assert!(x.abs() < 1e-3 && y.abs() < 1e-3, "{} {}", x, y);
+    }
+
+    fn acescg_scene(post_processors: Vec<PostProcessors>) -> Scene {
+        let red = Lambertian::new(SolidColor::new(1., 0., 0.), None);
+        Scene {
+            world: Bvh::new(vec![
+                Sphere::new(Vec3::new(0., 0., -10.), 1., DiffuseLight::new(1., 1., 1., None)),
+                Sphere::new(Vec3::new(0., 0., 10.), 1., red),
+            ]),
+            camera: CameraConfig::default(),
+            views: HashMap::new(),
+            background_color: Vec3::new(0., 1., 0.),
+            environment_map: None,
+            background: None,
+            render_config: RenderConfig {
+                color_space: ColorSpace::AcesCg,
+                post_processors,
+                ..RenderConfig::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_acescg_working_space() {
+        let renderer = Renderer::new(acescg_scene(Vec::new())).unwrap();
+
+        // The background is converted to ACEScg
+        let color = renderer.incident_radiance(ZERO_VECTOR, Vec3::new(0., 1., 0.));
+        assert_eq!(color, srgb_to_acescg(Vec3::new(0., 1., 0.)));
+
+        // Light reflected by the red sphere has the green and blue of red in ACEScg
+        let color = renderer.incident_radiance(ZERO_VECTOR, Vec3::new(0., 0., 1.));
+        assert!(color.y > 0. && color.z > 0., "{:?}", color);
+    }
+
+    #[test]
+    fn test_acescg_rejects_tone_mapping() {
+        let tone_map = ToneMapPostProcessor::new(ToneMapOperator::Aces, 0.);
+        assert!(Renderer::new(acescg_scene(vec![tone_map])).is_err());
+    }
+
+    #[test]
+    fn test_render_interactive_moves_camera() {
+        let camera = |z| CameraConfig {
+            look_at: Vec3::new(0., 0., z),
+            ..CameraConfig::default()
+        };
+        let renderer = Renderer::new(Scene {
+            world: Sphere::new(Vec3::new(0., 0., -10.), 9., DiffuseLight::new(1., 1., 1., None)),
+            camera: camera(-1.),
+            views: HashMap::new(),
+            background_color: ZERO_VECTOR,
+            environment_map: None,
+            background: None,
+            render_config: RenderConfig {
+                width: 4,
+                height: 2,
+                samples_per_pixel: 1,
+                ..RenderConfig::default()
+            },
+        })
+        .unwrap();
+
+        let (output_sender, output) = channel();
+        let (_abort_sender, abort) = channel();
+        let final_image = |output: &Receiver<RenderProgress>| {
+            let images: Vec<RenderImage> =
+                output.try_iter().filter_map(|p| p.render_image).collect();
+            assert_eq!(images.len(), 1);
+            images[0].clone().into_rgb8()
+        };
+        renderer.render(&output_sender, &abort).unwrap();
+        assert!(final_image(&output).pixels().all(|p| p.0 != [0, 0, 0]));
+
+        let (command_sender, commands) = channel();
+        // The camera turns away from the light, and the render returns once it is finished
+        // as no more commands can be sent
+        command_sender.send(RenderCommand::MoveCamera(camera(1.))).unwrap();
+        drop(command_sender);
+        renderer.render_interactive(&commands, &output_sender, &abort).unwrap();
+
+        assert!(final_image(&output).pixels().all(|p| p.0 == [0, 0, 0]));
+    }
 }
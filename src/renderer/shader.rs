@@ -78,7 +78,7 @@ impl Shader for PathTracingShader {
             ScatterEmission(s) => {
                 AttenuatedColor {
                     color: s.color,
-                    attenuation_factor: s.attenuation_factor,
+                    attenuation: s.attenuation,
                     accumulated_ray_length: total_ray_length,
                 }
             }
@@ -88,7 +88,7 @@ impl Shader for PathTracingShader {
 
                 AttenuatedColor {
                     color: s.color * ray_color_res.pixel_color.color,
-                    attenuation_factor: ray_color_res.pixel_color.attenuation_factor,
+                    attenuation: ray_color_res.pixel_color.attenuation,
                     accumulated_ray_length: ray_color_res.pixel_color.accumulated_ray_length,
                 }
             }
@@ -98,7 +98,7 @@ impl Shader for PathTracingShader {
 
                 AttenuatedColor {
                     color: filter_invalid_color_values(scatter_color),
-                    attenuation_factor: ray_color_res.pixel_color.attenuation_factor,
+                    attenuation: ray_color_res.pixel_color.attenuation,
                     accumulated_ray_length: ray_color_res.pixel_color.accumulated_ray_length,
                 }
             }
@@ -141,7 +141,7 @@ impl Shader for AlbedoShader {
     fn shade(&self, renderer: &Renderer, rec: &RayHit, ray: &Ray, _: u32, _: f64) -> AttenuatedColor {
         AttenuatedColor {
             color: match rec.material.scatter(ray, rec, &renderer.lights) {
-                ScatterEmission(s) => s.color,
+                ScatterEmission(s) => emission_albedo(s.color),
                 ScatterBasic(s) => s.color,
                 ScatterPdf(s) => s.color
             },
@@ -150,6 +150,17 @@ impl Shader for AlbedoShader {
     }
 }
 
+/// Albedo of an emissive surface, which is the color of the emitted light scaled down
+/// to at most 1, so that the pattern of a textured light is kept at any strength
+fn emission_albedo(color: Vec3) -> Vec3 {
+    let max = color.x.max(color.y).max(color.z);
+    if max > 1. {
+        color / max
+    } else {
+        color
+    }
+}
+
 #[derive(Clone)]
 /// Outputs the normals of the ray hit point
 pub struct NormalShader {}
@@ -193,7 +204,7 @@ impl Shader for SimpleShader {
     fn shade(&self, renderer: &Renderer, rec: &RayHit, ray: &Ray, _: u32, _: f64) -> AttenuatedColor {
         AttenuatedColor {
             color: match rec.material.scatter(ray, rec, &renderer.lights) {
-                ScatterEmission(s) => s.color,
+                ScatterEmission(s) => emission_albedo(s.color),
                 ScatterBasic(s) => {
                     // Get a factor to multiply attenuation color, range between .25 -> 1.25
                     // To get some decent flat shading
@@ -213,3 +224,14 @@ impl Shader for SimpleShader {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emission_albedo() {
+        assert_eq!(emission_albedo(Vec3::new(10., 5., 0.)), Vec3::new(1., 0.5, 0.));
+        assert_eq!(emission_albedo(Vec3::new(0.5, 0.25, 0.)), Vec3::new(0.5, 0.25, 0.));
+    }
+}
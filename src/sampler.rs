@@ -0,0 +1,169 @@
+//! Samplers deciding how two dimensional sample points, such as points on area lights,
+//! are distributed over the samples of a pixel.
+//!
+//! The renderer tells the sampler which pixel and sample it is working on, and
+//! hittables request points from it using [`next_2d`].
+//! Outside of rendering all points are plain random ones.
+
+use std::cell::Cell;
+
+use crate::random::random_normal_float;
+
+/// Available strategies for distributing two dimensional sample points
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sampler {
+    /// Every point is independently random
+    Random,
+    /// The unit square is divided into a grid with one cell per sample of a pixel,
+    /// and each sample of the pixel gets a random point within its own cell.
+    /// Gives faster converging soft shadows from area lights
+    Stratified,
+}
+
+#[derive(Copy, Clone)]
+struct SampleContext {
+    sampler: Sampler,
+    pixel_hash: u32,
+    sample_index: u32,
+    strata_per_side: u32,
+    dimension: u32,
+}
+
+thread_local! {
+    static SAMPLE_CONTEXT: Cell<SampleContext> = const {
+        Cell::new(SampleContext {
+            sampler: Sampler::Random,
+            pixel_hash: 0,
+            sample_index: 0,
+            strata_per_side: 1,
+            dimension: 0,
+        })
+    };
+}
+
+/// Sets which sample of which pixel the current thread is rendering,
+/// sample index starting at 0
+pub(crate) fn start_pixel_sample(
+    sampler: Sampler,
+    x: usize,
+    y: usize,
+    sample_index: u32,
+    samples_per_pixel: u32,
+) {
+    SAMPLE_CONTEXT.with(|c| {
+        c.set(SampleContext {
+            sampler,
+            pixel_hash: hash(
+                (x as u32).wrapping_mul(0x8da6b343) ^ (y as u32).wrapping_mul(0xd8163841),
+            ),
+            sample_index,
+            strata_per_side: ((samples_per_pixel as f64).sqrt() as u32).max(1),
+            dimension: 0,
+        })
+    })
+}
+
+/// Returns the next point in the unit square for the current sample.
+/// Each call within a sample uses a separate stratification, so that
+/// subsequent bounces are not correlated with each other
+pub fn next_2d() -> (f64, f64) {
+    SAMPLE_CONTEXT.with(|c| {
+        let mut context = c.get();
+        let strata = context.strata_per_side * context.strata_per_side;
+
+        if context.sampler == Sampler::Random || context.sample_index >= strata {
+            return (random_normal_float(), random_normal_float());
+        }
+
+        let dimension_hash = hash(context.pixel_hash ^ context.dimension.wrapping_mul(0x9e3779b9));
+        context.dimension += 1;
+        c.set(context);
+
+        let stratum = permute(context.sample_index, strata, dimension_hash);
+        let sx = stratum % context.strata_per_side;
+        let sy = stratum / context.strata_per_side;
+        let cell_size = 1. / context.strata_per_side as f64;
+        (
+            (sx as f64 + random_normal_float()) * cell_size,
+            (sy as f64 + random_normal_float()) * cell_size,
+        )
+    })
+}
+
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^ (x >> 16)
+}
+
+/// Permutes index i within 0 to <l, differently for each seed p.
+/// From Kensler, "Correlated Multi-Jittered Sampling"
+fn permute(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % l
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permute_is_permutation() {
+        for l in [1, 5, 16, 100] {
+            let mut values: Vec<u32> = (0..l).map(|i| permute(i, l, 1234)).collect();
+            values.sort();
+            assert_eq!(values, (0..l).collect::<Vec<u32>>());
+        }
+    }
+
+    #[test]
+    fn test_stratified_covers_every_stratum() {
+        let mut strata = vec![0; 16];
+        for sample_index in 0..16 {
+            start_pixel_sample(Sampler::Stratified, 3, 7, sample_index, 16);
+            let (u, v) = next_2d();
+            strata[(u * 4.) as usize + 4 * (v * 4.) as usize] += 1;
+        }
+        assert_eq!(strata, vec![1; 16]);
+    }
+
+    #[test]
+    fn test_random_within_unit_square() {
+        start_pixel_sample(Sampler::Random, 0, 0, 0, 16);
+        for _ in 0..100 {
+            let (u, v) = next_2d();
+            assert!((0. ..1.).contains(&u));
+            assert!((0. ..1.).contains(&v));
+        }
+    }
+}
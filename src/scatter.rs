@@ -0,0 +1,255 @@
+//! Scatters instances randomly over the surfaces of hittables, for placing things
+//! like grass, rocks or crowds. The instances are distributed by surface area,
+//! and optionally by a density texture
+use fastrand::Rng;
+
+use crate::geo::transformation::{RotationX, RotationY, Scale, Transformations, Translation};
+use crate::geo::vec3::{Vec3, UNIT_Y};
+use crate::geo::Ray;
+use crate::hittable::Hittables::{BvhType, QuadType, SphereType, TriangleMeshType, TriangleType};
+use crate::hittable::{Hittable, Hittables, Quad, Sphere, Triangle};
+use crate::material::texture::{brightness, Texture, Textures};
+use crate::util::interval::Interval;
+
+/// Attempts made per requested instance before giving up on surfaces with low density
+const MAX_ATTEMPTS_PER_INSTANCE: usize = 100;
+/// Distance above the surface that the texture coordinates of an instance are probed from
+const PROBE_DISTANCE: f64 = 0.001;
+
+/// How the scattered instances are distributed and varied
+#[derive(Clone, Debug)]
+pub struct ScatterDistribution {
+    /// Range that the uniform scale of each instance is randomly chosen from
+    pub scale: Interval,
+    /// Instances are randomly rotated around their up axis by up to this many degrees
+    pub max_rotation_degrees: f64,
+    /// Tilts the up axis of the instances to the surface normal, otherwise they stand upright
+    pub align_to_normal: bool,
+    /// Texture where the brightness on the surface is the probability of keeping an instance
+    pub density: Option<Textures>,
+}
+
+impl Default for ScatterDistribution {
+    fn default() -> Self {
+        ScatterDistribution {
+            scale: Interval::new(1., 1.),
+            max_rotation_degrees: 360.,
+            align_to_normal: false,
+            density: None,
+        }
+    }
+}
+
+/// An instance placed on a surface
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScatteredInstance {
+    /// Point on the surface
+    pub position: Vec3,
+    /// Normal of the surface at the point
+    pub normal: Vec3,
+    /// Direction that the y axis of the instance is rotated to
+    pub up: Vec3,
+    /// Uniform scale of the instance
+    pub scale: f64,
+    /// Rotation of the instance around its up axis
+    pub rotation_degrees: f64,
+}
+
+impl ScatteredInstance {
+    /// Transformation that places a model at the instance,
+    /// where the model stands on the origin with y pointing up
+    pub fn transformation(&self) -> Transformations {
+        let tilt = self.up.y.clamp(-1., 1.).acos().to_degrees();
+        let heading = if self.up.x == 0. && self.up.z == 0. {
+            0.
+        } else {
+            (-self.up.x).atan2(-self.up.z).to_degrees()
+        };
+        Transformations::new(vec![
+            Box::new(Scale::new(self.scale)),
+            Box::new(RotationY::new(self.rotation_degrees)),
+            Box::new(RotationX::new(tilt)),
+            Box::new(RotationY::new(heading)),
+            Box::new(Translation::new(self.position)),
+        ])
+    }
+}
+
+/// Randomly places the given number of instances over the surfaces of the host hittable.
+/// Spheres, quads and triangles are scattered on, also when contained in a
+/// [`crate::hittable::Bvh`].
+/// The same seed gives the same instances. With a density texture fewer instances
+/// than requested may be returned, if the density is very low on most of the surfaces
+pub fn scatter_on_surface(
+    host: &Hittables,
+    count: usize,
+    seed: u64,
+    distribution: &ScatterDistribution,
+) -> Vec<ScatteredInstance> {
+    let surfaces = surfaces(host);
+    let cumulative_areas: Vec<f64> = surfaces
+        .iter()
+        .scan(0., |sum, surface| {
+            *sum += surface.area();
+            Some(*sum)
+        })
+        .collect();
+    let total_area = cumulative_areas.last().copied().unwrap_or(0.);
+    if total_area <= 0. {
+        return vec![];
+    }
+
+    let mut rng = Rng::with_seed(seed);
+    let mut instances = Vec::with_capacity(count);
+    for _ in 0..count * MAX_ATTEMPTS_PER_INSTANCE {
+        if instances.len() == count {
+            break;
+        }
+        let area = rng.f64() * total_area;
+        let index = cumulative_areas.partition_point(|a| *a <= area).min(surfaces.len() - 1);
+        let (position, normal) = surfaces[index].point(rng.f64(), rng.f64());
+
+        if let Some(density) = &distribution.density {
+            // Shoot a ray at the point to get its texture coordinates
+            let ray = Ray::new(position + normal * PROBE_DISTANCE, normal.neg());
+            let Some(rec) = host.hit(&ray, &Interval::new(0., 2. * PROBE_DISTANCE)) else {
+                continue;
+            };
+            if brightness(density.color(rec.uv, 0.)) <= rng.f64() {
+                continue;
+            }
+        }
+
+        instances.push(ScatteredInstance {
+            position,
+            normal,
+            up: if distribution.align_to_normal {
+                normal
+            } else {
+                UNIT_Y
+            },
+            scale: distribution.scale.min + rng.f64() * distribution.scale.size(),
+            rotation_degrees: rng.f64() * distribution.max_rotation_degrees,
+        });
+    }
+    instances
+}
+
+/// A surface that instances can be placed on
+enum Surface<'a> {
+    Sphere(&'a Sphere),
+    Quad(&'a Quad),
+    Triangle(&'a Triangle),
+    /// A triangle of a [`crate::hittable::TriangleMesh`]
+    MeshTriangle(Box<Triangle>),
+}
+
+impl Surface<'_> {
+    fn area(&self) -> f64 {
+        match self {
+            Surface::Sphere(s) => s.area(),
+            Surface::Quad(q) => q.area(),
+            Surface::Triangle(t) => t.area(),
+            Surface::MeshTriangle(t) => t.area(),
+        }
+    }
+
+    fn point(&self, s: f64, t: f64) -> (Vec3, Vec3) {
+        match self {
+            Surface::Sphere(sphere) => sphere.surface_point(s, t),
+            Surface::Quad(q) => q.surface_point(s, t),
+            Surface::Triangle(triangle) => triangle.surface_point(s, t),
+            Surface::MeshTriangle(triangle) => triangle.surface_point(s, t),
+        }
+    }
+}
+
+fn surfaces(hittable: &Hittables) -> Vec<Surface> {
+    match hittable {
+        BvhType(b) => b.leaves().into_iter().flat_map(surfaces).collect(),
+        SphereType(s) => vec![Surface::Sphere(s)],
+        QuadType(q) => vec![Surface::Quad(q)],
+        TriangleType(t) => vec![Surface::Triangle(t)],
+        TriangleMeshType(m) => m
+            .triangles()
+            .into_iter()
+            .map(|t| Surface::MeshTriangle(Box::new(t)))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use image::{Rgb, RgbImage};
+
+    use crate::geo::transformation::{NopTransformer, Transformer};
+    use crate::hittable::Bvh;
+    use crate::material::texture::{ImageMap, SolidColor};
+    use crate::material::Lambertian;
+
+    use super::*;
+
+    fn floor() -> Hittables {
+        Bvh::new(vec![Quad::new(
+            Vec3::new(-5., 0., -5.),
+            Vec3::new(0., 0., 10.),
+            Vec3::new(10., 0., 0.),
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+            &NopTransformer(),
+        )])
+    }
+
+    #[test]
+    fn test_scatter_on_floor() {
+        let distribution = ScatterDistribution {
+            scale: Interval::new(1., 2.),
+            ..ScatterDistribution::default()
+        };
+        let instances = scatter_on_surface(&floor(), 100, 1, &distribution);
+        assert_eq!(instances.len(), 100);
+        for instance in &instances {
+            assert_eq!(instance.position.y, 0.);
+            assert_eq!(instance.up, UNIT_Y);
+            assert!(distribution.scale.contains(instance.scale));
+        }
+        assert_eq!(instances, scatter_on_surface(&floor(), 100, 1, &distribution));
+        assert_ne!(instances, scatter_on_surface(&floor(), 100, 2, &distribution));
+    }
+
+    #[test]
+    fn test_density_texture() {
+        // Texture coordinate u follows z over the floor, and the density is zero for u below 0.5
+        let image = RgbImage::from_fn(2, 1, |x, _| match x {
+            0 => Rgb([0, 0, 0]),
+            _ => Rgb([255, 255, 255]),
+        });
+        let distribution = ScatterDistribution {
+            density: Some(ImageMap::new(Arc::new(image))),
+            ..ScatterDistribution::default()
+        };
+        let instances = scatter_on_surface(&floor(), 100, 1, &distribution);
+        assert_eq!(instances.len(), 100);
+        assert!(instances.iter().all(|i| i.position.z >= 0.));
+    }
+
+    #[test]
+    fn test_align_to_normal() {
+        let sphere = Sphere::new(
+            Vec3::new(1., 2., 3.),
+            2.,
+            Lambertian::new(SolidColor::new(1., 1., 1.), None),
+        );
+        let distribution = ScatterDistribution {
+            align_to_normal: true,
+            ..ScatterDistribution::default()
+        };
+        for instance in scatter_on_surface(&sphere, 100, 1, &distribution) {
+            assert!(((instance.position - Vec3::new(1., 2., 3.)).length() - 2.).abs() < 1e-9);
+            let up = instance.transformation().transform(UNIT_Y, true);
+            assert!((up - instance.normal).length() < 1e-9);
+        }
+    }
+}
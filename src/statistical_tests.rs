@@ -0,0 +1,420 @@
+//! Statistical tests validating that every [`Pdf`] generates directions matching its value,
+//! and that materials sample and weight their scattered rays consistently.
+//!
+//! The tests are slow, so they are only compiled with the `statistical-tests` feature:
+//! `cargo test --release --features statistical-tests statistical_tests`
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::geo::transformation::NopTransformer;
+use crate::geo::vec3::{Vec3, ONE_VECTOR};
+use crate::geo::{Onb, Ray, Uv};
+use crate::hittable::{EnvironmentMap, Hittables, Quad, Sphere, Triangle};
+use crate::material::texture::{SolidColor, Textures};
+use crate::material::{
+    merl_bytes, Blend, Coat, Dielectric, DiffractionGrating, DiffuseLight, Glint, Hair, Isotropic,
+    Lambertian, Material, Materials, MeasuredBrdf, MeasuredMaterial, Metal, PrincipledMaterial,
+    RayHit, RayScatter, Sheen, Translucent,
+};
+use crate::pdf::{
+    mix_generate, mix_value, ContainerPdf, CosinePdf, GgxPdf, Pdf, Pdfs, SpherePdf,
+    TwoSidedCosinePdf,
+};
+use crate::random;
+use crate::util::chi_squared::chi_squared_test;
+
+const FURNACE_SAMPLE_COUNT: usize = 200_000;
+const FURNACE_TOLERANCE: f64 = 0.01;
+
+fn lights() -> Vec<Hittables> {
+    vec![
+        Quad::new(
+            Vec3::new(-1., 2., -1.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 0., 1.),
+            DiffuseLight::new(1., 1., 1., None),
+            &NopTransformer(),
+        ),
+        Sphere::new(
+            Vec3::new(2., 1., 1.),
+            0.5,
+            DiffuseLight::new(1., 1., 1., None),
+        ),
+        Triangle::new(
+            Vec3::new(-2., 0.5, 1.),
+            Vec3::new(-2., 1.5, 0.),
+            Vec3::new(-1.5, 1., 1.),
+            DiffuseLight::new(1., 1., 1., None),
+            &NopTransformer(),
+        ),
+    ]
+}
+
+fn white() -> Textures {
+    SolidColor::new_from_vec3(ONE_VECTOR)
+}
+
+fn normal() -> Vec3 {
+    Vec3::new(0.2, 1., 0.1).unit()
+}
+
+fn ray_hit(material: &Materials) -> RayHit {
+    RayHit::new(
+        Vec3::new(0., 0., 0.),
+        Onb::new(normal()),
+        material,
+        1.,
+        Uv::default(),
+        true,
+        0.,
+    )
+}
+
+fn incoming_ray() -> Ray {
+    Ray::new(Vec3::new(-1., 1., 0.), Vec3::new(1., -1., 0.))
+}
+
+fn scattered_direction(material: &Materials, lights: &[Hittables]) -> Vec3 {
+    match material.scatter(&incoming_ray(), &ray_hit(material), lights) {
+        RayScatter::ScatterPdf(s) => s.ray.direction,
+        RayScatter::ScatterBasic(s) => s.ray.direction,
+        RayScatter::ScatterEmission(_) => panic!("Material does not scatter"),
+    }
+}
+
+/// Average of the factor the scattered light is multiplied by, per color channel
+fn furnace_average(material: &Materials, lights: &[Hittables]) -> Vec3 {
+    random::seed(7);
+    let mut sum = Vec3::default();
+    for _ in 0..FURNACE_SAMPLE_COUNT {
+        sum += match material.scatter(&incoming_ray(), &ray_hit(material), lights) {
+            RayScatter::ScatterPdf(s) => s.color * s.probability,
+            RayScatter::ScatterBasic(s) => s.color,
+            RayScatter::ScatterEmission(_) => panic!("Material does not scatter"),
+        }
+    }
+    sum / FURNACE_SAMPLE_COUNT as f64
+}
+
+fn assert_furnace(material: &Materials, lights: &[Hittables]) {
+    assert_conserves_energy(furnace_average(material, lights), FURNACE_TOLERANCE);
+}
+
+/// Asserts that the furnace average of a white material is within the tolerance of one
+fn assert_conserves_energy(average: Vec3, tolerance: f64) {
+    assert!(
+        (average - ONE_VECTOR).length() < tolerance,
+        "A white material should neither gain nor lose energy, average was {}",
+        average
+    );
+}
+
+/// Asserts that the furnace average of a white material does not go above one,
+/// and loses at most the light down to the minimum average in any color channel
+fn assert_loses_energy(average: Vec3, min_average: f64) {
+    assert!(
+        [average.x, average.y, average.z]
+            .iter()
+            .all(|c| *c < 1. + FURNACE_TOLERANCE && *c > min_average),
+        "A white material should not gain energy, average was {}",
+        average
+    );
+}
+
+fn assert_pdf(pdf: &Pdfs) {
+    assert_eq!(
+        chi_squared_test(|| pdf.generate(), |direction| pdf.value(direction)),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_cosine_pdf() {
+    assert_pdf(&CosinePdf::new(normal()));
+}
+
+#[test]
+fn test_sphere_pdf() {
+    assert_pdf(&SpherePdf::new());
+}
+
+#[test]
+fn test_two_sided_cosine_pdf() {
+    assert_pdf(&TwoSidedCosinePdf::new(normal(), 0.3));
+}
+
+#[test]
+fn test_ggx_pdf() {
+    let outgoing = incoming_ray().direction.neg();
+    assert_pdf(&GgxPdf::new(normal(), outgoing, 0.3));
+    assert_pdf(&GgxPdf::new(normal(), outgoing, 1.));
+}
+
+#[test]
+fn test_container_pdf() {
+    let lights = lights();
+    assert_pdf(&ContainerPdf::new(&lights, Vec3::new(0., 0., 0.)));
+}
+
+#[test]
+fn test_environment_map_pdf() {
+    let environment_map = EnvironmentMap::load("resources/textures/sky.hdr", 1.).unwrap();
+    let lights = [Hittables::from(environment_map)];
+    assert_pdf(&ContainerPdf::new(&lights, Vec3::new(0., 0., 0.)));
+}
+
+#[test]
+fn test_mixed_pdf() {
+    let lights = lights();
+    let light_pdf = ContainerPdf::new(&lights, Vec3::new(0., 0., 0.));
+    let pdf = CosinePdf::new(normal());
+    assert_eq!(
+        chi_squared_test(
+            || mix_generate(&light_pdf, &pdf),
+            |direction| mix_value(&light_pdf, &pdf, direction)
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_lambertian_sampling() {
+    let lights = lights();
+    let material = Lambertian::new(white(), None);
+    let light_pdf = ContainerPdf::new(&lights, Vec3::new(0., 0., 0.));
+    let pdf = CosinePdf::new(normal());
+    assert_eq!(
+        chi_squared_test(
+            || scattered_direction(&material, &lights),
+            |direction| mix_value(&light_pdf, &pdf, direction)
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_translucent_sampling() {
+    let lights = lights();
+    let material = Translucent::new(white(), None, 0.3);
+    let light_pdf = ContainerPdf::new(&lights, Vec3::new(0., 0., 0.));
+    let pdf = TwoSidedCosinePdf::new(normal(), 0.3);
+    assert_eq!(
+        chi_squared_test(
+            || scattered_direction(&material, &lights),
+            |direction| mix_value(&light_pdf, &pdf, direction)
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_isotropic_sampling() {
+    let lights = lights();
+    let material = Isotropic::new(white());
+    let light_pdf = ContainerPdf::new(&lights, Vec3::new(0., 0., 0.));
+    let pdf = SpherePdf::new();
+    assert_eq!(
+        chi_squared_test(
+            || scattered_direction(&material, &lights),
+            |direction| mix_value(&light_pdf, &pdf, direction)
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_blend_sampling() {
+    let lights = lights();
+    let material = Blend::new(Lambertian::new(white(), None), Isotropic::new(white()), 0.3);
+    let light_pdf = ContainerPdf::new(&lights, Vec3::new(0., 0., 0.));
+    let cosine_pdf = CosinePdf::new(normal());
+    let sphere_pdf = SpherePdf::new();
+    assert_eq!(
+        chi_squared_test(
+            || scattered_direction(&material, &lights),
+            |direction| 0.7 * mix_value(&light_pdf, &cosine_pdf, direction)
+                + 0.3 * mix_value(&light_pdf, &sphere_pdf, direction)
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_sheen_sampling() {
+    // Both the sheen and the lambertian base sample the lights and the cosine weighted hemisphere
+    let lights = lights();
+    let material = Sheen::new(Lambertian::new(white(), None), white(), 0.5);
+    let light_pdf = ContainerPdf::new(&lights, Vec3::new(0., 0., 0.));
+    let pdf = CosinePdf::new(normal());
+    assert_eq!(
+        chi_squared_test(
+            || scattered_direction(&material, &lights),
+            |direction| mix_value(&light_pdf, &pdf, direction)
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_metal_sampling() {
+    let lights = lights();
+    let fuzz = 0.5;
+    let material = Metal::new(white(), None, fuzz);
+    let reflected = incoming_ray().direction.unit().reflect(normal());
+    // Directions are uniformly distributed within a sphere of radius fuzz around the reflection
+    let cos_theta_max = (1. - fuzz * fuzz).sqrt();
+    assert_eq!(
+        chi_squared_test(
+            || scattered_direction(&material, &lights),
+            |direction| {
+                let cos_theta = direction.unit().dot(reflected);
+                if cos_theta < cos_theta_max {
+                    return 0.;
+                }
+                let sin_theta_squared = 1. - cos_theta * cos_theta;
+                let half_chord = (fuzz * fuzz - sin_theta_squared).sqrt();
+                let near = cos_theta - half_chord;
+                let far = cos_theta + half_chord;
+                (far.powi(3) - near.powi(3)) / 3. / (4. / 3. * PI * fuzz.powi(3))
+            }
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_lambertian_furnace() {
+    assert_furnace(&Lambertian::new(white(), None), &lights());
+}
+
+#[test]
+fn test_translucent_furnace() {
+    assert_furnace(&Translucent::new(white(), None, 0.3), &lights());
+}
+
+#[test]
+fn test_isotropic_furnace() {
+    assert_furnace(&Isotropic::new(white()), &lights());
+}
+
+#[test]
+fn test_blend_furnace() {
+    let material = Blend::new(Lambertian::new(white(), None), Isotropic::new(white()), 0.3);
+    assert_furnace(&material, &lights());
+}
+
+#[test]
+fn test_coat_furnace() {
+    let material = Coat::new(Lambertian::new(white(), None), None, 1.5);
+    assert_furnace(&material, &lights());
+}
+
+#[test]
+fn test_dielectric_furnace() {
+    assert_furnace(&Dielectric::new(white(), None, 1.5), &lights());
+}
+
+#[test]
+fn test_principled_furnace() {
+    let grey = |v| SolidColor::new(v, v, v);
+    let mirror = PrincipledMaterial::new(white(), None, grey(1.), grey(0.), grey(0.5), 1.5);
+    assert_furnace(&mirror, &lights());
+
+    // Rough surfaces lose some of the light to the shadowing of the microfacets,
+    // most for rough metals that only have the glossy reflection
+    for (metallic, roughness, min_average) in [(0., 0.3, 0.95), (1., 0.3, 0.95), (0., 1., 0.9)] {
+        let material = PrincipledMaterial::new(
+            white(),
+            None,
+            grey(metallic),
+            grey(roughness),
+            grey(0.5),
+            1.5,
+        );
+        assert_loses_energy(furnace_average(&material, &lights()), min_average);
+    }
+    let rough_metal = PrincipledMaterial::new(white(), None, grey(1.), grey(1.), grey(0.5), 1.5);
+    assert!(furnace_average(&rough_metal, &lights()).x < 1.);
+}
+
+#[test]
+fn test_sheen_furnace() {
+    for roughness in [0.1, 0.5, 1.] {
+        let material = Sheen::new(Lambertian::new(white(), None), white(), roughness);
+        assert_furnace(&material, &lights());
+    }
+}
+
+#[test]
+fn test_diffraction_grating_furnace() {
+    // Without grooves the grating is a mirror
+    assert_furnace(
+        &DiffractionGrating::new(white(), None, 1600., 0., 0.),
+        &lights(),
+    );
+
+    // The light of the orders that can not leave the surface is lost,
+    // and deeper grooves send more of the light into those orders
+    for (groove_depth, min_average) in [(100., 0.9), (1000., 0.1)] {
+        let material = DiffractionGrating::new(white(), None, 1600., groove_depth, 0.);
+        assert_loses_energy(furnace_average(&material, &lights()), min_average);
+    }
+}
+
+#[test]
+fn test_glint_furnace() {
+    // The flakes are placed by the position on the surface, so the hit points are spread out
+    // to scatter off both the flakes and the base between them
+    let material = Glint::new(Lambertian::new(white(), None), white(), 0.5, 0.1, 0.5);
+    let lights = lights();
+    random::seed(7);
+    let mut sum = Vec3::default();
+    let mut flake_count = 0;
+    for _ in 0..FURNACE_SAMPLE_COUNT {
+        let onb = Onb::new(normal());
+        let hit_point = onb.tangent * random::random_float(-10., 10.)
+            + onb.bi_tangent * random::random_float(-10., 10.);
+        let rec = RayHit::new(hit_point, onb, &material, 1., Uv::default(), true, 0.);
+        sum += match material.scatter(&incoming_ray(), &rec, &lights) {
+            RayScatter::ScatterPdf(s) => s.color * s.probability,
+            RayScatter::ScatterBasic(s) => {
+                flake_count += 1;
+                s.color
+            }
+            RayScatter::ScatterEmission(_) => panic!("Material does not scatter"),
+        }
+    }
+    assert_conserves_energy(sum / FURNACE_SAMPLE_COUNT as f64, FURNACE_TOLERANCE);
+    assert!(
+        flake_count > FURNACE_SAMPLE_COUNT / 10 && flake_count < FURNACE_SAMPLE_COUNT / 2,
+        "The flakes should be hit some of the time, were hit {} times",
+        flake_count
+    );
+}
+
+#[test]
+fn test_measured_furnace() {
+    // A measured brdf of a white lambertian surface, with the same value for all angles
+    let brdf = MeasuredBrdf::from_merl_bytes(&merl_bytes(|_, _, _, _| 1. / PI)).unwrap();
+    assert_furnace(&MeasuredMaterial::new(Arc::new(brdf), None), &lights());
+}
+
+#[test]
+fn test_hair_furnace() {
+    // Hair without pigments absorbs nothing, but the fiber scattering model
+    // only conserves energy approximately for a single offset across the fiber
+    for roughness in [0.2, 0.5, 0.9] {
+        let average = furnace_average(&Hair::new(0., 0., roughness, roughness), &lights());
+        assert_conserves_energy(average, 0.05);
+    }
+}
+
+#[test]
+fn test_diffuse_light_does_not_scatter() {
+    let material = DiffuseLight::new(1., 1., 1., None);
+    assert!(matches!(
+        material.scatter(&incoming_ray(), &ray_hit(&material), &lights()),
+        RayScatter::ScatterEmission(_)
+    ));
+}
@@ -0,0 +1,124 @@
+//! Chi-squared test for checking that sampled directions are distributed
+//! according to their probability density function
+
+use std::f64::consts::PI;
+
+use crate::geo::vec3::Vec3;
+use crate::random;
+
+const COS_THETA_BINS: usize = 20;
+const PHI_BINS: usize = 40;
+const INTEGRATION_STEPS: usize = 32;
+const SAMPLE_COUNT: usize = 50_000;
+const MIN_EXPECTED_FREQUENCY: f64 = 5.;
+/// Standard normal quantile for a significance level of 0.001
+const Z_QUANTILE: f64 = 3.09;
+
+/// Samples directions with `sample` and compares the histogram of them with the integral of
+/// `pdf` over each bin, failing if the difference is statistically significant.
+/// The random generator is seeded so the test is reproducible
+pub fn chi_squared_test(
+    sample: impl Fn() -> Vec3,
+    pdf: impl Fn(Vec3) -> f64,
+) -> Result<(), String> {
+    random::seed(5);
+
+    let mut observed = vec![0.; COS_THETA_BINS * PHI_BINS];
+    for _ in 0..SAMPLE_COUNT {
+        let direction = sample();
+        if direction.near_zero() || !direction.length_squared().is_finite() {
+            return Err(format!("Invalid sampled direction {:?}", direction));
+        }
+        observed[bin_index(direction.unit())] += 1.;
+    }
+
+    let expected = expected_frequencies(pdf);
+
+    let mut chi_squared = 0.;
+    let mut degrees_of_freedom = 0;
+    let mut pooled_observed = 0.;
+    let mut pooled_expected = 0.;
+    for (o, e) in observed.iter().zip(expected.iter()) {
+        if *e < MIN_EXPECTED_FREQUENCY {
+            pooled_observed += o;
+            pooled_expected += e;
+        } else {
+            chi_squared += (o - e) * (o - e) / e;
+            degrees_of_freedom += 1;
+        }
+    }
+    if pooled_observed > 0. || pooled_expected > 0. {
+        // Edges of the pdf are not exactly integrated, so a few stray samples
+        // in the pooled bins are tolerated
+        let pooled_expected = pooled_expected.max(MIN_EXPECTED_FREQUENCY);
+        chi_squared += (pooled_observed - pooled_expected) * (pooled_observed - pooled_expected)
+            / pooled_expected;
+        degrees_of_freedom += 1;
+    }
+    degrees_of_freedom -= 1;
+
+    if degrees_of_freedom < 1 {
+        return Err("Too few bins with significant expected frequency".to_string());
+    }
+
+    let limit = chi_squared_quantile(degrees_of_freedom as f64);
+    if chi_squared > limit {
+        return Err(format!(
+            "Chi-squared {} exceeds {} with {} degrees of freedom",
+            chi_squared, limit, degrees_of_freedom
+        ));
+    }
+    Ok(())
+}
+
+/// Bins are equally sized in solid angle, by dividing cos theta and phi evenly
+fn bin_index(direction: Vec3) -> usize {
+    let cos_theta_bin = ((direction.z + 1.) / 2. * COS_THETA_BINS as f64) as usize;
+    let phi = direction.y.atan2(direction.x) + PI;
+    let phi_bin = (phi / (2. * PI) * PHI_BINS as f64) as usize;
+    cos_theta_bin.min(COS_THETA_BINS - 1) * PHI_BINS + phi_bin.min(PHI_BINS - 1)
+}
+
+/// Integrates the pdf over each bin with the midpoint rule
+fn expected_frequencies(pdf: impl Fn(Vec3) -> f64) -> Vec<f64> {
+    let cos_theta_step = 2. / (COS_THETA_BINS * INTEGRATION_STEPS) as f64;
+    let phi_step = 2. * PI / (PHI_BINS * INTEGRATION_STEPS) as f64;
+
+    let mut expected = vec![0.; COS_THETA_BINS * PHI_BINS];
+    for i in 0..COS_THETA_BINS * INTEGRATION_STEPS {
+        let cos_theta = -1. + (i as f64 + 0.5) * cos_theta_step;
+        let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+        for j in 0..PHI_BINS * INTEGRATION_STEPS {
+            let phi = -PI + (j as f64 + 0.5) * phi_step;
+            let direction = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+            let bin = (i / INTEGRATION_STEPS) * PHI_BINS + j / INTEGRATION_STEPS;
+            expected[bin] += pdf(direction) * cos_theta_step * phi_step * SAMPLE_COUNT as f64;
+        }
+    }
+    expected
+}
+
+/// Wilson-Hilferty approximation of the chi-squared distribution quantile
+fn chi_squared_quantile(degrees_of_freedom: f64) -> f64 {
+    let a = 2. / (9. * degrees_of_freedom);
+    degrees_of_freedom * (1. - a + Z_QUANTILE * a.sqrt()).powi(3)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::vec3::random_unit_vector;
+
+    use super::*;
+
+    #[test]
+    fn test_uniform_sphere_passes() {
+        let res = chi_squared_test(random_unit_vector, |_| 1. / (4. * PI));
+        assert_eq!(res, Ok(()));
+    }
+
+    #[test]
+    fn test_wrong_pdf_fails() {
+        let res = chi_squared_test(random_unit_vector, |d| (d.z + 1.) / (4. * PI));
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Color spaces that the renderer can work in, and conversions between them
+use crate::geo::vec3::Vec3;
+
+/// Linear sRGB to ACEScg, with Bradford adaptation from the D65 to the D60 white point
+const SRGB_TO_ACESCG: [[f64; 3]; 3] = [
+    [0.613_097_402_4, 0.339_523_146_2, 0.047_379_451_4],
+    [0.070_193_722_5, 0.916_353_879_1, 0.013_452_398_5],
+    [0.020_615_592_9, 0.109_569_772_9, 0.869_814_634_2],
+];
+/// ACEScg to linear sRGB, the inverse of [`SRGB_TO_ACESCG`]
+const ACESCG_TO_SRGB: [[f64; 3]; 3] = [
+    [1.705_050_992_7, -0.621_792_120_7, -0.083_258_872_0],
+    [-0.130_256_417_5, 1.140_804_736_6, -0.010_548_319_1],
+    [-0.024_003_356_8, -0.128_968_976_1, 1.152_972_332_9],
+];
+/// Saturation adjustment of the reference rendering transform, in ACEScg
+const RRT_SATURATION: [[f64; 3]; 3] = [
+    [0.970_889, 0.026_963, 0.002_148],
+    [0.010_889, 0.986_963, 0.002_148],
+    [0.010_889, 0.026_963, 0.962_148],
+];
+/// Saturation adjustment of the sRGB output device transform, followed by
+/// the conversion from ACEScg to linear sRGB
+const ODT_SATURATION_TO_SRGB: [[f64; 3]; 3] = [
+    [1.604_75, -0.531_08, -0.073_67],
+    [-0.102_08, 1.108_13, -0.006_05],
+    [-0.003_27, -0.072_76, 1.076_02],
+];
+
+/// The color space that colors are multiplied in when rendering, set in
+/// [`crate::renderer::RenderConfig::color_space`]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ColorSpace {
+    /// Linear sRGB, where the colors of the scene are used as they are
+    #[default]
+    LinearSrgb,
+    /// The ACEScg working space of the Academy Color Encoding System, with a wider gamut than
+    /// sRGB. Texture, light and background colors are converted from sRGB to ACEScg when they
+    /// are shaded. Float images are output in ACEScg, for compositing in color managed tools.
+    /// 8 bit images are output with the ACES output transform for sRGB displays,
+    /// which compresses the highlights and replaces tone mapping, so it can not be combined
+    /// with the [`crate::post::ToneMapPostProcessor`]
+    AcesCg,
+}
+
+impl ColorSpace {
+    /// Converts a linear sRGB color of the scene into the color space
+    pub(crate) fn convert_srgb(self, color: Vec3) -> Vec3 {
+        match self {
+            ColorSpace::LinearSrgb => color,
+            ColorSpace::AcesCg => srgb_to_acescg(color),
+        }
+    }
+
+    /// Maps a scene referred color of the color space to a linear sRGB color for display,
+    /// applying the ACES output transform to ACEScg colors
+    pub(crate) fn to_display(self, color: Vec3) -> Vec3 {
+        match self {
+            ColorSpace::LinearSrgb => color,
+            ColorSpace::AcesCg => aces_output_transform(color),
+        }
+    }
+}
+
+/// Converts a linear sRGB color to ACEScg
+pub fn srgb_to_acescg(color: Vec3) -> Vec3 {
+    multiply(&SRGB_TO_ACESCG, color)
+}
+
+/// Converts an ACEScg color to linear sRGB
+pub fn acescg_to_srgb(color: Vec3) -> Vec3 {
+    multiply(&ACESCG_TO_SRGB, color)
+}
+
+/// The ACES output transform for sRGB displays, as fitted by Stephen Hill.
+/// Takes a scene referred ACEScg color and returns a linear sRGB color between 0 and 1
+pub fn aces_output_transform(color: Vec3) -> Vec3 {
+    let curve = |v: f64| {
+        let a = v * (v + 0.024_578_6) - 0.000_090_537;
+        let b = v * (0.983_729 * v + 0.432_951) + 0.238_081;
+        a / b
+    };
+    let c = multiply(&RRT_SATURATION, color);
+    let c = multiply(&ODT_SATURATION_TO_SRGB, Vec3::new(curve(c.x), curve(c.y), curve(c.z)));
+    Vec3::new(c.x.clamp(0., 1.), c.y.clamp(0., 1.), c.z.clamp(0., 1.))
+}
+
+fn multiply(m: &[[f64; 3]; 3], c: Vec3) -> Vec3 {
+    let row = |r: &[f64; 3]| r[0] * c.x + r[1] * c.y + r[2] * c.z;
+    Vec3::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let color = Vec3::new(0.8, 0.3, 0.1);
+        let round_trip = acescg_to_srgb(srgb_to_acescg(color));
+        assert!((round_trip - color).length() < 1e-6, "{:?}", round_trip);
+
+        // Greys stay grey, so scalar textures are not changed
+        let grey = srgb_to_acescg(Vec3::new(0.5, 0.5, 0.5));
+        assert!((grey - Vec3::new(0.5, 0.5, 0.5)).length() < 1e-6, "{:?}", grey);
+
+        // Saturated sRGB colors are inside the wider ACEScg gamut
+        let red = srgb_to_acescg(Vec3::new(1., 0., 0.));
+        assert!(red.x > red.y && red.y > 0. && red.z > 0.);
+    }
+
+    #[test]
+    fn test_output_transform() {
+        assert_eq!(aces_output_transform(Vec3::new(0., 0., 0.)), Vec3::new(0., 0., 0.));
+        let mut previous = 0.;
+        for v in [0.01, 0.18, 1., 4.] {
+            let c = aces_output_transform(Vec3::new(v, v, v));
+            assert!(c.x > previous && c.x <= 1., "{:?}", c);
+            assert!((c.x - c.y).abs() < 1e-3 && (c.y - c.z).abs() < 1e-3, "{:?}", c);
+            previous = c.x;
+        }
+        assert_eq!(aces_output_transform(Vec3::new(1e6, 1e6, 1e6)).x, 1.);
+    }
+
+    #[test]
+    fn test_convert_srgb() {
+        let color = Vec3::new(1., 0., 0.);
+        assert_eq!(ColorSpace::LinearSrgb.convert_srgb(color), color);
+        assert_eq!(ColorSpace::AcesCg.convert_srgb(color), srgb_to_acescg(color));
+    }
+}
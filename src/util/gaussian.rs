@@ -24,10 +24,48 @@ pub fn create_gaussian_blur_weights(kernel_size: usize, std_dev: f64) -> Vec<f64
     gaussian_blur_weights
 }
 
+/// Cumulative distribution function of the standard normal distribution,
+/// using the approximation of the error function by Abramowitz and Stegun
+pub fn normal_cdf(x: f64) -> f64 {
+    let z = x.abs() / 2f64.sqrt();
+    let t = 1. / (1. + 0.3275911 * z);
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1. - poly * (-z * z).exp();
+    if x >= 0. {
+        0.5 * (1. + erf)
+    } else {
+        0.5 * (1. - erf)
+    }
+}
+
+/// Inverse of [`normal_cdf`], found by bisection
+pub fn inverse_normal_cdf(p: f64) -> f64 {
+    let (mut low, mut high) = (-8., 8.);
+    for _ in 0..64 {
+        let mid = (low + high) / 2.;
+        if normal_cdf(mid) < p {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.
+}
+
 #[cfg(test)]
 mod tests {
     use crate::geo::vec3::ALMOST_ZERO;
-    use crate::util::gaussian::create_gaussian_blur_weights;
+    use crate::util::gaussian::{create_gaussian_blur_weights, inverse_normal_cdf, normal_cdf};
+
+    #[test]
+    fn test_normal_cdf() {
+        assert!((normal_cdf(0.) - 0.5).abs() < 1e-6);
+        assert!((normal_cdf(1.) - 0.8413447).abs() < 1e-6);
+        assert!((normal_cdf(-2.) - 0.0227501).abs() < 1e-6);
+        assert!((inverse_normal_cdf(0.8413447) - 1.).abs() < 1e-5);
+    }
 
     #[test]
     fn test_create_gaussian_blur_weights() {
@@ -2,6 +2,9 @@
 
 use std::f64::consts::PI;
 
+#[cfg(test)]
+pub(crate) mod chi_squared;
+pub mod color_space;
 pub mod gaussian;
 pub mod height_map;
 pub mod interval;
@@ -10,21 +10,19 @@ const COLOR_INTENSITY_INTERVAL: Interval = Interval {
 
 const COLOR_SCALE: f64 = 1.0 / 255.;
 
-/// Convert a color and a given number of samples used to generate that color to an rgb color
-pub fn to_rgb_color(col: Vec3, samples_per_pixel: u32) -> Rgb<u8> {
-    let c = to_float(col, samples_per_pixel);
+/// Convert a color that is the mean of all samples to an rgb color
+pub fn to_rgb_color(col: Vec3) -> Rgb<u8> {
+    let c = to_float(col);
     Rgb([(256. * c.x) as u8, (256. * c.y) as u8, (256. * c.z) as u8])
 }
 
-/// Converts a color in a Vec3 that is the sum of a given of amounts of samples
+/// Converts a color in a Vec3 that is the mean of all samples
 /// to a float color. Applies gamma correction to the output color.
-pub fn to_float(col: Vec3, samples_per_pixel: u32) -> Vec3 {
-    // Divide the color by the number of samples
-    // and gamma-correct for gamma=2.0
-    let scale = 1.0 / samples_per_pixel as f64;
-    let r = (scale * col.x).sqrt();
-    let g = (scale * col.y).sqrt();
-    let b = (scale * col.z).sqrt();
+pub fn to_float(col: Vec3) -> Vec3 {
+    // Gamma-correct for gamma=2.0
+    let r = col.x.sqrt();
+    let g = col.y.sqrt();
+    let b = col.z.sqrt();
 
     Vec3::new(
         COLOR_INTENSITY_INTERVAL.clamp(r),
@@ -33,6 +31,11 @@ pub fn to_float(col: Vec3, samples_per_pixel: u32) -> Vec3 {
     )
 }
 
+/// Perceived brightness of a linear color
+pub fn luminance(col: Vec3) -> f64 {
+    0.2126 * col.x + 0.7152 * col.y + 0.0722 * col.z
+}
+
 /// Converts rgb pixel to a Vec3 color
 pub fn rgb_to_vec3(pixel: &Rgb<u8>) -> Vec3 {
     Vec3::new(
@@ -56,7 +59,7 @@ mod tests {
 
     #[test]
     fn test_to_rgb_color() {
-        assert_eq!(Rgb([0, 140, 255]), to_rgb_color(Vec3::new(0., 0.3, 1.), 1));
-        assert_eq!(Rgb([0, 99, 181]), to_rgb_color(Vec3::new(0., 0.3, 1.), 2));
+        assert_eq!(Rgb([0, 140, 255]), to_rgb_color(Vec3::new(0., 0.3, 1.)));
+        assert_eq!(Rgb([0, 99, 181]), to_rgb_color(Vec3::new(0., 0.15, 0.5)));
     }
 }
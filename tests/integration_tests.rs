@@ -4,20 +4,26 @@ use std::error::Error;
 use std::ops::Deref;
 use std::sync::mpsc::channel;
 use std::thread;
+use std::time::{Duration, Instant};
 
 use image::imageops::FilterType;
-use image::RgbImage;
+use image::{GenericImage, RgbImage};
 use image_compare::Algorithm::RootMeanSquared;
 
+use solstrale::camera::CameraConfig;
 use solstrale::geo::transformation::{RotationX, RotationY, RotationZ, Transformer};
 use solstrale::geo::vec3::{Vec3, ZERO_VECTOR};
-use solstrale::post::{BloomPostProcessor, OidnPostProcessor, PostProcessor};
-use solstrale::ray_trace;
-use solstrale::renderer::{RenderConfig, Scene};
+use solstrale::hittable::{Bvh, EnvironmentMap, Sphere};
+use solstrale::material::DiffuseLight;
+use solstrale::post::{BloomPostProcessor, NopPostProcessor, OidnPostProcessor, PostProcessor};
+use solstrale::{ray_trace, render_image, RenderProgressIter};
+use solstrale::renderer::{stitch, Accumulation, ImageFormat, ImageRegion, Parallelism, RenderConfig, RenderImage, RenderImageStrategy, RenderProgress, Renderer, Scene, ThreadPlacement};
+use solstrale::renderer::background::CustomBackground;
 use solstrale::renderer::shader::{PathTracingShader, Shaders, SimpleShader};
-use solstrale::util::rgb_color::rgb_to_vec3;
+use solstrale::util::rgb_color::{rgb_to_vec3, to_rgb_color};
+use solstrale::util::color_space::ColorSpace;
 
-use crate::scenes::{create_blend_material_scene, create_light_attenuation_scene, create_normal_mapping_scene, create_normal_mapping_sphere_scene, create_obj_scene, create_obj_with_box, create_obj_with_triangle, create_quad_rotation_scene, create_simple_test_scene, create_test_scene, create_uv_scene};
+use crate::scenes::{create_blend_material_scene, create_holdout_scene, create_light_attenuation_scene, create_normal_mapping_scene, create_normal_mapping_sphere_scene, create_obj_scene, create_obj_with_box, create_obj_with_triangle, create_quad_rotation_scene, create_simple_test_scene, create_sun_shadow_scene, create_test_scene, create_uv_scene};
 
 mod scenes;
 
@@ -44,6 +50,195 @@ fn test_render_scene() {
     }
 }
 
+#[test]
+fn test_render_scene_with_warm_up() {
+    let render_config = RenderConfig {
+        width: 200,
+        height: 100,
+        samples_per_pixel: 25,
+        shader: PathTracingShader::new(50),
+        warm_up: true,
+        ..Default::default()
+    };
+    let scene = create_test_scene(render_config);
+
+    render_and_compare_output(scene, "pathTracing")
+}
+
+#[test]
+fn test_render_scene_numa_aware() {
+    let render_config = RenderConfig {
+        width: 200,
+        height: 100,
+        samples_per_pixel: 25,
+        shader: PathTracingShader::new(50),
+        thread_placement: ThreadPlacement::NumaAware,
+        ..Default::default()
+    };
+    let scene = create_test_scene(render_config);
+
+    render_and_compare_output(scene, "pathTracing")
+}
+
+#[test]
+fn test_tile_hashes() {
+    let hashes = |seed| {
+        let render_config = RenderConfig {
+            width: 40,
+            height: 20,
+            samples_per_pixel: 3,
+            parallelism: Parallelism::SingleThreaded(seed),
+            tile_hashes: true,
+            ..Default::default()
+        };
+        RenderProgressIter::new(create_test_scene(render_config))
+            .unwrap()
+            .flat_map(|p| p.tile_hashes)
+            .collect::<Vec<_>>()
+    };
+
+    let first = hashes(1234);
+    // Two tiles of 32 pixels cover the width of the image
+    assert_eq!(first.len(), 2 * 3);
+    assert_eq!(first[0].sample, 1);
+    assert_eq!((first[1].region.x, first[1].region.width), (32, 8));
+    assert_eq!(first, hashes(1234));
+    assert_ne!(first[0].hash, hashes(4321)[0].hash);
+}
+
+#[test]
+fn test_tile_updates() {
+    for color_space in [ColorSpace::LinearSrgb, ColorSpace::AcesCg] {
+        assert_tile_updates_make_up_image(color_space);
+    }
+}
+
+fn assert_tile_updates_make_up_image(color_space: ColorSpace) {
+    let render_config = RenderConfig {
+        width: 40,
+        height: 20,
+        samples_per_pixel: 2,
+        post_processors: vec![NopPostProcessor::new()],
+        parallelism: Parallelism::SingleThreaded(1234),
+        tile_updates: true,
+        color_space,
+        ..Default::default()
+    };
+    let progress: Vec<RenderProgress> =
+        RenderProgressIter::new(create_test_scene(render_config)).unwrap().collect();
+
+    // Two tiles and then the sample, for each of the samples
+    let updates: Vec<bool> = progress.iter().map(|p| p.tile_update.is_some()).collect();
+    assert_eq!(updates, vec![true, true, false, true, true, false]);
+    assert!(progress.windows(2).all(|p| p[0].progress <= p[1].progress));
+    assert_eq!(progress[1].progress, 0.5);
+
+    // The tiles of the last sample make up the final image
+    let mut image = RgbImage::new(40, 20);
+    for update in progress.iter().filter_map(|p| p.tile_update.as_ref()) {
+        let (width, height) = (update.region.width as u32, update.region.height as u32);
+        assert_eq!(update.image.dimensions(), (width, height));
+        if update.sample == 2 {
+            image.copy_from(&update.image, update.region.x as u32, update.region.y as u32).unwrap();
+        }
+    }
+    assert_eq!(Some(RenderImage::Rgb8(image)), progress[5].render_image);
+}
+
+#[test]
+fn test_aov_output() {
+    let render_config = RenderConfig {
+        width: 40,
+        height: 20,
+        samples_per_pixel: 2,
+        render_image_strategy: RenderImageStrategy::EverySample,
+        aov_output: true,
+        ..Default::default()
+    };
+    let progress: Vec<RenderProgress> =
+        RenderProgressIter::new(create_simple_test_scene(render_config, true)).unwrap().collect();
+
+    assert_eq!(progress.len(), 2);
+    for p in progress {
+        let aovs = p.aovs.unwrap();
+        assert_eq!(aovs.albedo_colors.len(), 40 * 20);
+        assert_eq!(aovs.normal_colors.len(), 40 * 20);
+        assert!(aovs.motion_vectors.is_empty());
+        assert!(aovs.sun_visibility.is_empty());
+
+        // The corner sees the background, and the middle sees the yellow sphere facing the camera
+        assert_eq!(aovs.albedo_colors[0], Vec3::new(0.2, 0.3, 0.5));
+        assert_eq!(aovs.normal_colors[0], ZERO_VECTOR);
+        let middle = 10 * 40 + 20;
+        assert!((aovs.albedo_colors[middle] - Vec3::new(1., 1., 0.)).near_zero());
+        assert!(aovs.normal_colors[middle].z > 0.9, "normal {}", aovs.normal_colors[middle]);
+    }
+}
+
+#[test]
+fn test_sun_visibility_aov() {
+    for (holdout_wall, aov_samples) in [(false, None), (true, None), (false, Some(3))] {
+        let render_config = RenderConfig {
+            width: 20,
+            height: 20,
+            samples_per_pixel: 4,
+            aov_output: true,
+            aov_samples,
+            ..Default::default()
+        };
+        let progress: Vec<RenderProgress> =
+            RenderProgressIter::new(create_sun_shadow_scene(render_config, holdout_wall))
+                .unwrap()
+                .collect();
+
+        let aovs = progress.last().unwrap().aovs.as_ref().unwrap();
+        assert_eq!(aovs.sun_visibility.len(), 20 * 20);
+        // The left half of the wall is in the shadow of the quad, the right half is lit
+        let (shadowed, lit) = (10 * 20 + 6, 10 * 20 + 13);
+        assert_eq!(aovs.sun_visibility[shadowed], 0.);
+        assert_eq!(aovs.sun_visibility[lit], 1.);
+    }
+}
+
+#[test]
+fn test_float_image_output() {
+    let render_config = RenderConfig {
+        width: 40,
+        height: 20,
+        samples_per_pixel: 2,
+        image_format: ImageFormat::Rgb32F,
+        ..Default::default()
+    };
+    let image = RenderProgressIter::new(create_simple_test_scene(render_config, true))
+        .unwrap()
+        .filter_map(|p| p.render_image)
+        .last()
+        .unwrap();
+
+    let RenderImage::Rgb32F(float_image) = &image else {
+        panic!("Expected a float image");
+    };
+    // The corner sees the background, with its linear color
+    assert_eq!(float_image.get_pixel(0, 0).0, [0.2, 0.3, 0.5]);
+    assert_eq!(image.into_rgb8().dimensions(), (40, 20));
+}
+
+#[test]
+fn test_render_single_threaded_is_deterministic() {
+    let render = || {
+        let render_config = RenderConfig {
+            width: 40,
+            height: 20,
+            samples_per_pixel: 5,
+            parallelism: Parallelism::SingleThreaded(1234),
+            ..Default::default()
+        };
+        render_image(create_test_scene(render_config), 40, 20).unwrap()
+    };
+
+    assert_eq!(render(), render());
+}
+
 #[test]
 #[cfg(feature = "oidn-postprocessor")]
 fn test_render_scene_with_oidn() {
@@ -192,6 +387,314 @@ fn test_render_scene_without_light() {
     }
 }
 
+#[test]
+fn test_render_scene_lit_by_environment_map() {
+    let mut scene = create_simple_test_scene(RenderConfig::default(), false);
+    scene.environment_map = Some(EnvironmentMap::load("resources/textures/sky.hdr", 1.).unwrap());
+
+    let image = render_image(scene, 20, 10).unwrap();
+    let mean_brightness = image.pixels().map(|p| rgb_to_vec3(p).length()).sum::<f64>() / 200.;
+    assert!(mean_brightness > 0.1);
+}
+
+#[test]
+fn test_render_views() {
+    let render_config = RenderConfig {
+        width: 20,
+        height: 10,
+        samples_per_pixel: 1,
+        ..Default::default()
+    };
+    let mut scene = create_simple_test_scene(render_config, true);
+    scene.views.insert(
+        "side".to_string(),
+        CameraConfig {
+            look_from: Vec3::new(4., 0., 0.),
+            ..CameraConfig::default()
+        },
+    );
+    let renderer = Renderer::new(scene).unwrap();
+    let (_, abort_receiver) = channel();
+
+    for view in [Some("side"), None, Some("side")] {
+        let (output_sender, output_receiver) = channel();
+        match view {
+            Some(view) => renderer.render_view(view, &output_sender, &abort_receiver),
+            None => renderer.render(&output_sender, &abort_receiver),
+        }
+        .unwrap();
+        let image = output_receiver.try_iter().filter_map(|p| p.render_image).last();
+        assert_eq!(image.unwrap().dimensions(), (20, 10));
+    }
+
+    let (output_sender, _) = channel();
+    match renderer.render_view("missing", &output_sender, &abort_receiver) {
+        Ok(_) => panic!("There should be an error"),
+        Err(e) => assert_eq!("Scene has no view named 'missing'", e.to_string()),
+    }
+}
+
+#[test]
+fn test_abort_during_sample() {
+    // Large enough that a single sample takes much longer than the abort
+    let render_config = RenderConfig {
+        width: 4000,
+        height: 4000,
+        samples_per_pixel: 1,
+        ..Default::default()
+    };
+    let scene = create_simple_test_scene(render_config, true);
+    let (output_sender, output_receiver) = channel();
+    let (abort_sender, abort_receiver) = channel();
+
+    let abort_thread = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        abort_sender.send(true).unwrap();
+    });
+    ray_trace(scene, &output_sender, &abort_receiver).unwrap();
+    abort_thread.join().unwrap();
+
+    assert_eq!(output_receiver.try_iter().count(), 0);
+}
+
+/// Renders a scene where each pixel takes at least a millisecond, and checks that
+/// an abort soon after the start stops the rendering without any progress
+fn assert_aborts_quickly(render_config: RenderConfig) {
+    let mut scene = create_simple_test_scene(render_config, true);
+    scene.background = Some(CustomBackground::new(|direction| {
+        thread::sleep(Duration::from_millis(1));
+        direction
+    }));
+    let (output_sender, output_receiver) = channel();
+    let (abort_sender, abort_receiver) = channel();
+
+    let abort_thread = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        abort_sender.send(true).unwrap();
+        Instant::now()
+    });
+    ray_trace(scene, &output_sender, &abort_receiver).unwrap();
+    let latency = abort_thread.join().unwrap().elapsed();
+
+    assert!(latency < Duration::from_millis(500), "took {:?} to abort", latency);
+    assert_eq!(output_receiver.try_iter().count(), 0);
+}
+
+#[test]
+fn test_abort_during_tile_single_threaded() {
+    // A tile takes a second
+    assert_aborts_quickly(RenderConfig {
+        width: 64,
+        height: 32,
+        samples_per_pixel: 1,
+        parallelism: Parallelism::SingleThreaded(1234),
+        ..Default::default()
+    });
+}
+
+#[test]
+fn test_abort_during_warm_up() {
+    // Warming up takes a minute of cpu time
+    assert_aborts_quickly(RenderConfig {
+        width: 1024,
+        height: 1024,
+        samples_per_pixel: 1,
+        warm_up: true,
+        ..Default::default()
+    });
+}
+
+#[test]
+fn test_panicking_tiles_are_reported() {
+    let render_config = RenderConfig {
+        width: 128,
+        height: 64,
+        samples_per_pixel: 2,
+        ..Default::default()
+    };
+    let mut scene = create_simple_test_scene(render_config, true);
+    // Only the camera rays see the background, which panics in the middle of the image
+    scene.world = Bvh::new(vec![Sphere::new(
+        Vec3::new(0., 100., 0.),
+        20.,
+        DiffuseLight::new(10., 10., 10., None),
+    )]);
+    scene.background = Some(CustomBackground::new(|direction| {
+        if direction.z < -0.995 {
+            panic!("Background is broken in the middle");
+        }
+        Vec3::new(0.2, 0.3, 0.5)
+    }));
+
+    let progress: Vec<RenderProgress> = RenderProgressIter::new(scene).unwrap().collect();
+    assert_eq!(progress.len(), 2);
+    for (i, p) in progress.iter().enumerate() {
+        // Only the tiles in the middle of the image see the panicking background
+        assert_eq!(p.tile_errors.len(), 4);
+        for e in &p.tile_errors {
+            assert_eq!(e.sample, i as u32 + 1);
+            assert!((32..96).contains(&e.region.x));
+            assert_eq!(e.message, "Background is broken in the middle");
+        }
+    }
+    assert!(progress[1].render_image.is_some());
+}
+
+#[test]
+fn test_abort_render_progress_iter() {
+    let render_config = RenderConfig {
+        width: 40,
+        height: 20,
+        samples_per_pixel: 100000,
+        ..Default::default()
+    };
+    let scene = create_simple_test_scene(render_config, true);
+
+    let mut progress_iter = RenderProgressIter::new(scene).unwrap();
+    let first_progress = progress_iter.next().unwrap();
+    progress_iter.abort();
+    let last_progress = progress_iter.last().map_or(first_progress.progress, |p| p.progress);
+
+    assert!(last_progress < 1.);
+}
+
+#[test]
+fn test_standard_errors_decrease() {
+    let render_config = RenderConfig {
+        width: 40,
+        height: 20,
+        samples_per_pixel: 50,
+        render_image_strategy: RenderImageStrategy::EverySample,
+        ..Default::default()
+    };
+    let scene = create_simple_test_scene(render_config, true);
+
+    let mean_standard_errors: Vec<f64> = RenderProgressIter::new(scene)
+        .unwrap()
+        .map(|p| {
+            let standard_errors = p.standard_errors.unwrap();
+            assert_eq!(standard_errors.len(), 40 * 20);
+            standard_errors.iter().sum::<f64>() / standard_errors.len() as f64
+        })
+        .collect();
+
+    assert_eq!(mean_standard_errors.len(), 50);
+    assert!(mean_standard_errors[0].is_infinite());
+    assert!(mean_standard_errors[49] < mean_standard_errors[4]);
+}
+
+#[test]
+fn test_render_progress_iter_scene_error() {
+    let scene = create_simple_test_scene(RenderConfig::default(), false);
+
+    match RenderProgressIter::new(scene) {
+        Ok(_) => panic!("There should be an error"),
+        Err(e) => assert_eq!("Scene should have at least one light", e.to_string()),
+    }
+}
+
+#[test]
+fn test_render_with_invalid_post_processor_chain() {
+    let render_config = RenderConfig {
+        post_processors: vec![OidnPostProcessor::new(), BloomPostProcessor::new(0.1, None, None).unwrap()],
+        ..RenderConfig::default()
+    };
+    let scene = create_simple_test_scene(render_config, true);
+
+    match RenderProgressIter::new(scene) {
+        Ok(_) => panic!("There should be an error"),
+        Err(e) => assert_eq!("OIDN can only be used as the last post processor", e.to_string()),
+    }
+}
+
+#[test]
+fn test_stitch_rendered_regions() {
+    let render_config = RenderConfig {
+        width: 40,
+        height: 20,
+        samples_per_pixel: 3,
+        parallelism: Parallelism::SingleThreaded(1234),
+        ..Default::default()
+    };
+    let full_image = render_image(create_test_scene(render_config.clone()), 40, 20).unwrap();
+
+    let parts: Vec<(ImageRegion, RgbImage)> = ImageRegion::split(40, 20, 16, 16)
+        .into_iter()
+        .map(|region| {
+            let render_config = RenderConfig {
+                region: Some(region),
+                ..render_config.clone()
+            };
+            (region, render_image(create_test_scene(render_config), 40, 20).unwrap())
+        })
+        .collect();
+    let stitched_image = stitch(40, 20, &parts).unwrap();
+
+    assert_eq!(full_image, stitched_image);
+}
+
+#[test]
+fn test_f32_accumulation() {
+    let render_config = RenderConfig {
+        width: 40,
+        height: 20,
+        samples_per_pixel: 20,
+        parallelism: Parallelism::SingleThreaded(1234),
+        ..Default::default()
+    };
+    let f64_image = render_image(create_test_scene(render_config.clone()), 40, 20).unwrap();
+    let render_config = RenderConfig {
+        accumulation: Accumulation::F32,
+        ..render_config
+    };
+    let f32_image = render_image(create_test_scene(render_config), 40, 20).unwrap();
+
+    for (a, b) in f64_image.pixels().zip(f32_image.pixels()) {
+        for (ca, cb) in a.0.iter().zip(b.0) {
+            assert!(ca.abs_diff(cb) <= 1, "pixel {:?} differs from {:?}", a, b);
+        }
+    }
+}
+
+#[test]
+fn test_render_region_outside_image() {
+    let render_config = RenderConfig {
+        width: 20,
+        height: 10,
+        region: Some(ImageRegion { x: 10, y: 0, width: 11, height: 10 }),
+        ..Default::default()
+    };
+    match Renderer::new(create_simple_test_scene(render_config, true)) {
+        Ok(_) => panic!("There should be an error"),
+        Err(e) => assert_eq!("Render region should be a non empty part of the image", e.to_string()),
+    }
+}
+
+#[test]
+fn test_render_scene_with_unsampleable_light() {
+    let light = DiffuseLight::new(10., 10., 10., None);
+    let scene = Scene {
+        world: Bvh::new_light(vec![
+            Sphere::new(Vec3::new(0., 0., 0.), 1., light.clone()),
+            Sphere::new(Vec3::new(2., 0., 0.), 1., light),
+        ]),
+        camera: Default::default(),
+        views: HashMap::new(),
+        background_color: ZERO_VECTOR,
+        environment_map: None,
+        background: None,
+        render_config: RenderConfig::default(),
+    };
+
+    match render_image(scene, 20, 10) {
+        Ok(_) => panic!("There should be an error"),
+        Err(e) => assert_eq!(
+            "Scene has a light that can not be sampled, a light bvh can only contain triangles, quads and discs as lights",
+            e.to_string()
+        ),
+    }
+}
+
 #[test]
 fn test_render_obj_with_normal_map() {
     let render_config = RenderConfig {
@@ -246,7 +749,7 @@ fn test_bloom() -> Result<(), Box<dyn Error>> {
     let h = bloom_image.height();
     let pixel_colors = image_to_vec3(bloom_image);
 
-    let res = post.post_process(&pixel_colors, &[ZERO_VECTOR; 0], &[ZERO_VECTOR; 0], w, h, 1)?;
+    let res = post.post_process(&pixel_colors, &[ZERO_VECTOR; 0], &[ZERO_VECTOR; 0], &[], w, h)?;
 
     compare_output("bloom", &res);
 
@@ -292,6 +795,38 @@ fn test_blended_materials() {
     }
 }
 
+#[test]
+fn test_holdout() {
+    let render_config = RenderConfig {
+        width: 40,
+        height: 20,
+        samples_per_pixel: 5,
+        ..RenderConfig::default()
+    };
+    let image = render_image(create_holdout_scene(render_config), 40, 20).unwrap();
+    let background = image.get_pixel(35, 10);
+
+    // The holdout sphere shows the background, even where it is in front of the quad
+    assert_eq!(image.get_pixel(20, 10), background);
+    assert_eq!(image.get_pixel(18, 10), background);
+    assert_ne!(image.get_pixel(5, 10), background);
+}
+
+#[test]
+fn test_holdout_receives_shadows() {
+    let render_config = RenderConfig {
+        width: 20,
+        height: 20,
+        samples_per_pixel: 5,
+        ..RenderConfig::default()
+    };
+    let image = render_image(create_sun_shadow_scene(render_config, true), 20, 20).unwrap();
+
+    // The holdout wall shows the background where it is lit, and is black in the shadow
+    assert_eq!(*image.get_pixel(13, 10), to_rgb_color(Vec3::new(0.2, 0.3, 0.5)));
+    assert_eq!(rgb_to_vec3(image.get_pixel(6, 10)), ZERO_VECTOR);
+}
+
 fn image_to_vec3(image: RgbImage) -> Vec<Vec3> {
     let mut ret = Vec::with_capacity((image.width() * image.height()) as usize);
     for y in 0..image.height() {
@@ -303,23 +838,9 @@ fn image_to_vec3(image: RgbImage) -> Vec<Vec3> {
 }
 
 fn render_and_compare_output(scene: Scene, name: &str) {
-    let (output_sender, output_receiver) = channel();
-    let (_, abort_receiver) = channel();
-
-    let width = scene.render_config.width as u32;
-    let height = scene.render_config.height as u32;
-
-    thread::spawn(move || {
-        ray_trace(scene, &output_sender, &abort_receiver).unwrap();
-    });
-
-    let mut image = RgbImage::new(width, height);
-    for render_output in output_receiver {
-        if let Some(render_image) = render_output.render_image {
-            image = render_image;
-        }
-    }
-
+    let width = scene.render_config.width;
+    let height = scene.render_config.height;
+    let image = render_image(scene, width, height).unwrap();
     compare_output(name, &image);
 }
 
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use solstrale::camera::CameraConfig;
 use solstrale::geo::transformation::{
     NopTransformer, RotationY, Transformations, Transformer, Translation,
@@ -6,12 +8,12 @@ use solstrale::geo::vec3::Vec3;
 use solstrale::geo::Uv;
 use solstrale::hittable::ConstantMedium;
 use solstrale::hittable::Sphere;
-use solstrale::hittable::Triangle;
-use solstrale::hittable::{Bvh, Quad};
+use solstrale::hittable::{Triangle, TriangleOptions};
+use solstrale::hittable::{Bvh, DirectionalLight, Quad};
 use solstrale::loader::obj::Obj;
 use solstrale::loader::Loader;
 use solstrale::material::texture::{load_normal_texture, ImageMap, SolidColor};
-use solstrale::material::{Blend, Dielectric, DiffuseLight, Lambertian};
+use solstrale::material::{Blend, Dielectric, DiffuseLight, Holdout, Lambertian};
 use solstrale::renderer::{RenderConfig, Scene};
 
 pub fn create_test_scene(render_config: RenderConfig) -> Scene {
@@ -21,6 +23,7 @@ pub fn create_test_scene(render_config: RenderConfig) -> Scene {
         look_from: Vec3::new(-5., 3., 6.),
         look_at: Vec3::new(0.25, 1., 0.),
         up: Vec3::new(0., 1., 0.),
+        focus_distance: None,
     };
 
     let mut world = Vec::new();
@@ -116,7 +119,10 @@ pub fn create_test_scene(render_config: RenderConfig) -> Scene {
     Scene {
         world: Bvh::new(world),
         camera,
+        views: HashMap::new(),
         background_color: Vec3::new(0.2, 0.3, 0.5),
+        environment_map: None,
+        background: None,
         render_config,
     }
 }
@@ -129,6 +135,7 @@ pub fn new_bvh_test_scene(render_config: RenderConfig, use_bvh: bool, num_triang
         look_from: Vec3::new(-0.5, 0., 4.),
         look_at: Vec3::new(-0.5, 0., 0.),
         up: Vec3::new(0., 1., 0.),
+        focus_distance: None,
     };
 
     let mut world = Vec::new();
@@ -161,7 +168,10 @@ pub fn new_bvh_test_scene(render_config: RenderConfig, use_bvh: bool, num_triang
     Scene {
         world: Bvh::new(world),
         camera,
+        views: HashMap::new(),
         background_color: Vec3::new(0.2, 0.3, 0.5),
+        environment_map: None,
+        background: None,
         render_config,
     }
 }
@@ -174,6 +184,7 @@ pub fn create_simple_test_scene(render_config: RenderConfig, add_light: bool) ->
         look_from: Vec3::new(0., 0., 4.),
         look_at: Vec3::new(0., 0., 0.),
         up: Vec3::new(0., 1., 0.),
+        focus_distance: None,
     };
 
     let mut world = Vec::new();
@@ -187,7 +198,10 @@ pub fn create_simple_test_scene(render_config: RenderConfig, add_light: bool) ->
     Scene {
         world: Bvh::new(world),
         camera,
+        views: HashMap::new(),
         background_color: Vec3::new(0.2, 0.3, 0.5),
+        environment_map: None,
+        background: None,
         render_config,
     }
 }
@@ -200,6 +214,7 @@ pub fn create_uv_scene(render_config: RenderConfig) -> Scene {
         look_from: Vec3::new(0., 1., 5.),
         look_at: Vec3::new(0., 1., 0.),
         up: Vec3::new(0., 1., 0.),
+        focus_distance: None,
     };
 
     let mut world = Vec::new();
@@ -210,13 +225,14 @@ pub fn create_uv_scene(render_config: RenderConfig) -> Scene {
     let tex = ImageMap::load("resources/textures/checker.jpg").unwrap();
     let checker_mat = Lambertian::new(tex, None);
 
-    world.push(Triangle::new_with_tex_coords(
+    world.push(Triangle::new_with_options(
         Vec3::new(-1., 0., 0.),
         Vec3::new(1., 0., 0.),
         Vec3::new(0., 2., 0.),
-        Uv::new(-1., -1.),
-        Uv::new(2., -1.),
-        Uv::new(0., 2.),
+        TriangleOptions {
+            uvs: [Uv::new(-1., -1.), Uv::new(2., -1.), Uv::new(0., 2.)],
+            ..Default::default()
+        },
         checker_mat,
         &NopTransformer(),
     ));
@@ -224,7 +240,10 @@ pub fn create_uv_scene(render_config: RenderConfig) -> Scene {
     Scene {
         world: Bvh::new(world),
         camera,
+        views: HashMap::new(),
         background_color: Vec3::new(0.2, 0.3, 0.5),
+        environment_map: None,
+        background: None,
         render_config,
     }
 }
@@ -241,6 +260,7 @@ pub fn create_normal_mapping_scene(
         look_from: Vec3::new(0.2, 0.2, 2.),
         look_at: Vec3::new(0., 0., 0.),
         up: Vec3::new(0., 1., 0.),
+        focus_distance: None,
     };
 
     let mut world = Vec::new();
@@ -274,7 +294,10 @@ pub fn create_normal_mapping_scene(
     Scene {
         world: Bvh::new(world),
         camera,
+        views: HashMap::new(),
         background_color: Vec3::new(0., 0., 0.),
+        environment_map: None,
+        background: None,
         render_config,
     }
 }
@@ -290,6 +313,7 @@ pub fn create_normal_mapping_sphere_scene(
         look_from: Vec3::new(0.2, 0.2, 2.),
         look_at: Vec3::new(0., 0., 0.),
         up: Vec3::new(0., 1., 0.),
+        focus_distance: None,
     };
 
     let mut world = Vec::new();
@@ -309,7 +333,10 @@ pub fn create_normal_mapping_sphere_scene(
     Scene {
         world: Bvh::new(world),
         camera,
+        views: HashMap::new(),
         background_color: Vec3::new(0., 0., 0.),
+        environment_map: None,
+        background: None,
         render_config,
     }
 }
@@ -322,6 +349,7 @@ pub fn create_obj_scene(render_config: RenderConfig) -> Scene {
         look_from: Vec3::new(-250., 30., 150.),
         look_at: Vec3::new(-50., 0., 0.),
         up: Vec3::new(0., 1., 0.),
+        focus_distance: None,
     };
 
     let mut world = Vec::new();
@@ -346,7 +374,10 @@ pub fn create_obj_scene(render_config: RenderConfig) -> Scene {
     Scene {
         world: Bvh::new(world),
         camera,
+        views: HashMap::new(),
         background_color: Vec3::new(0.2, 0.3, 0.5),
+        environment_map: None,
+        background: None,
         render_config,
     }
 }
@@ -359,6 +390,7 @@ pub fn create_obj_with_box(render_config: RenderConfig, path: &str, filename: &s
         look_from: Vec3::new(2., 1., 3.),
         look_at: Vec3::new(0., 0., 0.),
         up: Vec3::new(0., 1., 0.),
+        focus_distance: None,
     };
 
     let mut world = Vec::new();
@@ -375,7 +407,10 @@ pub fn create_obj_with_box(render_config: RenderConfig, path: &str, filename: &s
     Scene {
         world: Bvh::new(world),
         camera,
+        views: HashMap::new(),
         background_color: Vec3::new(0.2, 0.3, 0.5),
+        environment_map: None,
+        background: None,
         render_config,
     }
 }
@@ -388,6 +423,7 @@ pub fn create_obj_with_triangle(render_config: RenderConfig, path: &str, filenam
         look_from: Vec3::new(0., 0., 2.),
         look_at: Vec3::new(0., 0., 0.),
         up: Vec3::new(0., 1., 0.),
+        focus_distance: None,
     };
 
     let mut world = Vec::new();
@@ -403,7 +439,10 @@ pub fn create_obj_with_triangle(render_config: RenderConfig, path: &str, filenam
     Scene {
         world: Bvh::new(world),
         camera,
+        views: HashMap::new(),
         background_color: Vec3::new(0., 0., 0.),
+        environment_map: None,
+        background: None,
         render_config,
     }
 }
@@ -419,6 +458,7 @@ pub fn create_light_attenuation_scene(
         look_from: Vec3::new(0., 1., 2.),
         look_at: Vec3::new(0., 0.2, 0.),
         up: Vec3::new(0., 1., 0.),
+        focus_distance: None,
     };
 
     let mut world = Vec::new();
@@ -443,7 +483,10 @@ pub fn create_light_attenuation_scene(
     Scene {
         world: Bvh::new(world),
         camera,
+        views: HashMap::new(),
         background_color: Vec3::new(0., 0., 0.),
+        environment_map: None,
+        background: None,
         render_config,
     }
 }
@@ -473,7 +516,10 @@ pub fn create_quad_rotation_scene(
             look_from: Vec3::new(0., 200., -500.),
             ..CameraConfig::default()
         },
+        views: HashMap::new(),
         background_color: Default::default(),
+        environment_map: None,
+        background: None,
         render_config,
     }
 }
@@ -507,7 +553,92 @@ pub fn create_blend_material_scene(render_config: RenderConfig, blend_factor: f6
             look_from: Vec3::new(0., 400., -100.),
             ..CameraConfig::default()
         },
+        views: HashMap::new(),
         background_color: Default::default(),
+        environment_map: None,
+        background: None,
+        render_config,
+    }
+}
+
+#[allow(dead_code)]
+pub fn create_holdout_scene(render_config: RenderConfig) -> Scene {
+    Scene {
+        world: Bvh::new(vec![
+            Quad::new(
+                Vec3::new(-10., -10., -1.),
+                Vec3::new(10., 0., 0.),
+                Vec3::new(0., 20., 0.),
+                Lambertian::new(SolidColor::new(1., 1., 0.), None),
+                &NopTransformer(),
+            ),
+            Sphere::new(
+                Vec3::new(0., 0., 0.),
+                0.5,
+                Holdout::new(Lambertian::new(SolidColor::new(1., 1., 1.), None)),
+            ),
+            Sphere::new(
+                Vec3::new(0., 0., 100.),
+                20.,
+                DiffuseLight::new(10., 10., 10., None),
+            ),
+        ]),
+        camera: CameraConfig {
+            vertical_fov_degrees: 20.,
+            aperture_size: 0.,
+            look_from: Vec3::new(0., 0., 4.),
+            look_at: Vec3::new(0., 0., 0.),
+            up: Vec3::new(0., 1., 0.),
+            focus_distance: None,
+        },
+        views: HashMap::new(),
+        background_color: Vec3::new(0.2, 0.3, 0.5),
+        environment_map: None,
+        background: None,
+        render_config,
+    }
+}
+
+/// A wall facing the camera, with the shadow of a quad cast on its left half by a sun
+/// shining in from the right
+#[allow(dead_code)]
+pub fn create_sun_shadow_scene(render_config: RenderConfig, holdout_wall: bool) -> Scene {
+    let white = Lambertian::new(SolidColor::new(1., 1., 1.), None);
+    let wall_material = if holdout_wall {
+        Holdout::new(white.clone())
+    } else {
+        white.clone()
+    };
+    Scene {
+        world: Bvh::new(vec![
+            Quad::new(
+                Vec3::new(-5., -5., 0.),
+                Vec3::new(10., 0., 0.),
+                Vec3::new(0., 10., 0.),
+                wall_material,
+                &NopTransformer(),
+            ),
+            Quad::new(
+                Vec3::new(-2., -5., 1.),
+                Vec3::new(1., 0., 0.),
+                Vec3::new(0., 10., 0.),
+                white,
+                &NopTransformer(),
+            ),
+            DirectionalLight::new(Vec3::new(1., 0., -1.), Vec3::new(3., 3., 3.), 0.53),
+        ]),
+        camera: CameraConfig {
+            vertical_fov_degrees: 40.,
+            aperture_size: 0.,
+            look_from: Vec3::new(0., 0., 4.),
+            look_at: Vec3::new(0., 0., 0.),
+            up: Vec3::new(0., 1., 0.),
+            focus_distance: None,
+        },
+        views: HashMap::new(),
+        background_color: Vec3::new(0.2, 0.3, 0.5),
+        environment_map: None,
+        background: None,
         render_config,
     }
 }